@@ -0,0 +1,133 @@
+local P={}
+P.plugin_name="Arpeggiator"
+P.plugin_type="generate"
+P.plugin_info={
+    author="Andromeda",
+    description="Turns the selected chords into arpeggios. Notes starting on the same tick make up a chord, which gets played one note at a time for as long as it lasts."
+}
+P.dialog_fields={
+    {
+        id="pattern",
+        {
+            type="dropdown",
+            label="Pattern",
+            value=0,
+            value_labels={"Up","Down","Up-Down","Down-Up","Random","As Ordered"}
+        }
+    },
+    {
+        id="rate",
+        {
+            type="dropdown",
+            label="Rate",
+            value=2,
+            value_labels={"1/4","1/8","1/16","1/32","1/8 Triplet","1/16 Triplet"}
+        }
+    },
+    {
+        id="octaves",
+        {
+            type="number",
+            label="Octave Range",
+            value=1,
+            range={
+                min=1,
+                max=4
+            }
+        }
+    },
+    {
+        id="gate",
+        {
+            type="number",
+            label="Gate %",
+            value=90,
+            range={
+                min=5,
+                max=100
+            }
+        }
+    },
+    {},
+    {
+        type="label",
+        label="The selected notes are replaced by the arpeggio."
+    }
+}
+-- length of one step, in quarter notes
+local RATES={1,1/2,1/4,1/8,1/3,1/6}
+function get_chords(notes)
+    local chords={}
+    local by_start={}
+    notes:iter_selected(function(note)
+        local chord=by_start[note.start]
+        if not chord then
+            chord={start=note.start,finish=note.start,notes={}}
+            by_start[note.start]=chord
+            table.insert(chords,chord)
+        end
+        table.insert(chord.notes,{key=note.key,channel=note.channel,velocity=note.velocity})
+        chord.finish=math.max(chord.finish,note.start+note.length)
+    end)
+    table.sort(chords,function(a,b) return a.start<b.start end)
+    return chords
+end
+function build_sequence(chord_notes,pattern,octaves)
+    local ordered={}
+    for _,n in ipairs(chord_notes) do table.insert(ordered,n) end
+    if pattern~=5 then
+        table.sort(ordered,function(a,b) return a.key<b.key end)
+    end
+    local up={}
+    for octave=0,octaves-1 do
+        for _,n in ipairs(ordered) do
+            local key=n.key+octave*12
+            if key<=127 then
+                table.insert(up,{key=key,channel=n.channel,velocity=n.velocity})
+            end
+        end
+    end
+    local down={}
+    for i=#up,1,-1 do table.insert(down,up[i]) end
+    if pattern==1 then return down end
+    if pattern==2 or pattern==3 then
+        local first,second=up,down
+        if pattern==3 then first,second=down,up end
+        local seq={}
+        for _,n in ipairs(first) do table.insert(seq,n) end
+        -- the turning points aren't played twice
+        for i=2,#second-1 do table.insert(seq,second[i]) end
+        return seq
+    end
+    return up
+end
+function on_apply(notes)
+    local pattern=get_field_value("pattern")
+    local step=math.max(math.floor(andromeda:get_ppq()*RATES[get_field_value("rate")+1]+0.5),1)
+    local octaves=math.max(math.floor(get_field_value("octaves")),1)
+    local gate=get_field_value("gate")/100.0
+    local chords=get_chords(notes)
+    if #chords==0 then return end
+    notes:delete_selected()
+    for _,chord in ipairs(chords) do
+        local seq=build_sequence(chord.notes,pattern,octaves)
+        if #seq>0 then
+            local tick=chord.start
+            local i=0
+            while tick<chord.finish do
+                local n
+                if pattern==4 then
+                    n=seq[math.random(1,#seq)]
+                else
+                    n=seq[i%#seq+1]
+                end
+                local length=math.min(math.max(math.floor(step*gate),1),chord.finish-tick)
+                notes:create_note(tick,length,n.channel,n.key,n.velocity)
+                tick=tick+step
+                i=i+1
+            end
+        end
+    end
+end
+P.on_apply=on_apply
+return P
@@ -0,0 +1,168 @@
+local P={}
+P.plugin_name="Chord Generator"
+P.plugin_type="generate"
+P.plugin_info={
+    author="Andromeda",
+    description="Builds a chord on each selected note. Diatonic chords follow the key signature in effect at the note."
+}
+P.dialog_fields={
+    {
+        id="quality",
+        {
+            type="dropdown",
+            label="Quality",
+            value=0,
+            value_labels={"Diatonic Triad","Diatonic 7th","Major","Minor","Diminished","Augmented","Sus2","Sus4","Dominant 7th","Major 7th","Minor 7th"}
+        }
+    },
+    {
+        id="inversion",
+        {
+            type="number",
+            label="Inversion",
+            value=0,
+            range={
+                min=0,
+                max=3
+            }
+        }
+    },
+    {
+        id="voicing",
+        {
+            type="dropdown",
+            label="Voicing",
+            value=0,
+            value_labels={"Close","Open","Drop 2"}
+        }
+    },
+    {
+        id="snap_to_key",
+        {
+            type="toggle",
+            label="Snap Chord Tones to Key",
+            value=false
+        }
+    },
+    {},
+    {
+        type="label",
+        label="The selected notes are replaced by the chords. Snapping only affects the fixed qualities, diatonic chords are always in key."
+    }
+}
+-- semitones above the root, indexed by the quality dropdown (the diatonic ones are worked out from the key)
+local QUALITIES={
+    [2]={0,4,7},
+    [3]={0,3,7},
+    [4]={0,3,6},
+    [5]={0,4,8},
+    [6]={0,2,7},
+    [7]={0,5,7},
+    [8]={0,4,7,10},
+    [9]={0,4,7,11},
+    [10]={0,3,7,10}
+}
+local MAJOR_SCALE={0,2,4,5,7,9,11}
+function get_key_signatures()
+    local key_sigs={}
+    for _,meta in ipairs(andromeda:get_metas()) do
+        if meta.type=="key_signature" then
+            table.insert(key_sigs,meta)
+        end
+    end
+    table.sort(key_sigs,function(a,b) return a.tick<b.tick end)
+    return key_sigs
+end
+-- pitch classes of the key signature at `tick`, starting on its relative major. C major before the first one.
+function get_scale_at(key_sigs,tick)
+    local sharps_flats=0
+    for _,key_sig in ipairs(key_sigs) do
+        if key_sig.tick>tick then break end
+        sharps_flats=key_sig.sharps_flats
+    end
+    local tonic=(sharps_flats*7)%12
+    local scale={}
+    for i,interval in ipairs(MAJOR_SCALE) do
+        scale[i]=(tonic+interval)%12
+    end
+    return scale
+end
+function scale_degree(scale,key)
+    for i,pc in ipairs(scale) do
+        if pc==key%12 then return i end
+    end
+    return nil
+end
+-- moves the key to the closest key in the scale, ties go down
+function snap_to_scale(scale,key)
+    for dist=0,11 do
+        if key-dist>=0 and scale_degree(scale,key-dist) then return key-dist end
+        if key+dist<=127 and scale_degree(scale,key+dist) then return key+dist end
+    end
+    return key
+end
+-- stacks thirds of the scale on top of `root`
+function diatonic_chord(scale,root,size)
+    root=snap_to_scale(scale,root)
+    local degree=scale_degree(scale,root)
+    local keys={root}
+    local key=root
+    for i=1,(size-1)*2 do
+        local next_pc=scale[(degree+i-1)%7+1]
+        repeat key=key+1 until key%12==next_pc
+        if i%2==0 then table.insert(keys,key) end
+    end
+    return keys
+end
+function voice_chord(keys,inversion,voicing)
+    table.sort(keys)
+    for i=1,math.min(inversion,#keys-1) do
+        keys[1]=keys[1]+12
+        table.sort(keys)
+    end
+    if voicing==1 then
+        -- every other note from the second one goes up an octave
+        for i=2,#keys,2 do keys[i]=keys[i]+12 end
+    elseif voicing==2 and #keys>=3 then
+        keys[#keys-1]=keys[#keys-1]-12
+    end
+    table.sort(keys)
+    return keys
+end
+function on_apply(notes)
+    local quality=get_field_value("quality")
+    local inversion=math.floor(get_field_value("inversion"))
+    local voicing=get_field_value("voicing")
+    local snap=get_field_value("snap_to_key")
+    local key_sigs=get_key_signatures()
+    local roots={}
+    notes:iter_selected(function(note)
+        table.insert(roots,{start=note.start,length=note.length,channel=note.channel,key=note.key,velocity=note.velocity})
+    end)
+    if #roots==0 then return end
+    notes:delete_selected()
+    for _,root in ipairs(roots) do
+        local scale=get_scale_at(key_sigs,root.start)
+        local keys
+        if quality==0 or quality==1 then
+            keys=diatonic_chord(scale,root.key,quality==0 and 3 or 4)
+        else
+            keys={}
+            for _,interval in ipairs(QUALITIES[quality]) do
+                local key=root.key+interval
+                if snap then key=snap_to_scale(scale,key) end
+                table.insert(keys,key)
+            end
+        end
+        local placed={}
+        for _,key in ipairs(voice_chord(keys,inversion,voicing)) do
+            -- snapping can land two tones on the same key
+            if key>=0 and key<=127 and not placed[key] then
+                placed[key]=true
+                notes:create_note(root.start,root.length,root.channel,key,root.velocity)
+            end
+        end
+    end
+end
+P.on_apply=on_apply
+return P
@@ -0,0 +1,119 @@
+local P={}
+P.plugin_name="Strummer"
+P.plugin_type="manipulate"
+P.plugin_info={
+    author="Andromeda",
+    description="Staggers the notes of the selected chords like a strummed guitar. Notes starting on the same tick make up a chord."
+}
+P.dialog_fields={
+    {
+        id="direction",
+        {
+            type="dropdown",
+            label="Direction",
+            value=0,
+            value_labels={"Down (low to high)","Up (high to low)","Alternate"}
+        }
+    },
+    {
+        id="offset",
+        {
+            type="ticks",
+            label="Offset per Note",
+            value=20,
+            duration=true
+        }
+    },
+    {
+        id="keep_ends",
+        {
+            type="toggle",
+            label="Keep Note Ends",
+            value=true
+        }
+    },
+    {},
+    {
+        type="label",
+        label="Velocity Curve"
+    },
+    {
+        type="label"
+    },
+    {
+        id="curve",
+        {
+            type="dropdown",
+            label="Curve",
+            value=0,
+            value_labels={"Flat","Rising","Falling"}
+        }
+    },
+    {
+        id="curve_amount",
+        {
+            type="number",
+            label="Amount %",
+            value=30,
+            range={
+                min=0,
+                max=100
+            }
+        }
+    }
+}
+function on_apply(notes)
+    local direction=get_field_value("direction")
+    local offset=get_field_value("offset")
+    local keep_ends=get_field_value("keep_ends")
+    local curve=get_field_value("curve")
+    local amount=get_field_value("curve_amount")/100.0
+    -- first pass only looks, the second one visits the notes in the same order and moves them
+    local selected={}
+    local chords={}
+    local by_start={}
+    notes:iter_selected(function(note)
+        local entry={key=note.key,index=#selected+1}
+        table.insert(selected,entry)
+        local chord=by_start[note.start]
+        if not chord then
+            chord={start=note.start,notes={}}
+            by_start[note.start]=chord
+            table.insert(chords,chord)
+        end
+        table.insert(chord.notes,entry)
+    end)
+    table.sort(chords,function(a,b) return a.start<b.start end)
+    for c,chord in ipairs(chords) do
+        local upwards=direction==0 or (direction==2 and c%2==1)
+        table.sort(chord.notes,function(a,b)
+            if upwards then return a.key<b.key end
+            return a.key>b.key
+        end)
+        local count=#chord.notes
+        for i,entry in ipairs(chord.notes) do
+            local pos=0
+            if count>1 then pos=(i-1)/(count-1) end
+            entry.offset=(i-1)*offset
+            if curve==1 then
+                entry.velocity_scale=1.0-amount*(1.0-pos)
+            elseif curve==2 then
+                entry.velocity_scale=1.0-amount*pos
+            else
+                entry.velocity_scale=1.0
+            end
+        end
+    end
+    local i=0
+    notes:for_each_selected(function(note)
+        i=i+1
+        local entry=selected[i]
+        if keep_ends then
+            note.length=math.max(note.length-entry.offset,1)
+        end
+        note.start=note.start+entry.offset
+        note.velocity=math.min(math.max(math.floor(note.velocity*entry.velocity_scale+0.5),1),127)
+    end)
+end
+P.on_apply=on_apply
+return P
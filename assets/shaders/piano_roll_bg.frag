@@ -5,6 +5,7 @@ out vec4 fragColor;
 in vec2 uv;
 in float oddBarFac;
 in float bLength;
+flat in uint scale;
 
 uniform float width;
 uniform float height;
@@ -16,12 +17,23 @@ void main() {
     int key_int = int(key_pos) % 12;
     float key_sharp_fac = (key_int == 1 || key_int == 3 || key_int == 6 || key_int == 8 || key_int == 10) ? 0.9 : 1.0;
 
+    // scale highlighting. lower 12 bits are the scale mask, the next 4 are the tonic
+    vec3 scale_tint = vec3(0.0);
+    if (scale != 0u) {
+        bool in_scale = ((scale >> uint(key_int)) & 1u) == 1u;
+        key_sharp_fac = in_scale ? 1.0 : 0.75;
+        if (uint(key_int) == ((scale >> 12u) & 15u)) {
+            scale_tint = vec3(0.02, 0.03, 0.06);
+        }
+    }
+
     float beat_pos = uv.x * bLength / ppqNorm;
     int beat_int = int(beat_pos) % 2;
     float beat_odds_fac = (beat_int == 0) ? 0.95 : 1.0;
 
     vec3 color = vec3(0.2, 0.2, 0.25);
     color *= key_sharp_fac;
+    color += scale_tint;
     color *= beat_odds_fac;
     color *= oddBarFac;
 
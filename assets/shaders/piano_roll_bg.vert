@@ -3,10 +3,12 @@
 layout (location = 0) in float barStart;
 layout (location = 1) in float barLength;
 layout (location = 2) in uint barNumber;
+layout (location = 3) in uint barScale;
 
 out vec2 uv;
 out float oddBarFac;
 out float bLength;
+flat out uint scale;
 
 uniform float width;
 uniform float prBarBottom;
@@ -38,6 +40,7 @@ void main() {
     uv = uv_;
     oddBarFac = (int(barNumber) % 2 == 1) ? 0.8 : 1.0;
     bLength = barLength;
+    scale = barScale;
 
     float kbWidth = keyboardHeight / width;
     x_pos = x_pos * (1.0 - kbWidth) + kbWidth;
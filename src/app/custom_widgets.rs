@@ -3,6 +3,8 @@ use std::{fmt::Display, str::FromStr};
 use eframe::egui;
 use num_traits::{NumCast, ToPrimitive};
 
+use crate::util::expression_parser::eval_str;
+
 pub trait NumberField {
     fn show(&mut self, label: &str, ui: &mut egui::Ui, width: Option<f32>) -> egui::Response;
     fn changed(&self) -> bool;
@@ -39,8 +41,20 @@ where
         }
     }
 
+    /// Parses the buffer as a plain number, falling back to evaluating it as an expression (e.g. `960*3/4`).
+    fn parse_buffer(&self) -> Option<T> {
+        if let Ok(parsed) = self.buffer.parse::<T>() { return Some(parsed); }
+
+        let value = eval_str(&self.buffer).ok()?;
+        if !value.is_finite() { return None; }
+
+        // integer fields won't parse "720.5", so round those
+        value.to_string().parse::<T>().ok()
+            .or_else(|| NumCast::from(value.round()))
+    }
+
     fn update_buffer(&mut self) {
-        if let Ok(mut parsed) = self.buffer.parse::<T>() {
+        if let Some(mut parsed) = self.parse_buffer() {
             // clamping
             if let Some(min_value) = self.min_value {
                 if parsed < min_value { parsed = min_value; }
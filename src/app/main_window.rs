@@ -1,12 +1,13 @@
 // abstraction is NEEDED!!
 use crate::{
     LAST_PANIC, app::{
-        custom_widgets::{NumberField, NumericField}, rendering::{RenderManager, RenderType, Renderer, data_view::DataViewRenderer, note_cull_helper::NoteCullHelper, track_view::TrackViewRenderer}, shared::{NoteColorIndexing, NoteColors}, ui::{dialog::{Dialog, names::*}, dialog_drawer::DialogDrawer, dialog_manager::DialogManager, dialogs::{crash_dialog::CrashDialog, filter_channels::FilterChannelsDialog, simple_dialog::SimpleDialog}, edtior_info::EditorInfo, main_menu_bar::{MainMenuBar, MenuItem}, manual::EditorManualDialog}, util::image_loader::ImageResources, view_settings::{VS_PianoRoll_DataViewState, VS_PianoRoll_OnionColoring, VS_PianoRoll_OnionState}}, audio::{event_playback::PlaybackManager, kdmapi_engine::kdmapi::KDMAPI, midi_audio_engine::MIDIAudioEngine, midi_devices::MIDIDevices, track_mixer::TrackMixer}, editor::{
-            edit_functions::{EFChopDialog, EFGlueDialog}, editing::{SharedClipboard, SharedSelectedNotes, data_editing::{DataEditing, data_edit_flags::{DATA_EDIT_ANY_DIALOG_OPEN, DATA_EDIT_DRAW_EDIT_LINE, DATA_EDIT_MOUSE_OVER_UI}}, note_editing::note_edit_flags::NOTE_EDIT_MOUSE_OVER_UI, track_editing::track_flags::{TRACK_EDIT_ANY_DIALOG_OPEN, TRACK_EDIT_ERASING, TRACK_EDIT_MOUSE_OVER_UI}}, midi_bar_cacher::BarCacher, navigation::{GLOBAL_ZOOM_FACTOR, TrackViewNavigation}, playhead::Playhead, plugins::{PluginLoader, plugin_andromeda_obj::AndromedaObj, plugin_dialog::PluginDialog, plugin_error_dialog::PluginErrorDialog, plugin_lua::PluginLua}, project::{project_data, project_manager::ProjectManager}, settings::{editor_settings::{ESAudioEngineType, ESAudioSettings, ESGeneralSettings, ESSettingsWindow, PR_KEYBOARD_WIDTH, Settings}, project_settings::ProjectSettings}, util::{MIDITick, get_mouse_midi_pos, path_rel_to_abs}}, midi::{events::{meta_event::{MetaEvent, MetaEventType}, note}, io::MIDIParseStatus, midi_file::MIDIEvent}, util::{debugger::Debugger, send_discord_webhook_crash_message, system_stats::SystemStats, timer::Timer}};
+        custom_widgets::{NumberField, NumericField}, rendering::{RenderManager, RenderType, Renderer, data_view::DataViewRenderer, note_cull_helper::NoteCullHelper, track_view::TrackViewRenderer}, shared::{NoteColorIndexing, NoteColors}, ui::{dialog::{Dialog, names::*}, dialog_drawer::DialogDrawer, dialog_manager::DialogManager, dialogs::{crash_dialog::CrashDialog, duplicate_track::DuplicateTrackDialog, filter_channels::FilterChannelsDialog, merge_split_tracks::{MergeTracksDialog, SplitTrackDialog}, paste_special::PasteSpecialDialog, plugin_manager::PluginManagerDialog, save_macro::SaveMacroDialog, select_notes::{SelectNotesDialog, replace_selection_in_track}, simple_dialog::SimpleDialog, time_shift::TimeShiftDialog}, edtior_info::EditorInfo, main_menu_bar::{MainMenuBar, MenuItem}, manual::EditorManualDialog, note_inspector::NoteInspector, plugin_console::{ConsoleDock, PluginConsoleView}}, util::image_loader::ImageResources, view_settings::{VS_PianoRoll_DataViewState, VS_PianoRoll_OnionColoring, VS_PianoRoll_OnionState}}, audio::{event_playback::PlaybackManager, kdmapi_engine::kdmapi::KDMAPI, midi_audio_engine::MIDIAudioEngine, midi_devices::MIDIDevices, track_mixer::TrackMixer}, editor::{
+            edit_functions::{EFChopDialog, EFFormulaDialog, EFGlueDialog, EFQuantizeDialog, NoteFormulas, QuantizeSettings}, editing::{SharedClipboard, SharedSelectedNotes, data_editing::{DataEditing, TempoRampShape, data_edit_flags::{DATA_EDIT_ANY_DIALOG_OPEN, DATA_EDIT_DRAW_EDIT_LINE, DATA_EDIT_MOUSE_OVER_UI}}, note_editing::note_edit_flags::NOTE_EDIT_MOUSE_OVER_UI, track_editing::track_flags::{TRACK_EDIT_ANY_DIALOG_OPEN, TRACK_EDIT_ERASING, TRACK_EDIT_MOUSE_OVER_UI}}, midi_bar_cacher::BarCacher, navigation::{GLOBAL_ZOOM_FACTOR, TrackViewNavigation}, playhead::Playhead, plugins::{PluginLoader, plugin_andromeda_obj::AndromedaObj, plugin_dialog::PluginDialog, plugin_error_dialog::PluginErrorDialog, plugin_hooks::{HookOutcome, PluginHook, PluginHookRunner}, plugin_lua::PluginLua, plugin_macro::{MacroPlayer, MacroRecorder}, plugin_repl::PluginRepl, plugin_watcher::PLUGIN_WATCH_INTERVAL}, project::{project_data, project_manager::ProjectManager}, settings::{editor_settings::{ESAudioEngineType, ESAudioSettings, ESGeneralSettings, ESSettingsWindow, PR_KEYBOARD_WIDTH, Settings}, project_settings::ProjectSettings}, util::{MIDITick, get_min_max_ticks_in_selection, get_mouse_midi_pos, path_rel_to_abs, tempo_as_bytes}}, midi::{events::{meta_event::{MetaEvent, MetaEventType}, note}, io::MIDIParseStatus, midi_file::MIDIEvent}, util::{debugger::Debugger, send_discord_webhook_crash_message, system_stats::SystemStats, timer::Timer}};
 use crate::editor::editing::{
-    meta_editing::{MetaEditing, MetaEventInsertDialog},
+    meta_editing::{MetaEditing, MetaEventInsertDialog, meta_sequence_funcs::{find_adjacent_meta_tick, get_lyric_line_at}},
     note_editing::{NoteEditing, note_edit_flags::*},
-    track_editing::TrackEditing
+    track_editing::TrackEditing,
+    event_list_editing::EventListEditing
 };
 
 
@@ -23,9 +24,11 @@ use crate::{
         view_settings::ViewSettings,
     },
     editor::{
-        actions::{EditorActions},
+        actions::{EditorAction, EditorActions},
+        editing::note_editing::note_sequence_funcs::exclude,
         edit_functions::{EFStretchDialog, EditFunction, EditFunctions},
         navigation::PianoRollNavigation,
+        scales::{ScaleMap, ScaleMode},
         project::project_data::ProjectData
     },
     midi::{
@@ -37,6 +40,11 @@ use std::{
     any::Any, cell::RefCell, collections::HashMap, fs, panic::{AssertUnwindSafe, catch_unwind}, path::{Path, PathBuf}, rc::Rc, sync::{Arc, LazyLock, Mutex, RwLock}, time::Instant
 };
 
+const MARKER_LANE_HEIGHT: f32 = 32.0;
+const MARKER_COLOR: Color32 = Color32::from_rgb(255, 170, 60);
+const CUE_POINT_COLOR: Color32 = Color32::from_rgb(110, 190, 255);
+const LYRIC_COLOR: Color32 = Color32::from_rgb(200, 200, 200);
+
 pub static PLUGIN_PATH: LazyLock<PathBuf> = LazyLock::new(|| path_rel_to_abs("./assets/plugins".into()));
 const SNAP_MAPPINGS: [((u8, u16), &str); 14] = [
     ((0, 0), "No snap"),
@@ -72,6 +80,7 @@ impl Default for EditorTool {
 pub struct EditorToolSettings {
     curr_tool: EditorTool,
     pub snap_ratio: (u8, u16),
+    pub snap_to_scale: bool,
 }
 
 impl Default for EditorToolSettings {
@@ -79,6 +88,7 @@ impl Default for EditorToolSettings {
         Self {
             curr_tool: Default::default(),
             snap_ratio: (1, 4),
+            snap_to_scale: false,
         }
     }
 }
@@ -127,6 +137,7 @@ pub struct MainWindow {
     pub meta_editing: Arc<Mutex<MetaEditing>>,
     pub track_editing: Arc<Mutex<TrackEditing>>,
     pub data_editing: Arc<Mutex<DataEditing>>,
+    pub event_list_editing: Arc<Mutex<EventListEditing>>,
     track_mixer: Rc<RefCell<TrackMixer>>,
 
     // clipboard
@@ -179,15 +190,23 @@ pub struct MainWindow {
     // dialogs: HashMap<&'static str, Box<dyn Dialog>>,
     dialog_manager: Rc<RefCell<DialogManager>>,
     dialog_drawer: DialogDrawer,
+    note_inspector: NoteInspector,
+    plugin_console: PluginConsoleView,
 
     // images
     image_resources: Option<ImageResources>,
 
     // plugin stuff
     plugin_loader: Option<PluginLoader>,
+    plugin_hooks: Option<PluginHookRunner>,
+    // for telling hook plugins when the selection changed, see SharedSelectedNotes::get_change_count
+    last_selection_change: u64,
+    // on_project_loaded runs on the next frame, once everything is set up for the new project
+    project_loaded_pending: bool,
 
     // context menu stuff
     context_menu_shown: bool,
+    marker_lane_right_clicked: (Option<usize>, MIDITick),
 
     // ==== OTHER ====
     app_scale: f32,
@@ -355,11 +374,27 @@ impl MainWindow {
             dialog_manager.register_dialog(DIALOG_NAME_EF_GLUE, Box::new(move || { Box::new(EFGlueDialog::new(&note_editing, &editor_functions, &editor_actions)) }));
         }
 
+        {
+            let note_editing = self.note_editing.clone();
+            let editor_functions = self.editor_functions.clone();
+            let editor_actions = self.editor_actions.clone();
+            dialog_manager.register_dialog(DIALOG_NAME_EF_QUANTIZE, Box::new(move || { Box::new(EFQuantizeDialog::new(&note_editing, &editor_functions, &editor_actions)) }));
+        }
+
+        {
+            let note_editing = self.note_editing.clone();
+            let editor_functions = self.editor_functions.clone();
+            let editor_actions = self.editor_actions.clone();
+            dialog_manager.register_dialog(DIALOG_NAME_EF_FORMULA, Box::new(move || { Box::new(EFFormulaDialog::new(&note_editing, &editor_functions, &editor_actions)) }));
+        }
+
         dialog_manager.register_dialog(DIALOG_NAME_EDITOR_INFO, Box::new(|| { Box::new(EditorInfo::default()) }));
 
         {
             let project_manager = self.project_manager.clone();
-            dialog_manager.register_dialog(DIALOG_NAME_PROJECT_SETTINGS, Box::new(move || { Box::new(ProjectSettings::new(&project_manager)) }));
+            let editor_actions = self.editor_actions.clone();
+            let shared_selected_notes = self.shared_selected_notes.clone();
+            dialog_manager.register_dialog(DIALOG_NAME_PROJECT_SETTINGS, Box::new(move || { Box::new(ProjectSettings::new(&project_manager, &editor_actions, &shared_selected_notes)) }));
         }
 
         dialog_manager.register_dialog(DIALOG_NAME_INSERT_META, Box::new(|| { Box::new(MetaEventInsertDialog::default()) }));
@@ -382,11 +417,13 @@ impl MainWindow {
 
         {
             let note_editing = self.note_editing.clone();
+            let meta_editing = self.meta_editing.clone();
+            let bar_cacher = self.bar_cacher.clone();
             let editor_actions = self.editor_actions.clone();
 
             dialog_manager.register_dialog(DIALOG_NAME_PLUGIN_DIALOG, Box::new(move || {
                 let mut plugin_dialog = PluginDialog::default();
-                plugin_dialog.init(&editor_actions, &note_editing);
+                plugin_dialog.init(&editor_actions, &note_editing, &meta_editing, &bar_cacher);
                 Box::new(plugin_dialog)
             }));
         }
@@ -395,6 +432,58 @@ impl MainWindow {
             Box::new(FilterChannelsDialog::default())
         }));
 
+        {
+            let note_editing = self.note_editing.clone();
+            let shared_selected_notes = self.shared_selected_notes.clone();
+            let editor_actions = self.editor_actions.clone();
+            let bar_cacher = self.bar_cacher.clone();
+            dialog_manager.register_dialog(DIALOG_NAME_SELECT_NOTES, Box::new(move || {
+                Box::new(SelectNotesDialog::new(&note_editing, &shared_selected_notes, &editor_actions, &bar_cacher))
+            }));
+        }
+
+        {
+            let project_manager = self.project_manager.clone();
+            let editor_actions = self.editor_actions.clone();
+            let shared_selected_notes = self.shared_selected_notes.clone();
+            let bar_cacher = self.bar_cacher.clone();
+            dialog_manager.register_dialog(DIALOG_NAME_TIME_SHIFT, Box::new(move || {
+                Box::new(TimeShiftDialog::new(&project_manager, &editor_actions, &shared_selected_notes, &bar_cacher))
+            }));
+        }
+
+        {
+            let project_manager = self.project_manager.clone();
+            let track_editing = self.track_editing.clone();
+            dialog_manager.register_dialog(DIALOG_NAME_MERGE_TRACKS, Box::new(move || {
+                Box::new(MergeTracksDialog::new(&project_manager, &track_editing))
+            }));
+        }
+
+        {
+            let track_editing = self.track_editing.clone();
+            dialog_manager.register_dialog(DIALOG_NAME_SPLIT_TRACK, Box::new(move || {
+                Box::new(SplitTrackDialog::new(&track_editing))
+            }));
+        }
+
+        {
+            let track_editing = self.track_editing.clone();
+            dialog_manager.register_dialog(DIALOG_NAME_DUPLICATE_TRACK, Box::new(move || {
+                Box::new(DuplicateTrackDialog::new(&track_editing))
+            }));
+        }
+
+        {
+            let note_editing = self.note_editing.clone();
+            let shared_clipboard = self.shared_clipboard.clone();
+            let shared_selected_notes = self.shared_selected_notes.clone();
+            let bar_cacher = self.bar_cacher.clone();
+            dialog_manager.register_dialog(DIALOG_NAME_PASTE_SPECIAL, Box::new(move || {
+                Box::new(PasteSpecialDialog::new(&note_editing, &shared_clipboard, &shared_selected_notes, &bar_cacher))
+            }));
+        }
+
         dialog_manager.register_dialog(DIALOG_NAME_CRASH, Box::new(move || {
             Box::new(CrashDialog::default())
         }))
@@ -428,6 +517,14 @@ impl MainWindow {
     fn export_midi_file(&mut self) {
         let midi_fd = rfd::FileDialog::new().add_filter("MIDI Files", &["mid"]);
         if let Some(file) = midi_fd.save_file() {
+            let path = file.to_string_lossy().into_owned();
+            let outcome = self.run_plugin_hook(PluginHook::BeforeExport, |event| event.set("path", path.clone()));
+            if let Some((plugin_name, reason)) = outcome.cancelled {
+                Debugger::log(format!("Export of {:?} was stopped by {}", file.file_name().unwrap(), plugin_name));
+                self.show_dialog_with_args(DIALOG_NAME_SIMPLE, vec![Box::new("Export stopped".to_string()), Box::new(format!("The plugin \"{}\" stopped the export.\n{}", plugin_name, reason)), Box::new("ExportStopped".to_string()), Box::new(false)]);
+                return;
+            }
+
             Debugger::log(format!("Starting export of {:?}", file.file_name().unwrap()));
             
             let export_timer = Instant::now();
@@ -486,6 +583,8 @@ impl MainWindow {
                     let mut editor_actions = self.editor_actions.borrow_mut();
                     editor_actions.clear_actions();
                 }
+
+                self.project_loaded_pending = true;
             },
             MIDIParseStatus::ParseError => {
                 self.show_dialog_with_args(DIALOG_NAME_SIMPLE, vec![Box::new("MIDI failed to import".to_string()), Box::new("The MIDI did not load correctly.".to_string()), Box::new("MIDILoadError".to_string()), Box::new(false)]);
@@ -519,9 +618,14 @@ impl MainWindow {
             let mut meta_editing = self.meta_editing.lock().unwrap();
             let mut track_editing = self.track_editing.lock().unwrap();
 
+            let mut data_editing = self.data_editing.lock().unwrap();
+            let mut event_list_editing = self.event_list_editing.lock().unwrap();
+
             note_editing.ppq = ppq;
             meta_editing.ppq = ppq;
             track_editing.ppq = ppq;
+            data_editing.ppq = ppq;
+            event_list_editing.ppq = ppq;
         }
     }
 
@@ -575,6 +679,7 @@ impl MainWindow {
 
         if let Some(playback_manager) = self.playback_manager.as_ref() {
             let note_editing = &self.note_editing;
+            let meta_editing = &self.meta_editing;
             let track_editing = &self.track_editing;
 
             render_manager.init_renderers(
@@ -590,6 +695,7 @@ impl MainWindow {
                 &self.shared_selected_notes,
 
                 note_editing,
+                meta_editing,
                 track_editing
             );
 
@@ -626,6 +732,9 @@ impl MainWindow {
 
             let nav = self.nav.as_ref().unwrap();
             let editor_tool = &self.editor_tool;
+            // the note editor snaps to the scale through the meta editor, so that one comes first
+            self.meta_editing = Arc::new(Mutex::new(MetaEditing::new(metas, &self.bar_cacher, &self.editor_actions, tempo_map)));
+
             // self.note_editing = Arc::new(Mutex::new(NoteEditing::new(notes, nav, editor_tool, render_manager, self.data_view_renderer.as_ref().unwrap(), &self.editor_actions, &self.toolbar_settings)));
             let note_editing = NoteEditing::new(
                 tracks,
@@ -634,7 +743,9 @@ impl MainWindow {
                 &self.editor_actions,
                 &self.toolbar_settings,
                 &self.shared_clipboard,
-                &self.shared_selected_notes
+                &self.shared_selected_notes,
+                &self.meta_editing,
+                self.view_settings.as_ref().unwrap()
             );
 
             let track_editing = TrackEditing::new(
@@ -650,10 +761,28 @@ impl MainWindow {
             );
 
             self.note_editing = Arc::new(Mutex::new(note_editing));
-            self.meta_editing = Arc::new(Mutex::new(MetaEditing::new(metas, &self.bar_cacher, &self.editor_actions, tempo_map)));
             self.track_editing = Arc::new(Mutex::new(track_editing));
-            self.data_editing = Arc::new(Mutex::new(DataEditing::new(tracks, self.view_settings.as_ref().unwrap(), &self.editor_tool, &self.editor_actions, self.nav.as_ref().unwrap())));
-        }
+            self.data_editing = Arc::new(Mutex::new(DataEditing::new(tracks, self.view_settings.as_ref().unwrap(), &self.editor_tool, &self.editor_actions, self.nav.as_ref().unwrap(), &self.meta_editing)));
+            self.event_list_editing = Arc::new(Mutex::new(EventListEditing::new(tracks, metas, nav, &self.editor_actions, &self.shared_selected_notes, &self.meta_editing)));
+        }
+
+        self.note_inspector = NoteInspector::new(&self.note_editing, &self.project_manager, &self.bar_cacher);
+        self.plugin_console.use_repl(PluginRepl::new(
+            &self.note_editing,
+            &self.meta_editing,
+            &self.editor_actions,
+            &self.project_manager,
+            &self.playhead,
+            &self.bar_cacher
+        ));
+        self.plugin_hooks = Some(PluginHookRunner::new(
+            &self.note_editing,
+            &self.meta_editing,
+            &self.editor_actions,
+            &self.project_manager,
+            &self.playhead,
+            &self.bar_cacher
+        ));
     }
 
     fn init_main_menu(&mut self) {
@@ -676,14 +805,31 @@ impl MainWindow {
             ("".into(), MenuItem::Separator),
             ("Insert...".into(), MenuItem::SubMenu(vec![
                 ("Time Signature".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.insert_meta(MetaEventType::TimeSignature); })))),
-                ("Tempo".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.insert_meta(MetaEventType::Tempo); }))))
+                ("Tempo".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.insert_meta(MetaEventType::Tempo); })))),
+                ("Key Signature".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.insert_meta(MetaEventType::KeySignature); })))),
+                ("".into(), MenuItem::Separator),
+                ("Marker".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.insert_meta(MetaEventType::Marker); })))),
+                ("Lyric".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.insert_meta(MetaEventType::Lyric); })))),
+                ("Cue Point".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.insert_meta(MetaEventType::CuePoint); }))))
+            ])),
+            ("Go to...".into(), MenuItem::SubMenu(vec![
+                ("Next marker".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.jump_to_marker(true); })))),
+                ("Previous marker".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.jump_to_marker(false); }))))
             ])),
             ("".into(), MenuItem::Separator),
             ("Copy".into(), MenuItem::MenuButtonEnabled(Some(Box::new(|mw| { mw.request_editing_copy(); })), Box::new(|mw| { mw.can_copy() }))),
             ("Cut".into(), MenuItem::MenuButtonEnabled(Some(Box::new(|mw| { mw.request_editing_cut(); })), Box::new(|mw| { mw.can_copy() }))),
             ("Paste".into(), MenuItem::MenuButtonEnabled(Some(Box::new(|mw| { mw.request_editing_paste(); })), Box::new(|mw| { mw.can_paste() }))),
+            ("Paste Special...".into(), MenuItem::MenuButtonEnabled(Some(Box::new(|mw| { mw.show_paste_special_dialog(); })), Box::new(|mw| { mw.can_paste() }))),
             ("".into(), MenuItem::Separator),
+            ("Time".into(), MenuItem::SubMenu(vec![
+                ("Insert time...".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.show_time_shift_dialog(true); })))),
+                ("Delete time...".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.show_time_shift_dialog(false); })))),
+            ])),
             ("Select...".into(), MenuItem::SubMenu(vec![
+                ("Select notes by query...".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.show_select_notes_dialog(); })))),
+                ("Invert selection".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.invert_selection(); })))),
+                ("".into(), MenuItem::Separator),
                 ("Filter Selection...".into(), MenuItem::SubMenu(vec![
                     ("Filter channnels".into(), MenuItem::MenuButtonEnabled(
                         Some(Box::new(|mw| {
@@ -696,6 +842,11 @@ impl MainWindow {
                 ]))
             ]))
         ]);
+        menu_bar.add_menu("View", vec![
+            ("Piano Roll".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.switch_view(RenderType::PianoRoll); })))),
+            ("Track View".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.switch_view(RenderType::TrackView); })))),
+            ("Event List".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.switch_view(RenderType::EventList); }))))
+        ]);
         menu_bar.add_menu("Options", vec![
             ("Preferences...".into(), MenuItem::MenuButton(Some(Box::new(|mw| { mw.show_dialog("EditorSettings"); }))))
         ]);
@@ -732,9 +883,27 @@ impl MainWindow {
                         let shared_selected = mw.shared_selected_notes.read().unwrap();
                         shared_selected.is_any_note_selected()
                     }) 
+                )),
+                ("".into(), MenuItem::Separator),
+                ("Conform selection to scale".into(), MenuItem::MenuButtonEnabled(
+                    Some(Box::new(|mw| {
+                        let scale_map = mw.get_scale_map();
+                        mw.apply_function(EditFunction::ConformToScale(scale_map));
+                    })),
+                    Box::new(|mw| {
+                        let shared_selected = mw.shared_selected_notes.read().unwrap();
+                        shared_selected.is_any_note_selected()
+                    })
                 ))
             ])),
             ("Editing".into(), MenuItem::SubMenu(vec![
+                ("Quantize...".into(), MenuItem::MenuButtonEnabled(
+                    Some(Box::new(|mw| { mw.apply_function(EditFunction::Quantize(Vec::new(), QuantizeSettings::default())); })),
+                    Box::new(|mw| {
+                        let shared_selected = mw.shared_selected_notes.read().unwrap();
+                        shared_selected.is_any_note_selected()
+                    })
+                )),
                 ("Stretch selection...".into(), MenuItem::MenuButtonEnabled(
                     Some(Box::new(|mw| { mw.apply_function(EditFunction::Stretch(Vec::new(), 0.0)); })),
                     Box::new(|mw| {  
@@ -769,6 +938,13 @@ impl MainWindow {
                         shared_selected.is_any_note_selected()
                     })
                 )),
+                ("Set property by formula...".into(), MenuItem::MenuButtonEnabled(
+                    Some(Box::new(|mw| { mw.apply_function(EditFunction::SetByFormula(Vec::new(), NoteFormulas::default())); })),
+                    Box::new(|mw| {
+                        let shared_selected = mw.shared_selected_notes.read().unwrap();
+                        shared_selected.is_any_note_selected()
+                    })
+                )),
                 ("Remove Overlaps".into(),  MenuItem::MenuButtonEnabled(
                     Some(Box::new(|mw| { mw.apply_function(EditFunction::RemoveOverlaps) })),
                     Box::new(|mw| {  
@@ -827,15 +1003,62 @@ impl MainWindow {
                         }
                         gen_plugins_buttons
                     })),
+                    ("Hooks...".into(), MenuItem::SubMenu({
+                        let mut hook_plugins_buttons = Vec::new();
+                        for plugin in plugins.hook_plugins.iter() {
+                            let (plugin_name, enabled) = {
+                                let plugin = plugin.try_borrow().unwrap();
+                                (plugin.plugin_name.clone(), plugin.hooks_enabled)
+                            };
+                            let label = if enabled { format!("✔ {}", plugin_name) } else { plugin_name };
+
+                            let plugin = plugin.clone();
+                            hook_plugins_buttons.push((label, MenuItem::MenuButtonWithTooltop("Hook plugins run by themselves when things happen in the editor. Click to turn this one on or off.".into(),
+                                Some(Box::new(move |mw| {
+                                    {
+                                        let mut plugin = plugin.borrow_mut();
+                                        plugin.hooks_enabled = !plugin.hooks_enabled;
+                                    }
+                                    mw.init_main_menu();
+                                }))
+                            )));
+                        }
+                        hook_plugins_buttons
+                    })),
+                    ("Macro...".into(), MenuItem::SubMenu(vec![
+                        ("Start recording".into(), MenuItem::MenuButtonEnabled(
+                            Some(Box::new(|_| { MacroRecorder::start(); })),
+                            Box::new(|_| { !MacroRecorder::is_recording() })
+                        )),
+                        ("Stop and save...".into(), MenuItem::MenuButtonEnabled(
+                            Some(Box::new(|mw| { mw.stop_macro_recording(); })),
+                            Box::new(|_| { MacroRecorder::is_recording() })
+                        )),
+                        ("Discard recording".into(), MenuItem::MenuButtonEnabled(
+                            Some(Box::new(|_| { MacroRecorder::stop(); })),
+                            Box::new(|_| { MacroRecorder::is_recording() })
+                        ))
+                    ])),
                     ("".into(), MenuItem::Separator),
+                    ("Manage packages...".into(),
+                        MenuItem::MenuButtonWithTooltop("Install plugin packages, turn them on and off, and see why one isn't loaded.".into(),
+                            Some(Box::new(|mw| { mw.show_plugin_manager(); }))
+                        )
+                    ),
                     ("Reload all plugins".into(),
-                        MenuItem::MenuButtonWithTooltop("Only reloads the plugins andromeda has loaded at startup (plugins that were added after startup are not added, therefore a restart is required for newly added plugins).".into(),
+                        MenuItem::MenuButtonWithTooltop("Reloads every loaded plugin. Plugins in the custom plugin folder are also reloaded on their own whenever their files change.".into(),
                             Some(Box::new(move |mw| {
                                 let plugin_loader = mw.plugin_loader.as_mut().unwrap();
                                 plugin_loader.reload_plugins();
                             }))
                         )
                     ),
+                    ("Show console".into(),
+                        MenuItem::MenuButton(Some(Box::new(|mw| {
+                            let mut view_settings = mw.view_settings.as_ref().unwrap().lock().unwrap();
+                            view_settings.show_plugin_console = true;
+                        })))
+                    ),
                     ("Open plugin folder".into(),
                         MenuItem::MenuButton(
                             Some(Box::new(move |_| {
@@ -892,9 +1115,26 @@ impl MainWindow {
             let mut note_editing = self.note_editing.lock().unwrap();
             let mut meta_editing = self.meta_editing.lock().unwrap();
             let mut track_editing = self.track_editing.lock().unwrap();
-            note_editing.apply_action(action);
-            meta_editing.apply_action(action);
-            track_editing.apply_action(action);
+            let mut event_list_editing = self.event_list_editing.lock().unwrap();
+            Self::apply_action_to_editors(action, &mut note_editing, &mut meta_editing, &mut track_editing, &mut event_list_editing);
+        }
+    }
+
+    /// Bulk actions are taken apart here instead of by each editor, so every step reaches all of them before the next
+    /// one runs. Otherwise notes could get pasted back into a track the track editor hasn't added back yet.
+    fn apply_action_to_editors(action: &mut EditorAction, note_editing: &mut NoteEditing, meta_editing: &mut MetaEditing, track_editing: &mut TrackEditing, event_list_editing: &mut EventListEditing) {
+        match action {
+            EditorAction::Bulk(actions) => {
+                for action in actions.iter_mut().rev() {
+                    Self::apply_action_to_editors(action, note_editing, meta_editing, track_editing, event_list_editing);
+                }
+            },
+            _ => {
+                note_editing.apply_action(action);
+                meta_editing.apply_action(action);
+                track_editing.apply_action(action);
+                event_list_editing.apply_action(action);
+            }
         }
     }
 
@@ -911,9 +1151,8 @@ impl MainWindow {
             let mut note_editing = self.note_editing.lock().unwrap();
             let mut meta_editing = self.meta_editing.lock().unwrap();
             let mut track_editing = self.track_editing.lock().unwrap();
-            note_editing.apply_action(action);
-            meta_editing.apply_action(action);
-            track_editing.apply_action(action);
+            let mut event_list_editing = self.event_list_editing.lock().unwrap();
+            Self::apply_action_to_editors(action, &mut note_editing, &mut meta_editing, &mut track_editing, &mut event_list_editing);
         }
     }
 
@@ -945,6 +1184,65 @@ impl MainWindow {
         note_editing.paste_notes(curr_track);
     }
 
+    fn show_paste_special_dialog(&mut self) {
+        let playhead_tick = {
+            let playhead = self.playhead.borrow();
+            playhead.start_tick
+        };
+
+        let curr_track = self.get_current_track().unwrap();
+        self.show_dialog_with_args(DIALOG_NAME_PASTE_SPECIAL, vec![Box::new(playhead_tick), Box::new(curr_track)]);
+    }
+
+    fn show_time_shift_dialog(&mut self, insert: bool) {
+        let playhead_tick = {
+            let playhead = self.playhead.borrow();
+            playhead.start_tick
+        };
+
+        // span of the selected notes across all tracks
+        let selection_range = {
+            let shared_selected = self.shared_selected_notes.read().unwrap();
+            let project_manager = self.project_manager.read().unwrap();
+            let tracks = project_manager.get_tracks().read().unwrap();
+
+            shared_selected.get_selected().into_iter()
+                .filter_map(|(track, ids)| get_min_max_ticks_in_selection(tracks.get(track as usize)?.get_notes(), ids))
+                .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+        };
+
+        self.show_dialog_with_args(DIALOG_NAME_TIME_SHIFT, vec![Box::new(insert), Box::new(playhead_tick), Box::new(selection_range)]);
+    }
+
+    fn show_select_notes_dialog(&mut self) {
+        let clicked_key = {
+            let note_editing = self.note_editing.lock().unwrap();
+            note_editing.get_last_clicked_note_key()
+        };
+
+        self.show_dialog_with_args(DIALOG_NAME_SELECT_NOTES, vec![Box::new(clicked_key)]);
+    }
+
+    /// Selects every unselected note in the current track and deselects the rest.
+    fn invert_selection(&mut self) {
+        let Some(curr_track) = self.get_current_track() else { return; };
+
+        let note_editing = self.note_editing.lock().unwrap();
+        let tracks = note_editing.get_tracks();
+        let tracks = tracks.read().unwrap();
+        let Some(track) = tracks.get(curr_track as usize) else { return; };
+
+        let mut shared_selected = self.shared_selected_notes.write().unwrap();
+        let old_ids = shared_selected.get_selected_ids_in_track(curr_track).cloned().unwrap_or_default();
+        let new_ids = exclude((0..track.get_notes().len()).collect(), &old_ids);
+
+        let actions = replace_selection_in_track(&mut shared_selected, curr_track, new_ids);
+        if !actions.is_empty() {
+            let mut editor_actions = self.editor_actions.borrow_mut();
+            editor_actions.register_action(EditorAction::Bulk(actions));
+        }
+    }
+
     fn filter_selection_channels(&mut self) {
         let mut dialog_manager = self.dialog_manager.borrow_mut();
         let shared_selected_notes = self.shared_selected_notes.clone();
@@ -957,30 +1255,144 @@ impl MainWindow {
     }
 
     fn insert_meta(&mut self, meta_type: MetaEventType) {
+        let playhead_pos = {
+            let playhead = self.playhead.try_borrow().unwrap();
+            playhead.start_tick
+        };
+
+        self.insert_meta_at(meta_type, playhead_pos);
+    }
+
+    fn insert_meta_at(&mut self, meta_type: MetaEventType, tick: MIDITick) {
         match meta_type {
-            MetaEventType::TimeSignature | MetaEventType::Tempo => {
+            MetaEventType::TimeSignature | MetaEventType::Tempo | MetaEventType::KeySignature => {
                 let meta_editing = self.meta_editing.clone();
-                let playhead_pos = {
-                    let playhead = self.playhead.try_borrow().unwrap();
-                    playhead.start_tick
-                };
+                let playhead_pos = tick;
 
                 // let meta_dialog = self.get_dialog_mut::<MetaEventInsertDialog>("InsertMetaDialog");
                 let mut meta_dialog = MetaEventInsertDialog::default();
                 meta_dialog.init_meta_dialog(meta_type, move |data| {
                     let mut meta_editing = meta_editing.try_lock().unwrap();
-                    meta_editing.insert_meta_event(MetaEvent { tick: playhead_pos, event_type: meta_type, data });
+                    // only one key signature can be in effect per tick
+                    if meta_type == MetaEventType::KeySignature {
+                        meta_editing.replace_metas_in_range(meta_type, playhead_pos, playhead_pos, vec![MetaEvent { tick: playhead_pos, event_type: meta_type, data }]);
+                    } else {
+                        meta_editing.insert_meta_event(MetaEvent { tick: playhead_pos, event_type: meta_type, data });
+                    }
                 });
                 // meta_dialog.show();
                 let mut dialog_manager = self.dialog_manager.borrow_mut();
                 dialog_manager.open_dialog(Box::new(meta_dialog), Vec::new());
             },
+            MetaEventType::Marker | MetaEventType::Lyric | MetaEventType::CuePoint => {
+                let meta_editing = self.meta_editing.clone();
+
+                let mut meta_dialog = MetaEventInsertDialog::default();
+                meta_dialog.init_meta_dialog(meta_type, move |data| {
+                    let mut meta_editing = meta_editing.try_lock().unwrap();
+                    meta_editing.add_meta(MetaEvent { tick, event_type: meta_type, data });
+                });
+
+                let mut dialog_manager = self.dialog_manager.borrow_mut();
+                dialog_manager.open_dialog(Box::new(meta_dialog), Vec::new());
+            },
             _ => {
 
             }
         }
     }
 
+    fn edit_meta(&mut self, meta_idx: usize, meta: MetaEvent) {
+        match meta.event_type {
+            MetaEventType::KeySignature => {
+                let meta_editing = self.meta_editing.clone();
+                let (tick, meta_type) = (meta.tick, meta.event_type);
+
+                let mut meta_dialog = MetaEventInsertDialog::default();
+                meta_dialog.init_edit_meta_dialog(&meta, move |data| {
+                    let mut meta_editing = meta_editing.try_lock().unwrap();
+                    meta_editing.replace_metas_in_range(meta_type, tick, tick, vec![MetaEvent { tick, event_type: meta_type, data }]);
+                });
+
+                let mut dialog_manager = self.dialog_manager.borrow_mut();
+                dialog_manager.open_dialog(Box::new(meta_dialog), Vec::new());
+            },
+            meta_type if meta_type.is_text() => {
+                let meta_editing = self.meta_editing.clone();
+
+                let mut meta_dialog = MetaEventInsertDialog::default();
+                let old_meta = meta.clone();
+                meta_dialog.init_edit_meta_dialog(&meta, move |data| {
+                    let mut meta_editing = meta_editing.try_lock().unwrap();
+                    // the metas might have changed while the dialog was open
+                    if let Some(meta_idx) = meta_editing.find_meta(meta_idx, &old_meta) {
+                        meta_editing.set_meta_data(meta_idx, data);
+                    }
+                });
+
+                let mut dialog_manager = self.dialog_manager.borrow_mut();
+                dialog_manager.open_dialog(Box::new(meta_dialog), Vec::new());
+            },
+            _ => {}
+        }
+    }
+
+    /// Moves the playhead to the next (or previous) marker or cue point and scrolls it into view.
+    fn jump_to_marker(&mut self, forward: bool) {
+        let playhead_pos = self.get_playhead_pos(false) as MIDITick;
+        let target_tick = {
+            let meta_editing = self.meta_editing.lock().unwrap();
+            let metas = meta_editing.get_metas();
+            let metas = metas.read().unwrap();
+            find_adjacent_meta_tick(&metas, &[MetaEventType::Marker, MetaEventType::CuePoint], playhead_pos, forward)
+        };
+
+        let Some(tick) = target_tick else { return; };
+
+        let is_playing = self.is_playing();
+        if is_playing {
+            // same as the playback buttons, restart playback from the marker
+            let mut playback_manager = self.playback_manager.as_ref().unwrap().lock().unwrap();
+            playback_manager.toggle_playback();
+            playback_manager.navigate_to(tick);
+            playback_manager.toggle_playback();
+        } else {
+            self.set_playhead_pos(tick);
+        }
+
+        if let Some(nav) = self.nav.as_ref() {
+            let mut nav = nav.lock().unwrap();
+            nav.scroll_to_tick(tick as f32);
+        }
+
+        if let Some(nav) = self.track_view_nav.as_ref() {
+            let mut nav = nav.lock().unwrap();
+            nav.scroll_to_tick(tick as f32);
+        }
+    }
+
+    fn get_min_snap_length(&self) -> MIDITick {
+        let editor_tool = self.editor_tool.try_borrow().unwrap();
+        let snap_ratio = editor_tool.snap_ratio;
+        if snap_ratio.0 == 0 { 1 }
+        else {
+            let ppq = self.get_ppq() as MIDITick;
+            (ppq * 4 * snap_ratio.0 as MIDITick) / snap_ratio.1 as MIDITick
+        }
+    }
+
+    /// Key signature changes of the project, using the scale mode from the view settings.
+    pub fn get_scale_map(&self) -> ScaleMap {
+        let scale_mode = {
+            let view_settings = self.view_settings.as_ref().unwrap().lock().unwrap();
+            view_settings.pr_scale_mode
+        };
+
+        let project_manager = self.project_manager.read().unwrap();
+        let metas = project_manager.get_metas().read().unwrap();
+        ScaleMap::from_metas(&metas, scale_mode)
+    }
+
     pub fn apply_function(&mut self, function_type: EditFunction) {
         match function_type {
             EditFunction::Stretch(_, _) => {
@@ -1000,6 +1412,20 @@ impl MainWindow {
                 self.show_note_properties_popup = false;
                 self.note_properties_mouse_up_processed = false;
                 self.show_dialog(DIALOG_NAME_EF_GLUE);
+            },
+            EditFunction::Quantize(_, _) => {
+                self.show_note_properties_popup = false;
+                self.note_properties_mouse_up_processed = false;
+
+                let snap_length = self.get_min_snap_length();
+                self.show_dialog_with_args(DIALOG_NAME_EF_QUANTIZE, vec![Box::new(snap_length)]);
+            },
+            EditFunction::SetByFormula(_, _) => {
+                self.show_note_properties_popup = false;
+                self.note_properties_mouse_up_processed = false;
+
+                let ppq = self.get_ppq();
+                self.show_dialog_with_args(DIALOG_NAME_EF_FORMULA, vec![Box::new(ppq)]);
             }
             EditFunction::SliceAtTick(_, playhead_tick) => {
                 //let project_manager = self.project_manager.read().unwrap();
@@ -1088,6 +1514,26 @@ impl MainWindow {
                         &mut editor_actions
                     );
                 });
+            },
+            EditFunction::ConformToScale(scale_map) => {
+                let note_editing = self.note_editing.lock().unwrap();
+                let curr_track = self.get_current_track().unwrap();
+
+                let mut sel_notes = note_editing.get_shared_selected_ids().write().unwrap();
+                let sel_notes = sel_notes.get_selected_ids_mut(curr_track);
+
+                note_editing.with_notes_mut(curr_track as usize, |notes| {
+                    let mut editor_functions = self.editor_functions.borrow_mut();
+                    let mut editor_actions = self.editor_actions.borrow_mut();
+
+                    editor_functions.apply_function(
+                        notes,
+                        sel_notes,
+                        EditFunction::ConformToScale(scale_map),
+                        curr_track,
+                        &mut editor_actions
+                    );
+                });
             }
             _ => {}
         }
@@ -1100,17 +1546,17 @@ impl MainWindow {
         };
 
         let track_idx = self.get_current_track().unwrap() as usize;
-        lua.globals().set("curr_track", track_idx).unwrap();
-
-        let andromeda_obj = AndromedaObj::new(&self.project_manager, &self.playhead);
-        let andromeda_obj = lua.create_userdata(andromeda_obj).unwrap();
-        lua.globals().set("andromeda", andromeda_obj).unwrap();
+        let andromeda_obj = AndromedaObj::new(&self.project_manager, &self.playhead, &self.bar_cacher);
+        andromeda_obj.set_globals(&lua, track_idx).unwrap();
 
     
         let run_result = {
             // let plugin_dialog = self.get_dialog_mut::<PluginDialog>("PluginDialog");
             let mut plugin_dialog = PluginDialog::default();
-            plugin_dialog.init(&self.editor_actions, &self.note_editing);
+            plugin_dialog.init(&self.editor_actions, &self.note_editing, &self.meta_editing, &self.bar_cacher);
+            plugin_dialog.use_render_manager(self.render_manager.as_ref().unwrap());
+            plugin_dialog.use_settings(&self.plugin_loader.as_ref().unwrap().settings);
+            plugin_dialog.use_macro_player(self.get_macro_player());
             plugin_dialog.curr_track = track_idx;
 
             match plugin_dialog.load_plugin_dialog(&plugin) {
@@ -1118,9 +1564,9 @@ impl MainWindow {
                     if should_show_dialog {
                         let mut dialog_manager = self.dialog_manager.borrow_mut();
                         dialog_manager.open_dialog(Box::new(plugin_dialog), Vec::new());
+                        Ok(())
                     }
-                    else { plugin_dialog.run_plugin(); }
-                    Ok(())
+                    else { plugin_dialog.run_plugin() }
                 },
                 Err(lua_error) => {
                     Err(lua_error)
@@ -1139,6 +1585,42 @@ impl MainWindow {
         }
     }
 
+    /// What plugins run from the menu use to play back the steps they queue.
+    fn get_macro_player(&self) -> MacroPlayer {
+        let plugins = self.plugin_loader.as_ref().unwrap();
+        MacroPlayer::new(
+            &self.note_editing,
+            &self.meta_editing,
+            &self.track_editing,
+            &self.editor_functions,
+            &self.editor_actions,
+            &self.project_manager,
+            &self.playhead,
+            &self.bar_cacher,
+            plugins.manip_plugins.iter().chain(plugins.gen_plugins.iter()).cloned().collect()
+        )
+    }
+
+    fn stop_macro_recording(&mut self) {
+        let steps = MacroRecorder::stop();
+        if steps.is_empty() {
+            Debugger::log("Nothing was recorded, so there's no macro to save.");
+            return;
+        }
+
+        let folder = self.plugin_loader.as_ref().unwrap().get_custom_plugin_folder();
+        let mut dialog_manager = self.dialog_manager.borrow_mut();
+        dialog_manager.close_all_dialogs();
+        dialog_manager.open_dialog(Box::new(SaveMacroDialog::new(steps, folder)), Vec::new());
+    }
+
+    fn show_plugin_manager(&mut self) {
+        let plugins_path = self.plugin_loader.as_ref().unwrap().get_plugins_path().to_path_buf();
+        let mut dialog_manager = self.dialog_manager.borrow_mut();
+        dialog_manager.close_all_dialogs();
+        dialog_manager.open_dialog(Box::new(PluginManagerDialog::new(plugins_path)), Vec::new());
+    }
+
     /*fn reload_plugins(&mut self) {
         let plugins = self.plugin_loader.as_ref().unwrap();
         for plugin in plugins.gen_plugins.iter() {
@@ -1185,7 +1667,26 @@ impl MainWindow {
                 if !(mouse_over_ui || any_window_opened) { self.handle_trackview_navigation(ui); }
                 self.handle_trackview_inputs(ctx, ui, mouse_over_ui, any_window_opened);
             }
+            // the list is made of egui widgets, they take their own input
+            RenderType::EventList => {}
         }
+
+        self.sync_system_clipboard(ctx);
+    }
+
+    /// Puts freshly copied notes on the system clipboard so other windows (and other programs) can paste them.
+    fn sync_system_clipboard(&mut self, ctx: &egui::Context) {
+        let ppq = {
+            let project_manager = self.project_manager.read().unwrap();
+            project_manager.get_ppq()
+        };
+
+        let export = {
+            let mut shared_clipboard = self.shared_clipboard.write().unwrap();
+            shared_clipboard.take_system_export(ppq)
+        };
+
+        if let Some(text) = export { ctx.copy_text(text); }
     }
 
     fn handle_pianoroll_navigation(&mut self, ui: &mut Ui) {
@@ -1300,6 +1801,9 @@ impl MainWindow {
     }
 
     fn handle_key_inputs(&mut self, ui: &mut Ui) {
+        // typing into a text field (e.g. the note inspector) shouldn't delete or paste notes
+        if ui.ctx().wants_keyboard_input() { return; }
+
         let mut render_manager = self.render_manager.as_ref().unwrap().lock().unwrap();
         let render_type = *render_manager.get_render_type();
 
@@ -1310,7 +1814,7 @@ impl MainWindow {
 
             match render_type {
                 RenderType::PianoRoll => render_manager.switch_renderer(RenderType::TrackView),
-                RenderType::TrackView => render_manager.switch_renderer(RenderType::PianoRoll),
+                RenderType::TrackView | RenderType::EventList => render_manager.switch_renderer(RenderType::PianoRoll),
             }
         }
 
@@ -1322,7 +1826,17 @@ impl MainWindow {
             RenderType::TrackView => {
                 let mut track_editing = self.track_editing.lock().unwrap();
                 track_editing.on_key_down(ui);
-            }
+            },
+            RenderType::EventList => {}
+        }
+        drop(render_manager);
+
+        if ui.input(|i| i.key_pressed(egui::Key::ArrowRight) && i.modifiers.alt) {
+            self.jump_to_marker(true);
+        }
+
+        if ui.input(|i| i.key_pressed(egui::Key::ArrowLeft) && i.modifiers.alt) {
+            self.jump_to_marker(false);
         }
 
         if ui.input(|i| i.key_pressed(egui::Key::Z) && i.modifiers.command) {
@@ -1340,11 +1854,11 @@ impl MainWindow {
         }
     }
 
-    fn handle_data_viewer_inputs(&mut self, ctx: &egui::Context, ui: &mut Ui, mouse_over_ui: bool, any_window_opened: bool) {
+    fn handle_data_viewer_inputs(&mut self, ctx: &egui::Context, ui: &mut Ui, lane_rect: Rect, mouse_over_ui: bool, any_window_opened: bool) {
         let mut data_editing = self.data_editing.lock().unwrap();
         data_editing.set_flag(DATA_EDIT_MOUSE_OVER_UI, mouse_over_ui);
         data_editing.set_flag(DATA_EDIT_ANY_DIALOG_OPEN, any_window_opened);
-        data_editing.update(ui);
+        data_editing.update(ui, lane_rect);
 
         if ui.input(|i| i.pointer.primary_pressed()) {
             data_editing.on_mouse_down();
@@ -1517,7 +2031,8 @@ impl MainWindow {
                 if let Some(y_fac) = y_fac {
                     nav.zoom_tracks_by(y_fac);
                 }
-            }
+            },
+            RenderType::EventList => {}
         }
     }
 
@@ -1530,7 +2045,8 @@ impl MainWindow {
         };
 
         match rt {
-            RenderType::PianoRoll => {
+            // the event list has no timeline of its own, it follows the piano roll
+            RenderType::PianoRoll | RenderType::EventList => {
                 if let Some(nav) = self.nav.as_ref() {
                     let nav = nav.lock().unwrap();
                     (nav.tick_pos_smoothed as MIDITick, (nav.tick_pos_smoothed + nav.zoom_ticks_smoothed) as MIDITick)
@@ -1592,7 +2108,7 @@ impl MainWindow {
             };
 
             match rt {
-                RenderType::PianoRoll => {
+                RenderType::PianoRoll | RenderType::EventList => {
                     let nav = self.nav.as_ref().unwrap();
                     let nav = nav.lock().unwrap();
                     nav.tick_pos_smoothed
@@ -1639,7 +2155,7 @@ impl MainWindow {
 
         match render_type {
             RenderType::PianoRoll => { self.update_cursor(ctx, ui); }
-            RenderType::TrackView => {
+            RenderType::TrackView | RenderType::EventList => {
                 ctx.set_cursor_icon(egui::CursorIcon::Default);
             }
         }
@@ -1665,6 +2181,20 @@ impl MainWindow {
             return;
         }
 
+        if self.is_on_event_list() {
+            self.handle_main_inputs(ctx, ui, mouse_over_ui, any_window_opened);
+            self.handle_cursor_icon(ctx, ui);
+
+            let playhead_tick = {
+                let playhead = self.playhead.try_borrow().unwrap();
+                playhead.start_tick
+            };
+
+            let mut event_list_editing = self.event_list_editing.lock().unwrap();
+            event_list_editing.draw(ui, playhead_tick);
+            return;
+        }
+
         let available_size = ui.available_size();
         let (rect, _response) = ui.allocate_exact_size(available_size, egui::Sense::hover());
         let app_scale = self.app_scale;
@@ -1744,7 +2274,8 @@ impl MainWindow {
 
                 let (tl, br) = track_editing.get_selection_range_ui(ui);
                 (track_editing.get_can_draw_selection_box() || track_editing.has_selection, track_editing.get_flag(TRACK_EDIT_ERASING), track_editing.has_selection, (tl, br))
-            }
+            },
+            RenderType::EventList => (false, false, false, ((0.,0.),(0.,0.)))
         };
 
         if !draw { return; }
@@ -1825,6 +2356,17 @@ impl MainWindow {
         render_manager.get_render_type() == &RenderType::TrackView
     }
 
+    fn switch_view(&mut self, render_type: RenderType) {
+        let Some(render_manager) = self.render_manager.as_ref() else { return; };
+        let mut render_manager = render_manager.lock().unwrap();
+        render_manager.switch_renderer(render_type);
+    }
+
+    fn is_on_event_list(&self) -> bool {
+        let render_manager = self.render_manager.as_ref().unwrap().lock().unwrap();
+        render_manager.get_render_type() == &RenderType::EventList
+    }
+
     /// Allocates space for keyboard and returns the width of the space.
     fn allocate_for_keyboard(&self, ui: &mut Ui) -> f32 {
         if self.is_on_track_view() { return 0.0; }
@@ -1870,6 +2412,9 @@ impl MainWindow {
             }
         }
 
+        self.poll_plugin_changes(ctx);
+        self.run_live_plugin_hooks();
+
         egui::CentralPanel::default().show(ctx, |ui| {
             let menu_bar = {
                 let mb = self.menu_bar.as_mut().unwrap();
@@ -1898,6 +2443,9 @@ impl MainWindow {
                     self.mouse_over_ui |= ui.ui_contains_pointer();
                 });
 
+            self.draw_plugin_console(ctx);
+            self.draw_note_inspector(ctx);
+
             // Meta event viewer on the left
             self.draw_meta_event_view(ctx, ui);
             self.mouse_over_ui |= ctx.is_pointer_over_area();
@@ -1914,7 +2462,7 @@ impl MainWindow {
 
                 self.draw_scroll_navigation(ctx, ui);
 
-                if dataview_state != VS_PianoRoll_DataViewState::Hidden && !self.is_on_track_view() {
+                if dataview_state != VS_PianoRoll_DataViewState::Hidden && !self.is_on_track_view() && !self.is_on_event_list() {
                     egui::TopBottomPanel::bottom("data_viewer")
                         .show(ctx, |ui| {
                             ui.horizontal(|ui| {
@@ -1933,7 +2481,13 @@ impl MainWindow {
 
                                                 ui.selectable_value(&mut view_settings.pr_dataview_state, VS_PianoRoll_DataViewState::NoteVelocities, "Velocity");
                                                 ui.selectable_value(&mut view_settings.pr_dataview_state, VS_PianoRoll_DataViewState::PitchBend, "Pitch Bend");
+                                                ui.selectable_value(&mut view_settings.pr_dataview_state, VS_PianoRoll_DataViewState::Tempo, "Tempo");
                                             });
+
+                                        if dataview_state == VS_PianoRoll_DataViewState::Tempo {
+                                            ui.separator();
+                                            self.draw_tempo_lane_controls(ui);
+                                        }
                                     });
 
                                     mouse_over_ui |= ui.ui_contains_pointer();
@@ -1947,9 +2501,12 @@ impl MainWindow {
                     });
                 }
             }
-            self.draw_bar_numbers(ctx);
+            if !self.is_on_event_list() {
+                self.draw_bar_numbers(ctx);
+                self.draw_marker_lane(ctx);
+            }
 
-            // piano roll / track view rendering
+            // piano roll / track view / event list
             egui::CentralPanel::default().show(ctx, |ui| {
                 self.draw(ctx, ui, self.mouse_over_ui, any_window_opened);
                 self.mouse_over_ui = false;
@@ -2038,6 +2595,22 @@ impl MainWindow {
                     }
                     self.mouse_over_ui |= ui.ui_contains_pointer();
                 });
+                ui.menu_button("Scale", |ui| {
+                    {
+                        let mut editor_tool = self.editor_tool.try_borrow_mut().unwrap();
+                        ui.checkbox(&mut editor_tool.snap_to_scale, "Snap pencil to scale");
+                    }
+
+                    if let Some(vs) = self.view_settings.as_ref() {
+                        let mut vs = vs.lock().unwrap();
+                        ui.checkbox(&mut vs.pr_scale_highlight, "Highlight scale");
+                        ui.separator();
+                        for scale_mode in ScaleMode::ALL {
+                            ui.radio_value(&mut vs.pr_scale_mode, scale_mode, scale_mode.to_string());
+                        }
+                    }
+                    self.mouse_over_ui |= ui.ui_contains_pointer();
+                });
                 ui.separator();
                 // note gate and velocity
                 {
@@ -2186,8 +2759,24 @@ impl MainWindow {
                 ui.separator();
                 ui.label("Autoscroll");
 
-                let mut view_settings = self.view_settings.as_mut().unwrap().lock().unwrap();
-                ui.checkbox(&mut view_settings.pr_autoscroll, "");
+                {
+                    let mut view_settings = self.view_settings.as_mut().unwrap().lock().unwrap();
+                    ui.checkbox(&mut view_settings.pr_autoscroll, "");
+                    ui.separator();
+                    ui.checkbox(&mut view_settings.show_marker_lane, "Markers");
+                    ui.checkbox(&mut view_settings.show_note_inspector, "Inspector");
+                    ui.checkbox(&mut view_settings.show_plugin_console, "Console");
+                }
+
+                if MacroRecorder::is_recording() {
+                    ui.separator();
+                    ui.colored_label(Color32::LIGHT_RED, format!("⏺ Recording macro ({} steps)", MacroRecorder::get_step_count()))
+                        .on_hover_text("Plugins > Macro... to stop and save it");
+                }
+
+                if self.is_playing() {
+                    self.draw_current_lyrics(ui);
+                }
             });
 
             self.mouse_over_ui |= ui.ui_contains_pointer();
@@ -2236,6 +2825,166 @@ impl MainWindow {
         });
     }
 
+    fn draw_marker_lane(&mut self, ctx: &egui::Context) {
+        let show_marker_lane = {
+            let view_settings = self.view_settings.as_ref().unwrap().lock().unwrap();
+            view_settings.show_marker_lane
+        };
+        if !show_marker_lane { return; }
+
+        egui::TopBottomPanel::top("marker_lane").show_separator_line(false).exact_height(MARKER_LANE_HEIGHT).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let (min_tick, max_tick) = self.get_view_tick_range_with_playback();
+                let zoom_ticks = (max_tick - min_tick).max(1) as f32;
+
+                let space_alloc = self.allocate_for_keyboard(ui);
+                let rect = ui.max_rect();
+                let lane_width = ui.available_width();
+                let lane_rect = Rect::from_min_max(Pos2::new(rect.min.x + space_alloc, rect.min.y), rect.max);
+
+                let tick_to_x = |tick: MIDITick| lane_rect.min.x + ((tick as f32 - min_tick as f32) / zoom_ticks) * lane_width;
+                let x_to_tick = |x: f32| (min_tick as f32 + ((x - lane_rect.min.x) / lane_width) * zoom_ticks).max(0.0) as MIDITick;
+
+                let response = ui.allocate_rect(lane_rect, egui::Sense::click());
+                let painter = ui.painter_at(lane_rect);
+                let row_height = lane_rect.height() * 0.5;
+
+                // markers and cue points go on the top row, lyrics on the bottom one
+                let mut item_rects: Vec<(usize, Rect)> = Vec::new();
+                let metas = self.meta_editing.lock().unwrap().get_metas();
+                {
+                    let metas = metas.read().unwrap();
+                    for (idx, meta) in metas.iter().enumerate() {
+                        if meta.tick < min_tick { continue; }
+                        if meta.tick > max_tick { break; }
+
+                        let (color, row_top) = match meta.event_type {
+                            MetaEventType::Marker => (MARKER_COLOR, lane_rect.top()),
+                            MetaEventType::CuePoint => (CUE_POINT_COLOR, lane_rect.top()),
+                            MetaEventType::Lyric => (LYRIC_COLOR, lane_rect.top() + row_height),
+                            _ => continue
+                        };
+
+                        let x = tick_to_x(meta.tick);
+                        painter.line_segment([Pos2::new(x, row_top), Pos2::new(x, row_top + row_height)], Stroke::new(1.0, color));
+                        let text_rect = painter.text(
+                            Pos2::new(x + 3.0, row_top + row_height * 0.5),
+                            egui::Align2::LEFT_CENTER,
+                            meta.get_value_string(),
+                            egui::FontId::proportional(12.0),
+                            color
+                        );
+
+                        let hit_rect = text_rect.union(Rect::from_min_max(Pos2::new(x - 3.0, row_top), Pos2::new(x + 3.0, row_top + row_height)));
+                        item_rects.push((idx, hit_rect));
+                    }
+                }
+
+                // later items are drawn on top, so they get picked first
+                let hovered_item = response.hover_pos()
+                    .and_then(|pos| item_rects.iter().rev().find(|(_, r)| r.contains(pos)))
+                    .map(|(idx, _)| *idx);
+
+                let mouse_tick = response.hover_pos().map(|pos| {
+                    let snap = self.get_min_snap_length();
+                    x_to_tick(pos.x).rounded_div(snap) * snap
+                });
+
+                let get_meta = |idx: usize| {
+                    let metas = metas.read().unwrap();
+                    metas.get(idx).cloned()
+                };
+
+                if response.double_clicked() {
+                    match hovered_item {
+                        Some(idx) => {
+                            if let Some(meta) = get_meta(idx) { self.edit_meta(idx, meta); }
+                        },
+                        None => {
+                            if let Some(tick) = mouse_tick { self.insert_meta_at(MetaEventType::Marker, tick); }
+                        }
+                    }
+                } else if response.clicked() {
+                    if let Some(meta) = hovered_item.and_then(get_meta) {
+                        self.set_playhead_pos(meta.tick);
+                    }
+                }
+
+                if response.secondary_clicked() {
+                    self.marker_lane_right_clicked = (hovered_item, mouse_tick.unwrap_or(0));
+                }
+
+                response.context_menu(|ui| {
+                    let (clicked_item, clicked_tick) = self.marker_lane_right_clicked;
+                    let mut should_close = false;
+
+                    if let Some((idx, meta)) = clicked_item.and_then(|idx| get_meta(idx).map(|meta| (idx, meta))) {
+                        if ui.button(format!("Edit {}...", meta.event_type.to_string().to_lowercase())).clicked() {
+                            self.edit_meta(idx, meta.clone());
+                            should_close = true;
+                        }
+
+                        if ui.button("Go to").clicked() {
+                            self.set_playhead_pos(meta.tick);
+                            should_close = true;
+                        }
+
+                        if ui.button("Delete").clicked() {
+                            let mut meta_editing = self.meta_editing.lock().unwrap();
+                            meta_editing.delete_metas(vec![idx]);
+                            should_close = true;
+                        }
+
+                        ui.separator();
+                    }
+
+                    for (label, meta_type) in [
+                        ("Add marker here", MetaEventType::Marker),
+                        ("Add lyric here", MetaEventType::Lyric),
+                        ("Add cue point here", MetaEventType::CuePoint)
+                    ] {
+                        if ui.button(label).clicked() {
+                            self.insert_meta_at(meta_type, clicked_tick);
+                            should_close = true;
+                        }
+                    }
+
+                    if should_close {
+                        ui.close_menu();
+                    }
+
+                    self.mouse_over_ui |= ui.ui_contains_pointer();
+                });
+            });
+            self.mouse_over_ui |= ui.ui_contains_pointer();
+        });
+    }
+
+    /// Shows the lyric line at the playback position, with the part that was already sung highlighted.
+    fn draw_current_lyrics(&mut self, ui: &mut Ui) {
+        let playback_ticks = {
+            let playback_manager = self.playback_manager.as_ref().unwrap().lock().unwrap();
+            playback_manager.get_playback_ticks()
+        };
+
+        let lyric_line = {
+            let meta_editing = self.meta_editing.lock().unwrap();
+            let metas = meta_editing.get_metas();
+            let metas = metas.read().unwrap();
+            get_lyric_line_at(&metas, playback_ticks)
+        };
+
+        let Some((sung, unsung)) = lyric_line else { return; };
+
+        ui.separator();
+
+        let font = egui::FontId::proportional(15.0);
+        let mut job = egui::text::LayoutJob::default();
+        job.append(&sung, 0.0, egui::TextFormat::simple(font.clone(), Color32::WHITE));
+        job.append(&unsung, 0.0, egui::TextFormat::simple(font, Color32::GRAY));
+        ui.label(job);
+    }
+
     fn draw_playhead_ui(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::top("Playhead").show(ctx, |ui| {
             ui.horizontal_centered(|ui| {
@@ -2349,6 +3098,9 @@ impl MainWindow {
     }
 
     fn draw_scroll_navigation(&mut self, ctx: &egui::Context, ui: &mut Ui) {
+        // the event list scrolls by itself
+        if self.is_on_event_list() { return; }
+
         let mut is_track = false;
         egui::TopBottomPanel::bottom("scroll_navigation").show(ctx, |ui| {
             ui.style_mut().spacing.slider_width = ui.available_width();
@@ -2367,7 +3119,7 @@ impl MainWindow {
             };
 
             let (mut tick_pos, mut tick_end) = match rt {
-                RenderType::PianoRoll => {
+                RenderType::PianoRoll | RenderType::EventList => {
                     let nav = self.nav.as_ref().unwrap();
                     let nav = nav.lock().unwrap();
 
@@ -2396,7 +3148,7 @@ impl MainWindow {
             ).changed() {
                 let mut rend = self.render_manager.as_ref().unwrap().lock().unwrap();
                 match rt {
-                    RenderType::PianoRoll => {
+                    RenderType::PianoRoll | RenderType::EventList => {
                         let mut nav = self.nav.as_mut().unwrap().lock().unwrap();
                         nav.change_tick_pos(tick_pos, |time| {
                             rend.get_active_renderer().lock().unwrap().time_changed(time as u64);
@@ -2449,7 +3201,7 @@ impl MainWindow {
         if self.gl.is_none() || self.data_view_renderer.is_none() { return; }
 
         if ui.ui_contains_pointer() {
-            self.handle_data_viewer_inputs(ctx, ui, mouse_over_ui, any_window_opened);
+            self.handle_data_viewer_inputs(ctx, ui, rect, mouse_over_ui, any_window_opened);
         }
 
         let gl = self.gl.as_ref().unwrap();
@@ -2483,6 +3235,60 @@ impl MainWindow {
         self.mouse_over_ui |= mouse_over_ui;
     }
 
+    fn draw_tempo_lane_controls(&mut self, ui: &mut Ui) {
+        let mut data_editing = self.data_editing.lock().unwrap();
+
+        {
+            let tempo_ramp = &mut data_editing.tempo_ramp;
+
+            ui.label("Ramp");
+            egui::ComboBox::from_id_salt("tempo_ramp_shape")
+                .selected_text(tempo_ramp.shape.to_string())
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut tempo_ramp.shape, TempoRampShape::Linear, "Linear");
+                    ui.selectable_value(&mut tempo_ramp.shape, TempoRampShape::Curved, "Curved");
+                });
+
+            if tempo_ramp.shape == TempoRampShape::Curved {
+                tempo_ramp.curve.show("Curve", ui, Some(40.0));
+            }
+
+            tempo_ramp.steps_per_beat.show("Steps per beat", ui, Some(30.0));
+        }
+
+        ui.separator();
+
+        if ui.button("Tap").clicked() {
+            data_editing.tap_tempo.tap();
+        }
+
+        let tapped_bpm = data_editing.tap_tempo.get_bpm();
+        match tapped_bpm {
+            Some(bpm) => ui.label(format!("{:.2} BPM ({} taps)", bpm, data_editing.tap_tempo.get_tap_count())),
+            None => ui.label("-- BPM")
+        };
+
+        let insert_clicked = ui.add_enabled(tapped_bpm.is_some(), egui::Button::new("Insert at playhead")).clicked();
+        if ui.button("Reset").clicked() {
+            data_editing.tap_tempo.reset();
+        }
+        drop(data_editing);
+
+        if let (true, Some(bpm)) = (insert_clicked, tapped_bpm) {
+            let playhead_pos = {
+                let playhead = self.playhead.try_borrow().unwrap();
+                playhead.start_tick
+            };
+
+            let mut meta_editing = self.meta_editing.lock().unwrap();
+            meta_editing.replace_metas_in_range(MetaEventType::Tempo, playhead_pos, playhead_pos, vec![MetaEvent {
+                tick: playhead_pos,
+                event_type: MetaEventType::Tempo,
+                data: tempo_as_bytes(bpm).to_vec()
+            }]);
+        }
+    }
+
     fn draw_data_view_edit_line(&mut self, ctx: &egui::Context, ui: &mut Ui) {
         let data_editing = self.data_editing.lock().unwrap();
         if !data_editing.get_flag(DATA_EDIT_DRAW_EDIT_LINE) { drop(data_editing); return; }
@@ -2494,6 +3300,123 @@ impl MainWindow {
         ], Stroke::new(1.0, Color32::WHITE));
     }
 
+    fn draw_note_inspector(&mut self, ctx: &egui::Context) {
+        if let Some(view_settings) = self.view_settings.as_ref() {
+            let view_settings = view_settings.lock().unwrap();
+            if !view_settings.show_note_inspector { return; }
+        }
+
+        egui::SidePanel::right("note_inspector")
+            .resizable(false)
+            .default_width(260.0)
+            .show(ctx, |ui| {
+                self.note_inspector.draw(ui);
+                self.mouse_over_ui |= ui.ui_contains_pointer();
+            });
+    }
+
+    fn draw_plugin_console(&mut self, ctx: &egui::Context) {
+        if let Some(view_settings) = self.view_settings.as_ref() {
+            let view_settings = view_settings.lock().unwrap();
+            if !view_settings.show_plugin_console { return; }
+        }
+
+        let curr_track = self.get_current_track().unwrap_or(0) as usize;
+        let dock = self.plugin_console.dock;
+        let add_contents = |ui: &mut Ui| {
+            self.plugin_console.draw(ui, curr_track);
+            self.mouse_over_ui |= ui.ui_contains_pointer();
+        };
+
+        match dock {
+            ConsoleDock::Bottom => {
+                egui::TopBottomPanel::bottom("plugin_console")
+                    .resizable(true)
+                    .default_height(200.0)
+                    .show(ctx, add_contents);
+            },
+            ConsoleDock::Right => {
+                egui::SidePanel::right("plugin_console_right")
+                    .resizable(true)
+                    .default_width(360.0)
+                    .show(ctx, add_contents);
+            }
+        }
+    }
+
+    /// Reloads custom plugins whose files changed, and rebuilds the plugin menus if that changed anything.
+    fn poll_plugin_changes(&mut self, ctx: &egui::Context) {
+        let Some(plugin_loader) = self.plugin_loader.as_mut() else { return; };
+        let custom_changed = plugin_loader.poll_custom_plugins();
+        let packages_changed = plugin_loader.poll_packages();
+        if custom_changed || packages_changed { self.init_main_menu(); }
+
+        // keep looking even when nothing else asks for a repaint
+        ctx.request_repaint_after(PLUGIN_WATCH_INTERVAL);
+    }
+
+    /// Calls `hook` on the hook plugins. Opens the console if they have something to say, except during playback.
+    fn run_plugin_hook(&mut self, hook: PluginHook, fill_event: impl Fn(&mlua::Table) -> mlua::Result<()>) -> HookOutcome {
+        let (Some(hook_runner), Some(plugin_loader)) = (self.plugin_hooks.as_ref(), self.plugin_loader.as_ref()) else {
+            return HookOutcome::default();
+        };
+        if !PluginHookRunner::is_hooked(&plugin_loader.hook_plugins, hook) { return HookOutcome::default(); }
+
+        let curr_track = self.get_current_track().unwrap_or(0) as usize;
+        let outcome = hook_runner.dispatch(&plugin_loader.hook_plugins, hook, curr_track, fill_event);
+
+        if outcome.had_errors || (hook != PluginHook::PlaybackTick && !outcome.warnings.is_empty()) {
+            let mut view_settings = self.view_settings.as_ref().unwrap().lock().unwrap();
+            view_settings.show_plugin_console = true;
+        }
+        // a failed plugin got its hooks turned off, which the menu shows
+        if outcome.had_errors { self.init_main_menu(); }
+
+        outcome
+    }
+
+    /// Tells hook plugins about what happened since the last frame.
+    fn run_live_plugin_hooks(&mut self) {
+        if self.plugin_loader.is_none() { return; }
+
+        if self.project_loaded_pending {
+            self.project_loaded_pending = false;
+            // whatever was selected before doesn't count as a change in the new project
+            self.last_selection_change = self.shared_selected_notes.read().unwrap().get_change_count();
+            self.run_plugin_hook(PluginHook::ProjectLoaded, |_| Ok(()));
+        }
+
+        let placed_notes = self.editor_actions.borrow_mut().take_placed_notes();
+        for (track, ids) in placed_notes {
+            self.run_plugin_hook(PluginHook::NotePlaced, |event| {
+                event.set("track", track)?;
+                event.set("ids", ids.clone())
+            });
+        }
+
+        let selection_hooked = PluginHookRunner::is_hooked(&self.plugin_loader.as_ref().unwrap().hook_plugins, PluginHook::SelectionChanged);
+        if selection_hooked {
+            let selection_change = self.shared_selected_notes.read().unwrap().get_change_count();
+            if selection_change != self.last_selection_change {
+                let curr_track = self.get_current_track().unwrap_or(0);
+                self.run_plugin_hook(PluginHook::SelectionChanged, |event| event.set("track", curr_track));
+
+                // selecting something from inside the hook shouldn't set it off again
+                self.last_selection_change = self.shared_selected_notes.read().unwrap().get_change_count();
+            }
+        }
+
+        if self.is_playing() {
+            let tick = {
+                let playback_manager = self.playback_manager.as_ref().unwrap().lock().unwrap();
+                playback_manager.get_playback_ticks()
+            };
+            self.run_plugin_hook(PluginHook::PlaybackTick, |event| event.set("tick", tick));
+        } else if let Some(hook_runner) = self.plugin_hooks.as_ref() {
+            hook_runner.end_playback_run();
+        }
+    }
+
     fn draw_meta_event_view(&mut self, ctx: &egui::Context, _ui: &mut Ui) {
         if let Some(view_settings) = self.view_settings.as_ref() {
             let view_settings = view_settings.lock().unwrap();
@@ -2511,6 +3434,7 @@ impl MainWindow {
                     ui.separator();
                     egui::ScrollArea::vertical().show(ui, |ui| {
                         ui.allocate_at_least([ui.available_width(), 0.0].into(), egui::Sense::hover());
+                        let mut meta_to_edit = None;
                         egui::Grid::new("meta_event_grid")
                             .striped(true)
                             .show(ui, |ui| {
@@ -2518,7 +3442,7 @@ impl MainWindow {
                                 let meta_evs = meta_editing.get_metas();
                                 let meta_evs = meta_evs.read().unwrap();
                                 
-                                for meta in meta_evs.iter() {
+                                for (meta_idx, meta) in meta_evs.iter().enumerate() {
                                     /*if highlight {
                                         let rect = egui::Rect::from_min_size(
                                             row_rect.min,
@@ -2529,11 +3453,23 @@ impl MainWindow {
 
                                     ui.label(meta.tick.to_string());
                                     ui.label(meta.event_type.to_string());
-                                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| { ui.label(meta.get_value_string()) });
+                                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                        if meta.event_type == MetaEventType::KeySignature {
+                                            if ui.link(meta.get_value_string()).on_hover_text("Click to edit").clicked() {
+                                                meta_to_edit = Some((meta_idx, meta.clone()));
+                                            }
+                                        } else {
+                                            ui.label(meta.get_value_string());
+                                        }
+                                    });
                                     
                                     ui.end_row();
                                 }
                             });
+
+                        if let Some((meta_idx, meta)) = meta_to_edit {
+                            self.edit_meta(meta_idx, meta);
+                        }
                     });
                 });
         });
@@ -2549,7 +3485,7 @@ impl MainWindow {
 
         match render_type {
             RenderType::TrackView => self.draw_trackview_context_menu(ui),
-            RenderType::PianoRoll => {}
+            RenderType::PianoRoll | RenderType::EventList => {}
         };
     }
 
@@ -2603,6 +3539,22 @@ impl MainWindow {
                 should_close = true;
             }
 
+            if ui.button("Duplicate Track").clicked() {
+                let mut track_editing = self.track_editing.lock().unwrap();
+                let right_clicked_track = track_editing.get_right_clicked_track();
+                track_editing.duplicate_track(right_clicked_track, 1, 0, 0);
+                should_close = true;
+            }
+
+            if ui.button("Duplicate Track N Times...").on_hover_text("Makes several copies, each with a channel and transpose offset.").clicked() {
+                let right_clicked_track = {
+                    let track_editing = self.track_editing.lock().unwrap();
+                    track_editing.get_right_clicked_track()
+                };
+                self.show_dialog_with_args(DIALOG_NAME_DUPLICATE_TRACK, vec![Box::new(right_clicked_track)]);
+                should_close = true;
+            }
+
             ui.separator();
 
             if ui.button("Decompose Track").on_hover_text("Separates all channels in this track.").clicked() {
@@ -2612,6 +3564,30 @@ impl MainWindow {
                 should_close = true;
             }
 
+            if ui.button("Merge Tracks...").on_hover_text("Merges several tracks into one.").clicked() {
+                let tracks_to_merge = {
+                    let track_editing = self.track_editing.lock().unwrap();
+                    match track_editing.get_selected_track_range() {
+                        Some((first, last)) if last > first + 1 => (first..last).collect::<Vec<u16>>(),
+                        _ => {
+                            let right_clicked_track = track_editing.get_right_clicked_track();
+                            vec![right_clicked_track, right_clicked_track + 1]
+                        }
+                    }
+                };
+                self.show_dialog_with_args(DIALOG_NAME_MERGE_TRACKS, vec![Box::new(tracks_to_merge)]);
+                should_close = true;
+            }
+
+            if ui.button("Split Track...").on_hover_text("Splits this track by key range, velocity, channel or overlapping notes.").clicked() {
+                let right_clicked_track = {
+                    let track_editing = self.track_editing.lock().unwrap();
+                    track_editing.get_right_clicked_track()
+                };
+                self.show_dialog_with_args(DIALOG_NAME_SPLIT_TRACK, vec![Box::new(right_clicked_track)]);
+                should_close = true;
+            }
+
             if should_close {
                 ui.close_menu();
             }
@@ -2688,6 +3664,8 @@ impl eframe::App for MainWindow {
                                     editor_actions.clear_actions();
                                 }
 
+                                self.project_loaded_pending = true;
+
                                 {
                                     let mut playhead = self.playhead.try_borrow_mut().unwrap();
                                     playhead.set_start(0);
@@ -1,7 +1,7 @@
 use std::sync::{Arc, Mutex, RwLock};
 use eframe::egui::Vec2;
 use eframe::glow;
-use crate::{app::{rendering::{note_cull_helper::NoteCullHelper, piano_roll::PianoRollRenderer, track_view::TrackViewRenderer}, shared::NoteColors, view_settings::ViewSettings}, audio::event_playback::PlaybackManager, editor::{editing::{SharedSelectedNotes, note_editing::NoteEditing, track_editing::TrackEditing}, midi_bar_cacher::BarCacher, navigation::{PianoRollNavigation, TrackViewNavigation}, project::project_manager::{self, ProjectManager}}, midi::events::note::Note, util::debugger::Debugger};
+use crate::{app::{rendering::{event_list::EventListRenderer, note_cull_helper::NoteCullHelper, piano_roll::PianoRollRenderer, track_view::TrackViewRenderer}, shared::NoteColors, view_settings::ViewSettings}, audio::event_playback::PlaybackManager, editor::{editing::{SharedSelectedNotes, meta_editing::MetaEditing, note_editing::NoteEditing, track_editing::TrackEditing}, midi_bar_cacher::BarCacher, navigation::{PianoRollNavigation, TrackViewNavigation}, project::project_manager::{self, ProjectManager}}, midi::events::note::Note, util::debugger::Debugger};
 
 pub mod buffers;
 pub mod piano_roll;
@@ -9,6 +9,7 @@ pub mod shaders;
 pub mod track_view;
 pub mod data_view;
 pub mod note_cull_helper;
+pub mod event_list;
 
 pub trait Renderer {
     fn draw(&mut self);
@@ -25,7 +26,8 @@ pub trait Renderer {
 #[derive(PartialEq, Clone, Copy)]
 pub enum RenderType {
     PianoRoll,
-    TrackView
+    TrackView,
+    EventList
 }
 
 pub struct RenderManager {
@@ -57,6 +59,7 @@ impl RenderManager {
         shared_selected_notes: &Arc<RwLock<SharedSelectedNotes>>,
 
         note_editing: &Arc<Mutex<NoteEditing>>,
+        meta_editing: &Arc<Mutex<MetaEditing>>,
         track_editing: &Arc<Mutex<TrackEditing>>,
     ) {
         // initialize piano roll renderer
@@ -77,6 +80,7 @@ impl RenderManager {
                     colors,
                     note_cull_helper,
                     shared_selected_notes,
+                    meta_editing,
                 )
             };
 
@@ -112,6 +116,7 @@ impl RenderManager {
 
             self.renderers.push(piano_roll_renderer);
             self.renderers.push(track_view_renderer);
+            self.renderers.push(Arc::new(Mutex::new(EventListRenderer)));
         }
     }
 
@@ -144,6 +149,9 @@ impl RenderManager {
             },
             RenderType::TrackView => {
                 &mut self.renderers[1]
+            },
+            RenderType::EventList => {
+                &mut self.renderers[2]
             }
         }
     }
@@ -159,24 +167,20 @@ impl RenderManager {
             },
             RenderType::TrackView => {
                 self.renderers[1].clone()
+            },
+            RenderType::EventList => {
+                self.renderers[2].clone()
             }
         }
     }
 
     fn set_active(&mut self, render_type: RenderType) {
-        match render_type {
-            RenderType::PianoRoll => {
-                let tmp = self.get_renderer(RenderType::TrackView);
-                tmp.lock().unwrap().set_active(false);
-                self.render_type = RenderType::PianoRoll;
-            },
-            RenderType::TrackView => {
-                let tmp = self.get_renderer(RenderType::PianoRoll);
-                tmp.lock().unwrap().set_active(false);
-                self.render_type = RenderType::TrackView;
-            }
+        for other in [RenderType::PianoRoll, RenderType::TrackView, RenderType::EventList] {
+            if other == render_type { continue; }
+            self.get_renderer(other).lock().unwrap().set_active(false);
         }
-        
+        self.render_type = render_type;
+
         self.get_renderer(render_type).lock().unwrap().set_active(true);
     }
 }
\ No newline at end of file
@@ -6,10 +6,12 @@ use crate::app::view_settings::{VS_PianoRoll_DataViewState,
 ViewSettings};
 use crate::audio::event_playback::PlaybackManager;
 use crate::editor::editing::SharedSelectedNotes;
+use crate::editor::editing::data_editing::{TEMPO_LANE_MAX_BPM, TEMPO_LANE_MIN_BPM};
 use crate::editor::midi_bar_cacher::BarCacher;
 use crate::editor::project::project_manager::ProjectManager;
-use crate::editor::util::get_next_specific_ch_ev_idx;
+use crate::editor::util::{bytes_as_tempo, get_next_specific_ch_ev_idx};
 use crate::midi::events::channel_event::ChannelEventType;
+use crate::midi::events::meta_event::{MetaEvent, MetaEventType};
 use crate::midi::events::note::Note;
 use crate::midi::midi_track::MIDITrack;
 use std::sync::{Arc, Mutex, RwLock};
@@ -87,6 +89,7 @@ pub struct DataViewRenderer {
     dv_handles_render: Vec<RenderDataViewHandle>,
     // notes: Arc<RwLock<Vec<Vec<Note>>>>,
     all_tracks: Arc<RwLock<Vec<MIDITrack>>>,
+    global_metas: Arc<RwLock<Vec<MetaEvent>>>,
 
     note_cull_helper: Arc<Mutex<NoteCullHelper>>,
 
@@ -176,9 +179,9 @@ impl DataViewRenderer {
         gl.vertex_attrib_divisor(1, 1);
         gl.vertex_attrib_divisor(2, 1);
 
-        let tracks = {
+        let (tracks, global_metas) = {
             let project_manager = project_manager.read().unwrap();
-            project_manager.get_tracks().clone()
+            (project_manager.get_tracks().clone(), project_manager.get_metas().clone())
         };
 
         Self {
@@ -205,6 +208,7 @@ impl DataViewRenderer {
             bars_render: dv_bars_render.to_vec(),
 
             all_tracks: tracks,
+            global_metas,
             view_settings: view_settings.clone(),
             note_colors: note_colors.clone(),
 
@@ -661,6 +665,69 @@ impl DataViewRenderer {
         }
     }
 
+    fn draw_tempo(&mut self, tick_pos: f32, zoom_ticks: f32) {
+        let tick_pos_offs = tick_pos + self.view_offset;
+        let view_end = tick_pos_offs + zoom_ticks;
+
+        let metas = self.global_metas.read().unwrap();
+        let mut tempos = metas.iter()
+            .filter(|m| m.event_type == MetaEventType::Tempo)
+            .peekable();
+
+        let mut handle_id = 0;
+
+        self.dv_handles_vao.bind();
+        self.dv_handles_ibo.bind();
+        self.dv_handles_vbo.bind();
+        self.dv_handles_ebo.bind();
+
+        while let Some(tempo) = tempos.next() {
+            // each tempo lasts until the next one (or the end of the view)
+            let end_tick = match tempos.peek() {
+                Some(next) => next.tick as f32,
+                None => view_end.max(tempo.tick as f32)
+            };
+
+            if end_tick < tick_pos_offs { continue; }
+            if tempo.tick as f32 > view_end { break; }
+
+            let bpm = bytes_as_tempo(&tempo.data);
+            let value = ((bpm - TEMPO_LANE_MIN_BPM) / (TEMPO_LANE_MAX_BPM - TEMPO_LANE_MIN_BPM)).clamp(0.0, 1.0);
+
+            self.dv_handles_render[handle_id] = RenderDataViewHandle {
+                0: [(tempo.tick as f32 - tick_pos_offs) / zoom_ticks,
+                    (end_tick - tempo.tick as f32) / zoom_ticks,
+                    0.0,
+                    value],
+                1: 127u32 << 4
+            };
+
+            handle_id += 1;
+
+            if handle_id >= HANDLE_BUFFER_SIZE {
+                self.dv_handles_ibo.set_data(self.dv_handles_render.as_slice(), glow::DYNAMIC_DRAW);
+
+                unsafe {
+                    self.gl.draw_elements_instanced(
+                        glow::TRIANGLES, 6, glow::UNSIGNED_INT, 0, HANDLE_BUFFER_SIZE as i32
+                    );
+                }
+
+                handle_id = 0;
+            }
+        }
+
+        if handle_id != 0 {
+            self.dv_handles_ibo.set_data(self.dv_handles_render.as_slice(), glow::DYNAMIC_DRAW);
+
+            unsafe {
+                self.gl.draw_elements_instanced(
+                    glow::TRIANGLES, 6, glow::UNSIGNED_INT, 0, handle_id as i32
+                );
+            }
+        }
+    }
+
     /*pub fn set_ghost_notes(&mut self, notes: Arc<Mutex<Vec<Note>>>) {
         self.ghost_notes = Some(notes);
     }
@@ -806,6 +873,9 @@ impl Renderer for DataViewRenderer {
                         VS_PianoRoll_DataViewState::PitchBend => {
                             self.draw_channel_event_data(tick_pos, zoom_ticks, ChannelEventType::PitchBend(0, 0));
                         },
+                        VS_PianoRoll_DataViewState::Tempo => {
+                            self.draw_tempo(tick_pos, zoom_ticks);
+                        },
                         _ => {}
                     }
                 }
@@ -0,0 +1,10 @@
+use crate::app::rendering::Renderer;
+
+/// The event list is made of egui widgets rather than drawn with GL, so this renderer draws nothing.
+/// It's only here so the event list can be switched to like the other views.
+#[derive(Default)]
+pub struct EventListRenderer;
+
+impl Renderer for EventListRenderer {
+    fn draw(&mut self) {}
+}
@@ -3,6 +3,7 @@ use crate::app::rendering::Renderer;
 use crate::app::shared::NoteColors;
 use crate::audio::event_playback::PlaybackManager;
 use crate::editor::editing::SharedSelectedNotes;
+use crate::editor::editing::meta_editing::MetaEditing;
 use crate::editor::midi_bar_cacher::BarCacher;
 use crate::editor::project::project_manager::ProjectManager;
 use crate::editor::settings::editor_settings::PR_KEYBOARD_WIDTH;
@@ -27,10 +28,11 @@ const NOTE_BUFFER_SIZE: usize = 8192;
 pub type BarStart = f32;
 pub type BarLength = f32;
 pub type BarNumber = u32;
+pub type BarScale = u32; // lower 12 bits are the scale mask, the next 4 bits are the tonic. 0 means no highlighting
 
 #[repr(C)]
 #[derive(Clone, Copy)]
-pub struct RenderPianoRollBar(BarStart, BarLength, BarNumber);
+pub struct RenderPianoRollBar(BarStart, BarLength, BarNumber, BarScale);
 
 // Piano Roll Notes
 pub type NoteRect = [f32; 4]; // (start, length, note bottom, note top)
@@ -115,6 +117,7 @@ pub struct PianoRollRenderer {
     kb_render: [RenderPianoRollKeyboard; 128],
     //render_notes: Arc<RwLock<Vec<Vec<Note>>>>,
     all_tracks: Arc<RwLock<Vec<MIDITrack>>>,
+    meta_editing: Arc<Mutex<MetaEditing>>,
     note_colors: Arc<Mutex<NoteColors>>,
 
     note_cull_helper: Arc<Mutex<NoteCullHelper>>,
@@ -144,6 +147,7 @@ impl PianoRollRenderer {
         colors: &Arc<Mutex<NoteColors>>,
         note_cull_helper: &Arc<Mutex<NoteCullHelper>>,
         shared_selected_notes: &Arc<RwLock<SharedSelectedNotes>>,
+        meta_editing: &Arc<Mutex<MetaEditing>>,
     ) -> Self {
         let pr_program = ShaderProgram::create_from_files(gl.clone(), "./assets/shaders/piano_roll_bg");
         let pr_notes_program = ShaderProgram::create_from_files(gl.clone(), "./assets/shaders/piano_roll_note");
@@ -163,7 +167,8 @@ impl PianoRollRenderer {
             RenderPianoRollBar {
                 0: 0.0,
                 1: 1.0,
-                2: 0
+                2: 0,
+                3: 0
             }; 32
         ];
         pr_instance_buffer.set_data(pr_bars_render.as_slice(), glow::DYNAMIC_DRAW);
@@ -174,10 +179,13 @@ impl PianoRollRenderer {
         set_attribute!(glow::FLOAT, pr_vertex_array, pr_bar_length, RenderPianoRollBar::1);
         let pr_bar_number = pr_program.get_attrib_location("barNumber").unwrap();
         set_attribute!(glow::UNSIGNED_INT, pr_vertex_array, pr_bar_number, RenderPianoRollBar::2);
+        let pr_bar_scale = pr_program.get_attrib_location("barScale").unwrap();
+        set_attribute!(glow::UNSIGNED_INT, pr_vertex_array, pr_bar_scale, RenderPianoRollBar::3);
 
         gl.vertex_attrib_divisor(0, 1);
         gl.vertex_attrib_divisor(1, 1);
         gl.vertex_attrib_divisor(2, 1);
+        gl.vertex_attrib_divisor(3, 1);
 
         // -------- PIANO ROLL NOTES --------
         
@@ -285,6 +293,7 @@ impl PianoRollRenderer {
             kb_render: pr_kb_render,
             // render_notes: notes,
             all_tracks: tracks,
+            meta_editing: meta_editing.clone(),
 
             ppq: 960,
             note_colors: colors.clone(),
@@ -400,6 +409,16 @@ impl Renderer for PianoRollRenderer {
                     self.pr_vertex_buffer.bind();
                     self.pr_index_buffer.bind();
 
+                    let scale_map = {
+                        let view_settings = self.view_settings.lock().unwrap();
+                        if view_settings.pr_scale_highlight {
+                            let mut meta_editing = self.meta_editing.lock().unwrap();
+                            Some(meta_editing.get_scale_map(view_settings.pr_scale_mode))
+                        } else {
+                            None
+                        }
+                    };
+
                     let mut bar_cacher = self.bar_cacher.lock().unwrap();
                     while curr_bar_tick < zoom_ticks + tick_pos_offs {
                         let (bar_tick, bar_length) = {
@@ -414,10 +433,21 @@ impl Renderer for PianoRollRenderer {
                             continue;
                         }
 
+                        // the key signature at the start of the bar colors the whole bar
+                        let bar_scale = match scale_map.as_ref() {
+                            Some(scale_map) => {
+                                let key_sig = scale_map.key_signature_at(bar_tick);
+                                let mask = scale_map.mode.scale_mask(&key_sig) as u32;
+                                mask | ((key_sig.tonic() as u32) << 12)
+                            },
+                            None => 0
+                        };
+
                         self.bars_render[bar_id] = RenderPianoRollBar {
                             0: ((curr_bar_tick - tick_pos_offs) / zoom_ticks),
                             1: (bar_length as f32 / zoom_ticks),
-                            2: bar_num as u32
+                            2: bar_num as u32,
+                            3: bar_scale
                         };
                         bar_id += 1;
                         if bar_id >= 32 {
@@ -4,4 +4,6 @@ pub mod dialog;
 pub mod dialog_manager;
 pub mod dialog_drawer;
 pub mod edtior_info;
-pub mod dialogs;
\ No newline at end of file
+pub mod dialogs;
+pub mod note_inspector;
+pub mod plugin_console;
\ No newline at end of file
@@ -16,6 +16,8 @@ pub mod names {
     pub const DIALOG_NAME_EF_STRETCH: &'static str = "EFStretchDialog";
     pub const DIALOG_NAME_EF_CHOP: &'static str = "EFChopDialog";
     pub const DIALOG_NAME_EF_GLUE: &'static str = "EFGlueDialog";
+    pub const DIALOG_NAME_EF_QUANTIZE: &'static str = "EFQuantizeDialog";
+    pub const DIALOG_NAME_EF_FORMULA: &'static str = "EFFormulaDialog";
     pub const DIALOG_NAME_EDITOR_SETTINGS: &'static str = "EditorSettings";
     pub const DIALOG_NAME_PROJECT_SETTINGS: &'static str = "ProjectSettings";
     pub const DIALOG_NAME_INSERT_META: &'static str = "InsertMeta";
@@ -24,6 +26,14 @@ pub mod names {
     pub const DIALOG_NAME_PLUGIN_DIALOG: &'static str = "LuaPluginDialog";
     pub const DIALOG_NAME_PLUGIN_ERROR_DIALOG: &'static str = "LuaPluginErrorDialog";
     pub const DIALOG_NAME_FILTER_CHANNELS: &'static str = "FilterChannels";
+    pub const DIALOG_NAME_SELECT_NOTES: &'static str = "SelectNotes";
+    pub const DIALOG_NAME_TIME_SHIFT: &'static str = "TimeShift";
+    pub const DIALOG_NAME_MERGE_TRACKS: &'static str = "MergeTracks";
+    pub const DIALOG_NAME_SPLIT_TRACK: &'static str = "SplitTrack";
+    pub const DIALOG_NAME_DUPLICATE_TRACK: &'static str = "DuplicateTrack";
+    pub const DIALOG_NAME_PASTE_SPECIAL: &'static str = "PasteSpecial";
+    pub const DIALOG_NAME_SAVE_MACRO: &'static str = "SaveMacro";
+    pub const DIALOG_NAME_PLUGIN_MANAGER: &'static str = "PluginManager";
     pub const DIALOG_NAME_CRASH: &'static str = "CrashDialog";
 }
 
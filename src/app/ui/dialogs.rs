@@ -1,3 +1,10 @@
 pub mod filter_channels;
 pub mod crash_dialog;
-pub mod simple_dialog;
\ No newline at end of file
+pub mod simple_dialog;
+pub mod select_notes;
+pub mod time_shift;
+pub mod merge_split_tracks;
+pub mod duplicate_track;
+pub mod paste_special;
+pub mod save_macro;
+pub mod plugin_manager;
\ No newline at end of file
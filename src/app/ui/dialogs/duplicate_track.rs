@@ -0,0 +1,88 @@
+use std::sync::{Arc, Mutex};
+
+use eframe::egui;
+
+use crate::{app::{custom_widgets::{NumberField, NumericField}, ui::dialog::{Dialog, DialogAction, DialogActionButtons, dialog_default_close_action, names::DIALOG_NAME_DUPLICATE_TRACK}, util::image_loader::ImageResources}, editor::editing::track_editing::TrackEditing};
+
+/// The most copies one duplicate can make. Macros are held to it too.
+pub const MAX_DUPLICATES: u16 = 256;
+
+/// Makes several copies of a track, each one shifted further in channel and key than the last.
+pub struct DuplicateTrackDialog {
+    track: u16,
+    times: NumericField<u16>,
+    channel_offset: NumericField<i16>,
+    transpose: NumericField<i16>,
+
+    track_editing: Arc<Mutex<TrackEditing>>,
+}
+
+impl Default for DuplicateTrackDialog {
+    fn default() -> Self {
+        Self::new(&Default::default())
+    }
+}
+
+impl DuplicateTrackDialog {
+    pub fn new(track_editing: &Arc<Mutex<TrackEditing>>) -> Self {
+        Self {
+            track: 0,
+            times: NumericField::new(1, Some(1), Some(MAX_DUPLICATES)),
+            channel_offset: NumericField::new(0, Some(-15), Some(15)),
+            transpose: NumericField::new(0, Some(-127), Some(127)),
+
+            track_editing: track_editing.clone()
+        }
+    }
+}
+
+impl Dialog for DuplicateTrackDialog {
+    /// args: track to duplicate (u16)
+    fn init_dialog(&mut self, args: Vec<Box<dyn std::any::Any>>) -> Result<(), &'static str> {
+        self.track = *args[0].downcast_ref::<u16>().unwrap();
+        Ok(())
+    }
+
+    fn draw(&mut self, ui: &mut egui::Ui, _: &ImageResources) -> Option<DialogAction> {
+        ui.label(format!("Duplicating track {}", self.track));
+        ui.separator();
+
+        self.times.show("Copies:", ui, Some(50.0));
+        self.channel_offset.show("Channel offset per copy:", ui, Some(50.0));
+        self.transpose.show("Transpose per copy (semitones):", ui, Some(50.0));
+
+        ui.separator();
+        let (channel_offset, transpose) = (self.channel_offset.value(), self.transpose.value());
+        if channel_offset != 0 || transpose != 0 {
+            ui.label(format!(
+                "The last copy ends up {} channel(s) and {} semitone(s) away from the original.",
+                channel_offset * self.times.value() as i16, transpose * self.times.value() as i16
+            ));
+            ui.label("Channels wrap around, notes moved out of the key range are dropped.");
+        }
+
+        None
+    }
+
+    fn get_action_buttons(&self) -> Option<DialogActionButtons> {
+        Some(DialogActionButtons::OkCancel(
+            Box::new(|dlg| {
+                let dlg = dlg.as_any_mut().downcast_mut::<Self>().unwrap();
+                {
+                    let mut track_editing = dlg.track_editing.lock().unwrap();
+                    track_editing.duplicate_track(dlg.track, dlg.times.value(), dlg.channel_offset.value(), dlg.transpose.value());
+                }
+                Some(DialogAction::Close(dlg.get_dialog_name()))
+            }),
+            dialog_default_close_action()
+        ))
+    }
+
+    fn get_dialog_name(&self) -> &'static str {
+        DIALOG_NAME_DUPLICATE_TRACK
+    }
+
+    fn get_dialog_title(&self) -> String {
+        "Duplicate track".into()
+    }
+}
@@ -0,0 +1,283 @@
+use std::{mem::discriminant, sync::{Arc, Mutex, RwLock}};
+
+use eframe::egui;
+
+use crate::{app::{custom_widgets::{NumberField, NumericField}, ui::dialog::{Dialog, DialogAction, DialogActionButtons, dialog_default_close_action, names::{DIALOG_NAME_MERGE_TRACKS, DIALOG_NAME_SPLIT_TRACK}}, util::image_loader::ImageResources}, editor::{editing::track_editing::TrackEditing, project::{project_manager::ProjectManager, track_restructure::TrackSplitMode}, util::{MIDIKey, key_to_name}}};
+
+fn channel_remap_text(remap: Option<u8>) -> String {
+    match remap {
+        Some(channel) => format!("Channel {}", channel + 1),
+        None => "Keep channels".to_string()
+    }
+}
+
+struct MergeTrackRow {
+    include: bool,
+    remap: Option<u8>,
+    note_count: usize,
+}
+
+/// Merges the checked tracks into one, optionally moving each one to a different channel.
+pub struct MergeTracksDialog {
+    rows: Vec<MergeTrackRow>,
+    error: Option<String>,
+
+    project_manager: Arc<RwLock<ProjectManager>>,
+    track_editing: Arc<Mutex<TrackEditing>>,
+}
+
+impl Default for MergeTracksDialog {
+    fn default() -> Self {
+        Self::new(&Default::default(), &Default::default())
+    }
+}
+
+impl MergeTracksDialog {
+    pub fn new(project_manager: &Arc<RwLock<ProjectManager>>, track_editing: &Arc<Mutex<TrackEditing>>) -> Self {
+        Self {
+            rows: Vec::new(),
+            error: None,
+
+            project_manager: project_manager.clone(),
+            track_editing: track_editing.clone()
+        }
+    }
+
+    fn apply_merge(&mut self) -> Result<(), String> {
+        let (tracks, remap): (Vec<u16>, Vec<Option<u8>>) = self.rows.iter().enumerate()
+            .filter(|(_, row)| row.include)
+            .map(|(i, row)| (i as u16, row.remap))
+            .unzip();
+
+        if tracks.len() < 2 { return Err("Pick at least two tracks to merge.".into()); }
+
+        let mut track_editing = self.track_editing.lock().unwrap();
+        track_editing.merge_tracks(&tracks, &remap);
+        Ok(())
+    }
+}
+
+impl Dialog for MergeTracksDialog {
+    /// args: tracks to check by default (Vec<u16>)
+    fn init_dialog(&mut self, args: Vec<Box<dyn std::any::Any>>) -> Result<(), &'static str> {
+        let preselected = args.first()
+            .and_then(|arg| arg.downcast_ref::<Vec<u16>>())
+            .cloned()
+            .unwrap_or_default();
+
+        self.rows = {
+            let project_manager = self.project_manager.read().unwrap();
+            let tracks = project_manager.get_tracks().read().unwrap();
+            tracks.iter().enumerate().map(|(i, track)| MergeTrackRow {
+                include: preselected.contains(&(i as u16)),
+                remap: None,
+                note_count: track.get_notes().len()
+            }).collect()
+        };
+
+        if self.rows.len() < 2 { return Err("There needs to be at least two tracks to merge."); }
+        Ok(())
+    }
+
+    fn draw(&mut self, ui: &mut egui::Ui, _: &ImageResources) -> Option<DialogAction> {
+        ui.horizontal(|ui| {
+            if ui.button("Check all").clicked() { self.rows.iter_mut().for_each(|row| row.include = true); }
+            if ui.button("Uncheck all").clicked() { self.rows.iter_mut().for_each(|row| row.include = false); }
+        });
+        ui.separator();
+
+        let row_height = ui.spacing().interact_size.y;
+        egui::ScrollArea::vertical().max_height(300.0).show_rows(ui, row_height, self.rows.len(), |ui, range| {
+            for i in range {
+                let row = &mut self.rows[i];
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut row.include, format!("Track {} ({} notes)", i, row.note_count));
+                    ui.add_enabled_ui(row.include, |ui| {
+                        egui::ComboBox::from_id_salt(("merge_tracks_remap", i))
+                            .selected_text(channel_remap_text(row.remap))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut row.remap, None, channel_remap_text(None));
+                                for channel in 0..16 {
+                                    ui.selectable_value(&mut row.remap, Some(channel), channel_remap_text(Some(channel)));
+                                }
+                            });
+                    });
+                });
+            }
+        });
+
+        ui.separator();
+        let checked: Vec<usize> = self.rows.iter().enumerate().filter(|(_, row)| row.include).map(|(i, _)| i).collect();
+        match checked.first() {
+            Some(first) if checked.len() > 1 => { ui.label(format!("{} tracks will be merged into track {}.", checked.len(), first)); },
+            _ => { ui.label("Check the tracks to merge."); }
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+
+        None
+    }
+
+    fn get_action_buttons(&self) -> Option<DialogActionButtons> {
+        Some(DialogActionButtons::OkCancel(
+            Box::new(|dlg| {
+                let dlg = dlg.as_any_mut().downcast_mut::<Self>().unwrap();
+                match dlg.apply_merge() {
+                    Ok(()) => Some(DialogAction::Close(dlg.get_dialog_name())),
+                    Err(error) => { dlg.error = Some(error); None }
+                }
+            }),
+            dialog_default_close_action()
+        ))
+    }
+
+    fn get_dialog_name(&self) -> &'static str {
+        DIALOG_NAME_MERGE_TRACKS
+    }
+
+    fn get_dialog_title(&self) -> String {
+        "Merge tracks".into()
+    }
+}
+
+/// How many voices a track can be split into. Macros are held to it too.
+pub const MIN_SPLIT_VOICES: u16 = 2;
+pub const MAX_SPLIT_VOICES: u16 = 1024;
+
+/// Splits one track into several by key range, velocity, channel or overlapping voices.
+pub struct SplitTrackDialog {
+    track: u16,
+    mode: TrackSplitMode,
+
+    key_points: Vec<NumericField<MIDIKey>>,
+    velocity_points: Vec<NumericField<u8>>,
+    max_voices: NumericField<u16>,
+
+    track_editing: Arc<Mutex<TrackEditing>>,
+}
+
+impl Default for SplitTrackDialog {
+    fn default() -> Self {
+        Self::new(&Default::default())
+    }
+}
+
+impl SplitTrackDialog {
+    pub fn new(track_editing: &Arc<Mutex<TrackEditing>>) -> Self {
+        Self {
+            track: 0,
+            mode: TrackSplitMode::Key(Vec::new()),
+
+            key_points: vec![NumericField::new(60, Some(0), Some(127))],
+            velocity_points: vec![NumericField::new(64, Some(1), Some(127))],
+            max_voices: NumericField::new(16, Some(MIN_SPLIT_VOICES), Some(MAX_SPLIT_VOICES)),
+
+            track_editing: track_editing.clone()
+        }
+    }
+
+    fn build_mode(&self) -> TrackSplitMode {
+        let sorted_points = |points: &[NumericField<u8>]| {
+            let mut points: Vec<u8> = points.iter().map(|p| p.value()).collect();
+            points.sort_unstable();
+            points.dedup();
+            points
+        };
+
+        match self.mode {
+            TrackSplitMode::Key(_) => TrackSplitMode::Key(sorted_points(&self.key_points)),
+            TrackSplitMode::Velocity(_) => TrackSplitMode::Velocity(sorted_points(&self.velocity_points)),
+            TrackSplitMode::Channel => TrackSplitMode::Channel,
+            TrackSplitMode::Voices(_) => TrackSplitMode::Voices(self.max_voices.value())
+        }
+    }
+
+    /// Shows an editable list of split points. `point_label` describes where each layer starts.
+    fn draw_split_points(ui: &mut egui::Ui, points: &mut Vec<NumericField<u8>>, default_point: u8, point_label: impl Fn(u8) -> String) {
+        let mut to_remove = None;
+        for (i, point) in points.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                point.show(&format!("Split {}:", i + 1), ui, Some(40.0));
+                ui.label(point_label(point.value()));
+                if ui.button("Remove").clicked() { to_remove = Some(i); }
+            });
+        }
+
+        if let Some(i) = to_remove { points.remove(i); }
+
+        if ui.button("Add split point").clicked() {
+            let mut point = NumericField::new(default_point, Some(0), Some(127));
+            if let Some(last) = points.last() { point.set_value(last.value().saturating_add(12).min(127)); }
+            points.push(point);
+        }
+    }
+}
+
+impl Dialog for SplitTrackDialog {
+    /// args: track to split (u16)
+    fn init_dialog(&mut self, args: Vec<Box<dyn std::any::Any>>) -> Result<(), &'static str> {
+        self.track = *args[0].downcast_ref::<u16>().unwrap();
+        Ok(())
+    }
+
+    fn draw(&mut self, ui: &mut egui::Ui, _: &ImageResources) -> Option<DialogAction> {
+        ui.label(format!("Splitting track {}", self.track));
+
+        ui.horizontal(|ui| {
+            ui.label("Split by:");
+            for mode in [TrackSplitMode::Key(Vec::new()), TrackSplitMode::Velocity(Vec::new()), TrackSplitMode::Channel, TrackSplitMode::Voices(0)] {
+                let selected = discriminant(&self.mode) == discriminant(&mode);
+                if ui.radio(selected, mode.to_string()).clicked() { self.mode = mode; }
+            }
+        });
+        ui.separator();
+
+        match self.mode {
+            TrackSplitMode::Key(_) => {
+                ui.label("Each split point starts a new track from that key upwards.");
+                Self::draw_split_points(ui, &mut self.key_points, 60, |key| format!("({} and up)", key_to_name(key)));
+            },
+            TrackSplitMode::Velocity(_) => {
+                ui.label("Each split point starts a new track from that velocity upwards.");
+                Self::draw_split_points(ui, &mut self.velocity_points, 64, |velocity| format!("(velocity {} and up)", velocity));
+            },
+            TrackSplitMode::Channel => {
+                ui.label("Each channel used in the track gets its own track.");
+            },
+            TrackSplitMode::Voices(_) => {
+                ui.label("Overlapping notes are moved into separate tracks.");
+                self.max_voices.show("Max. tracks:", ui, Some(50.0));
+                ui.label("Once every track is taken, the remaining notes go into the last one.");
+            }
+        }
+
+        ui.separator();
+        ui.label("Channel events and metas stay in the first track.");
+        None
+    }
+
+    fn get_action_buttons(&self) -> Option<DialogActionButtons> {
+        Some(DialogActionButtons::OkCancel(
+            Box::new(|dlg| {
+                let dlg = dlg.as_any_mut().downcast_mut::<Self>().unwrap();
+                let mode = dlg.build_mode();
+                {
+                    let mut track_editing = dlg.track_editing.lock().unwrap();
+                    track_editing.split_track(dlg.track, &mode);
+                }
+                Some(DialogAction::Close(dlg.get_dialog_name()))
+            }),
+            dialog_default_close_action()
+        ))
+    }
+
+    fn get_dialog_name(&self) -> &'static str {
+        DIALOG_NAME_SPLIT_TRACK
+    }
+
+    fn get_dialog_title(&self) -> String {
+        "Split track".into()
+    }
+}
@@ -0,0 +1,204 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use eframe::egui;
+
+use crate::{app::{custom_widgets::{NumberField, NumericField}, ui::dialog::{Dialog, DialogAction, DialogActionButtons, dialog_default_close_action, names::DIALOG_NAME_PASTE_SPECIAL}, util::image_loader::ImageResources}, editor::{editing::{PasteMode, SharedClipboard, SharedSelectedNotes, note_editing::NoteEditing}, midi_bar_cacher::BarCacher, util::MIDITick}, midi::events::note::Note};
+
+/// Pastes the clipboard with more control than a regular paste: repeats, replacing, remapping tracks,
+/// or only taking over velocities/lengths.
+pub struct PasteSpecialDialog {
+    mode: PasteMode,
+    paste_tick: NumericField<MIDITick>,
+    repeat_count: NumericField<u32>,
+    bar_aligned: bool,
+
+    // (clipboard track, destination track)
+    track_map: Vec<(u16, NumericField<u16>)>,
+    clip_length: MIDITick,
+    playhead_tick: MIDITick,
+
+    note_editing: Arc<Mutex<NoteEditing>>,
+    shared_clipboard: Arc<RwLock<SharedClipboard>>,
+    shared_selected_notes: Arc<RwLock<SharedSelectedNotes>>,
+    bar_cacher: Arc<Mutex<BarCacher>>,
+}
+
+impl Default for PasteSpecialDialog {
+    fn default() -> Self {
+        Self::new(&Default::default(), &Default::default(), &Default::default(), &Default::default())
+    }
+}
+
+impl PasteSpecialDialog {
+    pub fn new(
+        note_editing: &Arc<Mutex<NoteEditing>>,
+        shared_clipboard: &Arc<RwLock<SharedClipboard>>,
+        shared_selected_notes: &Arc<RwLock<SharedSelectedNotes>>,
+        bar_cacher: &Arc<Mutex<BarCacher>>
+    ) -> Self {
+        Self {
+            mode: PasteMode::Merge,
+            paste_tick: NumericField::new(0, Some(0), None),
+            repeat_count: NumericField::new(1, Some(1), Some(1000)),
+            bar_aligned: false,
+
+            track_map: Vec::new(),
+            clip_length: 0,
+            playhead_tick: 0,
+
+            note_editing: note_editing.clone(),
+            shared_clipboard: shared_clipboard.clone(),
+            shared_selected_notes: shared_selected_notes.clone(),
+            bar_cacher: bar_cacher.clone()
+        }
+    }
+
+    /// Where each repeat starts. Without bar alignment the repeats are back to back,
+    /// otherwise each one waits for the next bar line after the previous one ends.
+    fn get_paste_ticks(&self) -> Vec<MIDITick> {
+        let count = self.repeat_count.value() as usize;
+        let mut ticks = Vec::with_capacity(count);
+        let mut tick = self.paste_tick.value();
+
+        let mut bar_cacher = self.bar_cacher.lock().unwrap();
+        let mut bar_num = 0;
+
+        for i in 0..count {
+            if i > 0 {
+                tick = tick.saturating_add(self.clip_length);
+                if self.bar_aligned {
+                    loop {
+                        let (bar_tick, bar_length) = bar_cacher.get_bar_interval(bar_num);
+                        if bar_tick >= tick || bar_length == 0 { tick = tick.max(bar_tick); break; }
+                        bar_num += 1;
+                    }
+                }
+            }
+            ticks.push(tick);
+        }
+
+        ticks
+    }
+
+    fn apply_paste(&self) {
+        let clipboard = {
+            let shared_clipboard = self.shared_clipboard.read().unwrap();
+            shared_clipboard.get_notes_from_clipboard()
+        };
+
+        let mut note_editing = self.note_editing.lock().unwrap();
+        match self.mode {
+            PasteMode::Merge | PasteMode::Replace => {
+                let dest_tracks: Vec<u16> = self.track_map.iter().map(|(_, dest)| dest.value()).collect();
+                note_editing.paste_notes_repeated(clipboard, &dest_tracks, &self.get_paste_ticks(), self.mode == PasteMode::Replace);
+            },
+            PasteMode::VelocitiesOnly | PasteMode::LengthsOnly => {
+                let mut source: Vec<Note> = clipboard.into_iter().flat_map(|(_, notes)| notes).collect();
+                source.sort_by_key(|n| n.start());
+
+                let tracks = {
+                    let shared_selected = self.shared_selected_notes.read().unwrap();
+                    shared_selected.get_active_selected_tracks()
+                };
+                note_editing.paste_note_properties(&source, &tracks, self.mode);
+            }
+        }
+    }
+}
+
+impl Dialog for PasteSpecialDialog {
+    /// args: playhead tick (MIDITick), current track (u16)
+    fn init_dialog(&mut self, args: Vec<Box<dyn std::any::Any>>) -> Result<(), &'static str> {
+        self.playhead_tick = *args[0].downcast_ref::<MIDITick>().unwrap();
+        let curr_track = *args[1].downcast_ref::<u16>().unwrap();
+
+        let clipboard = {
+            let shared_clipboard = self.shared_clipboard.read().unwrap();
+            shared_clipboard.get_notes_from_clipboard()
+        };
+        if clipboard.is_empty() { return Err("The clipboard is empty."); }
+
+        let clip_start = clipboard.iter().filter_map(|(_, notes)| notes.first()).map(|n| n.start()).min().unwrap_or(0);
+        let clip_end = clipboard.iter().flat_map(|(_, notes)| notes.iter()).map(|n| n.end()).max().unwrap_or(clip_start);
+        self.clip_length = clip_end - clip_start;
+
+        // pasting can add tracks, but only enough for every copied track to get a new one
+        let track_count = self.note_editing.lock().unwrap().get_tracks().read().unwrap().len();
+        let max_track = (track_count + clipboard.len()).saturating_sub(1).min(u16::MAX as usize) as u16;
+
+        // same layout as a regular multi-track paste: the first copied track lands on the current track
+        let first_track = clipboard[0].0;
+        self.track_map = clipboard.iter()
+            .map(|(track, _)| (*track, NumericField::new(curr_track + (track - first_track), Some(0), Some(max_track))))
+            .collect();
+
+        self.paste_tick.set_value(self.playhead_tick);
+        Ok(())
+    }
+
+    fn draw(&mut self, ui: &mut egui::Ui, _: &ImageResources) -> Option<DialogAction> {
+        egui::ComboBox::from_id_salt("paste_special_mode")
+            .selected_text(self.mode.to_string())
+            .show_ui(ui, |ui| {
+                for mode in [PasteMode::Merge, PasteMode::Replace, PasteMode::VelocitiesOnly, PasteMode::LengthsOnly] {
+                    ui.selectable_value(&mut self.mode, mode, mode.to_string());
+                }
+            });
+        ui.separator();
+
+        if matches!(self.mode, PasteMode::VelocitiesOnly | PasteMode::LengthsOnly) {
+            ui.label("The copied notes are applied to the selected notes in order.");
+            ui.label("If more notes are selected than copied, the copied pattern repeats.");
+            return None;
+        }
+
+        ui.horizontal(|ui| {
+            self.paste_tick.show("Paste at tick:", ui, Some(80.0));
+            if ui.button("Playhead").clicked() { self.paste_tick.set_value(self.playhead_tick); }
+        });
+
+        self.repeat_count.show("Repeat count:", ui, Some(50.0));
+        ui.horizontal(|ui| {
+            ui.label("Spacing:");
+            ui.radio_value(&mut self.bar_aligned, false, format!("Clip length ({} ticks)", self.clip_length));
+            ui.radio_value(&mut self.bar_aligned, true, "Next bar after the clip");
+        });
+
+        ui.separator();
+        if let [(_, dest)] = self.track_map.as_mut_slice() {
+            dest.show("Target track:", ui, Some(50.0));
+        } else {
+            ui.label("Target tracks:");
+            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                for (src, dest) in self.track_map.iter_mut() {
+                    dest.show(&format!("Copied from track {} ->", src), ui, Some(50.0));
+                }
+            });
+        }
+
+        if self.mode == PasteMode::Replace {
+            ui.label("Notes starting inside each pasted range are removed first.");
+        }
+
+        None
+    }
+
+    fn get_action_buttons(&self) -> Option<DialogActionButtons> {
+        Some(DialogActionButtons::OkCancel(
+            Box::new(|dlg| {
+                let dlg = dlg.as_any_mut().downcast_mut::<Self>().unwrap();
+                dlg.apply_paste();
+                Some(DialogAction::Close(dlg.get_dialog_name()))
+            }),
+            dialog_default_close_action()
+        ))
+    }
+
+    fn get_dialog_name(&self) -> &'static str {
+        DIALOG_NAME_PASTE_SPECIAL
+    }
+
+    fn get_dialog_title(&self) -> String {
+        "Paste special".into()
+    }
+}
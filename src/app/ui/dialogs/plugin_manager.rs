@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use eframe::egui::{self, Color32, RichText};
+
+use crate::{app::{ui::dialog::{Dialog, DialogAction, DialogActionButtons, dialog_default_close_action, names::DIALOG_NAME_PLUGIN_MANAGER}, util::image_loader::ImageResources}, editor::plugins::{plugin_console::PluginConsole, plugin_package::{PACKAGES_FOLDER_NAME, PackageIndex, PluginPackage, install_package, set_package_enabled}}};
+
+/// Lists the plugin packages, installs new ones and turns them on and off.
+/// Changes get picked up by the plugin loader on the next frame.
+pub struct PluginManagerDialog {
+    plugins_path: PathBuf,
+    index: PackageIndex,
+    // what the last install or toggle did
+    status: Option<Result<String, String>>
+}
+
+impl Default for PluginManagerDialog {
+    fn default() -> Self {
+        Self::new(PathBuf::new())
+    }
+}
+
+impl PluginManagerDialog {
+    pub fn new(plugins_path: PathBuf) -> Self {
+        let index = PackageIndex::scan(&plugins_path);
+        Self { plugins_path, index, status: None }
+    }
+
+    fn rescan(&mut self) {
+        self.index = PackageIndex::scan(&self.plugins_path);
+    }
+
+    fn install_from(&mut self, source: &Path) {
+        self.status = Some(match install_package(&self.plugins_path, source) {
+            Ok(manifest) => {
+                let message = format!("installed {} {}", manifest.name, manifest.version);
+                PluginConsole::log_info("plugin manager", &message);
+                Ok(message)
+            },
+            Err(err) => Err(format!("Couldn't install {}: {}", source.display(), err))
+        });
+        self.rescan();
+    }
+
+    fn set_enabled(&mut self, id: &str, enabled: bool) {
+        if let Err(err) = set_package_enabled(&self.plugins_path, id, enabled) {
+            self.status = Some(Err(format!("Couldn't save the package state: {}", err)));
+        }
+        self.rescan();
+    }
+
+    fn draw_package_details(ui: &mut egui::Ui, package: &PluginPackage) {
+        let manifest = &package.manifest;
+        ui.label(format!("Id: {}", manifest.id));
+        ui.label(format!("Folder: {}", package.root.display()));
+        if let Some(min_editor_version) = manifest.min_editor_version {
+            ui.label(format!("Needs editor {} or newer", min_editor_version));
+        }
+        for (dep_id, dep_version) in manifest.dependencies.iter() {
+            ui.label(format!("Needs {} {} or newer", dep_id, dep_version));
+        }
+        for capability in manifest.capabilities.iter() {
+            ui.label(capability.describe());
+        }
+    }
+}
+
+impl Dialog for PluginManagerDialog {
+    fn draw(&mut self, ui: &mut egui::Ui, _: &ImageResources) -> Option<DialogAction> {
+        ui.horizontal(|ui| {
+            if ui.button("Install from archive...").clicked() {
+                if let Some(archive) = rfd::FileDialog::new().add_filter("Plugin package", &["zip"]).pick_file() {
+                    self.install_from(&archive);
+                }
+            }
+            if ui.button("Install from folder...").clicked() {
+                if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                    self.install_from(&folder);
+                }
+            }
+            if ui.button("Refresh").clicked() { self.rescan(); }
+        });
+        ui.label(RichText::new(format!("Packages get installed to {}. Loose .lua files in the plugin folder are always loaded.",
+            self.plugins_path.join(PACKAGES_FOLDER_NAME).display())).small().color(Color32::GRAY));
+        ui.separator();
+
+        let mut toggled = None;
+        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            if self.index.packages.is_empty() {
+                ui.label("No packages installed.");
+                return;
+            }
+
+            egui::Grid::new("plugin_packages").striped(true).num_columns(4).show(ui, |ui| {
+                ui.label(RichText::new("On").strong());
+                ui.label(RichText::new("Package").strong());
+                ui.label(RichText::new("Version").strong());
+                ui.label(RichText::new("Status").strong());
+                ui.end_row();
+
+                for (i, package) in self.index.packages.iter().enumerate() {
+                    let mut enabled = package.enabled;
+                    if ui.checkbox(&mut enabled, "").changed() {
+                        toggled = Some((package.manifest.id.clone(), enabled));
+                    }
+
+                    ui.label(&package.manifest.name).on_hover_ui(|ui| Self::draw_package_details(ui, package));
+                    ui.label(package.manifest.version.to_string());
+
+                    ui.vertical(|ui| {
+                        if self.index.is_loaded(i) {
+                            ui.colored_label(Color32::LIGHT_GREEN, "Loaded");
+                        }
+                        for problem in self.index.get_problems(i) {
+                            let color = if problem.is_conflict() { Color32::LIGHT_RED } else { Color32::GRAY };
+                            ui.colored_label(color, problem.describe());
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+
+            if !self.index.broken.is_empty() {
+                ui.separator();
+                ui.label("These folders have a manifest that couldn't be read:");
+                for (folder, err) in self.index.broken.iter() {
+                    ui.colored_label(Color32::LIGHT_RED, format!("{}: {}", folder.display(), err));
+                }
+            }
+        });
+
+        if let Some((id, enabled)) = toggled {
+            self.set_enabled(&id, enabled);
+        }
+
+        match &self.status {
+            Some(Ok(message)) => { ui.label(message); },
+            Some(Err(err)) => { ui.colored_label(Color32::LIGHT_RED, err); },
+            None => {}
+        }
+        None
+    }
+
+    fn get_action_buttons(&self) -> Option<DialogActionButtons> {
+        Some(DialogActionButtons::Ok(dialog_default_close_action()))
+    }
+
+    fn get_dialog_name(&self) -> &'static str {
+        DIALOG_NAME_PLUGIN_MANAGER
+    }
+
+    fn get_dialog_title(&self) -> String {
+        "Plugin packages".into()
+    }
+}
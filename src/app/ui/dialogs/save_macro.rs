@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+
+use chrono::Local;
+use eframe::egui;
+
+use crate::{app::{ui::dialog::{Dialog, DialogAction, DialogActionButtons, names::DIALOG_NAME_SAVE_MACRO}, util::image_loader::ImageResources}, editor::plugins::{plugin_console::PluginConsole, plugin_macro::{MacroStep, save_macro_plugin}}};
+
+/// Names a finished macro recording and writes it out as a plugin.
+pub struct SaveMacroDialog {
+    name: String,
+    steps: Vec<MacroStep>,
+    folder: PathBuf,
+    error: Option<String>
+}
+
+impl Default for SaveMacroDialog {
+    fn default() -> Self {
+        Self::new(Vec::new(), PathBuf::new())
+    }
+}
+
+impl SaveMacroDialog {
+    /// `folder` is where the plugin file goes, normally the custom plugin folder.
+    pub fn new(steps: Vec<MacroStep>, folder: PathBuf) -> Self {
+        Self {
+            name: format!("Macro {}", Local::now().format("%Y-%m-%d %H:%M")),
+            steps,
+            folder,
+            error: None
+        }
+    }
+}
+
+impl Dialog for SaveMacroDialog {
+    fn draw(&mut self, ui: &mut egui::Ui, _: &ImageResources) -> Option<DialogAction> {
+        ui.horizontal(|ui| {
+            ui.label("Name:");
+            ui.text_edit_singleline(&mut self.name);
+        });
+        ui.label("It shows up under Plugins > Manipulate once saved.");
+        ui.separator();
+
+        ui.label(format!("{} recorded steps:", self.steps.len()));
+        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+            for (i, step) in self.steps.iter().enumerate() {
+                ui.label(egui::RichText::new(format!("{}. {}", i + 1, step.describe())).monospace());
+            }
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+        None
+    }
+
+    fn get_action_buttons(&self) -> Option<DialogActionButtons> {
+        Some(DialogActionButtons::OkCancel(
+            Box::new(|dlg| {
+                let dlg = dlg.as_any_mut().downcast_mut::<Self>().unwrap();
+
+                let name = dlg.name.trim().to_string();
+                if name.is_empty() {
+                    dlg.error = Some("The macro needs a name.".into());
+                    return None;
+                }
+
+                match save_macro_plugin(&dlg.folder, &name, &dlg.steps) {
+                    Ok(path) => {
+                        PluginConsole::log_info("macro recorder", &format!("saved \"{}\" to {}", name, path.display()));
+                        Some(DialogAction::Close(dlg.get_dialog_name()))
+                    },
+                    Err(err) => {
+                        dlg.error = Some(format!("Couldn't save the macro: {}", err));
+                        None
+                    }
+                }
+            }),
+            Box::new(|dlg| {
+                PluginConsole::log_info("macro recorder", "recording thrown away");
+                Some(DialogAction::Close(dlg.get_dialog_name()))
+            })
+        ))
+    }
+
+    fn get_dialog_name(&self) -> &'static str {
+        DIALOG_NAME_SAVE_MACRO
+    }
+
+    fn get_dialog_title(&self) -> String {
+        "Save macro".into()
+    }
+}
@@ -0,0 +1,337 @@
+use std::{cell::RefCell, rc::Rc, sync::{Arc, Mutex, RwLock}};
+
+use eframe::egui;
+
+use crate::{app::{custom_widgets::{NumberField, NumericField}, ui::dialog::{Dialog, DialogAction, DialogActionButtons, dialog_default_close_action, names::DIALOG_NAME_SELECT_NOTES}, util::image_loader::ImageResources}, editor::{actions::{EditorAction, EditorActions}, editing::{SelectionOp, SharedSelectedNotes, note_editing::{NoteEditing, note_sequence_funcs::{exclude, intersect, merge_unique}}}, midi_bar_cacher::BarCacher, util::{MIDIKey, MIDITick, key_to_name}}, midi::events::note::Note, util::debugger::Debugger};
+
+/// Replaces the selection in `track` with `new_ids`, returning the select/deselect actions needed to undo it.
+pub fn replace_selection_in_track(shared_selected: &mut SharedSelectedNotes, track: u16, new_ids: Vec<usize>) -> Vec<EditorAction> {
+    let old_ids = shared_selected.take_selected_from_track(track);
+
+    let deselected = exclude(old_ids.clone(), &new_ids);
+    let selected = exclude(new_ids.clone(), &old_ids);
+    shared_selected.set_selected_in_track(new_ids, track);
+
+    let mut actions = Vec::with_capacity(2);
+    if !deselected.is_empty() { actions.push(EditorAction::Deselect(deselected, track)); }
+    if !selected.is_empty() { actions.push(EditorAction::Select(selected, track)); }
+    actions
+}
+
+pub struct SelectNotesDialog {
+    selection_op: SelectionOp,
+    current_track_only: bool,
+
+    use_velocity: bool,
+    velocity_min: NumericField<u8>,
+    velocity_max: NumericField<u8>,
+
+    use_key: bool,
+    key_min: NumericField<MIDIKey>,
+    key_max: NumericField<MIDIKey>,
+    same_key_as_clicked: bool,
+    clicked_key: Option<MIDIKey>,
+
+    use_length: bool,
+    length_min: NumericField<MIDITick>,
+    length_max: NumericField<MIDITick>,
+
+    use_channels: bool,
+    channels: [bool; 16],
+
+    use_range: bool,
+    range_in_bars: bool,
+    range_start: NumericField<MIDITick>,
+    range_end: NumericField<MIDITick>,
+
+    overlapping_only: bool,
+    use_every_nth: bool,
+    every_nth: NumericField<usize>,
+    every_nth_offset: NumericField<usize>,
+    invert: bool,
+
+    note_editing: Arc<Mutex<NoteEditing>>,
+    shared_selected_notes: Arc<RwLock<SharedSelectedNotes>>,
+    editor_actions: Rc<RefCell<EditorActions>>,
+    bar_cacher: Arc<Mutex<BarCacher>>,
+}
+
+impl Default for SelectNotesDialog {
+    fn default() -> Self {
+        Self::new(&Default::default(), &Default::default(), &Default::default(), &Default::default())
+    }
+}
+
+impl SelectNotesDialog {
+    pub fn new(
+        note_editing: &Arc<Mutex<NoteEditing>>,
+        shared_selected_notes: &Arc<RwLock<SharedSelectedNotes>>,
+        editor_actions: &Rc<RefCell<EditorActions>>,
+        bar_cacher: &Arc<Mutex<BarCacher>>
+    ) -> Self {
+        Self {
+            selection_op: SelectionOp::NewSelection,
+            current_track_only: false,
+
+            use_velocity: false,
+            velocity_min: NumericField::new(1, Some(1), Some(127)),
+            velocity_max: NumericField::new(127, Some(1), Some(127)),
+
+            use_key: false,
+            key_min: NumericField::new(0, Some(0), Some(127)),
+            key_max: NumericField::new(127, Some(0), Some(127)),
+            same_key_as_clicked: false,
+            clicked_key: None,
+
+            use_length: false,
+            length_min: NumericField::new(0, Some(0), None),
+            length_max: NumericField::new(960, Some(0), None),
+
+            use_channels: false,
+            channels: [true; 16],
+
+            use_range: false,
+            range_in_bars: true,
+            range_start: NumericField::new(1, Some(0), None),
+            range_end: NumericField::new(4, Some(0), None),
+
+            overlapping_only: false,
+            use_every_nth: false,
+            every_nth: NumericField::new(2, Some(1), None),
+            every_nth_offset: NumericField::new(0, Some(0), None),
+            invert: false,
+
+            note_editing: note_editing.clone(),
+            shared_selected_notes: shared_selected_notes.clone(),
+            editor_actions: editor_actions.clone(),
+            bar_cacher: bar_cacher.clone()
+        }
+    }
+
+    /// Gets the tick range from the range fields. The end is exclusive.
+    fn get_tick_range(&self) -> (MIDITick, MIDITick) {
+        let (start, end) = (self.range_start.value(), self.range_end.value());
+
+        if !self.range_in_bars { return (start, end); }
+
+        // bars are shown 1-indexed and both ends are included
+        let mut bar_cacher = self.bar_cacher.lock().unwrap();
+        let (start_tick, _) = bar_cacher.get_bar_interval(start.max(1) as usize - 1);
+        let (end_bar_tick, end_bar_len) = bar_cacher.get_bar_interval(end.max(start).max(1) as usize - 1);
+        (start_tick, end_bar_tick + end_bar_len)
+    }
+
+    /// Returns the ids of every note in `notes` matching the query, in ascending order.
+    fn query_notes(&self, notes: &[Note], tick_range: (MIDITick, MIDITick)) -> Vec<usize> {
+        let overlapping = if self.overlapping_only { get_overlapping_notes(notes) } else { Vec::new() };
+        let key_filter = if self.same_key_as_clicked { self.clicked_key } else { None };
+
+        let mut matched: Vec<usize> = notes.iter().enumerate().filter(|(id, note)| {
+            if self.use_velocity && !(self.velocity_min.value()..=self.velocity_max.value()).contains(&note.velocity()) { return false; }
+            if self.use_key && !(self.key_min.value()..=self.key_max.value()).contains(&note.key()) { return false; }
+            if key_filter.map_or(false, |key| key != note.key()) { return false; }
+            if self.use_length && !(self.length_min.value()..=self.length_max.value()).contains(&note.length()) { return false; }
+            if self.use_channels && !self.channels[note.channel() as usize] { return false; }
+            if self.use_range && !(tick_range.0..tick_range.1).contains(&note.start()) { return false; }
+            if self.overlapping_only && overlapping.binary_search(id).is_err() { return false; }
+            true
+        }).map(|(id, _)| id).collect();
+
+        if self.use_every_nth {
+            let (nth, offset) = (self.every_nth.value(), self.every_nth_offset.value());
+            matched = matched.into_iter().skip(offset).step_by(nth).collect();
+        }
+
+        if self.invert {
+            matched = exclude((0..notes.len()).collect(), &matched);
+        }
+
+        matched
+    }
+
+    fn apply_query(&self) {
+        let tick_range = if self.use_range { self.get_tick_range() } else { (0, MIDITick::MAX) };
+
+        let note_editing = self.note_editing.lock().unwrap();
+        let tracks = note_editing.get_tracks();
+        let tracks = tracks.read().unwrap();
+
+        let target_tracks: Vec<u16> = if self.current_track_only { vec![note_editing.get_current_track()] }
+            else { (0..tracks.len() as u16).collect() };
+
+        let mut shared_selected = self.shared_selected_notes.write().unwrap();
+        let mut actions = Vec::new();
+        let mut num_selected = 0;
+
+        for track in target_tracks {
+            let Some(track_data) = tracks.get(track as usize) else { continue; };
+
+            let matched = self.query_notes(track_data.get_notes(), tick_range);
+            let old_ids = shared_selected.get_selected_ids_in_track(track).cloned().unwrap_or_default();
+
+            let new_ids = match self.selection_op {
+                SelectionOp::NewSelection => matched,
+                SelectionOp::AppendSelection => merge_unique(old_ids, matched),
+                SelectionOp::RemoveFromSelection => exclude(old_ids, &matched),
+                SelectionOp::IntersectSelection => intersect(old_ids, &matched)
+            };
+
+            num_selected += new_ids.len();
+            actions.extend(replace_selection_in_track(&mut shared_selected, track, new_ids));
+        }
+
+        Debugger::log(format!("{} notes selected.", num_selected));
+
+        if !actions.is_empty() {
+            let mut editor_actions = self.editor_actions.borrow_mut();
+            editor_actions.register_action(EditorAction::Bulk(actions));
+        }
+    }
+}
+
+/// Ids of notes that overlap another note on the same key, in ascending order.
+fn get_overlapping_notes(notes: &[Note]) -> Vec<usize> {
+    // per key: (furthest end so far, the note it belongs to)
+    let mut furthest_ends: [Option<(MIDITick, usize)>; 128] = [None; 128];
+    let mut overlapping = Vec::new();
+
+    for (id, note) in notes.iter().enumerate() {
+        let key = note.key() as usize;
+
+        match furthest_ends[key] {
+            Some((end, owner)) if note.start() < end => {
+                overlapping.push(owner);
+                overlapping.push(id);
+                if note.end() > end { furthest_ends[key] = Some((note.end(), id)); }
+            },
+            _ => furthest_ends[key] = Some((note.end(), id))
+        }
+    }
+
+    overlapping.sort_unstable();
+    overlapping.dedup();
+    overlapping
+}
+
+impl Dialog for SelectNotesDialog {
+    fn init_dialog(&mut self, args: Vec<Box<dyn std::any::Any>>) -> Result<(), &'static str> {
+        self.clicked_key = args.get(0).and_then(|a| a.downcast_ref::<Option<MIDIKey>>()).copied().flatten();
+        Ok(())
+    }
+
+    fn draw(&mut self, ui: &mut egui::Ui, _: &ImageResources) -> Option<DialogAction> {
+        ui.horizontal(|ui| {
+            ui.label("Matching notes:");
+            egui::ComboBox::from_id_salt("select_notes_op")
+                .selected_text(match self.selection_op {
+                    SelectionOp::NewSelection => "Replace selection",
+                    SelectionOp::AppendSelection => "Add to selection",
+                    SelectionOp::RemoveFromSelection => "Remove from selection",
+                    SelectionOp::IntersectSelection => "Intersect with selection",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.selection_op, SelectionOp::NewSelection, "Replace selection");
+                    ui.selectable_value(&mut self.selection_op, SelectionOp::AppendSelection, "Add to selection");
+                    ui.selectable_value(&mut self.selection_op, SelectionOp::RemoveFromSelection, "Remove from selection");
+                    ui.selectable_value(&mut self.selection_op, SelectionOp::IntersectSelection, "Intersect with selection");
+                });
+        });
+        ui.checkbox(&mut self.current_track_only, "Current track only");
+        ui.separator();
+
+        egui::Grid::new("select_notes_grid").num_columns(2).show(ui, |ui| {
+            ui.checkbox(&mut self.use_velocity, "Velocity");
+            ui.add_enabled_ui(self.use_velocity, |ui| {
+                ui.horizontal(|ui| {
+                    self.velocity_min.show("from", ui, Some(40.0));
+                    self.velocity_max.show("to", ui, Some(40.0));
+                });
+            });
+            ui.end_row();
+
+            ui.checkbox(&mut self.use_key, "Key");
+            ui.add_enabled_ui(self.use_key, |ui| {
+                ui.horizontal(|ui| {
+                    self.key_min.show("from", ui, Some(40.0));
+                    self.key_max.show("to", ui, Some(40.0));
+                    ui.label(format!("({} - {})", key_to_name(self.key_min.value()), key_to_name(self.key_max.value())));
+                });
+            });
+            ui.end_row();
+
+            ui.checkbox(&mut self.use_length, "Length");
+            ui.add_enabled_ui(self.use_length, |ui| {
+                ui.horizontal(|ui| {
+                    self.length_min.show("from", ui, Some(60.0));
+                    self.length_max.show("to", ui, Some(60.0));
+                    ui.label("ticks");
+                });
+            });
+            ui.end_row();
+
+            ui.checkbox(&mut self.use_range, "Position");
+            ui.add_enabled_ui(self.use_range, |ui| {
+                ui.horizontal(|ui| {
+                    self.range_start.show("from", ui, Some(60.0));
+                    self.range_end.show("to", ui, Some(60.0));
+                    ui.radio_value(&mut self.range_in_bars, true, "bars");
+                    ui.radio_value(&mut self.range_in_bars, false, "ticks");
+                });
+            });
+            ui.end_row();
+
+            ui.checkbox(&mut self.use_channels, "Channels");
+            ui.add_enabled_ui(self.use_channels, |ui| {
+                ui.vertical(|ui| {
+                    for row in 0..2 {
+                        ui.horizontal(|ui| {
+                            for chan in (row * 8)..(row * 8 + 8) {
+                                ui.checkbox(&mut self.channels[chan], (chan + 1).to_string());
+                            }
+                        });
+                    }
+                });
+            });
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.add_enabled_ui(self.clicked_key.is_some(), |ui| {
+            let label = match self.clicked_key {
+                Some(key) => format!("Same key as clicked note ({})", key_to_name(key)),
+                None => "Same key as clicked note (no note clicked)".into()
+            };
+            ui.checkbox(&mut self.same_key_as_clicked, label);
+        });
+        ui.checkbox(&mut self.overlapping_only, "Only overlapping notes");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.use_every_nth, "Every");
+            ui.add_enabled_ui(self.use_every_nth, |ui| {
+                self.every_nth.show("", ui, Some(30.0));
+                self.every_nth_offset.show("th note, starting at", ui, Some(30.0));
+            });
+        });
+        ui.checkbox(&mut self.invert, "Invert (match notes that don't fit the query)");
+
+        None
+    }
+
+    fn get_action_buttons(&self) -> Option<DialogActionButtons> {
+        Some(DialogActionButtons::OkCancel(
+            Box::new(|dlg| {
+                let dlg = dlg.as_any_mut().downcast_mut::<Self>().unwrap();
+                dlg.apply_query();
+                Some(DialogAction::Close(dlg.get_dialog_name()))
+            }),
+            dialog_default_close_action()
+        ))
+    }
+
+    fn get_dialog_name(&self) -> &'static str {
+        DIALOG_NAME_SELECT_NOTES
+    }
+
+    fn get_dialog_title(&self) -> String {
+        "Select notes".into()
+    }
+}
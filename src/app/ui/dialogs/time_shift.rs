@@ -0,0 +1,182 @@
+use std::{cell::RefCell, rc::Rc, sync::{Arc, Mutex, RwLock}};
+
+use eframe::egui;
+
+use crate::{app::{custom_widgets::{NumberField, NumericField}, ui::dialog::{Dialog, DialogAction, DialogActionButtons, dialog_default_close_action, names::DIALOG_NAME_TIME_SHIFT}, util::image_loader::ImageResources}, editor::{actions::{EditorAction, EditorActions}, editing::SharedSelectedNotes, midi_bar_cacher::BarCacher, project::{project_manager::ProjectManager, time_shift::{CrossingNoteHandling, TimeShift}}, util::MIDITick}, util::debugger::Debugger};
+
+/// Insert or delete a span of time in the whole project.
+pub struct TimeShiftDialog {
+    is_insert: bool,
+    start: NumericField<MIDITick>,
+    length: NumericField<MIDITick>,
+    crossing: CrossingNoteHandling,
+
+    playhead_tick: MIDITick,
+    selection_range: Option<(MIDITick, MIDITick)>,
+
+    project_manager: Arc<RwLock<ProjectManager>>,
+    editor_actions: Rc<RefCell<EditorActions>>,
+    shared_selected_notes: Arc<RwLock<SharedSelectedNotes>>,
+    bar_cacher: Arc<Mutex<BarCacher>>,
+}
+
+impl Default for TimeShiftDialog {
+    fn default() -> Self {
+        Self::new(&Default::default(), &Default::default(), &Default::default(), &Default::default())
+    }
+}
+
+impl TimeShiftDialog {
+    pub fn new(
+        project_manager: &Arc<RwLock<ProjectManager>>,
+        editor_actions: &Rc<RefCell<EditorActions>>,
+        shared_selected_notes: &Arc<RwLock<SharedSelectedNotes>>,
+        bar_cacher: &Arc<Mutex<BarCacher>>
+    ) -> Self {
+        Self {
+            is_insert: true,
+            start: NumericField::new(0, Some(0), None),
+            length: NumericField::new(0, Some(0), None),
+            crossing: CrossingNoteHandling::default(),
+
+            playhead_tick: 0,
+            selection_range: None,
+
+            project_manager: project_manager.clone(),
+            editor_actions: editor_actions.clone(),
+            shared_selected_notes: shared_selected_notes.clone(),
+            bar_cacher: bar_cacher.clone()
+        }
+    }
+
+    /// Length of the bar that `tick` falls in.
+    fn get_bar_length_at(&self, tick: MIDITick) -> MIDITick {
+        let mut bar_cacher = self.bar_cacher.lock().unwrap();
+        let mut bar_num = 0;
+        loop {
+            let (bar_tick, bar_length) = bar_cacher.get_bar_interval(bar_num);
+            if bar_tick + bar_length > tick || bar_length == 0 { return bar_length; }
+            bar_num += 1;
+        }
+    }
+
+    fn apply_shift(&self) {
+        let (start, length) = (self.start.value(), self.length.value());
+        if length == 0 { return; }
+
+        let shift = if self.is_insert { TimeShift::Insert(start, length, self.crossing) }
+            else { TimeShift::Delete(start, start.saturating_add(length)) };
+
+        let changes = {
+            let mut project_manager = self.project_manager.write().unwrap();
+            project_manager.shift_time(shift)
+        };
+
+        // note ids are all over the place now
+        {
+            let mut shared_selected = self.shared_selected_notes.write().unwrap();
+            shared_selected.clear_selected();
+        }
+
+        {
+            let mut bar_cacher = self.bar_cacher.lock().unwrap();
+            bar_cacher.clear_cache();
+        }
+
+        let mut editor_actions = self.editor_actions.borrow_mut();
+        editor_actions.register_action(EditorAction::ShiftTime(shift, Some(changes)));
+
+        Debugger::log(format!("{} {} ticks at tick {}", if self.is_insert { "Inserted" } else { "Deleted" }, length, start));
+    }
+}
+
+impl Dialog for TimeShiftDialog {
+    /// args: insert (bool), playhead tick, selection range (Option<(start, end)>)
+    fn init_dialog(&mut self, args: Vec<Box<dyn std::any::Any>>) -> Result<(), &'static str> {
+        self.is_insert = *args[0].downcast_ref::<bool>().unwrap();
+        self.playhead_tick = *args[1].downcast_ref::<MIDITick>().unwrap();
+        self.selection_range = *args[2].downcast_ref::<Option<(MIDITick, MIDITick)>>().unwrap();
+
+        // deleting defaults to the selection, inserting to one bar at the playhead
+        match (self.is_insert, self.selection_range) {
+            (false, Some((start, end))) => {
+                self.start.set_value(start);
+                self.length.set_value(end - start);
+            },
+            _ => {
+                self.start.set_value(self.playhead_tick);
+                self.length.set_value(self.get_bar_length_at(self.playhead_tick));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, ui: &mut egui::Ui, _: &ImageResources) -> Option<DialogAction> {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.is_insert, true, "Insert time");
+            ui.radio_value(&mut self.is_insert, false, "Delete time");
+        });
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            self.start.show("Start tick:", ui, Some(80.0));
+            if ui.button("Playhead").clicked() {
+                self.start.set_value(self.playhead_tick);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            self.length.show("Length (ticks):", ui, Some(80.0));
+            if ui.button("1 bar").clicked() {
+                let bar_length = self.get_bar_length_at(self.start.value());
+                self.length.set_value(bar_length);
+            }
+        });
+
+        ui.add_enabled_ui(self.selection_range.is_some(), |ui| {
+            if ui.button("Use selection range").clicked() {
+                let (start, end) = self.selection_range.unwrap();
+                self.start.set_value(start);
+                self.length.set_value(end - start);
+            }
+        });
+
+        if self.is_insert {
+            ui.horizontal(|ui| {
+                ui.label("Notes crossing the start:");
+                egui::ComboBox::from_id_salt("time_shift_crossing")
+                    .selected_text(self.crossing.to_string())
+                    .show_ui(ui, |ui| {
+                        for crossing in [CrossingNoteHandling::Split, CrossingNoteHandling::Extend, CrossingNoteHandling::Trim] {
+                            ui.selectable_value(&mut self.crossing, crossing, crossing.to_string());
+                        }
+                    });
+            });
+        } else {
+            ui.label("Notes crossing the range get the deleted part cut out.");
+        }
+
+        ui.label("Affects every track, tempo and time signature.");
+        None
+    }
+
+    fn get_action_buttons(&self) -> Option<DialogActionButtons> {
+        Some(DialogActionButtons::OkCancel(
+            Box::new(|dlg| {
+                let dlg = dlg.as_any_mut().downcast_mut::<Self>().unwrap();
+                dlg.apply_shift();
+                Some(DialogAction::Close(dlg.get_dialog_name()))
+            }),
+            dialog_default_close_action()
+        ))
+    }
+
+    fn get_dialog_name(&self) -> &'static str {
+        DIALOG_NAME_TIME_SHIFT
+    }
+
+    fn get_dialog_title(&self) -> String {
+        if self.is_insert { "Insert time".into() } else { "Delete time".into() }
+    }
+}
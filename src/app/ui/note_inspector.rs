@@ -0,0 +1,231 @@
+use std::{collections::HashMap, sync::{Arc, Mutex, RwLock}};
+
+use eframe::egui;
+
+use crate::{editor::{editing::note_editing::{NoteEditing, note_properties::{NoteProperty, NotePropertiesSummary, PropertyRange}}, midi_bar_cacher::BarCacher, project::project_manager::ProjectManager, util::{MIDIKey, MIDITick, key_to_name, name_to_key}}, util::expression_parser::eval_str};
+
+/// What got typed into a field. A leading `+` or `-` moves every note by that much, anything else sets every note to it.
+enum ValueInput {
+    Set(f64),
+    Offset(f64)
+}
+
+fn parse_value_input(input: &str) -> Result<ValueInput, String> {
+    let input = input.trim();
+    let (is_offset, expr) = match input.chars().next() {
+        Some('+') => (true, &input[1..]),
+        Some('-') => (true, input),
+        _ => (false, input)
+    };
+
+    let value = eval_str(expr).map_err(|err| err.to_string())?;
+    if !value.is_finite() { return Err(format!("'{}' isn't a number.", input)); }
+
+    Ok(if is_offset { ValueInput::Offset(value) } else { ValueInput::Set(value) })
+}
+
+fn format_range(range: PropertyRange, mut format: impl FnMut(i64) -> String) -> String {
+    if range.is_mixed() {
+        let (min, max) = (format(range.min), format(range.max));
+        format!("{} - {}", min, max)
+    } else {
+        format(range.min)
+    }
+}
+
+/// Shows the exact properties of the selected notes and lets them be typed in.
+/// Values that differ between the selected notes show up as ranges.
+pub struct NoteInspector {
+    // text typed into each field, replaced by the actual values whenever the field isn't being edited
+    buffers: HashMap<&'static str, String>,
+    error: Option<String>,
+
+    note_editing: Arc<Mutex<NoteEditing>>,
+    project_manager: Arc<RwLock<ProjectManager>>,
+    bar_cacher: Arc<Mutex<BarCacher>>,
+}
+
+impl Default for NoteInspector {
+    fn default() -> Self {
+        Self::new(&Default::default(), &Default::default(), &Default::default())
+    }
+}
+
+impl NoteInspector {
+    pub fn new(note_editing: &Arc<Mutex<NoteEditing>>, project_manager: &Arc<RwLock<ProjectManager>>, bar_cacher: &Arc<Mutex<BarCacher>>) -> Self {
+        Self {
+            buffers: HashMap::new(),
+            error: None,
+
+            note_editing: note_editing.clone(),
+            project_manager: project_manager.clone(),
+            bar_cacher: bar_cacher.clone()
+        }
+    }
+
+    pub fn draw(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered(|ui| { ui.label("Note Inspector"); });
+        ui.separator();
+
+        let summary = {
+            let mut note_editing = self.note_editing.lock().unwrap();
+            note_editing.get_selected_notes_summary()
+        };
+
+        let Some(summary) = summary else {
+            ui.label("No notes selected.");
+            self.error = None;
+            return;
+        };
+
+        ui.label(if summary.note_count == 1 { "1 note selected".to_string() } else { format!("{} notes selected", summary.note_count) });
+        ui.separator();
+
+        let mut edit = None;
+        egui::Grid::new("note_inspector_grid")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for (id, label) in [
+                    ("start_ticks", "Start (ticks)"),
+                    ("start_bbt", "Start (bar:beat:tick)"),
+                    ("start_secs", "Start (seconds)"),
+                    ("length", "Length (ticks)"),
+                    ("key", "Key"),
+                    ("velocity", "Velocity"),
+                    ("channel", "Channel")
+                ] {
+                    let display = self.format_field(id, &summary);
+                    if let Some(input) = self.draw_field(ui, id, label, display) {
+                        edit = Some((id, input));
+                    }
+                    ui.end_row();
+                }
+            });
+
+        ui.label("Type +x or -x to move every note by x.");
+
+        if let Some((id, input)) = edit {
+            self.error = self.apply_field(id, &input).err();
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+    }
+
+    /// Draws one row. Returns the typed text once the field loses focus with something new in it.
+    fn draw_field(&mut self, ui: &mut egui::Ui, id: &'static str, label: &str, display: String) -> Option<String> {
+        ui.label(label);
+
+        let buffer = self.buffers.entry(id).or_default();
+        let response = ui.add(egui::TextEdit::singleline(buffer).desired_width(140.0));
+
+        if response.lost_focus() {
+            let cancelled = ui.input(|i| i.key_pressed(egui::Key::Escape));
+            let input = std::mem::replace(buffer, display.clone());
+            if !cancelled && input.trim() != display { return Some(input); }
+        } else if !response.has_focus() {
+            *buffer = display;
+        }
+
+        None
+    }
+
+    fn format_field(&self, id: &str, summary: &NotePropertiesSummary) -> String {
+        match id {
+            "start_ticks" => format_range(summary.start, |tick| tick.to_string()),
+            "start_bbt" => {
+                let mut bar_cacher = self.bar_cacher.lock().unwrap();
+                format_range(summary.start, |tick| {
+                    let (bar, beat, tick) = bar_cacher.tick_to_bar_beat(tick as MIDITick);
+                    format!("{}:{}:{}", bar + 1, beat + 1, tick)
+                })
+            },
+            "start_secs" => {
+                let project_manager = self.project_manager.read().unwrap();
+                let ppq = project_manager.get_ppq();
+                let tempo_map = project_manager.get_tempo_map().read().unwrap();
+                format_range(summary.start, |tick| format!("{:.3}", tempo_map.ticks_to_secs_from_map(ppq, tick as f64)))
+            },
+            "length" => format_range(summary.length, |length| length.to_string()),
+            "key" => format_range(summary.key, |key| format!("{} ({})", key_to_name(key as MIDIKey), key)),
+            "velocity" => format_range(summary.velocity, |velocity| velocity.to_string()),
+            "channel" => format_range(summary.channel, |channel| (channel + 1).to_string()),
+            _ => String::new()
+        }
+    }
+
+    fn apply_field(&mut self, id: &str, input: &str) -> Result<(), String> {
+        let mut note_editing = self.note_editing.lock().unwrap();
+
+        match id {
+            "start_bbt" => {
+                // only absolute positions make sense here
+                let parts: Vec<&str> = input.trim().split(':').map(|part| part.trim()).collect();
+                let parsed: Option<Vec<u32>> = parts.iter().map(|part| part.parse::<u32>().ok()).collect();
+                let (bar, beat, tick) = match parsed.as_deref() {
+                    Some(&[bar, beat, tick]) if bar > 0 && beat > 0 => (bar, beat, tick),
+                    Some(&[bar, beat]) if bar > 0 && beat > 0 => (bar, beat, 0),
+                    Some(&[bar]) if bar > 0 => (bar, 1, 0),
+                    _ => return Err("Expected bar:beat:tick, e.g. 5:1:0.".into())
+                };
+
+                let tick = {
+                    let mut bar_cacher = self.bar_cacher.lock().unwrap();
+                    bar_cacher.bar_beat_to_tick(bar as usize - 1, beat - 1, tick)
+                };
+                note_editing.set_selected_notes_property(NoteProperty::Start, |_| tick as i64);
+            },
+            "start_secs" => {
+                let value = parse_value_input(input)?;
+
+                let project_manager = self.project_manager.read().unwrap();
+                let ppq = project_manager.get_ppq();
+                let tempo_map = project_manager.get_tempo_map().read().unwrap();
+
+                match value {
+                    ValueInput::Set(secs) => {
+                        let tick = tempo_map.secs_to_ticks_from_map(ppq, secs.max(0.0)).round() as i64;
+                        note_editing.set_selected_notes_property(NoteProperty::Start, |_| tick);
+                    },
+                    ValueInput::Offset(secs) => {
+                        note_editing.set_selected_notes_property(NoteProperty::Start, |tick| {
+                            let new_secs = (tempo_map.ticks_to_secs_from_map(ppq, tick as f64) + secs).max(0.0);
+                            tempo_map.secs_to_ticks_from_map(ppq, new_secs).round() as i64
+                        });
+                    }
+                }
+            },
+            _ => {
+                let (property, display_offset) = match id {
+                    "start_ticks" => (NoteProperty::Start, 0),
+                    "length" => (NoteProperty::Length, 0),
+                    "key" => (NoteProperty::Key, 0),
+                    "velocity" => (NoteProperty::Velocity, 0),
+                    "channel" => (NoteProperty::Channel, 1),
+                    _ => return Ok(())
+                };
+
+                // keys can also be typed as names
+                let value = match (property, name_to_key(input)) {
+                    (NoteProperty::Key, Some(key)) => ValueInput::Set(key as f64),
+                    _ => parse_value_input(input)?
+                };
+
+                match value {
+                    ValueInput::Set(value) => {
+                        let value = value.round() as i64 - display_offset;
+                        note_editing.set_selected_notes_property(property, |_| value);
+                    },
+                    ValueInput::Offset(offset) => {
+                        let offset = offset.round() as i64;
+                        note_editing.set_selected_notes_property(property, |value| value + offset);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use eframe::egui::{self, Color32, RichText};
+
+use crate::editor::plugins::{plugin_console::{ConsoleLineKind, PluginConsole}, plugin_repl::PluginRepl};
+
+/// Where the console panel sits.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ConsoleDock {
+    Bottom,
+    Right
+}
+
+/// Shows plugin output and errors, with a line at the bottom for running Lua against the project.
+pub struct PluginConsoleView {
+    pub dock: ConsoleDock,
+    repl: Option<PluginRepl>,
+    input: String,
+    // what's been typed before, newest last. Up/down walks through it.
+    history: Vec<String>,
+    history_index: Option<usize>,
+    show_output: bool,
+    show_errors: bool
+}
+
+impl Default for PluginConsoleView {
+    fn default() -> Self {
+        Self {
+            dock: ConsoleDock::Bottom,
+            repl: None,
+            input: String::new(),
+            history: Vec::new(),
+            history_index: None,
+            show_output: true,
+            show_errors: true
+        }
+    }
+}
+
+impl PluginConsoleView {
+    /// The REPL gets replaced whenever the project is, since it holds on to the project's editors.
+    pub fn use_repl(&mut self, repl: PluginRepl) {
+        self.repl = Some(repl);
+    }
+
+    pub fn draw(&mut self, ui: &mut egui::Ui, curr_track: usize) {
+        ui.horizontal(|ui| {
+            ui.label("Plugin Console");
+            ui.separator();
+            ui.checkbox(&mut self.show_output, "Output");
+            ui.checkbox(&mut self.show_errors, "Warnings and errors");
+            ui.separator();
+            ui.selectable_value(&mut self.dock, ConsoleDock::Bottom, "Bottom");
+            ui.selectable_value(&mut self.dock, ConsoleDock::Right, "Right");
+            ui.separator();
+            if ui.button("Clear").clicked() { PluginConsole::clear(); }
+        });
+        ui.separator();
+
+        // the input line is laid out first so the log can take whatever height is left
+        egui::TopBottomPanel::bottom(ui.id().with("console_input"))
+            .show_separator_line(false)
+            .show_inside(ui, |ui| {
+                self.draw_input(ui, curr_track);
+            });
+
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in PluginConsole::get_lines() {
+                    let shown = match line.kind {
+                        ConsoleLineKind::Error | ConsoleLineKind::Warning => self.show_errors,
+                        _ => self.show_output
+                    };
+                    if !shown { continue; }
+
+                    let (prefix, color) = match line.kind {
+                        ConsoleLineKind::Output => ("", ui.visuals().text_color()),
+                        ConsoleLineKind::Warning => ("warning: ", Color32::YELLOW),
+                        ConsoleLineKind::Error => ("error: ", Color32::LIGHT_RED),
+                        ConsoleLineKind::Info => ("", Color32::GRAY),
+                        ConsoleLineKind::Input => ("> ", Color32::LIGHT_BLUE)
+                    };
+
+                    ui.horizontal_top(|ui| {
+                        ui.label(RichText::new(format!("{} [{}]", line.time, line.source)).monospace().color(Color32::GRAY));
+                        // tracebacks are several lines, keep them together
+                        ui.add(egui::Label::new(RichText::new(format!("{}{}", prefix, line.text)).monospace().color(color)).selectable(true));
+                    });
+                }
+            });
+    }
+
+    fn draw_input(&mut self, ui: &mut egui::Ui, curr_track: usize) {
+        let Some(repl) = self.repl.as_mut() else { return; };
+
+        ui.horizontal(|ui| {
+            ui.label(RichText::new(">").monospace());
+
+            let response = ui.add(egui::TextEdit::singleline(&mut self.input)
+                .code_editor()
+                .desired_width(f32::INFINITY)
+                .hint_text("Lua, e.g. notes:for_each_selected(function(n) n.velocity = 100 end)"));
+
+            if response.has_focus() {
+                let (up, down) = ui.input(|i| (i.key_pressed(egui::Key::ArrowUp), i.key_pressed(egui::Key::ArrowDown)));
+                if up && !self.history.is_empty() {
+                    let index = self.history_index.map(|index| index.saturating_sub(1)).unwrap_or(self.history.len() - 1);
+                    self.history_index = Some(index);
+                    self.input = self.history[index].clone();
+                } else if down {
+                    match self.history_index {
+                        Some(index) if index + 1 < self.history.len() => {
+                            self.history_index = Some(index + 1);
+                            self.input = self.history[index + 1].clone();
+                        },
+                        _ => {
+                            self.history_index = None;
+                            self.input.clear();
+                        }
+                    }
+                }
+            }
+
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let input = std::mem::take(&mut self.input);
+                if !input.trim().is_empty() {
+                    repl.eval(&input, curr_track);
+                    if self.history.last() != Some(&input) { self.history.push(input); }
+                }
+                self.history_index = None;
+                response.request_focus();
+            }
+        });
+    }
+}
@@ -1,4 +1,4 @@
-use crate::app::custom_widgets::NumericField;
+use crate::{app::custom_widgets::NumericField, editor::scales::ScaleMode};
 
 #[derive(PartialEq)]
 pub enum VS_PianoRoll_OnionState {
@@ -53,7 +53,8 @@ impl ToString for VS_PianoRoll_OnionColoring {
 pub enum VS_PianoRoll_DataViewState {
     Hidden,
     NoteVelocities,
-    PitchBend
+    PitchBend,
+    Tempo
 }
 
 impl Default for VS_PianoRoll_DataViewState {
@@ -67,7 +68,8 @@ impl ToString for VS_PianoRoll_DataViewState {
         match self {
             VS_PianoRoll_DataViewState::Hidden => "None".to_string(),
             VS_PianoRoll_DataViewState::NoteVelocities => "Velocity".to_string(),
-            VS_PianoRoll_DataViewState::PitchBend => "Pitch bend".to_string()
+            VS_PianoRoll_DataViewState::PitchBend => "Pitch bend".to_string(),
+            VS_PianoRoll_DataViewState::Tempo => "Tempo".to_string()
         }
     }
 }
@@ -79,8 +81,13 @@ pub struct ViewSettings {
     pub pr_dataview_state: VS_PianoRoll_DataViewState,
     pub pr_dataview_size: f32,
     pub pr_autoscroll: bool,
+    pub pr_scale_highlight: bool,
+    pub pr_scale_mode: ScaleMode,
 
     pub show_meta_events: bool,
+    pub show_marker_lane: bool,
+    pub show_note_inspector: bool,
+    pub show_plugin_console: bool,
 }
 
 impl Default for ViewSettings {
@@ -92,8 +99,13 @@ impl Default for ViewSettings {
             pr_onion_state: Default::default(),
             pr_dataview_state: Default::default(),
             pr_autoscroll: true,
+            pr_scale_highlight: false,
+            pr_scale_mode: Default::default(),
 
-            show_meta_events: false
+            show_meta_events: false,
+            show_marker_lane: true,
+            show_note_inspector: false,
+            show_plugin_console: false
         }
     }
 }
\ No newline at end of file
@@ -133,7 +133,7 @@ pub struct PlaybackManager {
 
     tempo_map: Arc<RwLock<TempoMap>>,
     start_time: Arc<Mutex<Instant>>,
-    start_pos_secs_from_ticks: f32,
+    start_pos_secs_from_ticks: f64,
     batch_size: Arc<Mutex<MidiEventBatchSize>>,
 
     play_at_mouse: bool,
@@ -165,7 +165,7 @@ impl PlaybackManager {
             playback_start_pos: 0,
             playback_pos_ticks: Arc::new(MIDITickAtomic::new(0)),
             start_time: Arc::new(Mutex::new(Instant::now())),
-            start_pos_secs_from_ticks: 0.0f32,
+            start_pos_secs_from_ticks: 0.0f64,
             tempo_map: tempo_map.clone(),
             batch_size: Arc::new(Mutex::new(MidiEventBatchSize::BatchSize(4096))),
             play_at_mouse: false,
@@ -196,7 +196,7 @@ impl PlaybackManager {
 
             let elapsed = {
                 let st = self.start_time.lock().unwrap();
-                st.elapsed().as_secs_f32() + self.start_pos_secs_from_ticks
+                st.elapsed().as_secs_f64() + self.start_pos_secs_from_ticks
             };
 
             let tempo_map = self.tempo_map.read().unwrap();
@@ -283,7 +283,7 @@ impl PlaybackManager {
 
         let start_pos_secs_from_ticks = {
             let tempo_map = tempo_map.read().unwrap();
-            self.start_pos_secs_from_ticks = tempo_map.ticks_to_secs_from_map(ppq, playback_pos.load(Ordering::SeqCst) as f32);
+            self.start_pos_secs_from_ticks = tempo_map.ticks_to_secs_from_map(ppq, playback_pos.load(Ordering::SeqCst) as f64);
             self.start_pos_secs_from_ticks
         };
 
@@ -317,7 +317,7 @@ impl PlaybackManager {
                 //let elapsed = (*start_time).elapsed().as_secs_f32() + start_pos_secs_from_ticks;
                 let elapsed = {
                     let st = start_time.lock().unwrap();
-                    st.elapsed().as_secs_f32() + start_pos_secs_from_ticks
+                    st.elapsed().as_secs_f64() + start_pos_secs_from_ticks
                 };
 
                 {
@@ -9,5 +9,6 @@ pub mod keybinds;
 pub mod plugins;
 pub mod editing;
 pub mod tempo_map;
+pub mod scales;
 pub mod project;
 pub mod selection_box;
\ No newline at end of file
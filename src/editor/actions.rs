@@ -3,7 +3,7 @@
 
 use std::collections::{VecDeque};
 
-use crate::{editor::util::{MIDITick, SignedMIDIKey, SignedMIDITick}, midi::{events::{channel_event::ChannelEvent, meta_event::MetaEvent, note::Note}, midi_track::MIDITrack}, util::debugger::Debugger};
+use crate::{editor::{project::{event_changes::ProjectChanges, ppq_conversion::{PPQCollisionHandling, PPQRounding}, time_shift::TimeShift}, util::{MIDITick, SignedMIDIKey, SignedMIDITick}}, midi::{events::{channel_event::ChannelEvent, meta_event::MetaEvent, note::Note}, midi_track::MIDITrack}, util::debugger::Debugger};
 
 #[derive(Clone)]
 pub enum EditorAction {
@@ -50,6 +50,27 @@ pub enum EditorAction {
         Vec<usize>,
         Option<Vec<MetaEvent>>
     ),
+    // the channel events and metas a track holds on its own, edited from the event list
+    AddChannelEvents(
+        Vec<usize>, // event ids
+        Option<Vec<ChannelEvent>>, // only used when undoing or redoing
+        u16 // track
+    ),
+    DeleteChannelEvents(
+        Vec<usize>,
+        Option<Vec<ChannelEvent>>,
+        u16
+    ),
+    AddTrackMetas(
+        Vec<usize>,
+        Option<Vec<MetaEvent>>,
+        u16
+    ),
+    DeleteTrackMetas(
+        Vec<usize>,
+        Option<Vec<MetaEvent>>,
+        u16
+    ),
     AddTrack(
         u16, // index of the track that got added
         Option<VecDeque<MIDITrack>>, // only used for undoing/redoing
@@ -61,6 +82,8 @@ pub enum EditorAction {
         bool // if this track is the last track
     ),
     SwapTracks(u16, u16),
+    // (track, mute state to switch to), each track once. applying it swaps the old states in, so undoing is just applying it again
+    SetTracksMuted(Vec<(u16, bool)>),
     DecomposeTrack(
         u16, // index of track that got decomposed
         u16 // how many channnels decomposed
@@ -69,6 +92,23 @@ pub enum EditorAction {
         u16,
         u16
     ),
+    ReplaceTracks(
+        Vec<u16>, // indices of the tracks that get removed (ascending)
+        Option<Vec<MIDITrack>>, // the removed tracks, filled in once they're removed
+        Vec<u16>, // indices the new tracks get inserted at after the removal (ascending)
+        Option<Vec<MIDITrack>> // the tracks to insert
+    ),
+    ChangePPQ(
+        u16, // ppq before the change
+        u16, // ppq after the change
+        PPQRounding,
+        PPQCollisionHandling,
+        Option<ProjectChanges> // the events the conversion merged or rounded off. taken when undoing, filled in again when redoing
+    ),
+    ShiftTime(
+        TimeShift, // what was inserted/deleted
+        Option<ProjectChanges> // the events the shift cut or removed. taken when undoing, filled in again when redoing
+    ),
     Bulk(Vec<EditorAction>) // for bulk actions
 }
 
@@ -82,11 +122,15 @@ pub struct EditorActions {
     actions: VecDeque<EditorAction>,
     max_actions: u16,
     undo_depth: u16,
+    // bumped on every register/undo/redo, so views can tell when the project might have changed
+    change_count: u64,
+    // notes placed since hook plugins last looked, see take_placed_notes
+    placed_notes: Vec<(u16, Vec<usize>)>,
 }
 
 impl Default for EditorActions {
     fn default() -> Self {
-        Self { actions: VecDeque::new(), max_actions: 10, undo_depth: 0 }
+        Self { actions: VecDeque::new(), max_actions: 10, undo_depth: 0, change_count: 0, placed_notes: Vec::new() }
     }
 }
 
@@ -95,11 +139,36 @@ impl EditorActions {
         Self {
             actions: VecDeque::with_capacity(max_actions as usize),
             max_actions,
-            undo_depth: 0
+            undo_depth: 0,
+            change_count: 0,
+            placed_notes: Vec::new()
         }
     }
 
     pub fn register_action(&mut self, action: EditorAction) {
+        // hook plugins get told about these
+        Self::collect_placed_notes(&action, &mut self.placed_notes);
+        self.push_action(action);
+    }
+
+    /// Registers a plugin's edits as one bulk action. Hook plugins don't get told about the notes it places,
+    /// so plugins never set themselves off. With `merge_since`, the edits get folded into the newest action instead
+    /// if nothing else was registered, undone or redone since [`EditorActions::get_change_count`] returned that.
+    pub fn register_plugin_actions(&mut self, mut actions: Vec<EditorAction>, merge_since: Option<u64>) {
+        if merge_since == Some(self.change_count) && self.undo_depth == 0 {
+            if let Some(EditorAction::Bulk(prev_actions)) = self.actions.back_mut() {
+                // newest first, like every other bulk action
+                actions.append(prev_actions);
+                *prev_actions = actions;
+                self.change_count += 1;
+                return;
+            }
+        }
+
+        self.push_action(EditorAction::Bulk(actions));
+    }
+
+    fn push_action(&mut self, action: EditorAction) {
         // if we have previously undid some action(s), remove those actions.
         // if we never removed them then the undo/redo system would no longer be accurate lol
         while self.undo_depth > 0 {
@@ -111,6 +180,23 @@ impl EditorActions {
         if self.actions.len() as u16 == self.max_actions { self.actions.pop_front().unwrap(); }
 
         self.actions.push_back(action);
+        self.change_count += 1;
+    }
+
+    fn collect_placed_notes(action: &EditorAction, placed_notes: &mut Vec<(u16, Vec<usize>)>) {
+        match action {
+            EditorAction::PlaceNotes(ids, _, track) => placed_notes.push((*track, ids.clone())),
+            // pastes come in as a Bulk, along with whatever they replaced
+            EditorAction::Bulk(actions) => {
+                for action in actions.iter().rev() { Self::collect_placed_notes(action, placed_notes); }
+            },
+            _ => {}
+        }
+    }
+
+    /// The (track, note ids) of every PlaceNotes registered since the last call, including ones inside bulk actions.
+    pub fn take_placed_notes(&mut self) -> Vec<(u16, Vec<usize>)> {
+        std::mem::take(&mut self.placed_notes)
     }
 
     // this will basically "invert" the actions, starting from the latest action (front of VecDeque)
@@ -126,6 +212,7 @@ impl EditorActions {
         action_to_undo = self.invert_action(action_to_undo);
         // put it back in the deque
         self.actions.insert(lastmost_undo_index, action_to_undo);
+        self.change_count += 1;
 
         Some(&mut self.actions[lastmost_undo_index])
     }
@@ -143,6 +230,7 @@ impl EditorActions {
         self.actions.insert(lastmost_redo_index, action_to_redo);
 
         self.undo_depth -= 1;
+        self.change_count += 1;
 
         Some(&mut self.actions[lastmost_redo_index])
     }
@@ -208,6 +296,18 @@ impl EditorActions {
             EditorAction::DeleteMeta(meta_ids, deleted_metas) => {
                 EditorAction::AddMeta(meta_ids, deleted_metas)
             },
+            EditorAction::AddChannelEvents(ev_ids, deleted_evs, track) => {
+                EditorAction::DeleteChannelEvents(ev_ids, deleted_evs, track)
+            },
+            EditorAction::DeleteChannelEvents(ev_ids, deleted_evs, track) => {
+                EditorAction::AddChannelEvents(ev_ids, deleted_evs, track)
+            },
+            EditorAction::AddTrackMetas(meta_ids, deleted_metas, track) => {
+                EditorAction::DeleteTrackMetas(meta_ids, deleted_metas, track)
+            },
+            EditorAction::DeleteTrackMetas(meta_ids, deleted_metas, track) => {
+                EditorAction::AddTrackMetas(meta_ids, deleted_metas, track)
+            },
             EditorAction::AddTrack(track, deleted_tracks, last_track) => {
                 EditorAction::RemoveTrack(track, deleted_tracks, last_track)
             },
@@ -217,12 +317,25 @@ impl EditorActions {
             EditorAction::SwapTracks(track_1, track_2) => {
                 EditorAction::SwapTracks(track_1, track_2)
             },
+            EditorAction::SetTracksMuted(mute_states) => {
+                EditorAction::SetTracksMuted(mute_states)
+            },
             EditorAction::DecomposeTrack(track, channel_count) => {
                 EditorAction::ComposeTrack(track, channel_count)
             },
             EditorAction::ComposeTrack(track, channel_count) => {
                 EditorAction::DecomposeTrack(track, channel_count)
             },
+            EditorAction::ReplaceTracks(removed_idx, removed_tracks, inserted_idx, inserted_tracks) => {
+                EditorAction::ReplaceTracks(inserted_idx, inserted_tracks, removed_idx, removed_tracks)
+            },
+            EditorAction::ChangePPQ(old_ppq, new_ppq, rounding, collisions, changes) => {
+                // whether it gets undone or redone depends on if the changes are there
+                EditorAction::ChangePPQ(old_ppq, new_ppq, rounding, collisions, changes)
+            },
+            EditorAction::ShiftTime(shift, changes) => {
+                EditorAction::ShiftTime(shift, changes)
+            },
             EditorAction::Bulk(actions) => {
                 {
                     let mut inv_actions = Vec::new();
@@ -238,6 +351,39 @@ impl EditorActions {
 
     pub fn clear_actions(&mut self) {
         self.actions.clear();
+        self.placed_notes.clear();
         self.undo_depth = 0;
+        self.change_count += 1;
+    }
+
+    pub fn get_change_count(&self) -> u64 {
+        self.change_count
+    }
+
+    /// Turns everything registered since [`EditorActions::get_change_count`] returned `change_count` into one action,
+    /// so it all gets undone at once. Leaves the actions alone if anything was undone in between, or if one of them
+    /// is handled by the track editor, which doesn't look inside bulk actions.
+    pub fn squash_since(&mut self, change_count: u64) {
+        if self.undo_depth > 0 { return; }
+
+        let registered = (self.change_count - change_count) as usize;
+        if registered < 2 || registered > self.actions.len() { return; }
+
+        if !self.actions.range(self.actions.len() - registered..).all(Self::can_go_in_bulk) { return; }
+
+        // newest first, like every other bulk action
+        let squashed = (0..registered).map(|_| self.actions.pop_back().unwrap()).collect();
+        self.actions.push_back(EditorAction::Bulk(squashed));
+    }
+
+    fn can_go_in_bulk(action: &EditorAction) -> bool {
+        match action {
+            EditorAction::Bulk(actions) => actions.iter().all(Self::can_go_in_bulk),
+            EditorAction::AddTrack(..) | EditorAction::RemoveTrack(..) | EditorAction::SwapTracks(..) |
+            EditorAction::DecomposeTrack(..) | EditorAction::ComposeTrack(..) | EditorAction::ReplaceTracks(..) |
+            EditorAction::PlaceNotesMultiTrack(..) | EditorAction::DeleteNotesMultiTrack(..) | EditorAction::NotesMoveMultiTrack(..) |
+            EditorAction::ChangePPQ(..) | EditorAction::ShiftTime(..) => false,
+            _ => true
+        }
     }
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 use std::{cell::RefCell, collections::{HashMap, HashSet}, rc::Rc, sync::{Arc, Mutex}};
 use eframe::egui;
-use crate::{app::{custom_widgets::{NumberField, NumericField}, ui::dialog::{Dialog, DialogAction, DialogActionButtons, dialog_default_close_action, names::*}, util::image_loader::ImageResources}, deprecated, editor::{actions::{EditorAction, EditorActions}, editing::note_editing::note_sequence_funcs::{extract, merge_notes, merge_notes_and_return_ids}, util::{MIDIKey, MIDITick, SignedMIDIKey, SignedMIDITick, bin_search_notes, get_min_max_keys_in_selection, get_min_max_ticks_in_selection, manipulate_note_lengths, manipulate_note_ticks}}, midi::events::note::Note, util::debugger::Debugger};
+use crate::{app::{custom_widgets::{NumberField, NumericField}, ui::dialog::{Dialog, DialogAction, DialogActionButtons, dialog_default_close_action, names::*}, util::image_loader::ImageResources}, deprecated, editor::{actions::{EditorAction, EditorActions}, plugins::plugin_macro::{MacroRecorder, MacroStep}, scales::ScaleMap, editing::note_editing::note_sequence_funcs::{extract, merge_notes, merge_notes_and_return_ids, move_each_note_by}, util::{MIDIKey, MIDITick, SignedMIDIKey, SignedMIDITick, XorShiftRng, bin_search_notes, get_min_max_keys_in_selection, get_min_max_ticks_in_selection, manipulate_note_lengths, manipulate_note_ticks}}, midi::events::note::Note, util::{debugger::Debugger, expression_parser::{EvalContext, Expr, ExpressionError, parse_assignment}}};
 use crate::editor::editing::note_editing::NoteEditing;
 
 // modular edit_function
@@ -17,14 +17,282 @@ pub enum EditFunction {
     RemoveOverlaps,
     SliceAtTick(Vec<usize>, MIDITick),
     FadeNotes(bool),
-    Transpose(SignedMIDIKey)
+    Transpose(SignedMIDIKey),
+    ConformToScale(ScaleMap),
+    Quantize(Vec<usize>, QuantizeSettings),
+    SetByFormula(Vec<usize>, NoteFormulas)
+}
+
+/// A note property that can be assigned in a formula.
+#[derive(Clone, Copy, PartialEq)]
+pub enum NoteProperty {
+    Start,
+    End,
+    Length,
+    Key,
+    Velocity,
+    Channel
+}
+
+impl NoteProperty {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "start" => Some(NoteProperty::Start),
+            "end" => Some(NoteProperty::End),
+            "length" => Some(NoteProperty::Length),
+            "key" => Some(NoteProperty::Key),
+            "velocity" => Some(NoteProperty::Velocity),
+            "channel" => Some(NoteProperty::Channel),
+            _ => None
+        }
+    }
+}
+
+/// `property = expression` lines, applied top to bottom.
+#[derive(Default)]
+pub struct NoteFormulas {
+    pub formulas: Vec<(NoteProperty, Expr)>,
+    pub ppq: u16,
+}
+
+impl NoteFormulas {
+    /// Parses one assignment per line. Empty lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str, ppq: u16) -> Result<Self, (usize, ExpressionError)> {
+        let mut formulas = Vec::new();
+
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let (name, expr) = parse_assignment(line).map_err(|e| (line_num + 1, e))?;
+            let property = NoteProperty::from_name(&name)
+                .ok_or_else(|| (line_num + 1, ExpressionError::UnknownVariable(name)))?;
+            formulas.push((property, expr));
+        }
+
+        Ok(Self { formulas, ppq })
+    }
+}
+
+/// What happens to the end of a note when quantizing.
+#[derive(Clone, Copy, PartialEq)]
+pub enum QuantizeEndMode {
+    /// the length stays the same, so the end follows the start
+    Keep,
+    /// the end gets snapped to the grid separately
+    Snap,
+    /// the length gets rounded to a multiple of the grid
+    Length
+}
+
+#[derive(Clone, Copy)]
+pub struct QuantizeSettings {
+    pub grid: MIDITick,
+    pub quantize_start: bool,
+    pub end_mode: QuantizeEndMode,
+    /// 0.0 - 1.0, how far notes get pulled towards the grid
+    pub strength: f32,
+    /// 0.0 - 1.0, 1.0 delays every other grid line by a third of the grid (triplet feel)
+    pub swing: f32,
+    /// 0.0 - 1.0, only notes within this fraction of half a grid step get quantized
+    pub window: f32,
+    /// random offset range in ticks, 0 to disable
+    pub humanize: MIDITick,
+}
+
+impl Default for QuantizeSettings {
+    fn default() -> Self {
+        Self { grid: 240, quantize_start: true, end_mode: QuantizeEndMode::Keep, strength: 1.0, swing: 0.0, window: 1.0, humanize: 0 }
+    }
+}
+
+impl QuantizeSettings {
+    fn nearest_grid_point(&self, tick: MIDITick) -> MIDITick {
+        let grid = self.grid as u64;
+        let swing_offset = (grid as f32 * self.swing / 3.0).round() as u64;
+
+        // the grid repeats every two steps, with the second step pushed back by the swing
+        let pair_start = (tick as u64 / (grid * 2)) * grid * 2;
+        let candidates = [pair_start, pair_start + grid + swing_offset, pair_start + grid * 2];
+
+        candidates.into_iter()
+            .min_by_key(|&point| point.abs_diff(tick as u64))
+            .unwrap()
+            .min(MIDITick::MAX as u64) as MIDITick
+    }
+
+    fn quantize_tick(&self, tick: MIDITick, rng: &mut XorShiftRng) -> MIDITick {
+        let target = self.nearest_grid_point(tick);
+        let dist = target as f32 - tick as f32;
+
+        let mut new_tick = tick as SignedMIDITick;
+        if self.window >= 1.0 || dist.abs() <= self.window * self.grid as f32 * 0.5 {
+            new_tick += (dist * self.strength).round() as SignedMIDITick;
+        }
+
+        new_tick += rng.next_offset(self.humanize as SignedMIDITick);
+        new_tick.max(0) as MIDITick
+    }
+
+    fn quantize_length(&self, length: MIDITick) -> MIDITick {
+        let target = (((length as f32 / self.grid as f32).round() as MIDITick) * self.grid).max(self.grid);
+        let dist = target as f32 - length as f32;
+        (length as f32 + dist * self.strength).round().max(1.0) as MIDITick
+    }
 }
 
 #[derive(Default)]
 pub struct EditFunctions;
 
 impl EditFunctions {
+    /// Quantizes the notes at `note_ids` in place.
+    /// Returns the actions needed to undo it (empty if nothing changed) along with the new ids of the quantized notes.
+    pub fn quantize_notes(&mut self, notes: &mut Vec<Note>, note_ids: &[usize], settings: &QuantizeSettings, rng: &mut XorShiftRng, curr_track: u16) -> (Vec<EditorAction>, Vec<usize>) {
+        if note_ids.is_empty() || settings.grid == 0 { return (Vec::new(), note_ids.to_vec()); }
+
+        let mut length_ids = Vec::new();
+        let mut length_changes = Vec::new();
+        let mut pos_changes = Vec::with_capacity(note_ids.len());
+
+        // lengths first, the ids are still valid until the notes get moved
+        for &id in note_ids.iter() {
+            let note = &mut notes[id];
+            let old_start = note.start();
+            let old_length = note.length();
+
+            let new_start = if settings.quantize_start { settings.quantize_tick(old_start, rng) } else { old_start };
+            let new_length = match settings.end_mode {
+                QuantizeEndMode::Keep => old_length,
+                QuantizeEndMode::Snap => settings.quantize_tick(note.end(), rng).saturating_sub(new_start).max(1),
+                QuantizeEndMode::Length => settings.quantize_length(old_length),
+            };
+
+            if new_length != old_length {
+                *(note.length_mut()) = new_length;
+                length_ids.push(id);
+                length_changes.push(new_length as SignedMIDITick - old_length as SignedMIDITick);
+            }
+
+            pos_changes.push((new_start as SignedMIDITick - old_start as SignedMIDITick, 0i16));
+        }
+
+        let (mut actions, new_ids) = Self::move_notes_by(notes, note_ids, pos_changes, curr_track);
+
+        // bulk actions run back to front, so the length change (made on the old ids) goes last
+        if !length_ids.is_empty() {
+            actions.push(EditorAction::LengthChange(length_ids, length_changes, curr_track));
+        }
+
+        (actions, new_ids)
+    }
+
+    /// Moves the notes at `note_ids` by `pos_changes` and keeps the track sorted.
+    /// Returns the [`EditorAction::NotesMove`] for it (if anything moved) and the new ids of the moved notes.
+    fn move_notes_by(notes: &mut Vec<Note>, note_ids: &[usize], pos_changes: Vec<(SignedMIDITick, i16)>, curr_track: u16) -> (Vec<EditorAction>, Vec<usize>) {
+        if pos_changes.iter().all(|&(dt_tick, dt_key)| dt_tick == 0 && dt_key == 0) {
+            return (Vec::new(), note_ids.to_vec());
+        }
+
+        let old_notes = std::mem::take(notes);
+        let (notes_to_move, remaining_notes) = extract(old_notes, note_ids);
+
+        let (moved_notes, moved_dt): (Vec<_>, Vec<_>) = move_each_note_by(notes_to_move, &pos_changes).into_iter().unzip();
+        let (merged, moved_ids) = merge_notes_and_return_ids(remaining_notes, moved_notes);
+        *notes = merged;
+
+        (vec![EditorAction::NotesMove(moved_ids.clone(), moved_dt, curr_track, true)], moved_ids)
+    }
+
+    /// Runs `formulas` on copies of the notes at `note_ids`, in order. Nothing in `notes` is changed,
+    /// so a bad formula can be reported before anything gets edited.
+    pub fn evaluate_formulas(&self, notes: &[Note], note_ids: &[usize], formulas: &NoteFormulas, rng: &mut XorShiftRng, track: u16) -> Result<Vec<Note>, ExpressionError> {
+        let count = note_ids.len() as f64;
+
+        note_ids.iter().enumerate().map(|(index, &id)| {
+            let mut note = notes[id].clone();
+
+            for (property, expr) in formulas.formulas.iter() {
+                let variables = [
+                    ("start", note.start() as f64),
+                    ("end", note.end() as f64),
+                    ("length", note.length() as f64),
+                    ("key", note.key() as f64),
+                    ("velocity", note.velocity() as f64),
+                    ("channel", note.channel() as f64),
+                    ("index", index as f64),
+                    ("count", count),
+                    ("track", track as f64),
+                    ("ppq", formulas.ppq as f64),
+                ];
+
+                let value = expr.eval(&mut EvalContext { variables: &variables, rng: &mut *rng })?.round();
+                if !value.is_finite() { continue; }
+
+                match property {
+                    NoteProperty::Start => *(note.start_mut()) = value.clamp(0.0, MIDITick::MAX as f64) as MIDITick,
+                    NoteProperty::End => *(note.length_mut()) = (value - note.start() as f64).clamp(1.0, MIDITick::MAX as f64) as MIDITick,
+                    NoteProperty::Length => *(note.length_mut()) = value.clamp(1.0, MIDITick::MAX as f64) as MIDITick,
+                    NoteProperty::Key => note.set_key(value.clamp(0.0, 127.0) as MIDIKey),
+                    NoteProperty::Velocity => *(note.velocity_mut()) = value.clamp(1.0, 127.0) as u8,
+                    NoteProperty::Channel => *(note.channel_mut()) = value.clamp(0.0, 15.0) as u8,
+                }
+            }
+
+            Ok(note)
+        }).collect()
+    }
+
+    /// Replaces the notes at `note_ids` with `new_notes` (see [`EditFunctions::evaluate_formulas`]).
+    /// Returns the actions needed to undo it and the new ids of the changed notes.
+    pub fn set_note_values(&mut self, notes: &mut Vec<Note>, note_ids: &[usize], new_notes: Vec<Note>, curr_track: u16) -> (Vec<EditorAction>, Vec<usize>) {
+        let mut length_change = (Vec::new(), Vec::new());
+        let mut velocity_change = (Vec::new(), Vec::new());
+        let mut channel_change = (Vec::new(), Vec::new());
+        let mut key_change = (Vec::new(), Vec::new());
+        let mut pos_changes = Vec::with_capacity(note_ids.len());
+
+        for (&id, new_note) in note_ids.iter().zip(new_notes.into_iter()) {
+            let note = &mut notes[id];
+
+            if new_note.length() != note.length() {
+                length_change.0.push(id);
+                length_change.1.push(new_note.length() as SignedMIDITick - note.length() as SignedMIDITick);
+            }
+            if new_note.velocity() != note.velocity() {
+                velocity_change.0.push(id);
+                velocity_change.1.push(new_note.velocity() as i8 - note.velocity() as i8);
+            }
+            if new_note.channel() != note.channel() {
+                channel_change.0.push(id);
+                channel_change.1.push(new_note.channel() as i8 - note.channel() as i8);
+            }
+            if new_note.key() != note.key() {
+                key_change.0.push(id);
+                key_change.1.push(new_note.key() as SignedMIDIKey - note.key() as SignedMIDIKey);
+            }
+            pos_changes.push((new_note.start() as SignedMIDITick - note.start() as SignedMIDITick, 0i16));
+
+            // the start gets changed by the move below
+            let start = note.start();
+            *note = new_note;
+            *(note.start_mut()) = start;
+        }
+
+        let (mut actions, new_ids) = Self::move_notes_by(notes, note_ids, pos_changes, curr_track);
+
+        if !key_change.0.is_empty() { actions.push(EditorAction::KeyChange(key_change.0, key_change.1, curr_track)); }
+        if !velocity_change.0.is_empty() { actions.push(EditorAction::VelocityChange(velocity_change.0, velocity_change.1, curr_track)); }
+        if !channel_change.0.is_empty() { actions.push(EditorAction::ChannelChange(channel_change.0, channel_change.1, curr_track)); }
+        if !length_change.0.is_empty() { actions.push(EditorAction::LengthChange(length_change.0, length_change.1, curr_track)); }
+
+        (actions, new_ids)
+    }
+
     pub fn apply_function(&mut self, notes: &mut Vec<Note>, sel_note_ids: &mut Vec<usize>, func: EditFunction, curr_track: u16, editor_actions: &mut EditorActions) {
+        if MacroRecorder::is_recording() {
+            if let Some(step) = MacroStep::from_edit_function(&func, notes, sel_note_ids) { MacroRecorder::record(step); }
+        }
+
         match func {
             EditFunction::FlipX(_) => {
                 deprecated!("use flip x from plugins instead");
@@ -278,6 +546,53 @@ impl EditFunctions {
                     editor_actions.register_action(EditorAction::KeyChange(affected_note_ids, key_changes, curr_track));
                 }
             },
+            EditFunction::ConformToScale(scale_map) => {
+                let mut key_changes = Vec::with_capacity(sel_note_ids.len());
+                let mut affected_note_ids = Vec::new();
+
+                for &id in sel_note_ids.iter() {
+                    let note = &mut notes[id];
+                    let old_key = note.key();
+                    let new_key = scale_map.snap_key(old_key, note.start());
+                    if new_key == old_key { continue; }
+
+                    affected_note_ids.push(id);
+                    key_changes.push(new_key as SignedMIDIKey - old_key as SignedMIDIKey);
+                    note.set_key(new_key);
+                }
+
+                Debugger::log(format!("Moved {} notes into the scale.", affected_note_ids.len()));
+
+                if !key_changes.is_empty() {
+                    editor_actions.register_action(EditorAction::KeyChange(affected_note_ids, key_changes, curr_track));
+                }
+            },
+            EditFunction::Quantize(note_ids, settings) => {
+                let mut rng = XorShiftRng::from_time();
+                let (actions, new_ids) = self.quantize_notes(notes, &note_ids, &settings, &mut rng, curr_track);
+                *sel_note_ids = new_ids;
+
+                if !actions.is_empty() {
+                    editor_actions.register_action(EditorAction::Bulk(actions));
+                }
+            },
+            EditFunction::SetByFormula(note_ids, formulas) => {
+                let mut rng = XorShiftRng::from_time();
+                let new_notes = match self.evaluate_formulas(notes, &note_ids, &formulas, &mut rng, curr_track) {
+                    Ok(new_notes) => new_notes,
+                    Err(err) => {
+                        Debugger::log_warning(format!("Formula failed: {}", err));
+                        return;
+                    }
+                };
+
+                let (actions, new_ids) = self.set_note_values(notes, &note_ids, new_notes, curr_track);
+                *sel_note_ids = new_ids;
+
+                if !actions.is_empty() {
+                    editor_actions.register_action(EditorAction::Bulk(actions));
+                }
+            },
             EditFunction::RemoveOverlaps => {
                 if sel_note_ids.is_empty() { return; }
 
@@ -623,4 +938,303 @@ impl Dialog for EFGlueDialog {
     fn get_dialog_title(&self) -> String {
         "Glue notes".into()
     }
-}
\ No newline at end of file
+}
+pub struct EFQuantizeDialog {
+    /// grid length from the editor snap, passed in when the dialog opens
+    pub snap_grid: MIDITick,
+    pub use_snap_grid: bool,
+    pub custom_grid: NumericField<MIDITick>,
+    pub quantize_start: bool,
+    pub end_mode: QuantizeEndMode,
+    pub strength: f32,
+    pub swing: f32,
+    pub window: f32,
+    pub humanize: bool,
+    pub humanize_range: NumericField<MIDITick>,
+
+    note_editing: Arc<Mutex<NoteEditing>>,
+    edit_functions: Rc<RefCell<EditFunctions>>,
+    edit_actions: Rc<RefCell<EditorActions>>
+}
+
+impl Default for EFQuantizeDialog {
+    fn default() -> Self {
+        Self::new(&Default::default(), &Default::default(), &Default::default())
+    }
+}
+
+impl EFQuantizeDialog {
+    pub fn new(
+        note_editing: &Arc<Mutex<NoteEditing>>,
+        edit_functions: &Rc<RefCell<EditFunctions>>,
+        edit_actions: &Rc<RefCell<EditorActions>>,
+    ) -> Self {
+        Self {
+            snap_grid: 240,
+            use_snap_grid: true,
+            custom_grid: NumericField::new(240, Some(1), Some(MIDITick::MAX.into())),
+            quantize_start: true,
+            end_mode: QuantizeEndMode::Keep,
+            strength: 100.0,
+            swing: 0.0,
+            window: 100.0,
+            humanize: false,
+            humanize_range: NumericField::new(10, Some(0), Some(MIDITick::MAX.into())),
+
+            note_editing: note_editing.clone(),
+            edit_functions: edit_functions.clone(),
+            edit_actions: edit_actions.clone()
+        }
+    }
+
+    fn get_settings(&self) -> QuantizeSettings {
+        QuantizeSettings {
+            grid: if self.use_snap_grid { self.snap_grid } else { self.custom_grid.value() },
+            quantize_start: self.quantize_start,
+            end_mode: self.end_mode,
+            strength: self.strength / 100.0,
+            swing: self.swing / 100.0,
+            window: self.window / 100.0,
+            humanize: if self.humanize { self.humanize_range.value() } else { 0 }
+        }
+    }
+}
+
+impl Dialog for EFQuantizeDialog {
+    fn init_dialog(&mut self, args: Vec<Box<dyn std::any::Any>>) -> Result<(), &'static str> {
+        if let Some(snap_grid) = args.get(0).and_then(|a| a.downcast_ref::<MIDITick>()) {
+            self.snap_grid = *snap_grid;
+            self.custom_grid.set_value(*snap_grid);
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ui: &mut egui::Ui, _: &ImageResources) -> Option<DialogAction> {
+        ui.label("Grid");
+        ui.radio_value(&mut self.use_snap_grid, true, format!("Editor snap ({} ticks)", self.snap_grid));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.use_snap_grid, false, "Custom");
+            ui.add_enabled_ui(!self.use_snap_grid, |ui| {
+                self.custom_grid.show("ticks:", ui, Some(60.0));
+            });
+        });
+
+        ui.separator();
+        ui.checkbox(&mut self.quantize_start, "Quantize note starts");
+        ui.horizontal(|ui| {
+            ui.label("Note ends:");
+            ui.radio_value(&mut self.end_mode, QuantizeEndMode::Keep, "Keep length");
+            ui.radio_value(&mut self.end_mode, QuantizeEndMode::Snap, "Quantize end");
+            ui.radio_value(&mut self.end_mode, QuantizeEndMode::Length, "Quantize length");
+        });
+
+        ui.separator();
+        ui.add(egui::Slider::new(&mut self.strength, 0.0..=100.0).text("Strength").suffix("%"));
+        ui.add(egui::Slider::new(&mut self.swing, 0.0..=100.0).text("Swing").suffix("%"));
+        ui.add(egui::Slider::new(&mut self.window, 0.0..=100.0).text("Window").suffix("%"))
+            .on_hover_text("Only notes this close to a grid line get quantized (100% = all notes)");
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.humanize, "Humanize");
+            ui.add_enabled_ui(self.humanize, |ui| {
+                self.humanize_range.show("± ticks:", ui, Some(60.0));
+            });
+        });
+        None
+    }
+
+    fn get_action_buttons(&self) -> Option<DialogActionButtons> {
+        Some(
+            DialogActionButtons::OkCancel(
+                Box::new(|dlg| {
+                    let dlg = dlg.as_any_mut().downcast_mut::<Self>().unwrap();
+                    let dlg_name = dlg.get_dialog_name();
+                    let settings = dlg.get_settings();
+
+                    let note_editing = dlg.note_editing.lock().unwrap();
+
+                    let tracks = note_editing.get_tracks();
+                    let mut tracks = tracks.write().unwrap();
+
+                    let sel_notes = note_editing.get_shared_selected_ids();
+                    let mut sel_notes = sel_notes.write().unwrap();
+
+                    let mut edit_functions = dlg.edit_functions.try_borrow_mut().unwrap();
+                    let mut rng = XorShiftRng::from_time();
+
+                    // every selected track goes into one bulk action so a single undo reverts all of them
+                    let mut actions = Vec::new();
+                    for track in sel_notes.get_active_selected_tracks() {
+                        if track as usize >= tracks.len() { continue; }
+
+                        let ids = sel_notes.take_selected_from_track(track);
+                        let notes = (*tracks)[track as usize].get_notes_mut();
+
+                        let (track_actions, new_ids) = edit_functions.quantize_notes(notes, &ids, &settings, &mut rng, track);
+                        sel_notes.set_selected_in_track(new_ids, track);
+                        actions.extend(track_actions);
+                    }
+
+                    if !actions.is_empty() {
+                        let mut editor_actions = dlg.edit_actions.try_borrow_mut().unwrap();
+                        editor_actions.register_action(EditorAction::Bulk(actions));
+                    } else {
+                        Debugger::log("Selection is already quantized.");
+                    }
+                    MacroRecorder::record(MacroStep::quantize(&settings));
+
+                    Some(DialogAction::Close(dlg_name))
+                }),
+                dialog_default_close_action()
+            )
+        )
+    }
+
+    fn get_dialog_name(&self) -> &'static str {
+        DIALOG_NAME_EF_QUANTIZE
+    }
+
+    fn get_dialog_title(&self) -> String {
+        "Quantize selection".into()
+    }
+}
+
+pub struct EFFormulaDialog {
+    pub formula_text: String,
+    pub error: Option<String>,
+    ppq: u16,
+
+    note_editing: Arc<Mutex<NoteEditing>>,
+    edit_functions: Rc<RefCell<EditFunctions>>,
+    edit_actions: Rc<RefCell<EditorActions>>
+}
+
+impl Default for EFFormulaDialog {
+    fn default() -> Self {
+        Self::new(&Default::default(), &Default::default(), &Default::default())
+    }
+}
+
+impl EFFormulaDialog {
+    pub fn new(
+        note_editing: &Arc<Mutex<NoteEditing>>,
+        edit_functions: &Rc<RefCell<EditFunctions>>,
+        edit_actions: &Rc<RefCell<EditorActions>>,
+    ) -> Self {
+        Self {
+            formula_text: "velocity = clamp(velocity * 0.8 + rand(-5, 5), 1, 127)".into(),
+            error: None,
+            ppq: 960,
+
+            note_editing: note_editing.clone(),
+            edit_functions: edit_functions.clone(),
+            edit_actions: edit_actions.clone()
+        }
+    }
+
+    fn parse_formulas(&self) -> Result<NoteFormulas, String> {
+        NoteFormulas::parse(&self.formula_text, self.ppq)
+            .map_err(|(line, err)| format!("Line {}: {}", line, err))
+    }
+
+    /// Applies the formulas to every selected note. If any note fails, nothing gets changed.
+    fn apply_formulas(&mut self) -> Result<(), String> {
+        let formulas = self.parse_formulas()?;
+        if formulas.formulas.is_empty() { return Ok(()); }
+
+        let note_editing = self.note_editing.lock().unwrap();
+
+        let tracks = note_editing.get_tracks();
+        let mut tracks = tracks.write().unwrap();
+
+        let sel_notes = note_editing.get_shared_selected_ids();
+        let mut sel_notes = sel_notes.write().unwrap();
+
+        let mut edit_functions = self.edit_functions.try_borrow_mut().unwrap();
+        let mut rng = XorShiftRng::from_time();
+
+        let mut evaluated = Vec::new();
+        for track in sel_notes.get_active_selected_tracks() {
+            let Some(track_data) = tracks.get(track as usize) else { continue; };
+            let ids = sel_notes.get_selected_ids_in_track(track).cloned().unwrap_or_default();
+
+            let new_notes = edit_functions.evaluate_formulas(track_data.get_notes(), &ids, &formulas, &mut rng, track)
+                .map_err(|err| err.to_string())?;
+            evaluated.push((track, ids, new_notes));
+        }
+
+        let mut actions = Vec::new();
+        for (track, ids, new_notes) in evaluated {
+            let notes = (*tracks)[track as usize].get_notes_mut();
+            let (track_actions, new_ids) = edit_functions.set_note_values(notes, &ids, new_notes, track);
+            sel_notes.set_selected_in_track(new_ids, track);
+            actions.extend(track_actions);
+        }
+
+        if !actions.is_empty() {
+            let mut editor_actions = self.edit_actions.try_borrow_mut().unwrap();
+            editor_actions.register_action(EditorAction::Bulk(actions));
+        }
+        MacroRecorder::record(MacroStep::formula(&self.formula_text));
+
+        Ok(())
+    }
+}
+
+impl Dialog for EFFormulaDialog {
+    fn init_dialog(&mut self, args: Vec<Box<dyn std::any::Any>>) -> Result<(), &'static str> {
+        if let Some(ppq) = args.get(0).and_then(|a| a.downcast_ref::<u16>()) {
+            self.ppq = *ppq;
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ui: &mut egui::Ui, _: &ImageResources) -> Option<DialogAction> {
+        ui.label("One `property = formula` per line, applied to every selected note from top to bottom.");
+        ui.label("Properties: start, end, length, key, velocity, channel (0-15)");
+        ui.label("Also readable: index, count, track, ppq");
+        ui.label("Functions: min, max, clamp, abs, round, floor, ceil, sqrt, sin, cos, snap(x, step), rand(), rand(a, b), if(cond, a, b)");
+
+        let response = ui.add(egui::TextEdit::multiline(&mut self.formula_text)
+            .code_editor()
+            .desired_rows(4)
+            .desired_width(400.0));
+
+        if response.changed() {
+            self.error = self.parse_formulas().err();
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+        None
+    }
+
+    fn get_action_buttons(&self) -> Option<DialogActionButtons> {
+        Some(
+            DialogActionButtons::OkCancel(
+                Box::new(|dlg| {
+                    let dlg = dlg.as_any_mut().downcast_mut::<Self>().unwrap();
+
+                    // keep the dialog open so the formula can be fixed
+                    if let Err(error) = dlg.apply_formulas() {
+                        dlg.error = Some(error);
+                        return None;
+                    }
+
+                    Some(DialogAction::Close(dlg.get_dialog_name()))
+                }),
+                dialog_default_close_action()
+            )
+        )
+    }
+
+    fn get_dialog_name(&self) -> &'static str {
+        DIALOG_NAME_EF_FORMULA
+    }
+
+    fn get_dialog_title(&self) -> String {
+        "Set property by formula".into()
+    }
+}
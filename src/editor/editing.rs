@@ -1,17 +1,45 @@
-use std::collections::HashMap;
+use std::{collections::{HashMap, hash_map::DefaultHasher}, hash::{Hash, Hasher}};
 
-use crate::{editor::util::{MIDITick, SignedMIDITick}, midi::events::note::Note};
+use crate::{editor::{editing::system_clipboard::{MAX_EXPORTED_NOTES, clipboard_from_text, clipboard_to_text}, util::{MIDITick, SignedMIDITick}}, midi::events::note::Note, util::debugger::Debugger};
 
 pub mod note_editing;
 pub mod meta_editing;
 pub mod track_editing;
 pub mod lua_note_editing;
 pub mod data_editing;
+pub mod system_clipboard;
+pub mod event_list_editing;
 
+#[derive(Clone, Copy, PartialEq)]
 pub enum SelectionOp {
     NewSelection,
     AppendSelection,
-    RemoveFromSelection
+    RemoveFromSelection,
+    IntersectSelection
+}
+
+/// What Paste Special does with the clipboard.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PasteMode {
+    /// paste on top of the notes already there
+    Merge,
+    /// remove the notes starting inside each pasted range first
+    Replace,
+    /// copy the clipboard's velocities onto the selected notes, in order
+    VelocitiesOnly,
+    /// copy the clipboard's lengths onto the selected notes, in order
+    LengthsOnly
+}
+
+impl ToString for PasteMode {
+    fn to_string(&self) -> String {
+        match self {
+            PasteMode::Merge => "Merge with existing notes".to_string(),
+            PasteMode::Replace => "Replace existing notes".to_string(),
+            PasteMode::VelocitiesOnly => "Velocities only (onto selection)".to_string(),
+            PasteMode::LengthsOnly => "Lengths only (onto selection)".to_string()
+        }
+    }
 }
 
 /// Contains information about what notes were copied
@@ -19,6 +47,11 @@ pub enum SelectionOp {
 pub struct SharedClipboard {
     notes_clipboard_map: HashMap<u16, Vec<Note>>,
     pub offset_from_playhead: SignedMIDITick,
+
+    // set whenever something gets copied, cleared once it's been put on the OS clipboard
+    needs_system_export: bool,
+    // hash of the text we last put on the OS clipboard, so pasting it back doesn't re-parse our own notes
+    last_exported_hash: Option<u64>,
 }
 
 impl SharedClipboard {
@@ -27,6 +60,7 @@ impl SharedClipboard {
         let clipboard_map = &mut self.notes_clipboard_map;
         if clear_clipboard { clipboard_map.clear(); }
         clipboard_map.insert(track, notes);
+        self.needs_system_export = true;
     }
 
     /// Overrides the current clipboard but allows for multiple notes to be moved to the clipboard
@@ -37,6 +71,7 @@ impl SharedClipboard {
         for (notes, track) in notes.into_iter().zip(tracks) {
             clipboard_map.insert(track, notes);
         }
+        self.needs_system_export = true;
     }
 
     /// Retrieves notes from the clipboard, cloning the notes from the clipboard.
@@ -70,14 +105,63 @@ impl SharedClipboard {
 
         start_tick
     }
+
+    /// Returns the clipboard as OS clipboard text if something was copied since the last call.
+    /// Returns [`None`] if there's nothing new, or the copy is too big to turn into text.
+    pub fn take_system_export(&mut self, ppq: u16) -> Option<String> {
+        if !std::mem::take(&mut self.needs_system_export) { return None; }
+
+        let clipboard = self.get_notes_from_clipboard();
+        let note_count: usize = clipboard.iter().map(|(_, notes)| notes.len()).sum();
+        if note_count == 0 { return None; }
+        if note_count > MAX_EXPORTED_NOTES {
+            Debugger::log_warning(format!("{} notes are too many for the system clipboard, they can only be pasted in this window.", note_count));
+            return None;
+        }
+
+        let text = clipboard_to_text(&clipboard, ppq);
+        self.last_exported_hash = Some(hash_text(&text));
+        Some(text)
+    }
+
+    /// Replaces the clipboard with notes pasted from the OS clipboard. Returns false (and keeps the current clipboard)
+    /// if the text doesn't contain any notes, or is the same text this clipboard last exported.
+    pub fn import_system_text(&mut self, text: &str, ppq: u16) -> bool {
+        if self.last_exported_hash == Some(hash_text(text)) { return false; }
+
+        let Some(clipboard) = clipboard_from_text(text, ppq) else { return false; };
+        let (tracks, notes): (Vec<u16>, Vec<Vec<Note>>) = clipboard.into_iter().unzip();
+        self.move_multi_notes_to_clipboard(notes, tracks);
+
+        // nothing to export, this came from the OS clipboard in the first place
+        self.needs_system_export = false;
+        self.last_exported_hash = Some(hash_text(text));
+
+        // pasted notes have no playhead they were copied relative to, so they land on the playhead
+        self.offset_from_playhead = -(self.get_clipboard_start_tick() as SignedMIDITick);
+        true
+    }
+}
+
+fn hash_text(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
 }
 
 #[derive(Default)]
 pub struct SharedSelectedNotes {
-    selected_notes_hash: HashMap<u16, Vec<usize>>
+    selected_notes_hash: HashMap<u16, Vec<usize>>,
+    // bumped whenever the selection might have changed
+    change_count: u64
 }
 
 impl SharedSelectedNotes {
+    /// Changes whenever the selection does, so views can tell without going through it.
+    pub fn get_change_count(&self) -> u64 {
+        self.change_count
+    }
+
     pub fn get_active_selected_tracks(&self) -> Vec<u16> {
         let mut tracks: Vec<u16> = self.selected_notes_hash.keys().map(|i| *i).collect();
         tracks.sort();
@@ -89,6 +173,7 @@ impl SharedSelectedNotes {
     }
 
     pub fn get_selected_ids_mut(&mut self, track: u16) -> &mut Vec<usize> {
+        self.change_count += 1;
         self.selected_notes_hash.entry(track).or_insert(vec![])
     }
 
@@ -101,6 +186,7 @@ impl SharedSelectedNotes {
     }
 
     pub fn set_selected_in_track(&mut self, ids: Vec<usize>, track: u16) {
+        self.change_count += 1;
         self.selected_notes_hash.insert(track, ids);
     }
 
@@ -113,11 +199,13 @@ impl SharedSelectedNotes {
     }
 
     pub fn take_selected_from_track(&mut self, track: u16) -> Vec<usize> {
+        self.change_count += 1;
         self.selected_notes_hash.remove(&track).unwrap_or_default()
         // std::mem::take(&mut self.selected_notes_hash.entry(track).or_default())
     }
 
     pub fn take_selected_from_all(&mut self) -> Vec<(u16, Vec<usize>)> {
+        self.change_count += 1;
         let mut result = Vec::with_capacity(self.selected_notes_hash.len());
         for (track, ids) in self.selected_notes_hash.drain() {
             result.push((track, ids));
@@ -126,6 +214,7 @@ impl SharedSelectedNotes {
     }
 
     pub fn clear_selected(&mut self) {
+        self.change_count += 1;
         self.selected_notes_hash.clear();
     }
 
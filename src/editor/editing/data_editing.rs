@@ -1,16 +1,19 @@
-use std::{cell::RefCell, rc::Rc, sync::{Arc, Mutex, RwLock}};
+use std::{cell::RefCell, collections::VecDeque, rc::Rc, sync::{Arc, Mutex, RwLock}, time::Instant};
 
 use crate::{
     app::{
+        custom_widgets::NumericField,
         main_window::{EditorTool, EditorToolSettings},
         view_settings::{VS_PianoRoll_DataViewState, ViewSettings},
     },
     editor::{
         actions::{EditorAction, EditorActions},
+        editing::meta_editing::MetaEditing,
         navigation::PianoRollNavigation,
-        util::MIDITick,
+        util::{bytes_as_tempo, tempo_as_bytes, MIDITick, SignedMIDITick, MIN_TEMPO_BPM},
     },
     midi::{
+        events::meta_event::{MetaEvent, MetaEventType},
         midi_track::MIDITrack,
     },
 };
@@ -25,9 +28,124 @@ pub mod data_edit_flags {
 }
 
 use data_edit_flags::*;
-use eframe::egui::Ui;
+use eframe::egui::{Rect, Ui};
 
-type DataNumType = i16;
+// f32, so tempos keep their fractions
+type DataNumType = f32;
+
+/// The range of tempos (in BPM) shown in the tempo lane. Anything above gets clamped to the top.
+pub const TEMPO_LANE_MIN_BPM: f32 = 10.0;
+pub const TEMPO_LANE_MAX_BPM: f32 = 310.0;
+
+/// How close (in pixels) the mouse has to be to a tempo point to grab or erase it.
+const TEMPO_POINT_HIT_RADIUS: f32 = 6.0;
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum TempoRampShape {
+    Linear,
+    Curved
+}
+
+impl Default for TempoRampShape {
+    fn default() -> Self {
+        TempoRampShape::Linear
+    }
+}
+
+impl ToString for TempoRampShape {
+    fn to_string(&self) -> String {
+        match self {
+            TempoRampShape::Linear => "Linear".to_string(),
+            TempoRampShape::Curved => "Curved".to_string()
+        }
+    }
+}
+
+impl TempoRampShape {
+    /// Maps `t` (0.0 -> 1.0) along the ramp. `curve` > 0 eases in, `curve` < 0 eases out.
+    pub fn apply(&self, t: f32, curve: f32) -> f32 {
+        match self {
+            TempoRampShape::Linear => t,
+            TempoRampShape::Curved => {
+                if curve >= 0.0 {
+                    t.powf(1.0 + curve * 3.0)
+                } else {
+                    1.0 - (1.0 - t).powf(1.0 - curve * 3.0)
+                }
+            }
+        }
+    }
+}
+
+pub struct TempoRampSettings {
+    pub shape: TempoRampShape,
+    pub curve: NumericField<f32>,
+    /// How many tempo events a ramp gets expanded into per quarter note
+    pub steps_per_beat: NumericField<u16>,
+}
+
+impl Default for TempoRampSettings {
+    fn default() -> Self {
+        Self {
+            shape: TempoRampShape::Linear,
+            curve: NumericField::new(0.5, Some(-1.0), Some(1.0)),
+            steps_per_beat: NumericField::new(8, Some(1), Some(64))
+        }
+    }
+}
+
+/// Averages the intervals between the last few taps to get a tempo.
+#[derive(Default)]
+pub struct TapTempo {
+    taps: VecDeque<Instant>
+}
+
+impl TapTempo {
+    const MAX_TAPS: usize = 8;
+    // if the user stops tapping for this long, start over
+    const RESET_AFTER_SECS: f32 = 2.0;
+
+    pub fn tap(&mut self) {
+        let now = Instant::now();
+        if let Some(last_tap) = self.taps.back() {
+            if now.duration_since(*last_tap).as_secs_f32() > Self::RESET_AFTER_SECS {
+                self.taps.clear();
+            }
+        }
+
+        self.taps.push_back(now);
+        if self.taps.len() > Self::MAX_TAPS { self.taps.pop_front(); }
+    }
+
+    pub fn get_bpm(&self) -> Option<f32> {
+        if self.taps.len() < 2 { return None; }
+
+        let first = self.taps.front().unwrap();
+        let last = self.taps.back().unwrap();
+        let span = last.duration_since(*first).as_secs_f32();
+        if span <= 0.0 { return None; }
+
+        Some(60.0 * (self.taps.len() - 1) as f32 / span)
+    }
+
+    pub fn get_tap_count(&self) -> usize {
+        self.taps.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.taps.clear();
+    }
+}
+
+struct TempoDragInfo {
+    old_idx: usize,
+    old_meta: MetaEvent,
+    // the tempo follows the mouse's vertical movement, so a point outside the lane's range keeps its value until it's moved
+    old_tempo: f32,
+    curr_idx: usize,
+    // the dragged point can't pass its neighbouring tempo points
+    tick_range: (MIDITick, MIDITick),
+}
 
 #[derive(Default)]
 pub struct DataEditMouseInfo {
@@ -47,8 +165,15 @@ pub struct DataEditing {
 
     editor_tool: Rc<RefCell<EditorToolSettings>>,
     editor_actions: Rc<RefCell<EditorActions>>,
+    meta_editing: Arc<Mutex<MetaEditing>>,
     mouse_info: DataEditMouseInfo,
+    view_rect: Rect,
     flags: u16,
+
+    pub tempo_ramp: TempoRampSettings,
+    pub tap_tempo: TapTempo,
+    tempo_drag: Option<TempoDragInfo>,
+    pub ppq: u16,
 }
 
 impl DataEditing {
@@ -57,7 +182,8 @@ impl DataEditing {
         view_settings: &Arc<Mutex<ViewSettings>>,
         editor_tool: &Rc<RefCell<EditorToolSettings>>,
         editor_actions: &Rc<RefCell<EditorActions>>,
-        nav: &Arc<Mutex<PianoRollNavigation>>
+        nav: &Arc<Mutex<PianoRollNavigation>>,
+        meta_editing: &Arc<Mutex<MetaEditing>>
     ) -> Self {
         Self {
             tracks: tracks.clone(),
@@ -67,8 +193,15 @@ impl DataEditing {
 
             editor_tool: editor_tool.clone(),
             editor_actions: editor_actions.clone(),
+            meta_editing: meta_editing.clone(),
             mouse_info: Default::default(),
-            flags: DATA_EDIT_FLAGS_NONE
+            view_rect: Rect::NOTHING,
+            flags: DATA_EDIT_FLAGS_NONE,
+
+            tempo_ramp: Default::default(),
+            tap_tempo: Default::default(),
+            tempo_drag: None,
+            ppq: 960
         }
     }
 
@@ -98,7 +231,7 @@ impl DataEditing {
     }
 
     pub fn on_mouse_move(&mut self) {
-        if self.get_flag(DATA_EDIT_MOUSE_DOWN_ON_UI | DATA_EDIT_CLICKED_IN_RECT) { return; }
+        if self.get_flag(DATA_EDIT_MOUSE_DOWN_ON_UI) || !self.get_flag(DATA_EDIT_CLICKED_IN_RECT) { return; }
 
         if self.get_flag(DATA_EDIT_ANY_DIALOG_OPEN | DATA_EDIT_MOUSE_OVER_UI) { return; }
 
@@ -123,6 +256,7 @@ impl DataEditing {
         if !self.get_flag(DATA_EDIT_CLICKED_IN_RECT) {
             return;
         }
+        self.disable_flag(DATA_EDIT_CLICKED_IN_RECT);
 
         if self.get_flag(DATA_EDIT_MOUSE_OVER_UI | DATA_EDIT_ANY_DIALOG_OPEN) { return; }
 
@@ -139,21 +273,23 @@ impl DataEditing {
     }
 
     // ======== HELPER FUNCS ========
-    pub fn update(&mut self, ui: &mut Ui) {
+    /// `lane_rect` is where the data view is drawn.
+    pub fn update(&mut self, ui: &mut Ui, lane_rect: Rect) {
         // convert from mouse pos to data view pos
         let (mouse_x, mouse_y) = {
             let pos = ui.input(|i| i.pointer.hover_pos().unwrap_or_default());
             (pos.x, pos.y)
         };
 
-        self.mouse_info.mouse_data_pos = self.screen_pos_to_data_pos((mouse_x, mouse_y), ui);
+        self.view_rect = lane_rect;
+        self.mouse_info.mouse_data_pos = self.screen_pos_to_data_pos((mouse_x, mouse_y));
         self.mouse_info.mouse_screen_pos = (mouse_x, mouse_y);
     }
 
-    fn screen_pos_to_data_pos(&self, screen_pos: (f32, f32), ui: &mut Ui) -> (MIDITick, DataNumType) {
-        let rect = ui.min_rect();
+    fn screen_pos_to_data_pos(&self, screen_pos: (f32, f32)) -> (MIDITick, DataNumType) {
+        let rect = self.view_rect;
         let screen_x_norm = (screen_pos.0 - rect.left()) / rect.width();
-        let screen_y_norm = 1.0 - (screen_pos.1 - rect.top() - 21.0) / 200.0; // ugly hardcoded values :skull:
+        let screen_y_norm = 1.0 - (screen_pos.1 - rect.top()) / rect.height();
         
         let nav = self.nav.lock().unwrap();
         let screen_x_tick = (screen_x_norm * nav.zoom_ticks_smoothed + nav.tick_pos_smoothed) as MIDITick;
@@ -163,15 +299,15 @@ impl DataEditing {
         (screen_x_tick, screen_y_data)
     }
 
-    fn data_pos_to_screen_pos(&self, data_pos: (MIDITick, DataNumType), ui: &mut Ui) -> (f32, f32) {
+    fn data_pos_to_screen_pos(&self, data_pos: (MIDITick, DataNumType)) -> (f32, f32) {
         let nav = self.nav.lock().unwrap();
-        let data_x_norm = (data_pos.0 as f32 - nav.tick_pos_smoothed) / nav.zoom_keys_smoothed;
-        let data_y_norm = self.unscaled_y_from_curr_data(data_pos.1);
+        let data_x_norm = (data_pos.0 as f32 - nav.tick_pos_smoothed) / nav.zoom_ticks_smoothed;
         drop(nav);
+        let data_y_norm = self.unscaled_y_from_curr_data(data_pos.1);
 
-        let rect = ui.min_rect();
+        let rect = self.view_rect;
         let data_x_scr = data_x_norm * rect.width() + rect.left();
-        let data_y_scr = (1.0 - data_y_norm) * 200.0 + 21.0 + rect.top();
+        let data_y_scr = (1.0 - data_y_norm) * rect.height() + rect.top();
 
         (data_x_scr, data_y_scr)
     }
@@ -180,10 +316,13 @@ impl DataEditing {
         let vs = self.view_settings.lock().unwrap();
         match vs.pr_dataview_state {
             VS_PianoRoll_DataViewState::NoteVelocities => {
-                ((y * 127.0) as DataNumType).clamp(0, 127)
+                (y * 127.0).trunc().clamp(0.0, 127.0)
             },
             VS_PianoRoll_DataViewState::PitchBend => {
-                (((y * 2.0 - 1.0) * 8192.0) as DataNumType).clamp(-8192, 8191)
+                ((y * 2.0 - 1.0) * 8192.0).trunc().clamp(-8192.0, 8191.0)
+            },
+            VS_PianoRoll_DataViewState::Tempo => {
+                (TEMPO_LANE_MIN_BPM + y * (TEMPO_LANE_MAX_BPM - TEMPO_LANE_MIN_BPM)).clamp(TEMPO_LANE_MIN_BPM, TEMPO_LANE_MAX_BPM)
             },
             _ => 0
         }
@@ -193,10 +332,13 @@ impl DataEditing {
         let vs = self.view_settings.lock().unwrap();
         match vs.pr_dataview_state {
             VS_PianoRoll_DataViewState::NoteVelocities => {
-                y as f32 / 127.0
+                y / 127.0
             },
             VS_PianoRoll_DataViewState::PitchBend => {
-                y as f32 / 8192.0 * 0.5 + 0.5
+                y / 8192.0 * 0.5 + 0.5
+            },
+            VS_PianoRoll_DataViewState::Tempo => {
+                (y - TEMPO_LANE_MIN_BPM) / (TEMPO_LANE_MAX_BPM - TEMPO_LANE_MIN_BPM)
             },
             _ => 0.0
         }
@@ -218,6 +360,11 @@ impl DataEditing {
     fn pencil_mouse_up(&mut self) {
         self.disable_flag(DATA_EDIT_DRAW_EDIT_LINE);
 
+        if self.get_dataview_state() == VS_PianoRoll_DataViewState::Tempo {
+            self.pencil_tempo();
+            return;
+        }
+
         // get points in data space
         let (data_pos_1, data_pos_2) = {
             let mouse_info = &self.mouse_info;
@@ -238,7 +385,7 @@ impl DataEditing {
             VS_PianoRoll_DataViewState::PitchBend => {
 
             },
-            VS_PianoRoll_DataViewState::Hidden => {
+            _ => {
 
             }
         }
@@ -246,7 +393,7 @@ impl DataEditing {
 
     // ERASER EVENTS
     fn eraser_mouse_down(&mut self) {
-
+        self.update_last_mouse_data_pos();
     }
 
     fn eraser_mouse_move(&mut self) {
@@ -254,20 +401,100 @@ impl DataEditing {
     }
 
     fn eraser_mouse_up(&mut self) {
+        if self.get_dataview_state() != VS_PianoRoll_DataViewState::Tempo { return; }
+
+        let (start_tick, end_tick) = if self.is_mouse_click() {
+            // just a click, erase whatever point is under the mouse
+            match self.find_tempo_point_near_mouse() {
+                Some((_, tick)) => (tick, tick),
+                None => return
+            }
+        } else {
+            let (dp1, dp2) = (self.mouse_info.last_data_click_pos, self.mouse_info.mouse_data_pos);
+            (dp1.0.min(dp2.0), dp1.0.max(dp2.0))
+        };
+
+        // the tempo at tick 0 always has to exist
+        let start_tick = start_tick.max(1);
+        if start_tick > end_tick { return; }
 
+        let mut meta_editing = self.meta_editing.lock().unwrap();
+        meta_editing.replace_metas_in_range(MetaEventType::Tempo, start_tick, end_tick, Vec::new());
     }
 
     // SELECTOR EVENTS
     fn select_mouse_down(&mut self) {
+        // the mouse might have been released outside of the data view
+        if self.tempo_drag.is_some() { self.select_mouse_up(); }
+
+        self.update_last_mouse_data_pos();
+        if self.get_dataview_state() != VS_PianoRoll_DataViewState::Tempo { return; }
+
+        let Some((meta_idx, tick)) = self.find_tempo_point_near_mouse() else { return; };
+
+        let (old_meta, tick_range) = {
+            let meta_editing = self.meta_editing.lock().unwrap();
+            let metas = meta_editing.get_metas();
+            let metas = metas.read().unwrap();
+
+            let prev_tick = metas[..meta_idx].iter()
+                .rev()
+                .find(|m| m.event_type == MetaEventType::Tempo)
+                .map(|m| m.tick);
+            let next_tick = metas[meta_idx + 1..].iter()
+                .find(|m| m.event_type == MetaEventType::Tempo)
+                .map(|m| m.tick);
+
+            let tick_range = if tick == 0 {
+                // the first tempo stays pinned to the start
+                (0, 0)
+            } else {
+                (prev_tick.map_or(1, |t| t + 1), next_tick.map_or(MIDITick::MAX, |t| t.saturating_sub(1)))
+            };
+
+            (metas[meta_idx].clone(), tick_range)
+        };
 
+        self.tempo_drag = Some(TempoDragInfo {
+            old_idx: meta_idx,
+            old_tempo: bytes_as_tempo(&old_meta.data),
+            old_meta,
+            curr_idx: meta_idx,
+            tick_range
+        });
     }
 
     fn select_mouse_move(&mut self) {
+        let Some(drag) = self.tempo_drag.as_ref() else { return; };
+
+        let (min_tick, max_tick) = drag.tick_range;
+        let curr_idx = drag.curr_idx;
+        let moved_by = self.mouse_info.mouse_data_pos.1 - self.mouse_info.last_data_click_pos.1;
+        let new_data = if moved_by == 0.0 {
+            drag.old_meta.data.clone()
+        } else {
+            tempo_as_bytes((drag.old_tempo + moved_by).clamp(MIN_TEMPO_BPM, 60000000.0)).to_vec()
+        };
 
+        let new_tick = if min_tick == max_tick {
+            min_tick
+        } else {
+            (self.snap_tick(self.mouse_info.mouse_data_pos.0 as SignedMIDITick) as MIDITick).clamp(min_tick, max_tick)
+        };
+
+        let new_idx = {
+            let mut meta_editing = self.meta_editing.lock().unwrap();
+            meta_editing.move_meta_unregistered(curr_idx, new_tick, new_data)
+        };
+
+        self.tempo_drag.as_mut().unwrap().curr_idx = new_idx;
     }
 
     fn select_mouse_up(&mut self) {
-        
+        let Some(drag) = self.tempo_drag.take() else { return; };
+
+        let mut meta_editing = self.meta_editing.lock().unwrap();
+        meta_editing.register_meta_moved(drag.old_idx, drag.old_meta, drag.curr_idx);
     }
 
     // ======== DATA EDITING ========
@@ -305,6 +532,95 @@ impl DataEditing {
         editor_actions.register_action(EditorAction::VelocityChange(ids, vel_changes, curr_track));
     }
 
+    // ======== TEMPO EDITING ========
+
+    /// A click places a single tempo point, a drag draws a ramp between the two points.
+    fn pencil_tempo(&mut self) {
+        let (start_pos, end_pos) = {
+            let (dp1, dp2) = (self.mouse_info.last_data_click_pos, self.mouse_info.mouse_data_pos);
+            if dp1.0 > dp2.0 { (dp2, dp1) }
+            else { (dp1, dp2) }
+        };
+
+        let start_tick = self.snap_tick(start_pos.0 as SignedMIDITick) as MIDITick;
+        let end_tick = self.snap_tick(end_pos.0 as SignedMIDITick) as MIDITick;
+
+        let new_tempos = if self.is_mouse_click() || start_tick == end_tick {
+            let tick = self.snap_tick(self.mouse_info.mouse_data_pos.0 as SignedMIDITick) as MIDITick;
+            vec![MetaEvent {
+                tick,
+                event_type: MetaEventType::Tempo,
+                data: tempo_as_bytes(self.mouse_info.mouse_data_pos.1).to_vec()
+            }]
+        } else {
+            self.build_tempo_ramp(start_tick, start_pos.1, end_tick, end_pos.1)
+        };
+
+        let (range_start, range_end) = (new_tempos[0].tick, new_tempos[new_tempos.len() - 1].tick);
+
+        let mut meta_editing = self.meta_editing.lock().unwrap();
+        meta_editing.replace_metas_in_range(MetaEventType::Tempo, range_start, range_end, new_tempos);
+    }
+
+    /// Expands a ramp into stepped tempo events, spaced by [`TempoRampSettings::steps_per_beat`].
+    pub fn build_tempo_ramp(&self, start_tick: MIDITick, start_tempo: f32, end_tick: MIDITick, end_tempo: f32) -> Vec<MetaEvent> {
+        let step = (self.ppq as MIDITick / self.tempo_ramp.steps_per_beat.value() as MIDITick).max(1);
+        let shape = self.tempo_ramp.shape;
+        let curve = self.tempo_ramp.curve.value();
+
+        let ramp_length = (end_tick - start_tick) as f32;
+        let mut tempos: Vec<MetaEvent> = Vec::new();
+
+        let mut push_tempo = |tick: MIDITick, tempo: f32| {
+            let data = tempo_as_bytes(tempo).to_vec();
+            // no point in repeating the same tempo
+            if let Some(last) = tempos.last() {
+                if last.data == data { return; }
+            }
+            tempos.push(MetaEvent { tick, event_type: MetaEventType::Tempo, data });
+        };
+
+        let mut tick = start_tick;
+        while tick < end_tick {
+            let t = shape.apply((tick - start_tick) as f32 / ramp_length, curve);
+            push_tempo(tick, start_tempo + (end_tempo - start_tempo) * t);
+            tick += step;
+        }
+        push_tempo(end_tick, end_tempo);
+
+        tempos
+    }
+
+    /// Returns the index and tick of the tempo point closest to the mouse, if it's close enough to grab.
+    fn find_tempo_point_near_mouse(&self) -> Option<(usize, MIDITick)> {
+        let (mouse_x, mouse_y) = self.mouse_info.mouse_screen_pos;
+
+        let meta_editing = self.meta_editing.lock().unwrap();
+        let metas = meta_editing.get_metas();
+        let metas = metas.read().unwrap();
+
+        let mut closest = None;
+        let mut closest_dist = TEMPO_POINT_HIT_RADIUS;
+
+        for (i, meta) in metas.iter().enumerate() {
+            if meta.event_type != MetaEventType::Tempo { continue; }
+
+            let tempo = bytes_as_tempo(&meta.data).clamp(TEMPO_LANE_MIN_BPM, TEMPO_LANE_MAX_BPM);
+            let (x, y) = self.data_pos_to_screen_pos((meta.tick, tempo));
+
+            // points are drawn as the top-left corner of each tempo segment, so only the x distance has to be tight
+            if (mouse_y - y).abs() > TEMPO_POINT_HIT_RADIUS * 4.0 { continue; }
+
+            let dist = (mouse_x - x).abs();
+            if dist <= closest_dist {
+                closest_dist = dist;
+                closest = Some((i, meta.tick));
+            }
+        }
+
+        closest
+    }
+
     // ======== FLAG HELPER FUNCTIONS ========
 
     #[inline(always)]
@@ -350,4 +666,32 @@ impl DataEditing {
         let nav = self.nav.lock().unwrap();
         nav.curr_track
     }
+
+    fn get_dataview_state(&self) -> VS_PianoRoll_DataViewState {
+        let vs = self.view_settings.lock().unwrap();
+        vs.pr_dataview_state
+    }
+
+    /// If the mouse barely moved since it was pressed.
+    fn is_mouse_click(&self) -> bool {
+        let (x1, y1) = self.mouse_info.last_screen_click_pos;
+        let (x2, y2) = self.mouse_info.mouse_screen_pos;
+        (x2 - x1).abs() < 3.0 && (y2 - y1).abs() < 3.0
+    }
+
+    fn snap_tick(&self, tick: SignedMIDITick) -> SignedMIDITick {
+        let snap = self.get_min_snap_tick_length() as SignedMIDITick;
+        if snap == 1 { return tick.max(0); }
+
+        let half = snap / 2;
+        (((tick + half) / snap) * snap).max(0)
+    }
+
+    fn get_min_snap_tick_length(&self) -> MIDITick {
+        let editor_tool = self.editor_tool.try_borrow().unwrap();
+        let snap_ratio = editor_tool.snap_ratio;
+        if snap_ratio.0 == 0 { return 1; }
+        return (self.ppq as MIDITick * 4 * snap_ratio.0 as MIDITick)
+            /  snap_ratio.1 as MIDITick;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,918 @@
+// event_list_editing.rs - the event list view: every event of the current track in one long table,
+// for fixing stray controllers and metas that the piano roll and data view don't show.
+
+use std::{cell::RefCell, rc::Rc, sync::{Arc, Mutex, RwLock}};
+
+use eframe::egui::{self, Ui};
+
+use crate::{
+    editor::{
+        actions::{EditorAction, EditorActions},
+        editing::{
+            SharedSelectedNotes,
+            meta_editing::{META_TEXT_MAX_LEN, MetaEditing},
+            note_editing::note_sequence_funcs::{extract, extract_and_remap_ids, insert_at, merge_notes_and_return_ids}
+        },
+        navigation::PianoRollNavigation,
+        util::{MIDITick, MIN_TEMPO_BPM, key_to_name, name_to_key, tempo_as_bytes}
+    },
+    midi::{events::{channel_event::{ChannelEvent, ChannelEventType}, meta_event::{KeySignature, MetaEvent, MetaEventType}, note::Note}, midi_track::MIDITrack},
+    util::{debugger::Debugger, expression_parser::eval_str}
+};
+
+const ROW_HEIGHT: f32 = 18.0;
+const COLUMN_WIDTHS: [f32; 6] = [90.0, 120.0, 40.0, 140.0, 90.0, 90.0];
+const COLUMN_NAMES: [&str; 6] = ["Tick", "Type", "Ch.", "Value 1", "Value 2", "Length"];
+
+/// Points at one event. Notes, channel events and track metas are indices into the current track,
+/// global metas are indices into the project's metas.
+#[derive(Clone, Copy, PartialEq)]
+pub enum EventRef {
+    Note(usize),
+    Channel(usize),
+    TrackMeta(usize),
+    GlobalMeta(usize)
+}
+
+/// Which events the list shows.
+#[derive(Clone, PartialEq)]
+pub struct EventListFilter {
+    pub notes: bool,
+    pub controllers: bool,
+    pub program_changes: bool,
+    pub pitch_bends: bool,
+    pub aftertouch: bool,
+    pub track_metas: bool,
+    pub global_metas: bool,
+    /// only show events on this channel. metas don't have one, so they're unaffected
+    pub channel: Option<u8>
+}
+
+impl Default for EventListFilter {
+    fn default() -> Self {
+        Self {
+            notes: true,
+            controllers: true,
+            program_changes: true,
+            pitch_bends: true,
+            aftertouch: true,
+            track_metas: true,
+            global_metas: true,
+            channel: None
+        }
+    }
+}
+
+impl EventListFilter {
+    fn allows_channel_event(&self, ev: &ChannelEvent) -> bool {
+        if self.channel.is_some_and(|channel| channel != ev.channel) { return false; }
+
+        match ev.event_type {
+            ChannelEventType::NoteOn(..) | ChannelEventType::NoteOff(..) => self.notes,
+            ChannelEventType::Controller(..) => self.controllers,
+            ChannelEventType::ProgramChange(..) => self.program_changes,
+            ChannelEventType::PitchBend(..) => self.pitch_bends,
+            ChannelEventType::NoteAftertouch(..) | ChannelEventType::ChannelAftertouch(..) => self.aftertouch
+        }
+    }
+}
+
+/// Events that can be inserted from the list.
+#[derive(Clone, Copy, PartialEq)]
+pub enum EventInsertKind {
+    Note,
+    Controller,
+    ProgramChange,
+    PitchBend,
+    ChannelAftertouch,
+    TrackText,
+    Tempo,
+    Marker
+}
+
+impl EventInsertKind {
+    pub const ALL: [EventInsertKind; 8] = [
+        EventInsertKind::Note, EventInsertKind::Controller, EventInsertKind::ProgramChange, EventInsertKind::PitchBend,
+        EventInsertKind::ChannelAftertouch, EventInsertKind::TrackText, EventInsertKind::Tempo, EventInsertKind::Marker
+    ];
+}
+
+impl ToString for EventInsertKind {
+    fn to_string(&self) -> String {
+        match self {
+            EventInsertKind::Note => "Note",
+            EventInsertKind::Controller => "Controller",
+            EventInsertKind::ProgramChange => "Program change",
+            EventInsertKind::PitchBend => "Pitch bend",
+            EventInsertKind::ChannelAftertouch => "Channel aftertouch",
+            EventInsertKind::TrackText => "Text (track)",
+            EventInsertKind::Tempo => "Tempo",
+            EventInsertKind::Marker => "Marker"
+        }.to_string()
+    }
+}
+
+/// The columns of a row as text: tick, channel, value 1, value 2, length.
+#[derive(Clone, Default, PartialEq)]
+struct RowFields([String; 5]);
+
+/// How a row looks, and which of its fields can be typed into.
+struct RowView {
+    kind: String,
+    fields: RowFields,
+    editable: [bool; 5]
+}
+
+/// Everything the row list was built from. The rows get rebuilt whenever any of it changes.
+#[derive(Clone, PartialEq)]
+struct RowsKey {
+    track: u16,
+    filter: EventListFilter,
+    change_count: u64,
+    counts: [usize; 4]
+}
+
+enum RowCommand {
+    Select(EventRef),
+    Edit(EventRef, RowFields),
+    Delete(EventRef),
+    Insert(EventInsertKind, MIDITick, u8)
+}
+
+fn format_channel_event(ev: &ChannelEvent) -> (String, [String; 2]) {
+    match ev.event_type {
+        ChannelEventType::NoteOn(key, velocity) => ("Note on".into(), [key_to_name(key), velocity.to_string()]),
+        ChannelEventType::NoteOff(key) => ("Note off".into(), [key_to_name(key), String::new()]),
+        ChannelEventType::NoteAftertouch(key, pressure) => ("Key aftertouch".into(), [key_to_name(key), pressure.to_string()]),
+        ChannelEventType::Controller(controller, value) => ("Controller".into(), [controller.to_string(), value.to_string()]),
+        ChannelEventType::ProgramChange(program) => ("Program change".into(), [program.to_string(), String::new()]),
+        ChannelEventType::ChannelAftertouch(pressure) => ("Channel aftertouch".into(), [pressure.to_string(), String::new()]),
+        ChannelEventType::PitchBend(lsb, msb) => {
+            let value = (((msb as i32) << 7) | lsb as i32) - 8192;
+            ("Pitch bend".into(), [value.to_string(), String::new()])
+        }
+    }
+}
+
+/// The value column of a meta, and whether it can be edited as text.
+fn format_meta_value(meta: &MetaEvent) -> (String, bool) {
+    match meta.event_type {
+        MetaEventType::Tempo if meta.data.len() >= 3 => (meta.get_value_string(), true),
+        MetaEventType::TimeSignature if meta.data.len() >= 2 => (format!("{}/{}", meta.data[0], 1u32 << meta.data[1].min(31)), true),
+        MetaEventType::KeySignature if meta.data.len() >= 2 => (KeySignature::from_meta_data(&meta.data).name(), false),
+        meta_type if meta_type.is_text() => (String::from_utf8_lossy(&meta.data).to_string(), true),
+        _ => (meta.data.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" "), false)
+    }
+}
+
+/// Parses a number (or an expression like `960*4`) within `[min, max]`.
+fn parse_field(text: &str, name: &str, min: i64, max: i64) -> Result<i64, String> {
+    let text = text.trim();
+    let value = match text.parse::<i64>() {
+        Ok(value) => value as f64,
+        Err(_) => eval_str(text).map_err(|err| format!("{}: {}", name, err))?
+    };
+
+    if !value.is_finite() { return Err(format!("{}: '{}' isn't a number.", name, text)); }
+
+    let value = value.round() as i64;
+    if value < min || value > max { return Err(format!("{} has to be between {} and {}.", name, min, max)); }
+    Ok(value)
+}
+
+fn parse_key_field(text: &str, name: &str) -> Result<u8, String> {
+    name_to_key(text).ok_or_else(|| format!("{}: '{}' isn't a key. Use a number or a name like C4.", name, text.trim()))
+}
+
+/// Merges two row lists sorted by tick. Rows from `a` come first on equal ticks.
+fn merge_rows(a: Vec<(MIDITick, EventRef)>, b: Vec<(MIDITick, EventRef)>) -> Vec<(MIDITick, EventRef)> {
+    if a.is_empty() { return b; }
+    if b.is_empty() { return a; }
+
+    let mut merged = Vec::with_capacity(a.len() + b.len());
+    let mut a_iter = a.into_iter().peekable();
+    let mut b_iter = b.into_iter().peekable();
+
+    loop {
+        let take_a = match (a_iter.peek(), b_iter.peek()) {
+            (Some(row_a), Some(row_b)) => row_a.0 <= row_b.0,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => break
+        };
+
+        merged.push(if take_a { a_iter.next().unwrap() } else { b_iter.next().unwrap() });
+    }
+
+    merged
+}
+
+pub struct EventListEditing {
+    tracks: Arc<RwLock<Vec<MIDITrack>>>,
+    global_metas: Arc<RwLock<Vec<MetaEvent>>>,
+    nav: Arc<Mutex<PianoRollNavigation>>,
+    editor_actions: Rc<RefCell<EditorActions>>,
+    shared_selected_notes: Arc<RwLock<SharedSelectedNotes>>,
+    meta_editing: Arc<Mutex<MetaEditing>>,
+
+    pub filter: EventListFilter,
+    insert_kind: EventInsertKind,
+
+    rows: Vec<(MIDITick, EventRef)>,
+    rows_key: Option<RowsKey>,
+
+    selected: Option<EventRef>,
+    // what's typed into the selected row. follows the event while none of its fields are focused
+    edit_fields: RowFields,
+    scroll_to_row: Option<usize>,
+    error: Option<String>,
+
+    pub ppq: u16,
+}
+
+impl Default for EventListEditing {
+    fn default() -> Self {
+        Self::new(&Default::default(), &Default::default(), &Default::default(), &Default::default(), &Default::default(), &Default::default())
+    }
+}
+
+impl EventListEditing {
+    pub fn new(
+        tracks: &Arc<RwLock<Vec<MIDITrack>>>,
+        global_metas: &Arc<RwLock<Vec<MetaEvent>>>,
+        nav: &Arc<Mutex<PianoRollNavigation>>,
+        editor_actions: &Rc<RefCell<EditorActions>>,
+        shared_selected_notes: &Arc<RwLock<SharedSelectedNotes>>,
+        meta_editing: &Arc<Mutex<MetaEditing>>
+    ) -> Self {
+        Self {
+            tracks: tracks.clone(),
+            global_metas: global_metas.clone(),
+            nav: nav.clone(),
+            editor_actions: editor_actions.clone(),
+            shared_selected_notes: shared_selected_notes.clone(),
+            meta_editing: meta_editing.clone(),
+
+            filter: EventListFilter::default(),
+            insert_kind: EventInsertKind::Controller,
+
+            rows: Vec::new(),
+            rows_key: None,
+
+            selected: None,
+            edit_fields: RowFields::default(),
+            scroll_to_row: None,
+            error: None,
+
+            ppq: 960
+        }
+    }
+
+    fn get_current_track(&self) -> u16 {
+        let nav = self.nav.lock().unwrap();
+        nav.curr_track
+    }
+
+    // ======== ROWS ========
+
+    fn get_rows_key(&self) -> RowsKey {
+        let track = self.get_current_track();
+        let change_count = {
+            let editor_actions = self.editor_actions.borrow();
+            editor_actions.get_change_count()
+        };
+
+        let tracks = self.tracks.read().unwrap();
+        let global_metas = self.global_metas.read().unwrap();
+        let counts = match tracks.get(track as usize) {
+            Some(t) => [t.get_notes().len(), t.get_channel_evs().len(), t.get_meta_events().len(), global_metas.len()],
+            None => [0, 0, 0, global_metas.len()]
+        };
+
+        RowsKey { track, filter: self.filter.clone(), change_count, counts }
+    }
+
+    fn refresh_rows(&mut self) {
+        let key = self.get_rows_key();
+        if self.rows_key.as_ref() == Some(&key) { return; }
+
+        self.rebuild_rows(key.track);
+        self.rows_key = Some(key);
+
+        // the selected event might be gone (or not shown anymore)
+        if let Some(selected) = self.selected {
+            if !self.rows.iter().any(|(_, row)| *row == selected) { self.selected = None; }
+        }
+    }
+
+    fn rebuild_rows(&mut self, track: u16) {
+        let filter = &self.filter;
+        let tracks = self.tracks.read().unwrap();
+        let global_metas = self.global_metas.read().unwrap();
+
+        // on equal ticks: global metas, track metas, channel events, notes
+        let mut sources: Vec<Vec<(MIDITick, EventRef)>> = Vec::with_capacity(4);
+
+        if filter.global_metas {
+            sources.push(global_metas.iter().enumerate().map(|(i, m)| (m.tick, EventRef::GlobalMeta(i))).collect());
+        }
+
+        if let Some(t) = tracks.get(track as usize) {
+            if filter.track_metas {
+                sources.push(t.get_meta_events().iter().enumerate().map(|(i, m)| (m.tick, EventRef::TrackMeta(i))).collect());
+            }
+
+            sources.push(t.get_channel_evs().iter().enumerate()
+                .filter(|(_, ev)| filter.allows_channel_event(ev))
+                .map(|(i, ev)| (ev.tick, EventRef::Channel(i)))
+                .collect());
+
+            if filter.notes {
+                sources.push(t.get_notes().iter().enumerate()
+                    .filter(|(_, n)| filter.channel.map_or(true, |channel| channel == n.channel()))
+                    .map(|(i, n)| (n.start(), EventRef::Note(i)))
+                    .collect());
+            }
+        }
+
+        self.rows = sources.into_iter().fold(Vec::new(), merge_rows);
+    }
+
+    fn get_row_view(event: EventRef, tracks: &[MIDITrack], global_metas: &[MetaEvent], track: u16) -> Option<RowView> {
+        let t = tracks.get(track as usize);
+
+        match event {
+            EventRef::Note(i) => {
+                let note = t?.get_notes().get(i)?;
+                Some(RowView {
+                    kind: "Note".into(),
+                    fields: RowFields([
+                        note.start().to_string(), (note.channel() + 1).to_string(),
+                        key_to_name(note.key()), note.velocity().to_string(), note.length().to_string()
+                    ]),
+                    editable: [true; 5]
+                })
+            },
+            EventRef::Channel(i) => {
+                let ev = t?.get_channel_evs().get(i)?;
+                let (kind, [value_1, value_2]) = format_channel_event(ev);
+                let has_value_2 = !value_2.is_empty();
+                Some(RowView {
+                    kind,
+                    fields: RowFields([ev.tick.to_string(), (ev.channel + 1).to_string(), value_1, value_2, String::new()]),
+                    editable: [true, true, true, has_value_2, false]
+                })
+            },
+            EventRef::TrackMeta(i) | EventRef::GlobalMeta(i) => {
+                let meta = match event {
+                    EventRef::TrackMeta(_) => t?.get_meta_events().get(i)?,
+                    _ => global_metas.get(i)?
+                };
+
+                let (value, value_editable) = format_meta_value(meta);
+                let kind = match event {
+                    EventRef::TrackMeta(_) => meta.event_type.to_string(),
+                    _ => format!("{} (global)", meta.event_type.to_string())
+                };
+
+                Some(RowView {
+                    kind,
+                    fields: RowFields([meta.tick.to_string(), String::new(), value, String::new(), String::new()]),
+                    editable: [true, false, value_editable, false, false]
+                })
+            }
+        }
+    }
+
+    // ======== EDITING ========
+
+    /// Builds the edited event out of the typed fields and puts it in place of the old one.
+    fn apply_row_edit(&mut self, event: EventRef, fields: &RowFields) -> Result<EventRef, String> {
+        let track = self.get_current_track();
+        let [tick, channel, value_1, value_2, length] = &fields.0;
+        let tick = parse_field(tick, "Tick", 0, MIDITick::MAX as i64)? as MIDITick;
+
+        match event {
+            EventRef::Note(i) => {
+                let note = Note {
+                    start: tick,
+                    channel: parse_field(channel, "Channel", 1, 16)? as u8 - 1,
+                    key: parse_key_field(value_1, "Key")?,
+                    velocity: parse_field(value_2, "Velocity", 1, 127)? as u8,
+                    length: parse_field(length, "Length", 1, MIDITick::MAX as i64)? as MIDITick
+                };
+                Ok(self.replace_note(track, i, note))
+            },
+            EventRef::Channel(i) => {
+                let old_type = {
+                    let tracks = self.tracks.read().unwrap();
+                    tracks[track as usize].get_channel_evs()[i].event_type.clone()
+                };
+
+                let event_type = match old_type {
+                    ChannelEventType::NoteOn(..) => ChannelEventType::NoteOn(parse_key_field(value_1, "Key")?, parse_field(value_2, "Velocity", 0, 127)? as u8),
+                    ChannelEventType::NoteOff(_) => ChannelEventType::NoteOff(parse_key_field(value_1, "Key")?),
+                    ChannelEventType::NoteAftertouch(..) => ChannelEventType::NoteAftertouch(parse_key_field(value_1, "Key")?, parse_field(value_2, "Pressure", 0, 127)? as u8),
+                    ChannelEventType::Controller(..) => ChannelEventType::Controller(parse_field(value_1, "Controller", 0, 127)? as u8, parse_field(value_2, "Value", 0, 127)? as u8),
+                    ChannelEventType::ProgramChange(_) => ChannelEventType::ProgramChange(parse_field(value_1, "Program", 0, 127)? as u8),
+                    ChannelEventType::ChannelAftertouch(_) => ChannelEventType::ChannelAftertouch(parse_field(value_1, "Pressure", 0, 127)? as u8),
+                    ChannelEventType::PitchBend(..) => {
+                        let value = (parse_field(value_1, "Pitch bend", -8192, 8191)? + 8192) as u16;
+                        ChannelEventType::PitchBend((value & 0x7F) as u8, (value >> 7) as u8)
+                    }
+                };
+
+                let ev = ChannelEvent { tick, channel: parse_field(channel, "Channel", 1, 16)? as u8 - 1, event_type };
+                Ok(self.replace_channel_event(track, i, ev))
+            },
+            EventRef::TrackMeta(i) | EventRef::GlobalMeta(i) => {
+                let old_meta = match event {
+                    EventRef::TrackMeta(_) => {
+                        let tracks = self.tracks.read().unwrap();
+                        tracks[track as usize].get_meta_events()[i].clone()
+                    },
+                    _ => self.global_metas.read().unwrap()[i].clone()
+                };
+
+                let data = if format_meta_value(&old_meta).0 == value_1.as_str() {
+                    old_meta.data.clone()
+                } else {
+                    Self::parse_meta_data(&old_meta, value_1)?
+                };
+
+                let meta = MetaEvent { tick, event_type: old_meta.event_type, data };
+                Ok(match event {
+                    EventRef::TrackMeta(_) => self.replace_track_meta(track, i, meta),
+                    _ => self.replace_global_meta(i, old_meta, meta)
+                })
+            }
+        }
+    }
+
+    fn parse_meta_data(old_meta: &MetaEvent, text: &str) -> Result<Vec<u8>, String> {
+        match old_meta.event_type {
+            MetaEventType::Tempo => {
+                let bpm: f32 = text.trim().parse().map_err(|_| format!("'{}' isn't a tempo.", text.trim()))?;
+                if !(MIN_TEMPO_BPM..=60000000.0).contains(&bpm) { return Err("The tempo is out of range.".into()); }
+                Ok(tempo_as_bytes(bpm).to_vec())
+            },
+            MetaEventType::TimeSignature => {
+                let (num, den) = text.split_once('/').ok_or("Expected a time signature like 3/4.")?;
+                let num = parse_field(num, "Numerator", 1, 255)? as u8;
+                let den = parse_field(den, "Denominator", 1, 128)? as u32;
+                if !den.is_power_of_two() { return Err("The denominator has to be a power of two.".into()); }
+
+                let mut data = old_meta.data.clone();
+                data[0] = num;
+                data[1] = den.trailing_zeros() as u8;
+                Ok(data)
+            },
+            meta_type if meta_type.is_text() => {
+                let mut text_len = text.len().min(META_TEXT_MAX_LEN);
+                while !text.is_char_boundary(text_len) { text_len -= 1; }
+                Ok(text.as_bytes()[..text_len].to_vec())
+            },
+            _ => Ok(old_meta.data.clone())
+        }
+    }
+
+    /// Takes note `id` out of `track`, keeping the selection of the other notes intact.
+    fn take_note(&mut self, track: u16, id: usize) -> Note {
+        let mut tracks = self.tracks.write().unwrap();
+        let notes = std::mem::take(tracks[track as usize].get_notes_mut());
+
+        let mut selected = self.shared_selected_notes.write().unwrap();
+        let (mut taken, remaining, remapped) = extract_and_remap_ids(notes, &[id], selected.take_selected_from_track(track));
+        selected.set_selected_in_track(remapped, track);
+
+        *tracks[track as usize].get_notes_mut() = remaining;
+        taken.pop().unwrap()
+    }
+
+    /// Merges `note` into `track` and returns where it ended up.
+    fn put_note(&mut self, track: u16, note: Note) -> usize {
+        let mut tracks = self.tracks.write().unwrap();
+        let notes = std::mem::take(tracks[track as usize].get_notes_mut());
+        let (merged, new_ids) = merge_notes_and_return_ids(notes, vec![note]);
+        *tracks[track as usize].get_notes_mut() = merged;
+
+        let new_id = new_ids[0];
+        let mut selected = self.shared_selected_notes.write().unwrap();
+        let mut remapped = selected.take_selected_from_track(track);
+        for id in remapped.iter_mut() { if *id >= new_id { *id += 1; } }
+        selected.set_selected_in_track(remapped, track);
+
+        new_id
+    }
+
+    fn replace_note(&mut self, track: u16, id: usize, note: Note) -> EventRef {
+        let old_note = self.take_note(track, id);
+        let new_id = self.put_note(track, note);
+
+        // bulk actions are undone front to back, so the addition comes first
+        let mut editor_actions = self.editor_actions.borrow_mut();
+        editor_actions.register_action(EditorAction::Bulk(vec![
+            EditorAction::PlaceNotes(vec![new_id], None, track),
+            EditorAction::DeleteNotes(vec![id], Some(vec![old_note]), track)
+        ]));
+
+        EventRef::Note(new_id)
+    }
+
+    fn replace_channel_event(&mut self, track: u16, id: usize, ev: ChannelEvent) -> EventRef {
+        let (old_ev, new_id) = {
+            let mut tracks = self.tracks.write().unwrap();
+            let channel_evs = tracks[track as usize].get_channel_evs_mut();
+            let old_ev = channel_evs.remove(id);
+            let new_id = channel_evs.partition_point(|e| e.tick <= ev.tick);
+            channel_evs.insert(new_id, ev);
+            (old_ev, new_id)
+        };
+
+        let mut editor_actions = self.editor_actions.borrow_mut();
+        editor_actions.register_action(EditorAction::Bulk(vec![
+            EditorAction::AddChannelEvents(vec![new_id], None, track),
+            EditorAction::DeleteChannelEvents(vec![id], Some(vec![old_ev]), track)
+        ]));
+
+        EventRef::Channel(new_id)
+    }
+
+    fn replace_track_meta(&mut self, track: u16, id: usize, meta: MetaEvent) -> EventRef {
+        let (old_meta, new_id) = {
+            let mut tracks = self.tracks.write().unwrap();
+            let metas = tracks[track as usize].get_meta_events_mut();
+            let old_meta = metas.remove(id);
+            let new_id = metas.partition_point(|m| m.tick <= meta.tick);
+            metas.insert(new_id, meta);
+            (old_meta, new_id)
+        };
+
+        let mut editor_actions = self.editor_actions.borrow_mut();
+        editor_actions.register_action(EditorAction::Bulk(vec![
+            EditorAction::AddTrackMetas(vec![new_id], None, track),
+            EditorAction::DeleteTrackMetas(vec![id], Some(vec![old_meta]), track)
+        ]));
+
+        EventRef::TrackMeta(new_id)
+    }
+
+    fn replace_global_meta(&mut self, id: usize, old_meta: MetaEvent, meta: MetaEvent) -> EventRef {
+        let mut meta_editing = self.meta_editing.lock().unwrap();
+        if meta.tick == old_meta.tick {
+            meta_editing.set_meta_data(id, meta.data);
+            return EventRef::GlobalMeta(id);
+        }
+
+        let new_id = meta_editing.move_meta_unregistered(id, meta.tick, meta.data);
+        meta_editing.register_meta_moved(id, old_meta, new_id);
+        EventRef::GlobalMeta(new_id)
+    }
+
+    fn delete_event(&mut self, event: EventRef) {
+        let track = self.get_current_track();
+
+        let action = match event {
+            EventRef::Note(i) => {
+                let note = self.take_note(track, i);
+                EditorAction::DeleteNotes(vec![i], Some(vec![note]), track)
+            },
+            EventRef::Channel(i) => {
+                let mut tracks = self.tracks.write().unwrap();
+                let ev = tracks[track as usize].get_channel_evs_mut().remove(i);
+                EditorAction::DeleteChannelEvents(vec![i], Some(vec![ev]), track)
+            },
+            EventRef::TrackMeta(i) => {
+                let mut tracks = self.tracks.write().unwrap();
+                let meta = tracks[track as usize].get_meta_events_mut().remove(i);
+                EditorAction::DeleteTrackMetas(vec![i], Some(vec![meta]), track)
+            },
+            EventRef::GlobalMeta(i) => {
+                // registers its own action
+                let mut meta_editing = self.meta_editing.lock().unwrap();
+                meta_editing.delete_metas(vec![i]);
+                return;
+            }
+        };
+
+        let mut editor_actions = self.editor_actions.borrow_mut();
+        editor_actions.register_action(action);
+    }
+
+    fn insert_event(&mut self, kind: EventInsertKind, tick: MIDITick, channel: u8) -> Option<EventRef> {
+        let track = self.get_current_track();
+        if self.tracks.read().unwrap().get(track as usize).is_none() { return None; }
+
+        let channel_ev_type = match kind {
+            EventInsertKind::Controller => Some(ChannelEventType::Controller(7, 100)),
+            EventInsertKind::ProgramChange => Some(ChannelEventType::ProgramChange(0)),
+            EventInsertKind::PitchBend => Some(ChannelEventType::PitchBend(0, 64)),
+            EventInsertKind::ChannelAftertouch => Some(ChannelEventType::ChannelAftertouch(0)),
+            _ => None
+        };
+
+        if let Some(event_type) = channel_ev_type {
+            let new_id = {
+                let mut tracks = self.tracks.write().unwrap();
+                let channel_evs = tracks[track as usize].get_channel_evs_mut();
+                let new_id = channel_evs.partition_point(|e| e.tick <= tick);
+                channel_evs.insert(new_id, ChannelEvent { tick, channel, event_type });
+                new_id
+            };
+
+            let mut editor_actions = self.editor_actions.borrow_mut();
+            editor_actions.register_action(EditorAction::AddChannelEvents(vec![new_id], None, track));
+            return Some(EventRef::Channel(new_id));
+        }
+
+        match kind {
+            EventInsertKind::Note => {
+                let note = Note { start: tick, length: self.ppq as MIDITick, key: 60, velocity: 100, channel };
+                let new_id = self.put_note(track, note);
+
+                let mut editor_actions = self.editor_actions.borrow_mut();
+                editor_actions.register_action(EditorAction::PlaceNotes(vec![new_id], None, track));
+                Some(EventRef::Note(new_id))
+            },
+            EventInsertKind::TrackText => {
+                let new_id = {
+                    let mut tracks = self.tracks.write().unwrap();
+                    let metas = tracks[track as usize].get_meta_events_mut();
+                    let new_id = metas.partition_point(|m| m.tick <= tick);
+                    metas.insert(new_id, MetaEvent { tick, event_type: MetaEventType::Text, data: b"Text".to_vec() });
+                    new_id
+                };
+
+                let mut editor_actions = self.editor_actions.borrow_mut();
+                editor_actions.register_action(EditorAction::AddTrackMetas(vec![new_id], None, track));
+                Some(EventRef::TrackMeta(new_id))
+            },
+            EventInsertKind::Tempo | EventInsertKind::Marker => {
+                let (event_type, data) = match kind {
+                    EventInsertKind::Tempo => (MetaEventType::Tempo, tempo_as_bytes(120.0).to_vec()),
+                    _ => (MetaEventType::Marker, b"Marker".to_vec())
+                };
+
+                {
+                    let mut meta_editing = self.meta_editing.lock().unwrap();
+                    meta_editing.add_meta(MetaEvent { tick, event_type, data });
+                }
+
+                let global_metas = self.global_metas.read().unwrap();
+                global_metas.iter().rposition(|m| m.tick == tick && m.event_type == event_type).map(EventRef::GlobalMeta)
+            },
+            _ => None
+        }
+    }
+
+    /// The channel of an event, if it has one.
+    fn get_event_channel(&self, event: EventRef) -> Option<u8> {
+        let track = self.get_current_track();
+        let tracks = self.tracks.read().unwrap();
+        let t = tracks.get(track as usize)?;
+
+        match event {
+            EventRef::Note(i) => t.get_notes().get(i).map(|n| n.channel()),
+            EventRef::Channel(i) => t.get_channel_evs().get(i).map(|ev| ev.channel),
+            _ => None
+        }
+    }
+
+    fn select_event(&mut self, event: Option<EventRef>) {
+        self.selected = event;
+        self.error = None;
+
+        // make sure the rows include the event before looking for it
+        self.refresh_rows();
+        if let Some(event) = event {
+            self.scroll_to_row = self.rows.iter().position(|(_, row)| *row == event);
+        }
+    }
+
+    fn run_command(&mut self, command: RowCommand) {
+        match command {
+            RowCommand::Select(event) => {
+                self.selected = Some(event);
+                self.error = None;
+            },
+            RowCommand::Edit(event, fields) => {
+                match self.apply_row_edit(event, &fields) {
+                    Ok(new_event) => self.select_event(Some(new_event)),
+                    Err(error) => self.error = Some(error)
+                }
+            },
+            RowCommand::Delete(event) => {
+                self.delete_event(event);
+                self.selected = None;
+                self.error = None;
+            },
+            RowCommand::Insert(kind, tick, channel) => {
+                let inserted = self.insert_event(kind, tick, channel);
+                if inserted.is_none() { Debugger::log_warning("Couldn't insert the event, the current track doesn't exist."); }
+                self.select_event(inserted);
+            }
+        }
+    }
+
+    pub fn apply_action(&mut self, action: &mut EditorAction) {
+        let mut tracks = self.tracks.write().unwrap();
+
+        match action {
+            EditorAction::AddChannelEvents(ev_ids, deleted_evs, track) => {
+                assert!(deleted_evs.is_some(), "[ADD CHANNEL EVENTS] Something has gone wrong while undoing channel event deletion.");
+                let channel_evs = tracks[*track as usize].get_channel_evs_mut();
+                let old_evs = std::mem::take(channel_evs);
+                *channel_evs = insert_at(old_evs, ev_ids, deleted_evs.take().unwrap());
+            },
+            EditorAction::DeleteChannelEvents(ev_ids, deleted_evs, track) => {
+                let channel_evs = tracks[*track as usize].get_channel_evs_mut();
+                let (deleted, remaining) = extract(std::mem::take(channel_evs), ev_ids);
+                *channel_evs = remaining;
+                *deleted_evs = Some(deleted);
+            },
+            EditorAction::AddTrackMetas(meta_ids, deleted_metas, track) => {
+                assert!(deleted_metas.is_some(), "[ADD TRACK METAS] Something has gone wrong while undoing meta deletion.");
+                let metas = tracks[*track as usize].get_meta_events_mut();
+                let old_metas = std::mem::take(metas);
+                *metas = insert_at(old_metas, meta_ids, deleted_metas.take().unwrap());
+            },
+            EditorAction::DeleteTrackMetas(meta_ids, deleted_metas, track) => {
+                let metas = tracks[*track as usize].get_meta_events_mut();
+                let (deleted, remaining) = extract(std::mem::take(metas), meta_ids);
+                *metas = remaining;
+                *deleted_metas = Some(deleted);
+            },
+            EditorAction::Bulk(actions) => {
+                drop(tracks);
+                for action in actions.iter_mut().rev() {
+                    self.apply_action(action);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // ======== UI ========
+
+    /// Draws the whole list. New events go on the selected event's tick, or on `playhead_tick` if nothing is selected.
+    pub fn draw(&mut self, ui: &mut Ui, playhead_tick: MIDITick) {
+        self.refresh_rows();
+
+        let mut command = None;
+        self.draw_controls(ui, playhead_tick, &mut command);
+        ui.separator();
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::LIGHT_RED, error);
+        }
+
+        ui.horizontal(|ui| {
+            for (name, width) in COLUMN_NAMES.iter().zip(COLUMN_WIDTHS) {
+                ui.add_sized([width, ROW_HEIGHT], egui::Label::new(egui::RichText::new(*name).strong()));
+            }
+        });
+        ui.separator();
+
+        let mut scroll_area = egui::ScrollArea::vertical().auto_shrink([false, false]);
+        if let Some(row) = self.scroll_to_row.take() {
+            let row_height = ROW_HEIGHT + ui.spacing().item_spacing.y;
+            scroll_area = scroll_area.vertical_scroll_offset((row as f32 * row_height - ui.available_height() * 0.5).max(0.0));
+        }
+
+        let track = self.get_current_track();
+        scroll_area.show_rows(ui, ROW_HEIGHT, self.rows.len(), |ui, range| {
+            let tracks = self.tracks.read().unwrap();
+            let global_metas = self.global_metas.read().unwrap();
+
+            for row in range {
+                let event = self.rows[row].1;
+                let Some(view) = Self::get_row_view(event, &tracks, &global_metas, track) else { continue; };
+
+                if self.selected == Some(event) {
+                    if let Some(fields) = Self::draw_selected_row(ui, view, &mut self.edit_fields) {
+                        command = Some(RowCommand::Edit(event, fields));
+                    }
+                } else {
+                    let response = ui.horizontal(|ui| {
+                        let texts = [&view.fields.0[0], &view.kind, &view.fields.0[1], &view.fields.0[2], &view.fields.0[3], &view.fields.0[4]];
+                        for (text, width) in texts.into_iter().zip(COLUMN_WIDTHS) {
+                            ui.add_sized([width, ROW_HEIGHT], egui::Label::new(text.as_str()).truncate().selectable(false));
+                        }
+                    }).response.interact(egui::Sense::click());
+
+                    // an edit committed by this same click wins over the selection change
+                    if response.clicked() && command.is_none() { command = Some(RowCommand::Select(event)); }
+                }
+            }
+        });
+
+        // delete the selected event with the delete key, unless something is being typed
+        if let Some(selected) = self.selected {
+            if !ui.ctx().wants_keyboard_input() && ui.input(|i| i.key_pressed(egui::Key::Delete)) {
+                command = Some(RowCommand::Delete(selected));
+            }
+        }
+
+        if let Some(command) = command { self.run_command(command); }
+    }
+
+    fn draw_controls(&mut self, ui: &mut Ui, playhead_tick: MIDITick, command: &mut Option<RowCommand>) {
+        ui.horizontal(|ui| {
+            ui.label(format!("Track {}", self.get_current_track()));
+            ui.separator();
+            ui.label(format!("{} events", self.rows.len()));
+            ui.separator();
+
+            ui.menu_button("Show...", |ui| {
+                ui.checkbox(&mut self.filter.notes, "Notes");
+                ui.checkbox(&mut self.filter.controllers, "Controllers");
+                ui.checkbox(&mut self.filter.program_changes, "Program changes");
+                ui.checkbox(&mut self.filter.pitch_bends, "Pitch bends");
+                ui.checkbox(&mut self.filter.aftertouch, "Aftertouch");
+                ui.checkbox(&mut self.filter.track_metas, "Track metas");
+                ui.checkbox(&mut self.filter.global_metas, "Global metas");
+            });
+
+            egui::ComboBox::from_id_salt("event_list_channel")
+                .selected_text(match self.filter.channel {
+                    Some(channel) => format!("Channel {}", channel + 1),
+                    None => "All channels".to_string()
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.filter.channel, None, "All channels");
+                    for channel in 0..16 {
+                        ui.selectable_value(&mut self.filter.channel, Some(channel), format!("Channel {}", channel + 1));
+                    }
+                });
+
+            ui.separator();
+
+            if ui.button("Go to playhead").clicked() {
+                let row = self.rows.partition_point(|(tick, _)| *tick < playhead_tick);
+                self.scroll_to_row = Some(row.min(self.rows.len().saturating_sub(1)));
+            }
+
+            ui.separator();
+
+            egui::ComboBox::from_id_salt("event_list_insert_kind")
+                .selected_text(self.insert_kind.to_string())
+                .show_ui(ui, |ui| {
+                    for kind in EventInsertKind::ALL {
+                        ui.selectable_value(&mut self.insert_kind, kind, kind.to_string());
+                    }
+                });
+
+            if ui.button("Insert").clicked() {
+                let (tick, channel) = match self.selected {
+                    Some(event) => (
+                        self.rows.iter().find(|(_, row)| *row == event).map(|(tick, _)| *tick).unwrap_or(playhead_tick),
+                        self.get_event_channel(event)
+                    ),
+                    None => (playhead_tick, None)
+                };
+
+                let channel = channel.or(self.filter.channel).unwrap_or(0);
+                *command = Some(RowCommand::Insert(self.insert_kind, tick, channel));
+            }
+
+            if ui.add_enabled(self.selected.is_some(), egui::Button::new("Delete")).clicked() {
+                *command = self.selected.map(RowCommand::Delete);
+            }
+        });
+    }
+
+    /// Draws the selected row with text fields. Returns the typed fields once an edited field loses focus.
+    fn draw_selected_row(ui: &mut Ui, view: RowView, edit_fields: &mut RowFields) -> Option<RowFields> {
+        let mut any_focused = false;
+        let mut committed = false;
+        let mut cancelled = false;
+
+        ui.horizontal(|ui| {
+            let bg = ui.visuals().selection.bg_fill.gamma_multiply(0.4);
+            ui.painter().rect_filled(ui.max_rect().with_max_y(ui.max_rect().min.y + ROW_HEIGHT), 0.0, bg);
+
+            // tick, type, channel, value 1, value 2, length
+            let field_columns = [Some(0), None, Some(1), Some(2), Some(3), Some(4)];
+            for (column, width) in field_columns.into_iter().zip(COLUMN_WIDTHS) {
+                match column {
+                    Some(field) if view.editable[field] => {
+                        let response = ui.add_sized([width, ROW_HEIGHT], egui::TextEdit::singleline(&mut edit_fields.0[field]));
+                        any_focused |= response.has_focus();
+                        if response.lost_focus() {
+                            if ui.input(|i| i.key_pressed(egui::Key::Escape)) { cancelled = true; } else { committed = true; }
+                        }
+                    },
+                    Some(field) => { ui.add_sized([width, ROW_HEIGHT], egui::Label::new(view.fields.0[field].as_str()).truncate()); },
+                    None => { ui.add_sized([width, ROW_HEIGHT], egui::Label::new(egui::RichText::new(view.kind.as_str()).strong()).truncate()); }
+                }
+            }
+        });
+
+        if committed && *edit_fields != view.fields {
+            return Some(std::mem::replace(edit_fields, view.fields));
+        }
+
+        if cancelled || !any_focused { *edit_fields = view.fields; }
+        None
+    }
+}
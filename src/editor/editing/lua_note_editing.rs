@@ -1,8 +1,8 @@
-use std::{collections::HashMap, sync::{Arc, Mutex}};
+use std::{collections::HashMap, sync::{Arc, Mutex, RwLock}};
 
-use mlua::{Function, IntoLua, Lua, UserData};
+use mlua::{Function, IntoLua, Lua, Table, UserData};
 
-use crate::{editor::{actions::{EditorAction, EditorActions}, editing::note_editing::{note_sequence_funcs::{extract_with, merge_notes_and_return_ids}, NoteEditing}, util::{get_min_max_keys_in_selection, get_min_max_ticks_in_selection, MIDITick, SignedMIDITick}}, midi::events::note::{Note}};
+use crate::{editor::{actions::{EditorAction, EditorActions}, editing::{meta_editing::MetaEditing, note_editing::{note_sequence_funcs::{extract, extract_and_remap_ids, extract_with, merge_notes_and_return_ids}, NoteEditing}}, plugins::plugin_macro::{MacroStep, MacroStepKind}, util::{get_min_max_keys_in_selection, get_min_max_ticks_in_selection, tempo_as_bytes, MIDITick, SignedMIDITick, MIN_TEMPO_BPM}}, midi::{events::{channel_event::{ChannelEvent, ChannelEventType}, meta_event::{KeySignature, MetaEvent, MetaEventType}, note::Note}, midi_track::MIDITrack}};
 
 impl UserData for Note {
     fn add_fields<F: mlua::UserDataFields<Self>>(fields: &mut F) {
@@ -38,30 +38,292 @@ impl UserData for Note {
     }
 }
 
+/// The name plugins see for a meta type, e.g. `time_signature`.
+pub fn meta_type_name(meta_type: MetaEventType) -> String {
+    meta_type.to_string().to_lowercase().replace(' ', "_")
+}
+
+/// A channel event as a plain Lua table. The fields depend on `type`:
+/// `note_on`/`note_off`/`key_aftertouch` have `key` and `velocity`, `controller` has `controller` and `value`,
+/// `program_change` has `program`, `channel_aftertouch` has `pressure` and `pitch_bend` has `value` (-8192 to 8191).
+pub fn channel_event_to_lua_table(lua: &Lua, ev: &ChannelEvent) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    table.set("tick", ev.tick)?;
+    table.set("channel", ev.channel)?;
+
+    match ev.event_type {
+        ChannelEventType::NoteOn(key, velocity) => {
+            table.set("type", "note_on")?;
+            table.set("key", key)?;
+            table.set("velocity", velocity)?;
+        },
+        ChannelEventType::NoteOff(key) => {
+            table.set("type", "note_off")?;
+            table.set("key", key)?;
+            table.set("velocity", 0)?;
+        },
+        ChannelEventType::NoteAftertouch(key, pressure) => {
+            table.set("type", "key_aftertouch")?;
+            table.set("key", key)?;
+            table.set("velocity", pressure)?;
+        },
+        ChannelEventType::Controller(controller, value) => {
+            table.set("type", "controller")?;
+            table.set("controller", controller)?;
+            table.set("value", value)?;
+        },
+        ChannelEventType::ProgramChange(program) => {
+            table.set("type", "program_change")?;
+            table.set("program", program)?;
+        },
+        ChannelEventType::ChannelAftertouch(pressure) => {
+            table.set("type", "channel_aftertouch")?;
+            table.set("pressure", pressure)?;
+        },
+        ChannelEventType::PitchBend(lsb, msb) => {
+            table.set("type", "pitch_bend")?;
+            table.set("value", (((msb as i32) << 7) | lsb as i32) - 8192)?;
+        }
+    }
+
+    Ok(table)
+}
+
+/// Opposite of [`channel_event_to_lua_table`]. Values are clamped to what MIDI allows.
+pub fn channel_event_from_lua_table(table: &Table) -> mlua::Result<ChannelEvent> {
+    let data_byte = |name: &str| -> mlua::Result<u8> { Ok(table.get::<i64>(name)?.clamp(0, 127) as u8) };
+
+    let ev_type: String = table.get("type")?;
+    let event_type = match ev_type.as_str() {
+        "note_on" => ChannelEventType::NoteOn(data_byte("key")?, data_byte("velocity")?),
+        "note_off" => ChannelEventType::NoteOff(data_byte("key")?),
+        "key_aftertouch" => ChannelEventType::NoteAftertouch(data_byte("key")?, data_byte("velocity")?),
+        "controller" => ChannelEventType::Controller(data_byte("controller")?, data_byte("value")?),
+        "program_change" => ChannelEventType::ProgramChange(data_byte("program")?),
+        "channel_aftertouch" => ChannelEventType::ChannelAftertouch(data_byte("pressure")?),
+        "pitch_bend" => {
+            let value = (table.get::<i64>("value")?.clamp(-8192, 8191) + 8192) as u16;
+            ChannelEventType::PitchBend((value & 0x7F) as u8, (value >> 7) as u8)
+        },
+        _ => return Err(mlua::Error::runtime(format!("unknown channel event type '{}'", ev_type)))
+    };
+
+    Ok(ChannelEvent {
+        tick: table.get::<i64>("tick")?.clamp(0, MIDITick::MAX as i64) as MIDITick,
+        channel: table.get::<i64>("channel")?.clamp(0, 15) as u8,
+        event_type
+    })
+}
+
+/// A meta as a plain Lua table, with `tick` and `type` (see [`meta_type_name`]). Tempos also have `bpm`,
+/// time signatures `numerator` and `denominator`, key signatures `sharps_flats`, `minor` and `tonic` (pitch class, 0 = C),
+/// and text metas (markers, lyrics, ...) `text`.
+pub fn meta_to_lua_table(lua: &Lua, meta: &MetaEvent) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    table.set("tick", meta.tick)?;
+    table.set("type", meta_type_name(meta.event_type))?;
+
+    match meta.event_type {
+        MetaEventType::Tempo if meta.data.len() >= 3 => {
+            let tempo = ((meta.data[0] as u32) << 16) | ((meta.data[1] as u32) << 8) | meta.data[2] as u32;
+            table.set("bpm", 60000000.0 / tempo.max(1) as f64)?;
+        },
+        MetaEventType::TimeSignature if meta.data.len() >= 2 => {
+            table.set("numerator", meta.data[0])?;
+            table.set("denominator", 1u32 << meta.data[1].min(31))?;
+        },
+        MetaEventType::KeySignature => {
+            let key_sig = KeySignature::from_meta_data(&meta.data);
+            table.set("sharps_flats", key_sig.sharps_flats)?;
+            table.set("minor", key_sig.minor)?;
+            table.set("tonic", key_sig.tonic())?;
+        },
+        meta_type if meta_type.is_text() => {
+            table.set("text", String::from_utf8_lossy(&meta.data).into_owned())?;
+        },
+        _ => {}
+    }
+
+    Ok(table)
+}
+
+/// Merges `new` (sorted by tick) into `old` (also sorted) and returns where each new element ended up.
+/// New elements go after old ones on the same tick.
+fn merge_by_tick_and_return_ids<T>(old: Vec<T>, new: Vec<T>, tick: impl Fn(&T) -> MIDITick) -> (Vec<T>, Vec<usize>) {
+    let mut merged = Vec::with_capacity(old.len() + new.len());
+    let mut ids = Vec::with_capacity(new.len());
+
+    let mut old_iter = old.into_iter().peekable();
+    for elem in new {
+        while let Some(old_elem) = old_iter.next_if(|o| tick(o) <= tick(&elem)) {
+            merged.push(old_elem);
+        }
+        ids.push(merged.len());
+        merged.push(elem);
+    }
+    merged.extend(old_iter);
+
+    (merged, ids)
+}
+
+/// What a plugin would do, worked out on a scratch copy of the project.
+#[derive(Default)]
+pub struct PluginPreview {
+    /// Changed and added notes of the previewed track, where they'd end up.
+    pub ghost_notes: Vec<Note>,
+    pub notes_added: usize,
+    pub notes_changed: usize,
+    pub notes_deleted: usize,
+    /// Channel events and metas
+    pub events_added: usize,
+    pub events_removed: usize,
+    /// edit functions, plugin runs and track operations, which only run for real
+    pub macro_steps: usize
+}
+
+impl PluginPreview {
+    pub fn is_empty(&self) -> bool {
+        self.notes_added == 0 && self.notes_changed == 0 && self.notes_deleted == 0 && self.events_added == 0 && self.events_removed == 0 && self.macro_steps == 0
+    }
+}
+
+/// What a plugin run changed. Note edits happen on the tracks right away (and get recorded as deltas),
+/// everything else is collected and applied in [`LuaNoteEditing::apply_changes`].
 pub struct LuaNoteEditing {
     pub note_editing: Arc<Mutex<NoteEditing>>,
-    pub delta_note_pos: HashMap<usize, (SignedMIDITick, i16)>,
-    pub delta_note_lengths: HashMap<usize, SignedMIDITick>,
-    pub delta_note_channels: HashMap<usize, i8>,
-    pub delta_note_velocities: HashMap<usize, i8>,
-
-    pub notes_to_add: Vec<Note>
+    pub meta_editing: Arc<Mutex<MetaEditing>>,
+    tracks: Arc<RwLock<Vec<MIDITrack>>>,
+
+    // all keyed by (track, note id)
+    pub delta_note_pos: HashMap<(u16, usize), (SignedMIDITick, i16)>,
+    pub delta_note_lengths: HashMap<(u16, usize), SignedMIDITick>,
+    pub delta_note_channels: HashMap<(u16, usize), i8>,
+    pub delta_note_velocities: HashMap<(u16, usize), i8>,
+
+    pub notes_to_add: Vec<(u16, Note)>,
+    // deleted once the plugin is done, so ids stay valid while it runs
+    notes_to_delete: Vec<(u16, usize)>,
+    channel_evs_to_add: Vec<(u16, ChannelEvent)>,
+    channel_evs_to_remove: Vec<(u16, usize)>,
+    metas_to_add: Vec<MetaEvent>,
+    metas_to_remove: Vec<usize>,
+    mute_changes: Vec<(u16, bool)>,
+    // steps queued for a MacroPlayer, None where there's nothing to play them
+    macro_steps: Option<Vec<MacroStep>>,
 }
 
 impl LuaNoteEditing {
-    pub fn new(note_editing: &Arc<Mutex<NoteEditing>>) -> Self {
-        let note_editing = note_editing.clone();
-        // println!("[LuaNoteEditing::new()] Track count: {}", note_editing.lock().unwrap().get_tracks().read().unwrap().len());
+    pub fn new(note_editing: &Arc<Mutex<NoteEditing>>, meta_editing: &Arc<Mutex<MetaEditing>>) -> Self {
+        let tracks = {
+            let note_editing = note_editing.lock().unwrap();
+            note_editing.get_tracks()
+        };
+
         Self { 
-            note_editing,
+            note_editing: note_editing.clone(),
+            meta_editing: meta_editing.clone(),
+            tracks,
             delta_note_pos: HashMap::new(),
             delta_note_lengths: HashMap::new(),
             delta_note_channels: HashMap::new(),
             delta_note_velocities: HashMap::new(),
             notes_to_add: Vec::new(),
+            notes_to_delete: Vec::new(),
+            channel_evs_to_add: Vec::new(),
+            channel_evs_to_remove: Vec::new(),
+            metas_to_add: Vec::new(),
+            metas_to_remove: Vec::new(),
+            mute_changes: Vec::new(),
+            macro_steps: None,
         }
     }
 
+    /// Lets the plugin queue macro steps (`notes:edit_function` and friends), see plugin_macro.
+    pub fn allow_macro_steps(&mut self) {
+        self.macro_steps.get_or_insert_with(Vec::new);
+    }
+
+    pub fn take_macro_steps(&mut self) -> Vec<MacroStep> {
+        self.macro_steps.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn queue_macro_step(&mut self, kind: MacroStepKind, name: String, params: Option<Table>) -> mlua::Result<()> {
+        let step = MacroStep::from_lua(kind, name, params)?;
+        match self.macro_steps.as_mut() {
+            Some(steps) => {
+                steps.push(step);
+                Ok(())
+            },
+            None => Err(mlua::Error::runtime("edit functions, plugin runs and track operations only work in plugins run from the Plugins menu"))
+        }
+    }
+
+    /// Like [`LuaNoteEditing::new`], but the plugin edits a copy of the tracks, so the project is left alone.
+    /// Meant to be finished with [`LuaNoteEditing::into_preview`], not applied.
+    pub fn new_scratch(note_editing: &Arc<Mutex<NoteEditing>>, meta_editing: &Arc<Mutex<MetaEditing>>) -> Self {
+        let mut scratch = Self::new(note_editing, meta_editing);
+        let tracks_copy = scratch.tracks.read().unwrap().clone();
+        scratch.tracks = Arc::new(RwLock::new(tracks_copy));
+        scratch
+    }
+
+    /// Sums up what the plugin did. Only notes in `track` end up as ghost notes.
+    pub fn into_preview(self, track: u16) -> PluginPreview {
+        let mut changed_ids: Vec<(u16, usize)> = self.delta_note_pos.keys()
+            .chain(self.delta_note_lengths.keys())
+            .chain(self.delta_note_channels.keys())
+            .chain(self.delta_note_velocities.keys())
+            .copied()
+            .collect();
+        changed_ids.sort_unstable();
+        changed_ids.dedup();
+
+        let mut deleted_ids = self.notes_to_delete.clone();
+        deleted_ids.sort_unstable();
+        deleted_ids.dedup();
+        // a note that gets deleted doesn't count as changed, whatever was done to it before
+        changed_ids.retain(|id| deleted_ids.binary_search(id).is_err());
+
+        let mut ghost_notes: Vec<Note> = {
+            let tracks = self.tracks.read().unwrap();
+            match tracks.get(track as usize) {
+                Some(midi_track) => {
+                    let notes = midi_track.get_notes();
+                    changed_ids.iter().filter(|&&(t, _)| t == track).map(|&(_, id)| notes[id]).collect()
+                },
+                None => Vec::new()
+            }
+        };
+        ghost_notes.extend(self.notes_to_add.iter().filter(|&&(t, _)| t == track).map(|&(_, note)| note));
+        ghost_notes.sort_by_key(|n| n.start());
+
+        let mut channel_evs_removed = self.channel_evs_to_remove.clone();
+        channel_evs_removed.sort_unstable();
+        channel_evs_removed.dedup();
+        let mut metas_removed = self.metas_to_remove.clone();
+        metas_removed.sort_unstable();
+        metas_removed.dedup();
+
+        PluginPreview {
+            ghost_notes,
+            notes_added: self.notes_to_add.len(),
+            notes_changed: changed_ids.len(),
+            notes_deleted: deleted_ids.len(),
+            events_added: self.channel_evs_to_add.len() + self.metas_to_add.len(),
+            events_removed: channel_evs_removed.len() + metas_removed.len(),
+            macro_steps: self.macro_steps.as_ref().map(|steps| steps.len()).unwrap_or(0)
+        }
+    }
+
+    /// Errors out if `track` doesn't exist, so plugins get a readable message instead of a panic.
+    fn check_track(&self, track: usize) -> mlua::Result<u16> {
+        let track_count = self.tracks.read().unwrap().len();
+        if track >= track_count {
+            return Err(mlua::Error::runtime(format!("track {} doesn't exist (there are {} tracks)", track, track_count)));
+        }
+        Ok(track as u16)
+    }
+
     /// Helper function for editing a note directly from lua.
     /// This also updates note deltas if needed.
     fn change_note_from_lua(&mut self, lua: &Lua, func: &Function, note: &mut Note) -> mlua::Result<()> {
@@ -83,7 +345,7 @@ impl LuaNoteEditing {
         Ok(())
     }
 
-    fn change_note_and_update_deltas(&mut self, lua: &Lua, func: &Function, note: &mut Note, id: usize) -> mlua::Result<()> {
+    fn change_note_and_update_deltas(&mut self, lua: &Lua, func: &Function, note: &mut Note, track: u16, id: usize) -> mlua::Result<()> {
         let old_start = note.start();
         let old_key = note.key();
         let old_length = note.length();
@@ -98,29 +360,64 @@ impl LuaNoteEditing {
         let delta_channel = note.channel() as i8 - old_channel as i8;
         let delta_velocity = note.velocity() as i8 - old_velocity as i8;
 
+        // deltas add up, a note might be visited more than once (e.g. by for_each_note and for_each_selected)
         if delta_start != 0 || delta_key != 0 {
-            let dt_pos = self.delta_note_pos.entry(id).or_default();
-            *dt_pos = (delta_start, delta_key);
+            let dt_pos = self.delta_note_pos.entry((track, id)).or_default();
+            *dt_pos = (dt_pos.0 + delta_start, dt_pos.1 + delta_key);
         }
 
         if delta_length != 0 {
-            let dt_length = self.delta_note_lengths.entry(id).or_default();
-            *dt_length = delta_length;
+            let dt_length = self.delta_note_lengths.entry((track, id)).or_default();
+            *dt_length += delta_length;
         }
 
         if delta_channel != 0 {
-            let dt_channel = self.delta_note_channels.entry(id).or_default();
-            *dt_channel = delta_channel;
+            let dt_channel = self.delta_note_channels.entry((track, id)).or_default();
+            *dt_channel += delta_channel;
         }
 
         if delta_velocity != 0 {
-            let dt_velocity = self.delta_note_velocities.entry(id).or_default();
-            *dt_velocity = delta_velocity;
+            let dt_velocity = self.delta_note_velocities.entry((track, id)).or_default();
+            *dt_velocity += delta_velocity;
         }
 
         Ok(())
     }
 
+    /// Lets `func` change the notes at `ids`. Each note is copied out and written back, so the tracks aren't locked
+    /// while the plugin runs and it can still ask `andromeda` about them.
+    fn change_notes_by_id(&mut self, lua: &Lua, track: u16, ids: impl IntoIterator<Item = usize>, func: &Function) -> mlua::Result<()> {
+        for id in ids {
+            let mut note = {
+                let tracks = self.tracks.read().unwrap();
+                match tracks[track as usize].get_notes().get(id) {
+                    Some(note) => *note,
+                    None => return Err(mlua::Error::runtime(format!("note {} doesn't exist in track {}", id, track)))
+                }
+            };
+
+            self.change_note_and_update_deltas(lua, func, &mut note, track, id)?;
+            self.tracks.write().unwrap()[track as usize].get_notes_mut()[id] = note;
+        }
+        Ok(())
+    }
+
+    fn for_each_note_in(&mut self, lua: &Lua, track: u16, func: &Function) -> mlua::Result<()> {
+        let note_count = self.tracks.read().unwrap()[track as usize].get_notes().len();
+        self.change_notes_by_id(lua, track, 0..note_count, func)
+    }
+
+    fn get_selected_ids(&self, track: u16) -> Vec<usize> {
+        let note_editing = self.note_editing.lock().unwrap();
+        let sel_ids = note_editing.get_shared_selected_ids().read().unwrap();
+        sel_ids.get_selected_ids_in_track(track).cloned().unwrap_or_default()
+    }
+
+    fn for_each_selected_in(&mut self, lua: &Lua, track: u16, func: &Function) -> mlua::Result<()> {
+        let sel_ids = self.get_selected_ids(track);
+        self.change_notes_by_id(lua, track, sel_ids, func)
+    }
+
     pub fn range_as_lua_table<T: IntoLua + 'static>(lua: &Lua, min: T, max: T) -> mlua::Result<mlua::Table> {
         let table = lua.create_table()?;
         table.set("min", min)?;
@@ -128,43 +425,93 @@ impl LuaNoteEditing {
         Ok(table)
     }
 
-    pub fn apply_changes(self, track: u16, editor_actions: &mut EditorActions) {
-        let note_editing = self.note_editing.clone();
+    /// Note edits of a single track, in the order they happened.
+    fn take_note_actions(&mut self, track: u16) -> Vec<EditorAction> {
+        fn take_track_deltas<T>(deltas: &mut HashMap<(u16, usize), T>, track: u16) -> Vec<(usize, T)> {
+            let keys: Vec<(u16, usize)> = deltas.keys().filter(|(t, _)| *t == track).copied().collect();
+            let mut track_deltas: Vec<(usize, T)> = keys.into_iter().map(|key| (key.1, deltas.remove(&key).unwrap())).collect();
+            track_deltas.sort_by_key(|&(id, _)| id);
+            track_deltas
+        }
 
-        let mut bulk_actions = Vec::new();
+        // takes the deltas of deleted notes out, and moves the others down to where their notes are after the deletion
+        fn remap_track_deltas<T>(deltas: &mut HashMap<(u16, usize), T>, track: u16, deleted_ids: &[usize]) -> Vec<(usize, T)> {
+            let mut deleted_deltas = Vec::new();
+            for (id, delta) in take_track_deltas(deltas, track) {
+                let shift = deleted_ids.partition_point(|&deleted_id| deleted_id < id);
+                if deleted_ids.get(shift) == Some(&id) {
+                    deleted_deltas.push((shift, delta));
+                } else {
+                    deltas.insert((track, id - shift), delta);
+                }
+            }
+            deleted_deltas
+        }
 
-        let mut dt_channels: Vec<(usize, i8)> = self.delta_note_channels.into_iter().map(|(k, v)| (k, v)).collect();
-        let mut dt_velocities: Vec<(usize, i8)> = self.delta_note_velocities.into_iter().map(|(k, v)| (k, v)).collect();
-        let mut dt_position: Vec<(usize, (SignedMIDITick, i16))> = self.delta_note_pos.into_iter().map(|(k, v)| (k, v)).collect();
-        let mut dt_length: Vec<(usize, SignedMIDITick)> = self.delta_note_lengths.into_iter().map(|(k, v)| (k, v)).collect();
-        let mut notes_to_add = self.notes_to_add;
+        let mut actions = Vec::new();
 
-        // 1. sort them all by id if needed
-        dt_channels.sort_by_key(|&(id, _)| id);
-        dt_velocities.sort_by_key(|&(id, _)| id);
-        dt_length.sort_by_key(|&(id, _)| id);
-        dt_position.sort_by_key(|&(id, _)| id);
+        let mut delete_ids: Vec<usize> = Vec::new();
+        self.notes_to_delete.retain(|&(t, id)| {
+            if t == track { delete_ids.push(id); false } else { true }
+        });
+        delete_ids.sort_unstable();
+        delete_ids.dedup();
+
+        // deleting goes first, so the deletion can be undone last, onto the notes as they were
+        if !delete_ids.is_empty() {
+            let dt_channels = remap_track_deltas(&mut self.delta_note_channels, track, &delete_ids);
+            let dt_velocities = remap_track_deltas(&mut self.delta_note_velocities, track, &delete_ids);
+            let dt_length = remap_track_deltas(&mut self.delta_note_lengths, track, &delete_ids);
+            let dt_position = remap_track_deltas(&mut self.delta_note_pos, track, &delete_ids);
+
+            let mut note_editing = self.note_editing.lock().unwrap();
+            let old_notes = note_editing.take_notes_in_track(track);
+
+            let shared_selected = note_editing.get_shared_selected_ids().clone();
+            let mut selected = shared_selected.write().unwrap();
+            let (mut deleted_notes, new_notes, new_sel_ids) = extract_and_remap_ids(old_notes, &delete_ids, selected.take_selected_from_track(track));
+            selected.set_selected_in_track(new_sel_ids, track);
+            drop(selected);
+
+            note_editing.set_notes_in_track(track, new_notes);
+
+            // whatever the plugin did to a note before deleting it is undone, so undoing brings back the original
+            for (i, dt) in dt_channels { deleted_notes[i].channel = (deleted_notes[i].channel as i8 - dt) as u8; }
+            for (i, dt) in dt_velocities { deleted_notes[i].velocity = (deleted_notes[i].velocity as i8 - dt) as u8; }
+            for (i, dt) in dt_length { deleted_notes[i].length = (deleted_notes[i].length as SignedMIDITick - dt) as MIDITick; }
+            for (i, (dt_start, dt_key)) in dt_position {
+                deleted_notes[i].start = (deleted_notes[i].start as SignedMIDITick - dt_start) as MIDITick;
+                deleted_notes[i].key = (deleted_notes[i].key as i16 - dt_key) as u8;
+            }
+            deleted_notes.sort_by_key(|n| n.start());
+
+            actions.push(EditorAction::DeleteNotes(delete_ids, Some(deleted_notes), track));
+        }
+
+        let dt_channels = take_track_deltas(&mut self.delta_note_channels, track);
+        let dt_velocities = take_track_deltas(&mut self.delta_note_velocities, track);
+        let dt_length = take_track_deltas(&mut self.delta_note_lengths, track);
+        let dt_position = take_track_deltas(&mut self.delta_note_pos, track);
 
-        // 2. apply note channel changes
         if !dt_channels.is_empty() {
             let (ids, ch_change): (Vec<usize>, Vec<i8>) = dt_channels.into_iter().unzip();
-            bulk_actions.push(EditorAction::ChannelChange(ids, ch_change, track));
+            actions.push(EditorAction::ChannelChange(ids, ch_change, track));
         }
 
         if !dt_velocities.is_empty() {
             let (ids, vel_change): (Vec<usize>, Vec<i8>) = dt_velocities.into_iter().unzip();
-            bulk_actions.push(EditorAction::VelocityChange(ids, vel_change, track));
+            actions.push(EditorAction::VelocityChange(ids, vel_change, track));
         }
 
         if !dt_length.is_empty() {
             let (ids, len_change): (Vec<usize>, Vec<SignedMIDITick>) = dt_length.into_iter().unzip();
-            bulk_actions.push(EditorAction::LengthChange(ids, len_change, track));
+            actions.push(EditorAction::LengthChange(ids, len_change, track));
         }
 
         if !dt_position.is_empty() {
             let (ids, delta_pos): (Vec<usize>, Vec<_>) = dt_position.into_iter().unzip();
 
-            let mut note_editing = note_editing.lock().unwrap();
+            let mut note_editing = self.note_editing.lock().unwrap();
             let old_notes = note_editing.take_notes_in_track(track);
             
             // group notes with delta while extracting them to prevent delta pos index invalidation when sorting by note start
@@ -175,88 +522,172 @@ impl LuaNoteEditing {
             let (merged, note_ids) = merge_notes_and_return_ids(old_notes, notes_to_move); // O(n+k)
             note_editing.set_notes_in_track(track, merged);
 
-            bulk_actions.push(EditorAction::NotesMove(note_ids, delta, track, true));
+            actions.push(EditorAction::NotesMove(note_ids, delta, track, true));
         }
 
+        let mut notes_to_add: Vec<Note> = Vec::new();
+        self.notes_to_add.retain(|&(t, note)| {
+            if t == track { notes_to_add.push(note); false } else { true }
+        });
+
         if !notes_to_add.is_empty() {
             notes_to_add.sort_unstable_by_key(|&n| n.start());
 
-            let mut note_editing = note_editing.lock().unwrap();
-            println!("Track count: {}", note_editing.get_tracks().read().unwrap().len());
-
+            let mut note_editing = self.note_editing.lock().unwrap();
             let old_notes = note_editing.take_notes_in_track(track);
             let (merged, ids) = merge_notes_and_return_ids(old_notes, notes_to_add);
             
             note_editing.set_notes_in_track(track, merged);
-            bulk_actions.push(EditorAction::PlaceNotes(ids, None, track));
+            actions.push(EditorAction::PlaceNotes(ids, None, track));
+        }
+
+        actions
+    }
+
+    /// Channel event edits of a single track, in the order they happened.
+    fn take_channel_event_actions(&mut self, track: u16) -> Vec<EditorAction> {
+        let mut actions = Vec::new();
+
+        let mut remove_ids: Vec<usize> = self.channel_evs_to_remove.iter().filter(|(t, _)| *t == track).map(|&(_, id)| id).collect();
+        remove_ids.sort_unstable();
+        remove_ids.dedup();
+
+        let mut evs_to_add = Vec::new();
+        self.channel_evs_to_add.retain(|(t, ev)| {
+            if *t == track { evs_to_add.push(ev.clone()); false } else { true }
+        });
+        evs_to_add.sort_by_key(|ev| ev.tick);
+
+        let mut tracks = self.tracks.write().unwrap();
+        let channel_evs = tracks[track as usize].get_channel_evs_mut();
+
+        if !remove_ids.is_empty() {
+            let (deleted, remaining) = extract(std::mem::take(channel_evs), &remove_ids);
+            *channel_evs = remaining;
+            actions.push(EditorAction::DeleteChannelEvents(remove_ids, Some(deleted), track));
+        }
+
+        if !evs_to_add.is_empty() {
+            let (merged, ids) = merge_by_tick_and_return_ids(std::mem::take(channel_evs), evs_to_add, |ev| ev.tick);
+            *channel_evs = merged;
+            actions.push(EditorAction::AddChannelEvents(ids, None, track));
         }
 
-        if !bulk_actions.is_empty() { editor_actions.register_action(EditorAction::Bulk(bulk_actions)); }
+        actions
+    }
+
+    /// Takes back the note edits a plugin made before it failed. Those are the only changes that happen while
+    /// it runs, so afterwards the project is as it was.
+    pub fn discard_changes(self) {
+        fn note_at(tracks: &mut [MIDITrack], track: u16, id: usize) -> &mut Note {
+            &mut tracks[track as usize].get_notes_mut()[id]
+        }
+
+        let mut tracks = self.tracks.write().unwrap();
+
+        for (&(track, id), &dt) in self.delta_note_channels.iter() {
+            let note = note_at(&mut tracks, track, id);
+            note.channel = (note.channel as i8 - dt) as u8;
+        }
+        for (&(track, id), &dt) in self.delta_note_velocities.iter() {
+            let note = note_at(&mut tracks, track, id);
+            note.velocity = (note.velocity as i8 - dt) as u8;
+        }
+        for (&(track, id), &dt) in self.delta_note_lengths.iter() {
+            let note = note_at(&mut tracks, track, id);
+            note.length = (note.length as SignedMIDITick - dt) as MIDITick;
+        }
+        // starts were changed in place, so putting them back also puts the notes back in order
+        for (&(track, id), &(dt_start, dt_key)) in self.delta_note_pos.iter() {
+            let note = note_at(&mut tracks, track, id);
+            note.start = (note.start as SignedMIDITick - dt_start) as MIDITick;
+            note.key = (note.key as i16 - dt_key) as u8;
+        }
+    }
+
+    /// Applies everything the plugin did and registers it as a single undoable action.
+    pub fn apply_changes(self, editor_actions: &mut EditorActions) {
+        self.apply_changes_merged(editor_actions, None);
+    }
+
+    /// Like [`LuaNoteEditing::apply_changes`], but folds the changes into the undo step registered at `merge_since`,
+    /// see [`EditorActions::register_plugin_actions`].
+    pub fn apply_changes_merged(mut self, editor_actions: &mut EditorActions, merge_since: Option<u64>) {
+        let mut actions = Vec::new();
+
+        let mut touched_tracks: Vec<u16> = self.delta_note_pos.keys()
+            .chain(self.delta_note_lengths.keys())
+            .chain(self.delta_note_channels.keys())
+            .chain(self.delta_note_velocities.keys())
+            .map(|&(track, _)| track)
+            .chain(self.notes_to_add.iter().map(|&(track, _)| track))
+            .chain(self.notes_to_delete.iter().map(|&(track, _)| track))
+            .chain(self.channel_evs_to_add.iter().map(|(track, _)| *track))
+            .chain(self.channel_evs_to_remove.iter().map(|&(track, _)| track))
+            .collect();
+        touched_tracks.sort_unstable();
+        touched_tracks.dedup();
+
+        for track in touched_tracks {
+            actions.extend(self.take_note_actions(track));
+            actions.extend(self.take_channel_event_actions(track));
+        }
+
+        if !self.metas_to_add.is_empty() || !self.metas_to_remove.is_empty() {
+            let mut remove_ids = std::mem::take(&mut self.metas_to_remove);
+            remove_ids.sort_unstable();
+            remove_ids.dedup();
+
+            let mut meta_editing = self.meta_editing.lock().unwrap();
+            let (deleted, added_ids) = meta_editing.edit_metas_unregistered(&remove_ids, std::mem::take(&mut self.metas_to_add));
+
+            if !remove_ids.is_empty() { actions.push(EditorAction::DeleteMeta(remove_ids, Some(deleted))); }
+            if !added_ids.is_empty() { actions.push(EditorAction::AddMeta(added_ids, None)); }
+        }
+
+        if !self.mute_changes.is_empty() {
+            let mut tracks = self.tracks.write().unwrap();
+            // only the last change to a track counts
+            let mut old_states: Vec<(u16, bool)> = Vec::new();
+            for &(track, muted) in self.mute_changes.iter() {
+                let old_muted = std::mem::replace(&mut tracks[track as usize].muted, muted);
+                if !old_states.iter().any(|&(t, _)| t == track) { old_states.push((track, old_muted)); }
+            }
+            actions.push(EditorAction::SetTracksMuted(old_states));
+        }
+
+        // bulk actions get redone back to front
+        actions.reverse();
+        if !actions.is_empty() { editor_actions.register_plugin_actions(actions, merge_since); }
     }
 }
 
 impl UserData for LuaNoteEditing {
     fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        // ======== NOTES (CURRENT TRACK) ========
+
         methods.add_method_mut("for_each_note", |lua, this, func: Function| {
             let curr_track: usize = lua.globals().get("curr_track")?;
-            
-            let tracks = {
-                let note_editing = this.note_editing.lock().unwrap();
-                note_editing.get_tracks().clone()
-            };
-
-            let mut tracks = tracks.write().unwrap();
-            let track = (*tracks)[curr_track].get_notes_mut();
-            
-            for (i, note) in track.iter_mut().enumerate() {
-                this.change_note_and_update_deltas(lua, &func, note, i)?;
-            }
-            Ok(())
+            let track = this.check_track(curr_track)?;
+            this.for_each_note_in(lua, track, &func)
         });
 
         methods.add_method_mut("for_each_selected", |lua, this, func: Function| {
             let curr_track: usize = lua.globals().get("curr_track")?;
-            
-            let (tracks, sel_ids) = {
-                let note_editing = this.note_editing.lock().unwrap();
-                (note_editing.get_tracks().clone(), note_editing.get_shared_selected_ids().clone())
-            };
-
-            let mut tracks = tracks.write().unwrap();
-            let track = (*tracks)[curr_track].get_notes_mut();
-
-            let empty = vec![];
-            let sel_ids = sel_ids.read().unwrap();
-            let sel_ids = sel_ids.get_selected_ids_in_track(curr_track as u16)
-                .unwrap_or(&empty);
-
-            for &sel_id in sel_ids.iter() {
-                let note = &mut track[sel_id];
-                this.change_note_and_update_deltas(lua, &func, note, sel_id)?;
-            }
-
-            Ok(())
+            let track = this.check_track(curr_track)?;
+            this.for_each_selected_in(lua, track, &func)
         });
 
         methods.add_method("iter_selected", |lua, this, func: Function| {
             let curr_track: usize = lua.globals().get("curr_track")?;
+            let track = this.check_track(curr_track)?;
+            let sel_ids = this.get_selected_ids(track);
 
-            let (tracks, sel_ids) = {
-                let note_editing = this.note_editing.lock().unwrap();
-                (note_editing.get_tracks().clone(), note_editing.get_shared_selected_ids().clone())
-            };
-
-            let mut tracks = tracks.write().unwrap();
-            let track = (*tracks)[curr_track].get_notes_mut();
-
-            let empty = vec![];
-            let sel_ids = sel_ids.read().unwrap();
-            let sel_ids = sel_ids.get_selected_ids_in_track(curr_track as u16)
-                .unwrap_or(&empty);
+            let tracks = this.tracks.read().unwrap();
+            let notes = (*tracks)[curr_track].get_notes();
 
-            for &sel_id in sel_ids.iter() {
-                let note = &track[sel_id];
-                this.call_lua_note_fn(lua, &func, note)?;
+            for sel_id in sel_ids {
+                this.call_lua_note_fn(lua, &func, &notes[sel_id])?;
             }
 
             Ok(())
@@ -265,26 +696,19 @@ impl UserData for LuaNoteEditing {
         // inclusive: if note lengths are considered
         methods.add_method::<_, _, Option<mlua::Table>>("get_selection_tick_range", |lua, this, inclusive: bool| {
             let curr_track: usize = lua.globals().get("curr_track")?;
+            let track = this.check_track(curr_track)?;
 
-            let (tracks, sel_ids) = {
-                let note_editing = this.note_editing.lock().unwrap();
-                (note_editing.get_tracks().clone(), note_editing.get_shared_selected_ids().clone())
-            };
-
-            let empty = vec![];
-            let sel_ids = sel_ids.read().unwrap();
-            let sel_ids = sel_ids.get_selected_ids_in_track(curr_track as u16)
-                .unwrap_or(&empty);
+            let sel_ids = this.get_selected_ids(track);
             if sel_ids.is_empty() { return Ok(None); }
 
-            let tracks = tracks.read().unwrap();
-            let track = (*tracks)[curr_track].get_notes();
+            let tracks = this.tracks.read().unwrap();
+            let notes = (*tracks)[curr_track].get_notes();
 
             let (min_tick, max_tick) = if inclusive {
-                get_min_max_ticks_in_selection(track, &sel_ids).unwrap()
+                get_min_max_ticks_in_selection(notes, &sel_ids).unwrap()
             } else {
-                let start_tick = track[sel_ids[0]].start();
-                let end_tick = track[sel_ids[sel_ids.len() - 1]].start();
+                let start_tick = notes[sel_ids[0]].start();
+                let end_tick = notes[sel_ids[sel_ids.len() - 1]].start();
                 (start_tick, end_tick)
             };
 
@@ -295,37 +719,196 @@ impl UserData for LuaNoteEditing {
 
         methods.add_method::<_, _, Option<mlua::Table>>("get_selection_key_range", |lua, this, _: ()| {
             let curr_track: usize = lua.globals().get("curr_track")?;
+            let track = this.check_track(curr_track)?;
 
-            let (tracks, sel_ids) = {
-                let note_editing = this.note_editing.lock().unwrap();
-                (note_editing.get_tracks().clone(), note_editing.get_shared_selected_ids().clone())
-            };
-
-            let empty = vec![];
-            let sel_ids = sel_ids.read().unwrap();
-            let sel_ids = sel_ids.get_selected_ids_in_track(curr_track as u16)
-                .unwrap_or(&empty);
+            let sel_ids = this.get_selected_ids(track);
             if sel_ids.is_empty() { return Ok(None); }
 
-            let tracks = tracks.read().unwrap();
-            let track = (*tracks)[curr_track].get_notes();
+            let tracks = this.tracks.read().unwrap();
+            let notes = (*tracks)[curr_track].get_notes();
 
-            let (min_key, max_key) = get_min_max_keys_in_selection(track, &sel_ids).unwrap();
+            let (min_key, max_key) = get_min_max_keys_in_selection(notes, &sel_ids).unwrap();
 
             let table = Self::range_as_lua_table(lua, min_key, max_key)?;
 
             Ok(Some(table))
         });
 
-        methods.add_method_mut("create_note", |_, this, (start, length, channel, key, velocity): (MIDITick, MIDITick, u8, u8, u8)| {
-            this.notes_to_add.push(Note {
-                start,
-                length,
-                channel,
-                key,
-                velocity
+        methods.add_method_mut("create_note", |lua, this, (start, length, channel, key, velocity): (MIDITick, MIDITick, u8, u8, u8)| {
+            let curr_track: usize = lua.globals().get("curr_track")?;
+            let track = this.check_track(curr_track)?;
+            this.notes_to_add.push((track, Note { start, length, channel, key, velocity }));
+            Ok(())
+        });
+
+        // the selected notes are still there (and still selected) until the plugin is done
+        methods.add_method_mut("delete_selected", |lua, this, _: ()| {
+            let curr_track: usize = lua.globals().get("curr_track")?;
+            let track = this.check_track(curr_track)?;
+            let sel_ids = this.get_selected_ids(track);
+            this.notes_to_delete.extend(sel_ids.into_iter().map(|id| (track, id)));
+            Ok(())
+        });
+
+        // ======== NOTES (ANY TRACK) ========
+
+        methods.add_method_mut("for_each_note_in_track", |lua, this, (track, func): (usize, Function)| {
+            let track = this.check_track(track)?;
+            this.for_each_note_in(lua, track, &func)
+        });
+
+        methods.add_method_mut("for_each_selected_in_track", |lua, this, (track, func): (usize, Function)| {
+            let track = this.check_track(track)?;
+            this.for_each_selected_in(lua, track, &func)
+        });
+
+        // only the notes with the given ids, e.g. the ones an `on_note_placed` hook got
+        methods.add_method_mut("for_each_note_by_id", |lua, this, (track, ids, func): (usize, Vec<usize>, Function)| {
+            let track = this.check_track(track)?;
+            this.change_notes_by_id(lua, track, ids, &func)
+        });
+
+        // read only, changes to the notes are ignored
+        methods.add_method("iter_notes_in_track", |lua, this, (track, func): (usize, Function)| {
+            let track = this.check_track(track)?;
+
+            let tracks = this.tracks.read().unwrap();
+            for note in (*tracks)[track as usize].get_notes().iter() {
+                this.call_lua_note_fn(lua, &func, note)?;
+            }
+            Ok(())
+        });
+
+        methods.add_method_mut("create_note_in_track", |_, this, (track, start, length, channel, key, velocity): (usize, MIDITick, MIDITick, u8, u8, u8)| {
+            let track = this.check_track(track)?;
+            this.notes_to_add.push((track, Note { start, length, channel, key, velocity }));
+            Ok(())
+        });
+
+        methods.add_method_mut("delete_notes_in_track", |_, this, (track, ids): (usize, Vec<usize>)| {
+            let track = this.check_track(track)?;
+            let note_count = this.tracks.read().unwrap()[track as usize].get_notes().len();
+            if let Some(id) = ids.iter().find(|&&id| id >= note_count) {
+                return Err(mlua::Error::runtime(format!("note {} doesn't exist in track {}", id, track)));
+            }
+            this.notes_to_delete.extend(ids.into_iter().map(|id| (track, id)));
+            Ok(())
+        });
+
+        // ======== CHANNEL EVENTS ========
+
+        // every channel event in the track as tables (see channel_event_to_lua_table), each with its `id`
+        methods.add_method("get_channel_events", |lua, this, track: usize| {
+            let track = this.check_track(track)?;
+
+            let tracks = this.tracks.read().unwrap();
+            let events = lua.create_table()?;
+            for (id, ev) in (*tracks)[track as usize].get_channel_evs().iter().enumerate() {
+                let ev_table = channel_event_to_lua_table(lua, ev)?;
+                ev_table.set("id", id)?;
+                events.push(ev_table)?;
+            }
+            Ok(events)
+        });
+
+        methods.add_method_mut("add_channel_event", |_, this, (track, ev): (usize, Table)| {
+            let track = this.check_track(track)?;
+            this.channel_evs_to_add.push((track, channel_event_from_lua_table(&ev)?));
+            Ok(())
+        });
+
+        methods.add_method_mut("add_controller", |_, this, (track, tick, channel, controller, value): (usize, MIDITick, u8, u8, u8)| {
+            let track = this.check_track(track)?;
+            this.channel_evs_to_add.push((track, ChannelEvent {
+                tick, channel: channel.min(15), event_type: ChannelEventType::Controller(controller.min(127), value.min(127))
+            }));
+            Ok(())
+        });
+
+        // value goes from -8192 to 8191, 0 is the center
+        methods.add_method_mut("add_pitch_bend", |_, this, (track, tick, channel, value): (usize, MIDITick, u8, i32)| {
+            let track = this.check_track(track)?;
+            let value = (value.clamp(-8192, 8191) + 8192) as u16;
+            this.channel_evs_to_add.push((track, ChannelEvent {
+                tick, channel: channel.min(15), event_type: ChannelEventType::PitchBend((value & 0x7F) as u8, (value >> 7) as u8)
+            }));
+            Ok(())
+        });
+
+        // removes every channel event in the track for which `func(event)` returns true
+        methods.add_method_mut("remove_channel_events", |lua, this, (track, func): (usize, Function)| {
+            let track = this.check_track(track)?;
+
+            let tracks = this.tracks.clone();
+            let tracks = tracks.read().unwrap();
+            for (id, ev) in (*tracks)[track as usize].get_channel_evs().iter().enumerate() {
+                if func.call::<bool>(channel_event_to_lua_table(lua, ev)?)? {
+                    this.channel_evs_to_remove.push((track, id));
+                }
+            }
+            Ok(())
+        });
+
+        // ======== METAS ========
+
+        methods.add_method_mut("add_tempo", |_, this, (tick, bpm): (MIDITick, f32)| {
+            if !(MIN_TEMPO_BPM..=60000000.0).contains(&bpm) { return Err(mlua::Error::runtime(format!("{} isn't a valid tempo", bpm))); }
+            this.metas_to_add.push(MetaEvent { tick, event_type: MetaEventType::Tempo, data: tempo_as_bytes(bpm).to_vec() });
+            Ok(())
+        });
+
+        methods.add_method_mut("add_time_signature", |_, this, (tick, numerator, denominator): (MIDITick, u8, u32)| {
+            if numerator == 0 || !denominator.is_power_of_two() {
+                return Err(mlua::Error::runtime(format!("{}/{} isn't a valid time signature", numerator, denominator)));
+            }
+            this.metas_to_add.push(MetaEvent {
+                tick, event_type: MetaEventType::TimeSignature, data: vec![numerator, denominator.trailing_zeros() as u8, 24, 8]
             });
             Ok(())
         });
+
+        methods.add_method_mut("add_marker", |_, this, (tick, text): (MIDITick, String)| {
+            this.metas_to_add.push(MetaEvent { tick, event_type: MetaEventType::Marker, data: text.into_bytes() });
+            Ok(())
+        });
+
+        // removes every global meta for which `func(meta)` returns true (see meta_to_lua_table)
+        methods.add_method_mut("remove_metas", |lua, this, func: Function| {
+            let metas = {
+                let meta_editing = this.meta_editing.lock().unwrap();
+                meta_editing.get_metas()
+            };
+
+            let metas = metas.read().unwrap();
+            for (id, meta) in metas.iter().enumerate() {
+                if func.call::<bool>(meta_to_lua_table(lua, meta)?)? {
+                    this.metas_to_remove.push(id);
+                }
+            }
+            Ok(())
+        });
+
+        // ======== TRACKS ========
+
+        methods.add_method_mut("set_track_muted", |_, this, (track, muted): (usize, bool)| {
+            let track = this.check_track(track)?;
+            this.mute_changes.push((track, muted));
+            Ok(())
+        });
+
+        // ======== MACRO STEPS ========
+        // queued, and run on the current track's selection after on_apply returns
+
+        methods.add_method_mut("edit_function", |_, this, (name, params): (String, Option<Table>)| {
+            this.queue_macro_step(MacroStepKind::EditFunction, name, params)
+        });
+
+        methods.add_method_mut("run_plugin", |_, this, (name, values): (String, Option<Table>)| {
+            this.queue_macro_step(MacroStepKind::RunPlugin, name, values)
+        });
+
+        methods.add_method_mut("track_op", |_, this, (name, params): (String, Option<Table>)| {
+            this.queue_macro_step(MacroStepKind::TrackOp, name, params)
+        });
     }
-}
\ No newline at end of file
+}
@@ -10,9 +10,9 @@ use crate::{
         },
     },
     editor::{
-        actions::{EditorAction, EditorActions}, editing::{meta_editing::meta_sequence_funcs::merge_metas, note_editing::note_sequence_funcs::{extract, extract_and_remap_ids}}, midi_bar_cacher::BarCacher, tempo_map::TempoMap, util::{MIDITick, tempo_as_bytes}
+        actions::{EditorAction, EditorActions}, editing::{meta_editing::meta_sequence_funcs::{merge_metas, merge_metas_and_return_ids}, note_editing::note_sequence_funcs::{extract, extract_and_remap_ids, insert_at}}, midi_bar_cacher::BarCacher, scales::{ScaleMap, ScaleMode}, tempo_map::TempoMap, util::{MIDITick, tempo_as_bytes}
     },
-    midi::events::meta_event::{MetaEvent, MetaEventType}, util::debugger::Debugger,
+    midi::events::meta_event::{KeySignature, MetaEvent, MetaEventType}, util::debugger::Debugger,
 };
 
 use std::{cell::RefCell, collections::VecDeque, rc::Rc, sync::{Arc, Mutex, RwLock}};
@@ -25,6 +25,8 @@ pub struct MetaEditing {
 
     tmp_del_metas: VecDeque<MetaEvent>,
     tempo_map: Arc<RwLock<TempoMap>>,
+    // built on first use, and along with the bars whenever the metas change. (change count, scale map)
+    scale_map: Option<(u64, Rc<ScaleMap>)>,
     pub ppq: u16,
 }
 
@@ -42,6 +44,7 @@ impl MetaEditing {
 
             tmp_del_metas: VecDeque::new(),
             tempo_map: tempo_map.clone(),
+            scale_map: None,
             ppq: 960,
         }
     }
@@ -117,8 +120,8 @@ impl MetaEditing {
 
                 let mut editor_actions = self.editor_actions.borrow_mut();
                 editor_actions.register_action(EditorAction::Bulk(vec![
-                    EditorAction::DeleteMeta(vec![insert_idx], Some(vec![old_meta])),
-                    EditorAction::AddMeta(vec![insert_idx], None)
+                    EditorAction::AddMeta(vec![insert_idx], None),
+                    EditorAction::DeleteMeta(vec![insert_idx], Some(vec![old_meta]))
                 ]));
 
                 Debugger::log("Meta event replaced");
@@ -138,6 +141,180 @@ impl MetaEditing {
         self.regenerate_bars();
     }
 
+    /// Removes every meta of `meta_type` within `[start_tick, end_tick]` and merges `new_metas` in their place.
+    /// Registered as a single action, so a replaced range is undone in one go.
+    pub fn replace_metas_in_range(&mut self, meta_type: MetaEventType, start_tick: MIDITick, end_tick: MIDITick, new_metas: Vec<MetaEvent>) {
+        let (removed_ids, removed_metas, added_ids) = {
+            let mut metas = self.global_metas.write().unwrap();
+            let old_metas = std::mem::take(&mut *metas);
+
+            let removed_ids = old_metas.iter()
+                .enumerate()
+                .filter(|(_, m)| m.event_type == meta_type && m.tick >= start_tick && m.tick <= end_tick)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+
+            let (removed_metas, remaining) = extract(old_metas, &removed_ids);
+            let (merged, added_ids) = merge_metas_and_return_ids(remaining, new_metas);
+            *metas = merged;
+
+            (removed_ids, removed_metas, added_ids)
+        };
+
+        // bulk actions are undone front to back, so the addition has to come first
+        let mut actions = Vec::with_capacity(2);
+        if !added_ids.is_empty() { actions.push(EditorAction::AddMeta(added_ids, None)); }
+        if !removed_ids.is_empty() { actions.push(EditorAction::DeleteMeta(removed_ids, Some(removed_metas))); }
+
+        if actions.is_empty() { return; }
+
+        {
+            let mut editor_actions = self.editor_actions.borrow_mut();
+            if actions.len() == 1 {
+                editor_actions.register_action(actions.pop().unwrap());
+            } else {
+                editor_actions.register_action(EditorAction::Bulk(actions));
+            }
+        }
+
+        self.on_metas_changed(meta_type);
+    }
+
+    /// Adds a meta without replacing anything already on its tick.
+    pub fn add_meta(&mut self, meta: MetaEvent) {
+        let meta_type = meta.event_type;
+        let added_ids = {
+            let mut metas = self.global_metas.write().unwrap();
+            let old_metas = std::mem::take(&mut *metas);
+            let (merged, added_ids) = merge_metas_and_return_ids(old_metas, vec![meta]);
+            *metas = merged;
+            added_ids
+        };
+
+        {
+            let mut editor_actions = self.editor_actions.borrow_mut();
+            editor_actions.register_action(EditorAction::AddMeta(added_ids, None));
+        }
+
+        self.on_metas_changed(meta_type);
+    }
+
+    /// Removes the metas at the (sorted) `meta_ids`.
+    pub fn delete_metas(&mut self, meta_ids: Vec<usize>) {
+        if meta_ids.is_empty() { return; }
+
+        let deleted = {
+            let mut metas = self.global_metas.write().unwrap();
+            let old_metas = std::mem::take(&mut *metas);
+            let (deleted, remaining) = extract(old_metas, &meta_ids);
+            *metas = remaining;
+            deleted
+        };
+
+        let mut changed_types = deleted.iter().map(|m| m.event_type).collect::<Vec<_>>();
+        changed_types.dedup();
+
+        {
+            let mut editor_actions = self.editor_actions.borrow_mut();
+            editor_actions.register_action(EditorAction::DeleteMeta(meta_ids, Some(deleted)));
+        }
+
+        for meta_type in changed_types {
+            self.on_metas_changed(meta_type);
+        }
+    }
+
+    /// Where `meta` is now, checking `hint` (where it used to be) first. Metas can move around or go away
+    /// while something like a modeless dialog holds on to one.
+    pub fn find_meta(&self, hint: usize, meta: &MetaEvent) -> Option<usize> {
+        let metas = self.global_metas.read().unwrap();
+        let is_same = |m: &MetaEvent| m.tick == meta.tick && m.event_type == meta.event_type && m.data == meta.data;
+
+        if metas.get(hint).is_some_and(is_same) { return Some(hint); }
+        let first_on_tick = metas.partition_point(|m| m.tick < meta.tick);
+        metas[first_on_tick..].iter()
+            .take_while(|m| m.tick == meta.tick)
+            .position(is_same)
+            .map(|i| first_on_tick + i)
+    }
+
+    /// Swaps the data of the meta at `meta_idx`, keeping its tick.
+    pub fn set_meta_data(&mut self, meta_idx: usize, data: Vec<u8>) {
+        let (meta_type, old_meta) = {
+            let mut metas = self.global_metas.write().unwrap();
+            let old_meta = metas[meta_idx].clone();
+            metas[meta_idx].data = data;
+            (old_meta.event_type, old_meta)
+        };
+
+        {
+            let mut editor_actions = self.editor_actions.borrow_mut();
+            editor_actions.register_action(EditorAction::Bulk(vec![
+                EditorAction::AddMeta(vec![meta_idx], None),
+                EditorAction::DeleteMeta(vec![meta_idx], Some(vec![old_meta]))
+            ]));
+        }
+
+        self.on_metas_changed(meta_type);
+    }
+
+    /// Moves the meta at `meta_idx` to `new_tick` and swaps its data without registering an action.
+    /// Used for live dragging; call [`MetaEditing::register_meta_moved`] once the drag is finished.
+    /// Returns the new index of the meta.
+    pub fn move_meta_unregistered(&mut self, meta_idx: usize, new_tick: MIDITick, new_data: Vec<u8>) -> usize {
+        let (meta_type, new_idx) = {
+            let mut metas = self.global_metas.write().unwrap();
+            let mut meta = metas.remove(meta_idx);
+            meta.tick = new_tick;
+            meta.data = new_data;
+
+            let meta_type = meta.event_type;
+            let new_idx = metas.partition_point(|m| m.tick <= new_tick);
+            metas.insert(new_idx, meta);
+            (meta_type, new_idx)
+        };
+
+        self.on_metas_changed(meta_type);
+        new_idx
+    }
+
+    pub fn register_meta_moved(&mut self, old_idx: usize, old_meta: MetaEvent, new_idx: usize) {
+        {
+            let metas = self.global_metas.read().unwrap();
+            let new_meta = &metas[new_idx];
+            if new_meta.tick == old_meta.tick && new_meta.data == old_meta.data { return; }
+        }
+
+        let mut editor_actions = self.editor_actions.borrow_mut();
+        editor_actions.register_action(EditorAction::Bulk(vec![
+            EditorAction::AddMeta(vec![new_idx], None),
+            EditorAction::DeleteMeta(vec![old_idx], Some(vec![old_meta]))
+        ]));
+    }
+
+    /// Removes the metas at the (sorted) `delete_ids` and merges in `new_metas`, without registering an action.
+    /// Returns the removed metas and the indices the new ones ended up at.
+    pub fn edit_metas_unregistered(&mut self, delete_ids: &[usize], mut new_metas: Vec<MetaEvent>) -> (Vec<MetaEvent>, Vec<usize>) {
+        new_metas.sort_by_key(|m| m.tick);
+
+        let (deleted, added_ids) = {
+            let mut metas = self.global_metas.write().unwrap();
+            let old_metas = std::mem::take(&mut *metas);
+            let (deleted, remaining) = extract(old_metas, delete_ids);
+            let (merged, added_ids) = merge_metas_and_return_ids(remaining, new_metas);
+            *metas = merged;
+            (deleted, added_ids)
+        };
+
+        {
+            let mut tempo_map = self.tempo_map.write().unwrap();
+            tempo_map.rebuild_tempo_map();
+        }
+
+        self.regenerate_bars();
+        (deleted, added_ids)
+    }
+
     pub fn take_metas(&mut self) -> Vec<MetaEvent> {
         let mut metas = self.global_metas.write().unwrap();
         std::mem::take(&mut *metas)
@@ -150,13 +327,18 @@ impl MetaEditing {
 
     pub fn apply_action(&mut self, action: &mut EditorAction) {
         match action {
-            EditorAction::AddMeta(_, deleted_metas) => {
+            EditorAction::AddMeta(meta_ids, deleted_metas) => {
                 assert!(deleted_metas.is_some(), "[ADD METAS] Something has gone wrong while undoing meta deletion.");
                 {
                     let recovered_metas = deleted_metas.take().unwrap();
                     let old_metas = self.take_metas();
 
-                    let merged = merge_metas(old_metas, recovered_metas);
+                    // put them back exactly where they were, otherwise metas sharing a tick can get reordered
+                    let merged = if meta_ids.len() == recovered_metas.len() {
+                        insert_at(old_metas, meta_ids, recovered_metas)
+                    } else {
+                        merge_metas(old_metas, recovered_metas)
+                    };
                     self.set_metas(merged);
                 }
 
@@ -184,6 +366,15 @@ impl MetaEditing {
 
                 self.regenerate_bars();
             },
+            EditorAction::ShiftTime(_, _) => {
+                // the events themselves get swapped by the track editor
+                self.regenerate_bars();
+            },
+            EditorAction::Bulk(actions) => {
+                for action in actions.iter_mut().rev() {
+                    self.apply_action(action);
+                }
+            },
             _ => {}
         }
     }
@@ -192,7 +383,41 @@ impl MetaEditing {
         self.global_metas.clone()
     }
 
+    /// The key signature changes of the project, with `mode` built on top. Only rebuilt when the metas have changed.
+    pub fn get_scale_map(&mut self, mode: ScaleMode) -> Rc<ScaleMap> {
+        // metas can also get replaced without going through here (loading a project, changing the PPQ),
+        // those always move the change count though
+        let change_count = self.editor_actions.try_borrow().map(|editor_actions| editor_actions.get_change_count()).ok();
+
+        if let Some((cached_count, scale_map)) = self.scale_map.as_ref() {
+            if scale_map.mode == mode && change_count.map_or(true, |count| count == *cached_count) {
+                return scale_map.clone();
+            }
+        }
+
+        let scale_map = {
+            let metas = self.global_metas.read().unwrap();
+            Rc::new(ScaleMap::from_metas(&metas, mode))
+        };
+        if let Some(change_count) = change_count {
+            self.scale_map = Some((change_count, scale_map.clone()));
+        }
+        scale_map
+    }
+
+    fn on_metas_changed(&mut self, meta_type: MetaEventType) {
+        if meta_type == MetaEventType::Tempo {
+            let mut tempo_map = self.tempo_map.write().unwrap();
+            tempo_map.rebuild_tempo_map();
+        }
+
+        self.regenerate_bars();
+    }
+
     fn regenerate_bars(&mut self) {
+        // key signatures could've moved too
+        self.scale_map = None;
+
         let mut bar_cacher = self.bar_cacher.lock().unwrap();
         bar_cacher.clear_cache();
     }
@@ -200,11 +425,16 @@ impl MetaEditing {
 
 
 
+pub const META_TEXT_MAX_LEN: usize = 127;
+
 pub struct MetaEventInsertDialog {
     is_showing: bool,
     dialog_type: MetaEventType,
 
     fields: Vec<(&'static str, Box<dyn NumberField>)>,
+    key_sig: KeySignature,
+    text: String,
+    is_editing: bool,
 
     meta_created: Option<Box<dyn Fn(Vec<u8>)>>
 }
@@ -215,6 +445,9 @@ impl Default for MetaEventInsertDialog {
             is_showing: false,
             dialog_type: MetaEventType::Lyric,
             fields: Vec::new(),
+            key_sig: KeySignature::default(),
+            text: String::new(),
+            is_editing: false,
             meta_created: None
         }
     }
@@ -225,6 +458,42 @@ impl Dialog for MetaEventInsertDialog {
         for (label, field) in self.fields.iter_mut() {
             field.show(label, ui, None);
         }
+
+        if self.dialog_type.is_text() {
+            ui.horizontal(|ui| {
+                ui.label("Text");
+                // the length of a meta is written as a single byte on export
+                ui.add(egui::TextEdit::singleline(&mut self.text).char_limit(META_TEXT_MAX_LEN));
+            });
+        }
+
+        if self.dialog_type == MetaEventType::KeySignature {
+            ui.horizontal(|ui| {
+                ui.label("Key");
+                egui::ComboBox::from_id_salt("key_sig_key")
+                    .selected_text(self.key_sig.name())
+                    .show_ui(ui, |ui| {
+                        for key_sig in KeySignature::all(self.key_sig.minor) {
+                            ui.selectable_value(&mut self.key_sig, key_sig, key_sig.name());
+                        }
+                    });
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Mode");
+                ui.radio_value(&mut self.key_sig.minor, false, "Major");
+                ui.radio_value(&mut self.key_sig.minor, true, "Minor");
+            });
+
+            let sf = self.key_sig.sharps_flats;
+            ui.label(match sf {
+                0 => "No sharps or flats".to_string(),
+                1 => "1 sharp".to_string(),
+                -1 => "1 flat".to_string(),
+                sf if sf > 0 => format!("{} sharps", sf),
+                sf => format!("{} flats", -sf)
+            });
+        }
         None
     }
 
@@ -238,7 +507,8 @@ impl Dialog for MetaEventInsertDialog {
     }
 
     fn get_dialog_title(&self) -> String {
-        format!("Insert {}", self.dialog_type.to_string())
+        if self.is_editing { format!("Edit {}", self.dialog_type.to_string()) }
+        else { format!("Insert {}", self.dialog_type.to_string()) }
     }
 
     fn get_action_buttons(&self) -> Option<crate::app::ui::dialog::DialogActionButtons> {
@@ -255,6 +525,14 @@ impl Dialog for MetaEventInsertDialog {
                         },
                         MetaEventType::Tempo => {
                             data = tempo_as_bytes(dlg.fields[0].1.as_f32()).to_vec();
+                        },
+                        MetaEventType::KeySignature => {
+                            data = dlg.key_sig.to_meta_data();
+                        },
+                        meta_type if meta_type.is_text() => {
+                            let mut text_len = dlg.text.len().min(META_TEXT_MAX_LEN);
+                            while !dlg.text.is_char_boundary(text_len) { text_len -= 1; }
+                            data = dlg.text.as_bytes()[..text_len].to_vec();
                         }
                         _ => {}
                     }
@@ -301,8 +579,35 @@ impl MetaEventInsertDialog {
                 ];
                 self.meta_created = Some(Box::new(on_meta_created));
                 self.is_showing = true;
+            },
+            MetaEventType::KeySignature => {
+                self.fields.clear();
+                self.meta_created = Some(Box::new(on_meta_created));
+                self.is_showing = true;
+            },
+            meta_type if meta_type.is_text() => {
+                self.fields.clear();
+                self.text.clear();
+                self.meta_created = Some(Box::new(on_meta_created));
+                self.is_showing = true;
             }
             _ => {}
         }
     }
+
+    /// Opens the dialog for changing an existing meta event, pre-filled with its data.
+    pub fn init_edit_meta_dialog(&mut self, meta: &MetaEvent, on_meta_edited: impl Fn(Vec<u8>) + 'static) {
+        self.init_meta_dialog(meta.event_type, on_meta_edited);
+        self.is_editing = true;
+
+        match meta.event_type {
+            MetaEventType::KeySignature => {
+                self.key_sig = KeySignature::from_meta_data(&meta.data);
+            },
+            meta_type if meta_type.is_text() => {
+                self.text = String::from_utf8_lossy(&meta.data).to_string();
+            },
+            _ => {}
+        }
+    }
 }
\ No newline at end of file
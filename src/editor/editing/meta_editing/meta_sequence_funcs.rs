@@ -1,4 +1,4 @@
-use crate::midi::events::meta_event::MetaEvent;
+use crate::{editor::util::MIDITick, midi::events::meta_event::{MetaEvent, MetaEventType}};
 
 pub fn merge_metas(metas_1: Vec<MetaEvent>, metas_2: Vec<MetaEvent>) -> Vec<MetaEvent> {
     let mut notes_1_iter = metas_1.into_iter().peekable();
@@ -30,4 +30,87 @@ pub fn merge_metas(metas_1: Vec<MetaEvent>, metas_2: Vec<MetaEvent>) -> Vec<Meta
     }
 
     merged
-}
\ No newline at end of file
+}
+
+/// Like [`merge_metas`], but returns the indices of where each meta in [`metas_2`] got inserted in [`metas_1`].
+pub fn merge_metas_and_return_ids(metas_1: Vec<MetaEvent>, metas_2: Vec<MetaEvent>) -> (Vec<MetaEvent>, Vec<usize>) {
+    let mut metas_1_iter = metas_1.into_iter().peekable();
+    let mut metas_2_iter = metas_2.into_iter().peekable();
+
+    let mut merged = Vec::with_capacity(metas_1_iter.size_hint().0 + metas_2_iter.size_hint().0);
+    let mut ids = Vec::with_capacity(metas_2_iter.size_hint().0);
+
+    loop {
+        match (metas_1_iter.peek(), metas_2_iter.peek()) {
+            (Some(m1), Some(m2)) => {
+                let meta = if m1.tick <= m2.tick {
+                    metas_1_iter.next().unwrap()
+                } else {
+                    ids.push(merged.len());
+                    metas_2_iter.next().unwrap()
+                };
+
+                merged.push(meta);
+            },
+            (Some(_), None) => {
+                merged.extend(metas_1_iter.by_ref());
+                break;
+            },
+            (None, Some(_)) => {
+                for meta in metas_2_iter.by_ref() {
+                    ids.push(merged.len());
+                    merged.push(meta);
+                }
+                break;
+            },
+            (None, None) => { break; }
+        }
+    }
+
+    (merged, ids)
+}
+
+/// Finds the closest meta of one of `types` strictly after (or before, if `forward` is false) `tick`.
+pub fn find_adjacent_meta_tick(metas: &[MetaEvent], types: &[MetaEventType], tick: MIDITick, forward: bool) -> Option<MIDITick> {
+    let mut matching = metas.iter().filter(|m| types.contains(&m.event_type));
+
+    if forward {
+        matching.find(|m| m.tick > tick).map(|m| m.tick)
+    } else {
+        matching.take_while(|m| m.tick < tick).last().map(|m| m.tick)
+    }
+}
+
+/// Returns the lyric line being sung at `tick`, split into the part that was already sung and the rest.
+/// Lines are split on the usual SMF conventions: a syllable starting with '/' or '\', or ending with a line break.
+/// `metas` has to be sorted by tick. Only the syllables of the line at `tick` get looked at.
+pub fn get_lyric_line_at(metas: &[MetaEvent], tick: MIDITick) -> Option<(String, String)> {
+    let is_lyric = |i: &usize| metas[*i].event_type == MetaEventType::Lyric;
+    let prev_lyric = |i: usize| (0..i).rev().find(is_lyric);
+    let next_lyric = |i: usize| (i + 1..metas.len()).find(is_lyric);
+
+    let starts_line = |i: usize| {
+        metas[i].data.starts_with(b"/")
+        || metas[i].data.starts_with(b"\\")
+        || prev_lyric(i).map_or(true, |prev| metas[prev].data.ends_with(b"\r") || metas[prev].data.ends_with(b"\n"))
+    };
+
+    let curr = prev_lyric(metas.partition_point(|m| m.tick <= tick))?;
+
+    let mut line_start = curr;
+    while !starts_line(line_start) { line_start = prev_lyric(line_start).unwrap(); }
+
+    let mut line_end = curr;
+    while let Some(next) = next_lyric(line_end) {
+        if starts_line(next) { break; }
+        line_end = next;
+    }
+
+    let clean = |range: std::ops::RangeInclusive<usize>| -> String {
+        range.filter(is_lyric)
+            .map(|i| String::from_utf8_lossy(&metas[i].data).trim_matches(['/', '\\', '\r', '\n']).to_string())
+            .collect()
+    };
+
+    Some((clean(line_start..=curr), clean(curr + 1..=line_end)))
+}
@@ -1,4 +1,4 @@
-use std::{cell::RefCell, rc::Rc, sync::{Arc, Mutex, RwLock}};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc, sync::{Arc, Mutex, RwLock}};
 use crate::{
     app::{
         main_window::{
@@ -6,6 +6,7 @@ use crate::{
             EditorToolSettings,
             ToolBarSettings
         },
+        view_settings::ViewSettings,
         rendering::{
             RenderManager,
             data_view::DataViewRenderer
@@ -17,9 +18,9 @@ use crate::{
             EditorActions
         },
         editing::{
-            SelectionOp, SharedClipboard, SharedSelectedNotes, note_editing::note_sequence_funcs::{
-                exclude, extract, extract_and_remap_ids, merge_notes, merge_notes_and_return_ids, merge_unique, move_all_notes_by, move_each_note_by, remove_note
-            }
+            PasteMode, SelectionOp, SharedClipboard, SharedSelectedNotes, meta_editing::MetaEditing, note_editing::{note_properties::{NoteProperty, NotePropertiesSummary}, note_sequence_funcs::{
+                exclude, extract, extract_and_remap_ids, intersect, merge_notes, merge_notes_and_return_ids, merge_unique, move_all_notes_by, move_each_note_by, remove_note
+            }}
         },
         navigation::PianoRollNavigation,
         settings::editor_settings::PR_KEYBOARD_WIDTH,
@@ -54,6 +55,7 @@ pub mod note_edit_flags {
 }
 
 pub mod note_sequence_funcs;
+pub mod note_properties;
 
 #[derive(Default)]
 struct NoteEditMouseInfo {
@@ -78,6 +80,10 @@ pub struct NoteEditing {
     editor_actions: Rc<RefCell<EditorActions>>,
     toolbar_settings: Rc<RefCell<ToolBarSettings>>,
 
+    // for snapping to the scale
+    meta_editing: Arc<Mutex<MetaEditing>>,
+    view_settings: Arc<Mutex<ViewSettings>>,
+
     // for sending to renderers
     // render_manager: Arc<Mutex<RenderManager>>,
     // data_view_renderer: Option<Arc<Mutex<DataViewRenderer>>>,
@@ -95,6 +101,8 @@ pub struct NoteEditing {
     pub latest_note_start: MIDITick,
     pub selection_range: (MIDITick, MIDITick, u8, u8),
     draw_select_box: bool,
+    // (change count, selection change count, summary), for the note inspector
+    selected_notes_summary: Option<(u64, u64, Option<NotePropertiesSummary>)>,
 
     flags: u16,
 }
@@ -110,6 +118,8 @@ impl NoteEditing {
 
         shared_clipboard: &Arc<RwLock<SharedClipboard>>,
         shared_selected_note_ids: &Arc<RwLock<SharedSelectedNotes>>,
+        meta_editing: &Arc<Mutex<MetaEditing>>,
+        view_settings: &Arc<Mutex<ViewSettings>>,
     ) -> Self {
 
         Self {
@@ -125,6 +135,9 @@ impl NoteEditing {
             editor_actions: editor_actions.clone(),
             toolbar_settings: toolbar_settings.clone(),
 
+            meta_editing: meta_editing.clone(),
+            view_settings: view_settings.clone(),
+
             note_old_positions: Vec::new(),
             note_old_lengths: Vec::new(),
 
@@ -132,6 +145,7 @@ impl NoteEditing {
             ppq: 960,
             selection_range: (0, 0, 0, 0),
             draw_select_box: false,
+            selected_notes_summary: None,
 
             flags: NOTE_EDIT_FLAGS_NONE
         }
@@ -304,7 +318,18 @@ impl NoteEditing {
                 self.cut_selected_notes(curr_track);
             }
 
-            if ui.input(|i| i.events.iter().any(|ev| matches!(ev, egui::Event::Paste(_)))) {
+            let pasted_text = ui.input(|i| i.events.iter().find_map(|ev| match ev {
+                egui::Event::Paste(text) => Some(text.clone()),
+                _ => None
+            }));
+
+            if let Some(pasted_text) = pasted_text {
+                // notes copied from another window take over the clipboard before pasting
+                {
+                    let mut shared_clipboard = self.shared_clipboard.write().unwrap();
+                    if shared_clipboard.import_system_text(&pasted_text, self.ppq) { Debugger::log("Imported notes from the system clipboard"); }
+                }
+
                 Debugger::log("Pasted");
                 self.paste_notes(curr_track);
             }
@@ -347,6 +372,11 @@ impl NoteEditing {
         self.mouse_info.last_clicked_note_idx
     }
 
+    /// Key of the note that was last clicked on, if the last click landed on a note.
+    pub fn get_last_clicked_note_key(&self) -> Option<MIDIKey> {
+        self.mouse_info.last_clicked_note_idx.map(|_| self.mouse_info.last_clicked_note_pos.1)
+    }
+
     // ======== PENCIL TOOL STUFF ========
 
     fn pencil_mouse_down(&mut self) {
@@ -660,6 +690,12 @@ impl NoteEditing {
                 else { ghost_key as u8 }
             };
 
+            let is_pencil = {
+                let editor_tool = self.editor_tool.try_borrow().unwrap();
+                editor_tool.get_tool() == EditorTool::Pencil
+            };
+            let ghost_key = if is_pencil { self.snap_key_to_scale(ghost_key, ghost_start) } else { ghost_key };
+
             self.set_first_ghost_note_pos(ghost_start, ghost_key);
         }
     }
@@ -769,8 +805,20 @@ impl NoteEditing {
                     }
                 }
             },
-            SelectionOp::RemoveFromSelection => {
+            SelectionOp::RemoveFromSelection | SelectionOp::IntersectSelection => {
+                let old_ids = selected_ids.take_selected_from_track(track);
+                let new_ids = match selection_op {
+                    SelectionOp::IntersectSelection => intersect(old_ids.clone(), &ids),
+                    _ => exclude(old_ids.clone(), &ids)
+                };
+
+                let deselected_ids = exclude(old_ids, &new_ids);
+                selected_ids.set_selected_in_track(new_ids, track);
 
+                if !deselected_ids.is_empty() {
+                    let mut editor_actions = self.editor_actions.borrow_mut();
+                    editor_actions.register_action(EditorAction::Deselect(deselected_ids, track));
+                }
             }
         }
     }
@@ -954,6 +1002,7 @@ impl NoteEditing {
         };
 
         let gn_start = self.snap_tick(gn_start as SignedMIDITick) as MIDITick;
+        let gn_key = self.snap_key_to_scale(gn_key, gn_start);
 
         self.set_first_ghost_note_pos(gn_start, gn_key);
     }
@@ -1243,6 +1292,195 @@ impl NoteEditing {
         editor_actions.register_action(EditorAction::PlaceNotes(new_ids, None, track));
     }
 
+    /// Pastes the clipboard once at every tick in `paste_ticks`, with the earliest copied note landing on the tick.
+    /// `clipboard[i]` goes into track `dest_tracks[i]`. With `replace`, notes starting inside a pasted range are removed first.
+    pub fn paste_notes_repeated(&mut self, clipboard: Vec<(u16, Vec<Note>)>, dest_tracks: &[u16], paste_ticks: &[MIDITick], replace: bool) {
+        if paste_ticks.is_empty() { return; }
+
+        let clip_start = clipboard.iter().filter_map(|(_, notes)| notes.first()).map(|n| n.start()).min();
+        let Some(clip_start) = clip_start else { return; };
+        let clip_end = clipboard.iter().flat_map(|(_, notes)| notes.iter()).map(|n| n.end()).max().unwrap_or(clip_start);
+        let clip_length = (clip_end - clip_start).max(1);
+
+        // several clipboard tracks can end up in the same track, those get pasted together
+        let mut notes_per_track: BTreeMap<u16, Vec<Note>> = BTreeMap::new();
+        for ((_, notes), &dest) in clipboard.into_iter().zip(dest_tracks) {
+            let pasted = notes_per_track.remove(&dest).unwrap_or_default();
+            let mut repeated = Vec::with_capacity(notes.len() * paste_ticks.len());
+            for &tick in paste_ticks {
+                repeated.extend(notes.iter().map(|n| Note { start: n.start() - clip_start + tick, ..*n }));
+            }
+            repeated.sort_by_key(|n| n.start());
+            notes_per_track.insert(dest, merge_notes(pasted, repeated));
+        }
+
+        // added tracks go last, so redoing adds them before anything gets pasted into them
+        let mut added_tracks = Vec::new();
+        if let Some(&last_track) = notes_per_track.keys().last() {
+            let mut tracks = self.tracks.write().unwrap();
+            while tracks.len() <= last_track as usize {
+                added_tracks.push(EditorAction::AddTrack(tracks.len() as u16, None, false));
+                tracks.push(MIDITrack::new_empty());
+            }
+        }
+        // and undoing removes the last one first
+        added_tracks.reverse();
+
+        let shared_selected = self.shared_selected_note_ids.clone();
+        let mut actions = Vec::new();
+        for (dest, pasted) in notes_per_track.into_iter() {
+            let mut old_notes = self.take_notes_in_track(dest);
+
+            // listed in reverse, the notes get removed before the new ones are placed
+            let mut track_actions = Vec::with_capacity(2);
+            if replace {
+                let replaced_ids: Vec<usize> = old_notes.iter().enumerate()
+                    .filter(|(_, n)| paste_ticks.iter().any(|&tick| n.start() >= tick && n.start() - tick < clip_length))
+                    .map(|(id, _)| id)
+                    .collect();
+
+                if !replaced_ids.is_empty() {
+                    let (replaced, kept) = extract(old_notes, &replaced_ids);
+                    old_notes = kept;
+                    track_actions.push(EditorAction::DeleteNotes(replaced_ids, Some(replaced), dest));
+                }
+            }
+
+            let (new_notes, new_ids) = merge_notes_and_return_ids(old_notes, pasted);
+            self.set_notes_in_track(dest, new_notes);
+
+            {
+                let mut selected = shared_selected.write().unwrap();
+                selected.set_selected_in_track(new_ids.clone(), dest);
+            }
+            track_actions.push(EditorAction::PlaceNotes(new_ids, None, dest));
+
+            track_actions.reverse();
+            actions.extend(track_actions);
+        }
+        actions.extend(added_tracks);
+
+        let mut editor_actions = self.editor_actions.try_borrow_mut().unwrap();
+        editor_actions.register_action(EditorAction::Bulk(actions));
+    }
+
+    /// Copies the velocities or lengths of `source` onto the selected notes in `tracks`, note by note.
+    /// If there are more selected notes than source notes, the source pattern starts over.
+    pub fn paste_note_properties(&mut self, source: &[Note], tracks: &[u16], mode: PasteMode) {
+        if source.is_empty() { return; }
+
+        let mut actions = Vec::new();
+        for &track in tracks {
+            let ids = {
+                let selected = self.shared_selected_note_ids.read().unwrap();
+                match selected.get_selected_ids_in_track(track) {
+                    Some(ids) if !ids.is_empty() => ids.clone(),
+                    _ => continue
+                }
+            };
+
+            let mut action = self.with_notes(track as usize, |notes| {
+                let sources = source.iter().cycle();
+                match mode {
+                    PasteMode::VelocitiesOnly => {
+                        let deltas = ids.iter().zip(sources).map(|(&id, src)| src.velocity() as i8 - notes[id].velocity() as i8).collect();
+                        EditorAction::VelocityChange(ids, deltas, track)
+                    },
+                    _ => {
+                        let deltas = ids.iter().zip(sources).map(|(&id, src)| src.length() as SignedMIDITick - notes[id].length() as SignedMIDITick).collect();
+                        EditorAction::LengthChange(ids, deltas, track)
+                    }
+                }
+            });
+
+            self.apply_action(&mut action);
+            actions.push(action);
+        }
+
+        if actions.is_empty() { Debugger::log_warning("No notes are selected to paste onto."); return; }
+
+        let mut editor_actions = self.editor_actions.try_borrow_mut().unwrap();
+        editor_actions.register_action(EditorAction::Bulk(actions));
+    }
+
+    /// Ranges of every property over the selected notes in all tracks, or [`None`] if nothing is selected.
+    /// Only gone through again once the project or the selection has changed.
+    pub fn get_selected_notes_summary(&mut self) -> Option<NotePropertiesSummary> {
+        let change_count = self.editor_actions.try_borrow().map(|editor_actions| editor_actions.get_change_count()).ok();
+        let selection_count = self.shared_selected_note_ids.read().unwrap().get_change_count();
+
+        if let (Some(change_count), Some((cached_count, cached_selection, summary))) = (change_count, self.selected_notes_summary) {
+            if cached_count == change_count && cached_selection == selection_count { return summary; }
+        }
+
+        let summary = self.summarize_selected_notes();
+        if let Some(change_count) = change_count {
+            self.selected_notes_summary = Some((change_count, selection_count, summary));
+        }
+        summary
+    }
+
+    fn summarize_selected_notes(&self) -> Option<NotePropertiesSummary> {
+        let shared_selected = self.shared_selected_note_ids.read().unwrap();
+        let tracks = self.tracks.read().unwrap();
+
+        let mut summary: Option<NotePropertiesSummary> = None;
+        for (track, ids) in shared_selected.get_selected() {
+            let Some(track) = tracks.get(track as usize) else { continue; };
+            let notes = track.get_notes();
+            for note in ids.iter().filter_map(|&id| notes.get(id)) {
+                match summary.as_mut() {
+                    Some(summary) => summary.include(note),
+                    None => summary = Some(NotePropertiesSummary::from_note(note))
+                }
+            }
+        }
+
+        summary
+    }
+
+    /// Sets `property` of every selected note to `new_value(old value)`, clamped to what the property allows.
+    /// Registered as one undoable action.
+    pub fn set_selected_notes_property(&mut self, property: NoteProperty, new_value: impl Fn(i64) -> i64) {
+        let selected = {
+            let shared_selected = self.shared_selected_note_ids.read().unwrap();
+            shared_selected.get_selected().into_iter()
+                .filter(|(_, ids)| !ids.is_empty())
+                .map(|(track, ids)| (track, ids.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let (min_value, max_value) = property.value_range();
+
+        let mut actions = Vec::new();
+        for (track, ids) in selected {
+            let deltas: Vec<i64> = self.with_notes(track as usize, |notes| {
+                ids.iter().map(|&id| {
+                    let old_value = property.get(&notes[id]);
+                    new_value(old_value).clamp(min_value, max_value) - old_value
+                }).collect()
+            });
+
+            if deltas.iter().all(|&delta| delta == 0) { continue; }
+
+            let mut action = match property {
+                NoteProperty::Start => EditorAction::NotesMove(ids, deltas.iter().map(|&d| (d as SignedMIDITick, 0)).collect(), track, true),
+                NoteProperty::Key => EditorAction::NotesMove(ids, deltas.iter().map(|&d| (0, d as i16)).collect(), track, true),
+                NoteProperty::Length => EditorAction::LengthChange(ids, deltas.iter().map(|&d| d as SignedMIDITick).collect(), track),
+                NoteProperty::Velocity => EditorAction::VelocityChange(ids, deltas.iter().map(|&d| d as i8).collect(), track),
+                NoteProperty::Channel => EditorAction::ChannelChange(ids, deltas.iter().map(|&d| d as i8).collect(), track)
+            };
+
+            self.apply_action(&mut action);
+            actions.push(action);
+        }
+
+        if actions.is_empty() { return; }
+
+        let mut editor_actions = self.editor_actions.try_borrow_mut().unwrap();
+        editor_actions.register_action(EditorAction::Bulk(actions));
+    }
+
     pub fn delete_notes(&mut self, ids: Vec<usize>) {
         let curr_track = self.get_current_track();
         
@@ -1489,6 +1727,23 @@ impl NoteEditing {
         (tbs.note_channel.value() as u8 - 1, tbs.note_gate.value() as MIDITick, tbs.note_velocity.value() as u8)
     }
 
+    /// Moves the key into the scale at `tick` if snapping to the scale is enabled.
+    fn snap_key_to_scale(&self, key: u8, tick: MIDITick) -> u8 {
+        let snap_to_scale = {
+            let editor_tool = self.editor_tool.try_borrow().unwrap();
+            editor_tool.snap_to_scale
+        };
+        if !snap_to_scale { return key; }
+
+        let scale_mode = {
+            let view_settings = self.view_settings.lock().unwrap();
+            view_settings.pr_scale_mode
+        };
+
+        let mut meta_editing = self.meta_editing.lock().unwrap();
+        meta_editing.get_scale_map(scale_mode).snap_key(key, tick)
+    }
+
     fn snap_tick(&self, tick: SignedMIDITick) -> SignedMIDITick {
         let snap = self.get_min_snap_tick_length() as SignedMIDITick;
         if snap == 1 { return tick; }
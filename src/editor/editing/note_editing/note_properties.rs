@@ -0,0 +1,102 @@
+// note_properties.rs - reading single note properties across a selection, for the note inspector.
+
+use crate::{editor::util::SignedMIDITick, midi::events::note::Note};
+
+/// A note property that can be viewed and edited on its own.
+#[derive(Clone, Copy, PartialEq)]
+pub enum NoteProperty {
+    Start,
+    Length,
+    Key,
+    Velocity,
+    Channel
+}
+
+impl NoteProperty {
+    pub fn get(&self, note: &Note) -> i64 {
+        match self {
+            NoteProperty::Start => note.start() as i64,
+            NoteProperty::Length => note.length() as i64,
+            NoteProperty::Key => note.key() as i64,
+            NoteProperty::Velocity => note.velocity() as i64,
+            NoteProperty::Channel => note.channel() as i64
+        }
+    }
+
+    /// The values the property can take. Ticks are capped so the change still fits in a [`SignedMIDITick`].
+    pub fn value_range(&self) -> (i64, i64) {
+        match self {
+            NoteProperty::Start => (0, SignedMIDITick::MAX as i64),
+            NoteProperty::Length => (1, SignedMIDITick::MAX as i64),
+            NoteProperty::Key => (0, 127),
+            NoteProperty::Velocity => (1, 127),
+            NoteProperty::Channel => (0, 15)
+        }
+    }
+}
+
+/// Lowest and highest value of a property among some notes.
+#[derive(Clone, Copy, PartialEq)]
+pub struct PropertyRange {
+    pub min: i64,
+    pub max: i64
+}
+
+impl PropertyRange {
+    fn new(value: i64) -> Self {
+        Self { min: value, max: value }
+    }
+
+    fn include(&mut self, value: i64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// True if the notes don't all share the same value.
+    pub fn is_mixed(&self) -> bool {
+        self.min != self.max
+    }
+}
+
+/// Every property of a set of notes at a glance.
+#[derive(Clone, Copy, PartialEq)]
+pub struct NotePropertiesSummary {
+    pub note_count: usize,
+    pub start: PropertyRange,
+    pub length: PropertyRange,
+    pub key: PropertyRange,
+    pub velocity: PropertyRange,
+    pub channel: PropertyRange
+}
+
+impl NotePropertiesSummary {
+    pub fn from_note(note: &Note) -> Self {
+        Self {
+            note_count: 1,
+            start: PropertyRange::new(note.start() as i64),
+            length: PropertyRange::new(note.length() as i64),
+            key: PropertyRange::new(note.key() as i64),
+            velocity: PropertyRange::new(note.velocity() as i64),
+            channel: PropertyRange::new(note.channel() as i64)
+        }
+    }
+
+    pub fn include(&mut self, note: &Note) {
+        self.note_count += 1;
+        self.start.include(note.start() as i64);
+        self.length.include(note.length() as i64);
+        self.key.include(note.key() as i64);
+        self.velocity.include(note.velocity() as i64);
+        self.channel.include(note.channel() as i64);
+    }
+
+    pub fn get(&self, property: NoteProperty) -> PropertyRange {
+        match property {
+            NoteProperty::Start => self.start,
+            NoteProperty::Length => self.length,
+            NoteProperty::Key => self.key,
+            NoteProperty::Velocity => self.velocity,
+            NoteProperty::Channel => self.channel
+        }
+    }
+}
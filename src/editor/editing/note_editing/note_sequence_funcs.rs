@@ -97,6 +97,32 @@ pub fn extract<T>(src: Vec<T>, ids: &[usize]) -> (Vec<T>, Vec<T>) {
     (extracted, new_arr)
 }
 
+/// The inverse of [`extract`]. Puts each element of `elems` back at its (sorted) index in `ids`.
+pub fn insert_at<T>(src: Vec<T>, ids: &[usize], elems: Vec<T>) -> Vec<T> {
+    assert_eq!(ids.len(), elems.len(), "ids and elems must have the same length");
+
+    let mut merged = Vec::with_capacity(src.len() + elems.len());
+    let mut src_iter = src.into_iter();
+    let mut ins_iter = ids.iter().zip(elems.into_iter()).peekable();
+
+    loop {
+        if let Some((&id, _)) = ins_iter.peek() {
+            if id <= merged.len() {
+                merged.push(ins_iter.next().unwrap().1);
+                continue;
+            }
+        }
+
+        match src_iter.next() {
+            Some(elem) => merged.push(elem),
+            None => break
+        }
+    }
+
+    merged.extend(ins_iter.map(|(_, elem)| elem));
+    merged
+}
+
 /// Excludes each element in B from A.
 pub fn exclude<T: Ord>(a: Vec<T>, b: &[T]) -> Vec<T> {
     let mut result = Vec::new();
@@ -127,6 +153,22 @@ pub fn exclude<T: Ord>(a: Vec<T>, b: &[T]) -> Vec<T> {
     result
 }
 
+/// Keeps only the elements of A that are also in B. Both have to be sorted.
+pub fn intersect<T: Ord>(a: Vec<T>, b: &[T]) -> Vec<T> {
+    let mut result = Vec::new();
+    let mut b_iter = b.iter().peekable();
+
+    for a_val in a.into_iter() {
+        while b_iter.next_if(|b_val| **b_val < a_val).is_some() {}
+
+        if b_iter.peek().map_or(false, |b_val| **b_val == a_val) {
+            result.push(a_val);
+        }
+    }
+
+    result
+}
+
 /// Merges two arrays but removes duplicates. Returns 1) The merged array with unique
 pub fn merge_unique<T: Ord>(a: Vec<T>, b: Vec<T>) -> Vec<T> {
     let mut result = Vec::with_capacity(a.len() + b.len());
@@ -0,0 +1,298 @@
+// system_clipboard.rs - moves copied notes in and out of the OS clipboard.
+//
+// The OS clipboard only takes text here, so the notes go in twice: as a readable note list, and packed into a
+// standard MIDI file (base64) on the last line. People can read and edit the list, so pasting goes by the list
+// whenever it can be read, and only falls back to the MIDI file if the list got mangled on the way.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::{editor::util::{MIDITick, key_to_name, name_to_key}, midi::{events::note::Note, midi_file::{MIDIEvent, MIDIFileWriter}}};
+
+const HEADER: &str = "; Andromeda notes";
+const COLUMNS: &str = "; track start length key velocity channel";
+const SMF_PREFIX: &str = "; smf ";
+
+/// Copies with more notes than this stay in Andromeda, turning them into text would take too long.
+pub const MAX_EXPORTED_NOTES: usize = 200_000;
+
+/// Turns clipboard contents (track, notes) into the text that goes on the OS clipboard.
+pub fn clipboard_to_text(clipboard: &[(u16, Vec<Note>)], ppq: u16) -> String {
+    let mut text = format!("{}, ppq {}\n{}\n", HEADER, ppq, COLUMNS);
+
+    for (track, notes) in clipboard.iter() {
+        for note in notes.iter() {
+            text.push_str(&format!("{} {} {} {} {} {}\n",
+                track, note.start(), note.length(), key_to_name(note.key()), note.velocity(), note.channel() + 1));
+        }
+    }
+
+    text.push_str(SMF_PREFIX);
+    text.push_str(&base64_encode(&clipboard_to_smf(clipboard, ppq)));
+    text.push('\n');
+    text
+}
+
+/// Reads notes from OS clipboard text, rescaled to `ppq`. The note list is used if it can be read, since it might
+/// have been edited, otherwise the embedded MIDI file. Returns [`None`] if the text has no notes in it.
+pub fn clipboard_from_text(text: &str, ppq: u16) -> Option<Vec<(u16, Vec<Note>)>> {
+    let from_list = parse_note_list(text).filter(|(_, clipboard)| !clipboard.is_empty());
+
+    let (src_ppq, clipboard) = match from_list {
+        Some(parsed) => parsed,
+        None => text.lines()
+            .find_map(|line| line.trim().strip_prefix(SMF_PREFIX.trim_end()))
+            .and_then(|encoded| base64_decode(encoded.trim()))
+            .and_then(|data| clipboard_from_smf(&data))?
+    };
+
+    if clipboard.iter().all(|(_, notes)| notes.is_empty()) { return None; }
+    Some(rescale(clipboard, src_ppq, ppq))
+}
+
+fn rescale(clipboard: Vec<(u16, Vec<Note>)>, from_ppq: u16, to_ppq: u16) -> Vec<(u16, Vec<Note>)> {
+    if from_ppq == to_ppq || from_ppq == 0 { return clipboard; }
+
+    let scale = |tick: MIDITick| ((tick as u64 * to_ppq as u64 + from_ppq as u64 / 2) / from_ppq as u64) as MIDITick;
+    clipboard.into_iter().map(|(track, notes)| {
+        let notes = notes.into_iter().map(|n| Note { start: scale(n.start()), length: scale(n.length()).max(1), ..n }).collect();
+        (track, notes)
+    }).collect()
+}
+
+// ======== TEXT ========
+
+fn parse_note_list(text: &str) -> Option<(u16, Vec<(u16, Vec<Note>)>)> {
+    let mut ppq = 0;
+    let mut tracks: Vec<(u16, Vec<Note>)> = Vec::new();
+
+    for line in text.lines().map(|l| l.trim()) {
+        if line.is_empty() { continue; }
+
+        if let Some(header) = line.strip_prefix(HEADER) {
+            if let Some(ppq_str) = header.split("ppq").nth(1) {
+                ppq = ppq_str.trim().parse().unwrap_or(ppq);
+            }
+            continue;
+        }
+        if line.starts_with(';') { continue; }
+
+        // any line that isn't a note means this isn't a note list
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [track, start, length, key, velocity, channel] = fields.as_slice() else { return None; };
+
+        let channel: u8 = channel.parse().ok()?;
+        let note = Note {
+            start: start.parse().ok()?,
+            length: length.parse::<MIDITick>().ok()?.max(1),
+            key: name_to_key(key)?,
+            velocity: velocity.parse::<u8>().ok()?.clamp(1, 127),
+            channel: channel.clamp(1, 16) - 1
+        };
+
+        let track: u16 = track.parse().ok()?;
+        match tracks.iter_mut().find(|(t, _)| *t == track) {
+            Some((_, notes)) => notes.push(note),
+            None => tracks.push((track, vec![note]))
+        }
+    }
+
+    for (_, notes) in tracks.iter_mut() { notes.sort_by_key(|n| n.start()); }
+    tracks.sort_by_key(|(track, _)| *track);
+    Some((if ppq == 0 { 960 } else { ppq }, tracks))
+}
+
+// ======== SMF ========
+
+fn clipboard_to_smf(clipboard: &[(u16, Vec<Note>)], ppq: u16) -> Vec<u8> {
+    let mut writer = MIDIFileWriter::new(ppq);
+
+    for (track, notes) in clipboard.iter() {
+        writer.new_track();
+
+        // the track name keeps the track layout of multi-track copies
+        let name = format!("track {}", track).into_bytes();
+        writer.flush_evs_to_track(vec![MIDIEvent { delta: 0, data: [vec![0xFF, 0x03, name.len() as u8], name].concat() }]);
+        writer.add_notes_to_midi(notes);
+        writer.end_track();
+    }
+
+    let mut data = Vec::new();
+    writer.write_midi_to(&mut data).unwrap();
+    data
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize
+}
+
+impl<'a> ByteReader<'a> {
+    fn read_u8(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn read_u16(&mut self) -> Option<u16> {
+        let b = self.read_bytes(2)?;
+        Some(((b[0] as u16) << 8) | b[1] as u16)
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        let b = self.read_bytes(4)?;
+        Some(((b[0] as u32) << 24) | ((b[1] as u32) << 16) | ((b[2] as u32) << 8) | b[3] as u32)
+    }
+
+    fn read_vlq(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.read_u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 { return Some(value); }
+        }
+        None
+    }
+}
+
+/// Reads the notes out of a standard MIDI file. Only meant for the small files made by [`clipboard_to_smf`],
+/// but anything that isn't SMPTE-timed works.
+fn clipboard_from_smf(data: &[u8]) -> Option<(u16, Vec<(u16, Vec<Note>)>)> {
+    let mut reader = ByteReader { data, pos: 0 };
+    if reader.read_u32()? != 0x4D546864 { return None; }
+
+    let header_len = reader.read_u32()? as usize;
+    let _format = reader.read_u16()?;
+    let track_count = reader.read_u16()?;
+    let ppq = reader.read_u16()?;
+    if ppq & 0x8000 != 0 { return None; }
+    reader.read_bytes(header_len.checked_sub(6)?)?;
+
+    let mut tracks = Vec::with_capacity(track_count as usize);
+    for i in 0..track_count {
+        if reader.read_u32()? != 0x4D54726B { return None; }
+        let len = reader.read_u32()? as usize;
+        let mut track_reader = ByteReader { data: reader.read_bytes(len)?, pos: 0 };
+
+        let (name, notes) = read_smf_track(&mut track_reader)?;
+        let track = name.as_deref()
+            .and_then(|name| name.strip_prefix("track "))
+            .and_then(|idx| idx.trim().parse().ok())
+            .unwrap_or(i);
+
+        if !notes.is_empty() { tracks.push((track, notes)); }
+    }
+
+    Some((ppq, tracks))
+}
+
+fn read_smf_track(reader: &mut ByteReader) -> Option<(Option<String>, Vec<Note>)> {
+    let mut notes = Vec::new();
+    let mut name = None;
+    // note ons waiting for their note off, per (channel, key)
+    let mut playing: HashMap<(u8, u8), VecDeque<(MIDITick, u8)>> = HashMap::new();
+
+    let mut tick: MIDITick = 0;
+    let mut running_status = 0u8;
+
+    while reader.pos < reader.data.len() {
+        tick = tick.saturating_add(reader.read_vlq()?);
+
+        let mut status = reader.read_u8()?;
+        if status < 0x80 {
+            // running status, the byte we just read was data
+            if running_status == 0 { return None; }
+            reader.pos -= 1;
+            status = running_status;
+        }
+
+        match status {
+            0xFF => {
+                running_status = 0;
+                let meta_type = reader.read_u8()?;
+                let len = reader.read_vlq()? as usize;
+                let meta_data = reader.read_bytes(len)?;
+                match meta_type {
+                    0x03 => name = Some(String::from_utf8_lossy(meta_data).into_owned()),
+                    0x2F => break,
+                    _ => {}
+                }
+            },
+            0xF0 | 0xF7 => {
+                running_status = 0;
+                let len = reader.read_vlq()? as usize;
+                reader.read_bytes(len)?;
+            },
+            _ => {
+                running_status = status;
+                let channel = status & 0x0F;
+                let data_len = if matches!(status & 0xF0, 0xC0 | 0xD0) { 1 } else { 2 };
+                let ev_data = reader.read_bytes(data_len)?;
+
+                match (status & 0xF0, ev_data) {
+                    (0x90, &[key, velocity]) if velocity > 0 => {
+                        playing.entry((channel, key)).or_default().push_back((tick, velocity));
+                    },
+                    (0x80, &[key, _]) | (0x90, &[key, _]) => {
+                        if let Some((start, velocity)) = playing.get_mut(&(channel, key)).and_then(|q| q.pop_front()) {
+                            notes.push(Note { channel, start, length: (tick - start).max(1), key, velocity });
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    notes.sort_by_key(|n| n.start());
+    Some((name, notes))
+}
+
+// ======== BASE64 ========
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let triple = ((bytes[0] as u32) << 16) | ((bytes[1] as u32) << 8) | bytes[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_CHARS[((triple >> (18 - i * 6)) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in encoded.bytes() {
+        if c == b'=' { break; }
+        let value = BASE64_CHARS.iter().position(|&b| b == c)? as u32;
+
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Some(decoded)
+}
@@ -24,7 +24,8 @@ use crate::{
             TrackViewNavigation
         },
         playhead::Playhead,
-        project::project_manager::ProjectManager, 
+        plugins::plugin_macro::{MacroRecorder, MacroStep},
+        project::{project_manager::ProjectManager, track_restructure::{TrackSplitMode, duplicate_track, merge_tracks, split_track}},
         util::{
             MIDITick, MIDITrk, SignedMIDITick, SignedMIDITrk, SignedMIDITrkVec, get_notes_in_range
         }
@@ -289,7 +290,18 @@ impl TrackEditing {
             self.cut_notes();
         }
 
-        if ui.input(|i| i.events.iter().any(|ev| matches!(ev, egui::Event::Paste(_)))) {
+        let pasted_text = ui.input(|i| i.events.iter().find_map(|ev| match ev {
+            egui::Event::Paste(text) => Some(text.clone()),
+            _ => None
+        }));
+
+        if let Some(pasted_text) = pasted_text {
+            // notes copied from another window take over the clipboard before pasting
+            {
+                let mut shared_clipboard = self.shared_clipboard.write().unwrap();
+                if shared_clipboard.import_system_text(&pasted_text, self.ppq) { Debugger::log("Imported notes from the system clipboard"); }
+            }
+
             Debugger::log("Pasted");
             self.paste_notes(curr_track);
         }
@@ -483,6 +495,13 @@ impl TrackEditing {
         (min_tick, max_tick, min_track, max_track)
     }
 
+    /// The tracks covered by the selection box as (first track, last track + 1), if there is a selection.
+    pub fn get_selected_track_range(&self) -> Option<(u16, u16)> {
+        if !self.has_selection { return None; }
+        let (_, _, min_track, max_track) = self.get_selection_range();
+        Some((min_track, max_track.min(self.get_used_track_count())))
+    }
+
     #[inline(always)]
     pub fn get_can_draw_selection_box(&self) -> bool {
         self.draw_select_box
@@ -846,6 +865,84 @@ impl TrackEditing {
         }
     }
 
+    /// Merges `tracks` into one track that takes the place of the topmost one.
+    /// `channel_remap[i]` moves everything in `tracks[i]` to that channel, [`None`] keeps the channels as they are.
+    pub fn merge_tracks(&mut self, tracks: &[u16], channel_remap: &[Option<u8>]) {
+        let mut sources: Vec<(u16, Option<u8>)> = tracks.iter().enumerate()
+            .filter(|(_, &track)| self.track_exists(track))
+            .map(|(i, &track)| (track, channel_remap.get(i).copied().flatten()))
+            .collect();
+        sources.sort_by_key(|(track, _)| *track);
+        sources.dedup_by_key(|(track, _)| *track);
+
+        if sources.len() < 2 { return; }
+
+        let (removed_idx, remap): (Vec<u16>, Vec<Option<u8>>) = sources.into_iter().unzip();
+        let merged = {
+            let project_manager = self.project_manager.read().unwrap();
+            let tracks = project_manager.get_tracks().read().unwrap();
+            let sources = removed_idx.iter().map(|&track| tracks[track as usize].clone()).collect();
+            merge_tracks(sources, &remap)
+        };
+
+        let first_track = removed_idx[0];
+        self.replace_tracks_and_register(removed_idx, vec![first_track], vec![merged]);
+    }
+
+    /// Splits `track` into layers according to `mode`. The layers are placed where the track was, in order.
+    pub fn split_track(&mut self, track: u16, mode: &TrackSplitMode) {
+        if !self.track_exists(track) { return; }
+
+        let split = {
+            let project_manager = self.project_manager.read().unwrap();
+            let tracks = project_manager.get_tracks().read().unwrap();
+            split_track(tracks[track as usize].clone(), mode)
+        };
+
+        if split.len() < 2 {
+            Debugger::log(format!("Track {} has nothing to split", track));
+            return;
+        }
+
+        let inserted_idx = (track..track + split.len() as u16).collect();
+        self.replace_tracks_and_register(vec![track], inserted_idx, split);
+        MacroRecorder::record(MacroStep::split_track(mode));
+    }
+
+    /// Inserts `times` copies of `track` right below it. Copy number `n` (starting at 1) gets its channels offset by
+    /// `n * channel_offset` and is transposed by `n * transpose`.
+    pub fn duplicate_track(&mut self, track: u16, times: u16, channel_offset: i16, transpose: i16) {
+        if !self.track_exists(track) { return; }
+
+        // the new track count still has to fit in a u16
+        let times = times.min(u16::MAX - self.get_used_track_count());
+        if times == 0 { return; }
+
+        let duplicates = {
+            let project_manager = self.project_manager.read().unwrap();
+            let tracks = project_manager.get_tracks().read().unwrap();
+            let source = &tracks[track as usize];
+            (1..=times as i32).map(|n| {
+                // channels wrap around at 16 anyway, and anything past 127 keys transposes every note out of range
+                let channel_offset = (n * channel_offset as i32).rem_euclid(16) as i16;
+                let transpose = (n * transpose as i32).clamp(-128, 128) as i16;
+                duplicate_track(source, channel_offset, transpose)
+            }).collect::<Vec<_>>()
+        };
+
+        let inserted_idx = (track + 1..=track + times).collect();
+        self.replace_tracks_and_register(Vec::new(), inserted_idx, duplicates);
+        MacroRecorder::record(MacroStep::duplicate_track(times, channel_offset, transpose));
+    }
+
+    fn replace_tracks_and_register(&mut self, removed_idx: Vec<u16>, inserted_idx: Vec<u16>, inserted: Vec<MIDITrack>) {
+        let mut action = EditorAction::ReplaceTracks(removed_idx, None, inserted_idx, Some(inserted));
+        self.apply_action(&mut action);
+
+        let mut editor_actions = self.editor_actions.try_borrow_mut().unwrap();
+        editor_actions.register_action(action);
+    }
+
     pub fn append_empty_track(&mut self) {
         let project_manager = self.project_manager.read().unwrap();
         let mut tracks = project_manager.get_tracks().write().unwrap();
@@ -1062,8 +1159,10 @@ impl TrackEditing {
             actions.push(EditorAction::PlaceNotes(new_ids, None, dest_track));
         }
 
+        // added tracks go last (and newest first), so redoing adds them before pasting into them and undoing removes them after
+        track_actions.reverse();
         let mut editor_actions = self.editor_actions.borrow_mut();
-        editor_actions.register_action(EditorAction::Bulk([track_actions, actions].concat()));
+        editor_actions.register_action(EditorAction::Bulk([actions, track_actions].concat()));
     }
 
     // ======== ACTIONS ========
@@ -1194,6 +1293,78 @@ impl TrackEditing {
             EditorAction::SwapTracks(track_1, track_2) => {
                 self.swap_tracks_and_register(*track_1, *track_2, false);
             },
+            EditorAction::SetTracksMuted(mute_states) => {
+                let project_manager = self.project_manager.read().unwrap();
+                let mut tracks = project_manager.get_tracks().write().unwrap();
+                for (track, muted) in mute_states.iter_mut() {
+                    std::mem::swap(&mut tracks[*track as usize].muted, muted);
+                }
+            },
+            EditorAction::ReplaceTracks(removed_idx, removed_tracks, inserted_idx, inserted_tracks) => {
+                assert!(inserted_tracks.is_some(), "[REPLACE TRACKS] Something has gone wrong while trying to replace tracks.");
+
+                let curr_track = self.get_pianoroll_track();
+
+                {
+                    let project_manager = self.project_manager.read().unwrap();
+                    let mut tracks = project_manager.get_tracks().write().unwrap();
+
+                    let mut removed = Vec::with_capacity(removed_idx.len());
+                    for &track in removed_idx.iter().rev() {
+                        removed.push(tracks.remove(track as usize));
+                    }
+                    removed.reverse();
+                    *removed_tracks = Some(removed);
+
+                    for (&track, inserted) in inserted_idx.iter().zip(inserted_tracks.take().unwrap()) {
+                        tracks.insert(track as usize, inserted);
+                    }
+                }
+
+                // follow the current track around. if it got removed, go to the first new track instead
+                let new_curr_track = if removed_idx.contains(&curr_track) {
+                    inserted_idx.first().copied().unwrap_or(curr_track.saturating_sub(1))
+                } else {
+                    let mut new_track = curr_track - removed_idx.iter().filter(|&&track| track < curr_track).count() as u16;
+                    for &track in inserted_idx.iter() {
+                        if track <= new_track { new_track += 1; }
+                    }
+                    new_track
+                };
+
+                if new_curr_track != curr_track {
+                    self.change_track(new_curr_track);
+                }
+
+                // selected note ids are stored per track index, which don't line up anymore
+                let mut shared_selected = self.shared_selected_note_ids.write().unwrap();
+                shared_selected.clear_selected();
+            },
+            EditorAction::ChangePPQ(old_ppq, new_ppq, rounding, collisions, changes) => {
+                {
+                    let mut project_manager = self.project_manager.write().unwrap();
+                    match changes.take() {
+                        Some(taken) => project_manager.revert_ppq(*old_ppq, taken),
+                        None => *changes = Some(project_manager.convert_ppq(*new_ppq, *rounding, *collisions))
+                    }
+                }
+
+                // notes might've been removed by the conversion, so old ids can't be trusted
+                let mut shared_selected = self.shared_selected_note_ids.write().unwrap();
+                shared_selected.clear_selected();
+            },
+            EditorAction::ShiftTime(shift, changes) => {
+                {
+                    let mut project_manager = self.project_manager.write().unwrap();
+                    match changes.take() {
+                        Some(taken) => project_manager.unshift_time(*shift, taken),
+                        None => *changes = Some(project_manager.shift_time(*shift))
+                    }
+                }
+
+                let mut shared_selected = self.shared_selected_note_ids.write().unwrap();
+                shared_selected.clear_selected();
+            },
             _ => {}
         }
     }
@@ -40,6 +40,49 @@ impl BarCacher {
         self.bar_cache[bar_num]
     }
 
+    /// The bar `tick` falls in (zero-based).
+    pub fn get_bar_at_tick(&mut self, tick: u32) -> usize {
+        loop {
+            let (bar_tick, bar_length) = self.get_bar_interval(self.bar_cache.len().saturating_sub(1));
+            if bar_tick + bar_length > tick || bar_length == 0 { break; }
+            self.validate_bars_until(self.bar_cache.len() + 63);
+        }
+
+        self.bar_cache.partition_point(|&(bar_tick, _)| bar_tick <= tick).saturating_sub(1)
+    }
+
+    /// Splits `tick` into (bar, beat, tick within the beat), all zero-based.
+    pub fn tick_to_bar_beat(&mut self, tick: u32) -> (usize, u32, u32) {
+        let bar_num = self.get_bar_at_tick(tick);
+        let (bar_tick, _) = self.bar_cache[bar_num];
+        let beat_length = self.get_beat_length_at(bar_tick).max(1);
+
+        let offset = tick.saturating_sub(bar_tick);
+        (bar_num, offset / beat_length, offset % beat_length)
+    }
+
+    /// Opposite of [`BarCacher::tick_to_bar_beat`]. Beats and ticks past the end of the bar just carry on into the next ones.
+    pub fn bar_beat_to_tick(&mut self, bar_num: usize, beat: u32, tick: u32) -> u32 {
+        let (bar_tick, _) = self.get_bar_interval(bar_num);
+        let beat_length = self.get_beat_length_at(bar_tick);
+        bar_tick.saturating_add(beat.saturating_mul(beat_length)).saturating_add(tick)
+    }
+
+    /// Length of a beat according to the time signature's denominator at `tick`.
+    fn get_beat_length_at(&self, tick: u32) -> u32 {
+        let project_manager = self.project_manager.read().unwrap();
+        let metas = project_manager.get_metas().read().unwrap();
+
+        let den = metas.iter()
+            .take_while(|m| m.tick as u32 <= tick)
+            .filter(|m| m.event_type == MetaEventType::TimeSignature)
+            .last()
+            .map(|ts| ts.data[1] as u32)
+            .unwrap_or(2);
+
+        ((project_manager.get_ppq() as u32) << 2) >> den.min(31)
+    }
+
     fn validate_bars_until(&mut self, target_bar: usize) {
         let project_manager = self.project_manager.read().unwrap();
         let metas = project_manager.get_metas().read().unwrap();
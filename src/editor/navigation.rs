@@ -80,6 +80,12 @@ impl PianoRollNavigation {
         self.zoom_ticks = new_zoom_ticks;
     }
 
+    /// Scrolls so `tick` is visible, leaving a bit of room before it. Does nothing if it's already in view.
+    pub fn scroll_to_tick(&mut self, tick: f32) {
+        if tick >= self.tick_pos && tick < self.tick_pos + self.zoom_ticks { return; }
+        self.tick_pos = (tick - self.zoom_ticks * 0.1).max(0.0);
+    }
+
     pub fn zoom_keys_by(&mut self, fac: f32) {
         let mut new_zoom_keys = self.zoom_keys * fac;
         if new_zoom_keys < 12.0 { new_zoom_keys = 12.0; }
@@ -163,6 +169,12 @@ impl TrackViewNavigation {
         self.zoom_ticks = new_zoom_ticks;
     }
 
+    /// Scrolls so `tick` is visible, leaving a bit of room before it. Does nothing if it's already in view.
+    pub fn scroll_to_tick(&mut self, tick: f32) {
+        if tick >= self.tick_pos && tick < self.tick_pos + self.zoom_ticks { return; }
+        self.tick_pos = (tick - self.zoom_ticks * 0.1).max(0.0);
+    }
+
     pub fn zoom_tracks_by(&mut self, fac: f32) {
         let mut new_zoom_tracks = self.zoom_tracks * fac;
         if new_zoom_tracks < 10.0 { new_zoom_tracks = 10.0; }
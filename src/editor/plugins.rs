@@ -2,48 +2,76 @@ pub mod plugin_lua;
 pub mod plugin_dialog;
 pub mod plugin_andromeda_obj;
 pub mod plugin_error_dialog;
+pub mod plugin_sandbox;
+pub mod plugin_dialog_fields;
+pub mod plugin_settings;
+pub mod plugin_console;
+pub mod plugin_repl;
+pub mod plugin_watcher;
+pub mod plugin_hooks;
+pub mod plugin_macro;
+pub mod plugin_package;
+pub mod plugin_test_runner;
 
-use std::path::Path;
-use crate::editor::plugins::plugin_lua::PluginLua;
-use crate::util::debugger::Debugger;
-use std::fs::{self, DirEntry, FileType};
+use std::path::{Path, PathBuf};
+use crate::editor::plugins::{plugin_console::PluginConsole, plugin_lua::PluginLua, plugin_package::{PackageContext, PackageIndex, is_package_folder, take_packages_changed}, plugin_sandbox::is_plugin_data_folder, plugin_settings::PluginSettingsStore, plugin_watcher::{PluginFileChange, PluginWatcher, is_lua_file}};
+use std::fs::{self, FileType};
 use std::io::Result;
 
 use std::rc::Rc;
 use std::cell::RefCell;
-use include_dir::include_dir;
+use include_dir::{Dir, include_dir};
+
+static BUILTIN_PLUGINS_DIR: Dir<'static> = include_dir!("assets/plugins/builtin");
 
 static BUILTIN_PLUGIN_NAMES: &[&'static str] = &[
+    "arpeggiator",
     "batch_edit",
+    "chord_generator",
     "flip_x",
     "flip_y",
     "humanize",
+    "strummer",
 ];
 
+/// The source of a builtin plugin, by file name without the extension (e.g. "arpeggiator").
+pub fn get_builtin_plugin_source(name: &str) -> Option<&'static str> {
+    BUILTIN_PLUGINS_DIR.get_file(format!("{}.lua", name))?.contents_utf8()
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub enum PluginType {
     Manipluate,
-    Generate
+    Generate,
+    /// runs on editor events, see plugin_hooks
+    Hook
 }
 
 pub struct PluginLoader {
     pub manip_plugins: Vec<Rc<RefCell<PluginLua>>>,
     pub gen_plugins: Vec<Rc<RefCell<PluginLua>>>,
-    plugins_path: &'static Path
+    pub hook_plugins: Vec<Rc<RefCell<PluginLua>>>,
+    /// last used dialog values and presets, shared by every plugin
+    pub settings: Rc<RefCell<PluginSettingsStore>>,
+    /// packages found in the plugin folder, loaded or not
+    pub packages: PackageIndex,
+    plugins_path: &'static Path,
+    // custom plugins get picked up again whenever their files change
+    custom_watcher: PluginWatcher
 }
 
 impl PluginLoader {
     pub fn new(plugins_path: &'static Path) -> Self {
         let mut plugin_loader = Self {
-            manip_plugins: Vec::new(), gen_plugins: Vec::new(),
-            plugins_path
+            manip_plugins: Vec::new(), gen_plugins: Vec::new(), hook_plugins: Vec::new(),
+            settings: Rc::new(RefCell::new(PluginSettingsStore::load(plugins_path.join("plugin_settings.json")))),
+            packages: PackageIndex::default(),
+            plugins_path,
+            custom_watcher: PluginWatcher::new(plugins_path.join("custom"))
         };
         // very first thing to do: load built-in plugins
-
-        let builtin_plugins_dir = include_dir!("assets/plugins/builtin");
-        
         for file_name in BUILTIN_PLUGIN_NAMES.iter() {
-            if let Some(file) = builtin_plugins_dir.get_file(format!("{}.lua", file_name)) {
-                let src_code = file.contents_utf8().unwrap();
+            if let Some(src_code) = get_builtin_plugin_source(file_name) {
                 plugin_loader.push_plugin_raw_str(file_name, src_code.into()).unwrap();
             }
         }
@@ -63,49 +91,188 @@ impl PluginLoader {
             let mut plugin = plugin.borrow_mut();
             (*plugin).reload_plugin()?;
         }
+
+        // reload all hook plugins
+        for plugin in self.hook_plugins.iter_mut() {
+            let mut plugin = plugin.borrow_mut();
+            (*plugin).reload_plugin()?;
+        }
         
         Ok(())
     }
 
     pub fn load_all_plugins(&mut self) -> Result<()> {
         self.load_plugins(self.plugins_path)?;
+        self.packages = PackageIndex::scan(self.plugins_path);
+        self.load_packages();
         Ok(())
     }
 
+    /// Loads every loose plugin file in `dir`. Packages are left to [`PluginLoader::load_packages`].
     pub fn load_plugins(&mut self, dir: &Path) -> Result<()> {
         let read_dir = fs::read_dir(dir)?;
         for entry in read_dir {
             let entry = entry?;
             let path = entry.path();
             if path.is_dir() {
+                if is_package_folder(&path) || is_plugin_data_folder(&path) { continue; }
                 self.load_plugins(&path)?;
             } else {
                 // only push plugin if its a lua file
-                if path.extension()
-                    .and_then(|ext| ext.to_str())
-                    .map(|ext| ext.eq_ignore_ascii_case("lua"))
-                    .unwrap_or(false)
-                {
-                    self.push_plugin(&entry)?;
+                if is_lua_file(&path) {
+                    self.push_plugin(&path)?;
                 }
             }
         }
         Ok(())
     }
 
-    fn push_plugin(&mut self, plugin_entry: &DirEntry) -> Result<()> {
-        let plugin_file_name = plugin_entry.file_name();
-        let plugin_file_name = plugin_file_name.to_str().unwrap();
+    /// Loads the plugins of every package that can be loaded, dependencies first, and reports the ones that can't.
+    fn load_packages(&mut self) {
+        for (path, err) in self.packages.broken.iter() {
+            PluginConsole::log_error(&path.file_name().unwrap().to_string_lossy(), err);
+        }
+        for (i, problem) in self.packages.problems.iter() {
+            if problem.is_conflict() {
+                PluginConsole::log_warning(&self.packages.packages[*i].manifest.id, &format!("not loaded: {}", problem.describe()));
+            }
+        }
+
+        let to_load: Vec<(PathBuf, PackageContext)> = self.packages.get_load_order()
+            .flat_map(|package| {
+                let context = self.packages.get_context(package);
+                package.get_plugin_files().into_iter().map(move |path| (path, context.clone()))
+            })
+            .collect();
+
+        for (path, context) in to_load {
+            let _ = self.push_plugin_in(&path, Some(context));
+        }
+    }
+
+    /// Throws out every plugin that came from a package and loads the packages again,
+    /// after they got installed, enabled or disabled. Returns true if that happened.
+    pub fn poll_packages(&mut self) -> bool {
+        if !take_packages_changed() { return false; }
+        self.reload_packages();
+        true
+    }
+
+    fn reload_packages(&mut self) {
+        for plugins in [&mut self.manip_plugins, &mut self.gen_plugins, &mut self.hook_plugins] {
+            plugins.retain(|plugin| plugin.borrow().get_package_id().is_none());
+        }
+        self.packages = PackageIndex::scan(self.plugins_path);
+        self.load_packages();
+    }
+
+    /// Picks up added, changed and deleted files in the custom plugin folder.
+    /// Returns true if the plugin lists changed, which means the plugin menus have to be rebuilt.
+    pub fn poll_custom_plugins(&mut self) -> bool {
+        let mut lists_changed = false;
+        let mut packages_changed = false;
+
+        for change in self.custom_watcher.poll() {
+            let path = match &change {
+                PluginFileChange::Added(path) | PluginFileChange::Modified(path) | PluginFileChange::Removed(path) => path
+            };
+            // a changed module can matter to any plugin in the package, so the whole thing gets loaded again
+            if self.packages.find_containing(path).is_some() || path.parent().is_some_and(is_package_folder) {
+                packages_changed = true;
+                continue;
+            }
+
+            match change {
+                PluginFileChange::Added(path) => {
+                    let plugin_count = self.get_plugin_count();
+                    let _ = self.push_plugin(&path);
+                    if self.get_plugin_count() != plugin_count {
+                        PluginConsole::log_info(&path.file_name().unwrap().to_string_lossy(), "loaded");
+                        lists_changed = true;
+                    }
+                },
+                PluginFileChange::Modified(path) => {
+                    let Some(plugin) = self.find_plugin_at(&path) else {
+                        // never loaded because it had errors, it might work now
+                        let _ = self.push_plugin(&path);
+                        lists_changed = true;
+                        continue;
+                    };
+
+                    // a failed reload keeps the plugin as it was, fixing the file brings it back
+                    let plugin_type = plugin.borrow().plugin_type;
+                    let _ = plugin.borrow_mut().reload_plugin();
+                    if plugin.borrow().plugin_type != plugin_type {
+                        self.take_plugin_at(&path);
+                        self.push_inner_shared(plugin);
+                    }
+                    // the name might have changed too
+                    lists_changed = true;
+                },
+                PluginFileChange::Removed(path) => {
+                    if self.take_plugin_at(&path).is_some() {
+                        PluginConsole::log_info(&path.file_name().unwrap().to_string_lossy(), "removed");
+                        lists_changed = true;
+                    }
+                }
+            }
+        }
+
+        if packages_changed {
+            self.reload_packages();
+            lists_changed = true;
+        }
+
+        lists_changed
+    }
+
+    pub fn get_plugins_path(&self) -> &'static Path {
+        self.plugins_path
+    }
+
+    /// Where plugins written by the editor itself (like recorded macros) go.
+    pub fn get_custom_plugin_folder(&self) -> PathBuf {
+        self.plugins_path.join("custom")
+    }
+
+    fn get_plugin_count(&self) -> usize {
+        self.manip_plugins.len() + self.gen_plugins.len() + self.hook_plugins.len()
+    }
+
+    fn find_plugin_at(&self, path: &Path) -> Option<Rc<RefCell<PluginLua>>> {
+        self.manip_plugins.iter()
+            .chain(self.gen_plugins.iter())
+            .chain(self.hook_plugins.iter())
+            .find(|plugin| plugin.borrow().get_plugin_path() == Some(path))
+            .cloned()
+    }
+
+    /// Removes the plugin loaded from `path` from the lists and returns it.
+    fn take_plugin_at(&mut self, path: &Path) -> Option<Rc<RefCell<PluginLua>>> {
+        for plugins in [&mut self.manip_plugins, &mut self.gen_plugins, &mut self.hook_plugins] {
+            if let Some(index) = plugins.iter().position(|plugin| plugin.borrow().get_plugin_path() == Some(path)) {
+                return Some(plugins.remove(index));
+            }
+        }
+        None
+    }
+
+    fn push_plugin(&mut self, plugin_path: &Path) -> Result<()> {
+        self.push_plugin_in(plugin_path, None)
+    }
+
+    fn push_plugin_in(&mut self, plugin_path: &Path, package: Option<PackageContext>) -> Result<()> {
+        let plugin_file_name = plugin_path.file_name().unwrap().to_string_lossy().into_owned();
 
-        let plugin_path = plugin_entry.path();
         let mut plugin = PluginLua::new();
-        match plugin.load_plugin_from_path(plugin_path) {
+        if let Some(package) = package { plugin.use_package(package); }
+        match plugin.load_plugin_from_path(plugin_path.to_path_buf()) {
             Ok(_) => {
                 self.push_inner(plugin);
                 Ok(())
             },
             Err(lua_err) => {
-                Debugger::log_error(format!("[PluginError] (in {}): \n--> {}", plugin_file_name, lua_err.to_string()));
+                PluginConsole::log_error(&plugin_file_name, &lua_err.to_string());
                 Ok(())
             }
         }
@@ -119,19 +286,27 @@ impl PluginLoader {
                 Ok(())
             },
             Err(lua_err) => {
-                Debugger::log_error(format!("[PluginError] (in {}): \n--> {}", plugin_name, lua_err.to_string()));
+                PluginConsole::log_error(plugin_name, &lua_err.to_string());
                 Ok(())
             }
         }
     }
 
     fn push_inner(&mut self, plugin: PluginLua) {
-        match plugin.plugin_type {
+        self.push_inner_shared(Rc::new(RefCell::new(plugin)));
+    }
+
+    fn push_inner_shared(&mut self, plugin: Rc<RefCell<PluginLua>>) {
+        let plugin_type = plugin.borrow().plugin_type;
+        match plugin_type {
             PluginType::Manipluate => {
-                self.manip_plugins.push(Rc::new(RefCell::new(plugin)));
+                self.manip_plugins.push(plugin);
             },
             PluginType::Generate => {
-                self.gen_plugins.push(Rc::new(RefCell::new(plugin)));
+                self.gen_plugins.push(plugin);
+            },
+            PluginType::Hook => {
+                self.hook_plugins.push(plugin);
             }
         }
     }
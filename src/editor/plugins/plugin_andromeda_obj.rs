@@ -1,22 +1,31 @@
-use std::{cell::RefCell, rc::Rc, sync::{Arc, RwLock}};
+use std::{cell::RefCell, rc::Rc, sync::{Arc, Mutex, RwLock}};
 
-use mlua::UserData;
-use crate::editor::{playhead::Playhead, project::{project_data::ProjectData, project_manager::ProjectManager}};
+use mlua::{Lua, UserData};
+use crate::editor::{editing::lua_note_editing::meta_to_lua_table, midi_bar_cacher::BarCacher, playhead::Playhead, project::{project_data::ProjectData, project_manager::ProjectManager}, util::MIDITick};
 
 // provides functions that can be called from lua plugins
+#[derive(Clone)]
 pub struct AndromedaObj {
     project_manager: Arc<RwLock<ProjectManager>>,
     // for getting the playhead pos
-    playhead: Rc<RefCell<Playhead>>
+    playhead: Rc<RefCell<Playhead>>,
+    bar_cacher: Arc<Mutex<BarCacher>>
 }
 
 impl AndromedaObj {
-    pub fn new(project_manager: &Arc<RwLock<ProjectManager>>, playhead: &Rc<RefCell<Playhead>>) -> Self {
+    pub fn new(project_manager: &Arc<RwLock<ProjectManager>>, playhead: &Rc<RefCell<Playhead>>, bar_cacher: &Arc<Mutex<BarCacher>>) -> Self {
         Self {
             project_manager: project_manager.clone(),
-            playhead: playhead.clone()
+            playhead: playhead.clone(),
+            bar_cacher: bar_cacher.clone()
         }
     }
+
+    /// Sets the globals a plugin runs with: `curr_track`, and this as `andromeda`.
+    pub fn set_globals(self, lua: &Lua, curr_track: usize) -> mlua::Result<()> {
+        lua.globals().set("curr_track", curr_track)?;
+        lua.globals().set("andromeda", lua.create_userdata(self)?)
+    }
 }
 
 impl UserData for AndromedaObj {
@@ -24,14 +33,14 @@ impl UserData for AndromedaObj {
         methods.add_method::<_, _, mlua::Number>("ticks_to_secs", |_, this, tick: mlua::Number| {
             let project_manager = this.project_manager.read().unwrap();
             let tempo_map = project_manager.get_tempo_map().read().unwrap();
-            let secs = tempo_map.ticks_to_secs_from_map(project_manager.get_ppq(), tick as f32);
+            let secs = tempo_map.ticks_to_secs_from_map(project_manager.get_ppq(), tick as f64);
             Ok(secs as mlua::Number)
         });
 
         methods.add_method::<_, _, mlua::Number>("secs_to_ticks", |_, this, secs: mlua::Number| {
             let project_manager = this.project_manager.read().unwrap();
             let tempo_map = project_manager.get_tempo_map().read().unwrap();
-            let ticks = tempo_map.secs_to_ticks_from_map(project_manager.get_ppq(), secs as f32);
+            let ticks = tempo_map.secs_to_ticks_from_map(project_manager.get_ppq(), secs as f64);
             Ok(ticks as mlua::Number)
         });
 
@@ -50,9 +59,73 @@ impl UserData for AndromedaObj {
             let playhead = this.playhead.try_borrow().unwrap();
             let project_manager = this.project_manager.read().unwrap();
             let tempo_map = project_manager.get_tempo_map().read().unwrap();
-            let secs = tempo_map.ticks_to_secs_from_map(project_manager.get_ppq(), playhead.start_tick as f32);
+            let secs = tempo_map.ticks_to_secs_from_map(project_manager.get_ppq(), playhead.start_tick as f64);
 
             Ok(secs as mlua::Number)
         });
+
+        methods.add_method::<_, _, mlua::Number>("get_track_count", |_, this, _: ()| {
+            let project_manager = this.project_manager.read().unwrap();
+            let tracks = project_manager.get_tracks().read().unwrap();
+            Ok(tracks.len() as mlua::Number)
+        });
+
+        methods.add_method("is_track_muted", |_, this, track: usize| {
+            let project_manager = this.project_manager.read().unwrap();
+            let tracks = project_manager.get_tracks().read().unwrap();
+            match tracks.get(track) {
+                Some(track) => Ok(track.muted),
+                None => Err(mlua::Error::runtime(format!("track {} doesn't exist (there are {} tracks)", track, tracks.len())))
+            }
+        });
+
+        // every global meta (tempo, time signatures, markers, ...) as tables, each with its `id`
+        methods.add_method("get_metas", |lua, this, _: ()| {
+            let project_manager = this.project_manager.read().unwrap();
+            let metas = project_manager.get_metas().read().unwrap();
+
+            let metas_table = lua.create_table()?;
+            for (id, meta) in metas.iter().enumerate() {
+                let meta_table = meta_to_lua_table(lua, meta)?;
+                meta_table.set("id", id)?;
+                metas_table.push(meta_table)?;
+            }
+            Ok(metas_table)
+        });
+
+        // bars and beats are zero-based, like everything else here
+
+        methods.add_method::<_, _, mlua::Number>("get_bar_at_tick", |_, this, tick: MIDITick| {
+            let mut bar_cacher = this.bar_cacher.lock().unwrap();
+            Ok(bar_cacher.get_bar_at_tick(tick) as mlua::Number)
+        });
+
+        methods.add_method::<_, _, mlua::Number>("get_bar_tick", |_, this, bar: usize| {
+            let mut bar_cacher = this.bar_cacher.lock().unwrap();
+            Ok(bar_cacher.get_bar_interval(bar).0 as mlua::Number)
+        });
+
+        methods.add_method::<_, _, mlua::Number>("get_bar_length", |_, this, bar: usize| {
+            let mut bar_cacher = this.bar_cacher.lock().unwrap();
+            Ok(bar_cacher.get_bar_interval(bar).1 as mlua::Number)
+        });
+
+        methods.add_method("tick_to_bar_beat", |lua, this, tick: MIDITick| {
+            let (bar, beat, tick) = {
+                let mut bar_cacher = this.bar_cacher.lock().unwrap();
+                bar_cacher.tick_to_bar_beat(tick)
+            };
+
+            let table = lua.create_table()?;
+            table.set("bar", bar)?;
+            table.set("beat", beat)?;
+            table.set("tick", tick)?;
+            Ok(table)
+        });
+
+        methods.add_method::<_, _, mlua::Number>("bar_beat_to_tick", |_, this, (bar, beat, tick): (usize, u32, Option<MIDITick>)| {
+            let mut bar_cacher = this.bar_cacher.lock().unwrap();
+            Ok(bar_cacher.bar_beat_to_tick(bar, beat, tick.unwrap_or(0)) as mlua::Number)
+        });
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,113 @@
+// plugin_console.rs - what plugins print, and the errors they run into.
+//
+// Lines are kept in a global buffer, the same way Debugger keeps its log file, so any plugin's `print` can reach it
+// without knowing who's showing the console. Everything still goes to the Debugger log too.
+
+use std::{collections::VecDeque, sync::Mutex};
+
+use chrono::Local;
+use mlua::{Lua, MultiValue};
+use once_cell::sync::Lazy;
+
+use crate::util::debugger::Debugger;
+
+/// Older lines get dropped past this.
+const MAX_CONSOLE_LINES: usize = 2000;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ConsoleLineKind {
+    /// `print` output
+    Output,
+    /// something a hook plugin wants the user to know about
+    Warning,
+    Error,
+    Info,
+    /// something typed into the REPL
+    Input
+}
+
+#[derive(Clone)]
+pub struct ConsoleLine {
+    pub kind: ConsoleLineKind,
+    pub time: String,
+    /// the plugin (or "repl") the line came from
+    pub source: String,
+    pub text: String
+}
+
+/// Which plugin is printing, stored in each plugin's Lua state.
+pub struct ConsoleSource(pub String);
+
+static CONSOLE_LINES: Lazy<Mutex<VecDeque<ConsoleLine>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+pub struct PluginConsole;
+
+impl PluginConsole {
+    fn push(kind: ConsoleLineKind, source: &str, text: &str) {
+        let line = ConsoleLine {
+            kind,
+            time: Local::now().format("%H:%M:%S").to_string(),
+            source: source.into(),
+            text: text.trim_end().into()
+        };
+
+        let mut lines = CONSOLE_LINES.lock().unwrap();
+        lines.push_back(line);
+        while lines.len() > MAX_CONSOLE_LINES { lines.pop_front(); }
+    }
+
+    pub fn log_output(source: &str, text: &str) {
+        Debugger::log(format!("[Plugin {}] {}", source, text));
+        Self::push(ConsoleLineKind::Output, source, text);
+    }
+
+    pub fn log_info(source: &str, text: &str) {
+        Debugger::log(format!("[Plugin {}] {}", source, text));
+        Self::push(ConsoleLineKind::Info, source, text);
+    }
+
+    pub fn log_warning(source: &str, text: &str) {
+        Debugger::log_warning(format!("[Plugin {}] {}", source, text));
+        Self::push(ConsoleLineKind::Warning, source, text);
+    }
+
+    /// Errors keep their stack traceback, which has the script lines in it.
+    pub fn log_error(source: &str, text: &str) {
+        Debugger::log_error(format!("[PluginError] ({}): \n--> {}", source, text));
+        Self::push(ConsoleLineKind::Error, source, text);
+    }
+
+    pub fn log_input(source: &str, text: &str) {
+        Self::push(ConsoleLineKind::Input, source, text);
+    }
+
+    pub fn get_lines() -> Vec<ConsoleLine> {
+        CONSOLE_LINES.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear() {
+        CONSOLE_LINES.lock().unwrap().clear();
+    }
+
+    /// Replaces `print` in `lua` so it writes to the console, labelled with the state's [`ConsoleSource`].
+    pub fn install_print(lua: &Lua, source: &str) -> mlua::Result<()> {
+        lua.set_app_data(ConsoleSource(source.into()));
+
+        let print = lua.create_function(|lua, args: MultiValue| {
+            let mut parts = Vec::with_capacity(args.len());
+            for arg in args.iter() {
+                parts.push(arg.to_string()?);
+            }
+
+            let source = lua.app_data_ref::<ConsoleSource>().map(|source| source.0.clone()).unwrap_or_default();
+            Self::log_output(&source, &parts.join("\t"));
+            Ok(())
+        })?;
+
+        lua.globals().set("print", print)
+    }
+
+    pub fn set_source(lua: &Lua, source: &str) {
+        lua.set_app_data(ConsoleSource(source.into()));
+    }
+}
@@ -1,10 +1,20 @@
-use std::{cell::RefCell, collections::HashMap, hash::{DefaultHasher, Hash, Hasher}, rc::Rc, sync::{Arc, Mutex}};
+use std::{cell::RefCell, collections::HashMap, hash::{DefaultHasher, Hash, Hasher}, rc::Rc, sync::{Arc, Mutex}, time::{Duration, Instant}};
 
 use as_any::AsAny;
 use eframe::egui::{self, Align2};
-use mlua::{FromLua, Table, Value};
+use mlua::Table;
 
-use crate::{app::{custom_widgets::{NumberField, NumericField}, ui::dialog::{Dialog, DialogAction, DialogActionButtons, names::{DIALOG_NAME_PLUGIN_DIALOG, DIALOG_NAME_PLUGIN_ERROR_DIALOG}}}, editor::{actions::EditorActions, editing::{lua_note_editing::LuaNoteEditing, note_editing::NoteEditing}, plugins::{plugin_error_dialog::PluginErrorDialog, plugin_lua::PluginLua}}, util::debugger::Debugger};
+use crate::{app::{rendering::{RenderManager, RenderType}, ui::dialog::{Dialog, DialogAction, DialogActionButtons, names::{DIALOG_NAME_PLUGIN_DIALOG, DIALOG_NAME_PLUGIN_ERROR_DIALOG}}}, editor::{actions::EditorActions, editing::{lua_note_editing::{LuaNoteEditing, PluginPreview}, meta_editing::MetaEditing, note_editing::NoteEditing}, midi_bar_cacher::BarCacher, plugins::{plugin_andromeda_obj::AndromedaObj, plugin_dialog_fields::{DialogField, FieldContext, collect_values, draw_fields, list_plugin_files, parse_fields, restore_values}, plugin_console::PluginConsole, plugin_error_dialog::PluginErrorDialog, plugin_lua::PluginLua, plugin_macro::{MacroPlayer, MacroRecorder, MacroStep}, plugin_sandbox::{ExecutionBudget, PluginCapability, is_budget_exceeded, run_with_budget}, plugin_settings::{FieldValues, PluginSettingsStore}}}, midi::events::note::Note, util::debugger::Debugger};
+
+/// How long the fields have to stay untouched before the preview runs again.
+const PREVIEW_DEBOUNCE: Duration = Duration::from_millis(300);
+/// Previews run while the settings are being changed, so they get cut off much sooner than a real run.
+const PREVIEW_BUDGET: ExecutionBudget = ExecutionBudget {
+    max_instructions: 100_000_000,
+    max_duration: Duration::from_millis(500)
+};
+/// Previewing copies the whole project, which gets too slow past this many notes.
+const PREVIEW_MAX_NOTES: usize = 2_000_000;
 
 fn hash_table_address(table: &Table) -> u64 {
     let ptr = table.to_pointer() as usize;
@@ -13,36 +23,106 @@ fn hash_table_address(table: &Table) -> u64 {
     hasher.finish()
 }
 
-pub enum DialogField {
-    Label { contents: String },
-    Number { field_id: String, label: String, field: NumericField<f64> },
-    Slider { field_id: String, label: String, value: f64, min: f64, max: f64, step: Option<f64> },
-    TextField { field_id: String, label: String, value: String },
-    Toggle { field_id: String, label: String, value: bool },
-    Dropdown { field_id: String, label: String, value: usize, value_labels: Vec<String> },
-    Separator
-}
-
-#[derive(Default)]
 pub struct PluginDialog {
     plugin: Option<Rc<RefCell<PluginLua>>>,
     // field id, and field itself
     fields: Vec<DialogField>,
     pub curr_track: usize,
+    // what the plugin itself sets the fields to
+    default_values: FieldValues,
+    plugin_files: Vec<String>,
+
+    settings: Rc<RefCell<PluginSettingsStore>>,
+    selected_preset: Option<String>,
+    new_preset_name: String,
 
     note_editing: Arc<Mutex<NoteEditing>>,
+    meta_editing: Arc<Mutex<MetaEditing>>,
+    bar_cacher: Arc<Mutex<BarCacher>>,
     editor_actions: Rc<RefCell<EditorActions>>,
     plugin_run_result: Option<Result<(), mlua::Error>>,
-    showing: bool
+    showing: bool,
+    // runs the steps the plugin queues with notes:edit_function and the like
+    macro_player: Option<MacroPlayer>,
+
+    render_manager: Option<Arc<Mutex<RenderManager>>>,
+    preview_enabled: bool,
+    // when the fields last changed, cleared once the preview has caught up
+    preview_requested_at: Option<Instant>,
+    preview: Option<Result<PluginPreview, String>>,
+    preview_notes: Arc<Mutex<Vec<Note>>>,
+    // if the piano roll is drawing preview_notes instead of the note editor's ghost notes
+    preview_notes_shown: bool
+}
+
+impl Default for PluginDialog {
+    fn default() -> Self {
+        Self {
+            plugin: None,
+            fields: Vec::new(),
+            curr_track: 0,
+            default_values: FieldValues::new(),
+            plugin_files: Vec::new(),
+
+            settings: Default::default(),
+            selected_preset: None,
+            new_preset_name: String::new(),
+
+            note_editing: Default::default(),
+            meta_editing: Default::default(),
+            bar_cacher: Default::default(),
+            editor_actions: Default::default(),
+            plugin_run_result: None,
+            showing: false,
+            macro_player: None,
+
+            render_manager: None,
+            preview_enabled: true,
+            preview_requested_at: None,
+            preview: None,
+            preview_notes: Default::default(),
+            preview_notes_shown: false
+        }
+    }
 }
 
 impl PluginDialog {
-    pub fn init(&mut self, editor_actions: &Rc<RefCell<EditorActions>>, note_editing: &Arc<Mutex<NoteEditing>>) {
+    pub fn init(&mut self, editor_actions: &Rc<RefCell<EditorActions>>, note_editing: &Arc<Mutex<NoteEditing>>, meta_editing: &Arc<Mutex<MetaEditing>>, bar_cacher: &Arc<Mutex<BarCacher>>) {
         self.editor_actions = editor_actions.clone();
         self.note_editing = note_editing.clone();
+        self.meta_editing = meta_editing.clone();
+        self.bar_cacher = bar_cacher.clone();
         self.plugin_run_result = Some(Ok(()));
     }
 
+    /// Lets the dialog preview the plugin's result as ghost notes in the piano roll.
+    pub fn use_render_manager(&mut self, render_manager: &Arc<Mutex<RenderManager>>) {
+        self.render_manager = Some(render_manager.clone());
+    }
+
+    /// Where the last used values and presets are kept. Without one, nothing is remembered.
+    pub fn use_settings(&mut self, settings: &Rc<RefCell<PluginSettingsStore>>) {
+        self.settings = settings.clone();
+    }
+
+    /// Lets the plugin run edit functions, other plugins and track operations. Without a player it can't.
+    pub fn use_macro_player(&mut self, macro_player: MacroPlayer) {
+        self.macro_player = Some(macro_player);
+    }
+
+    /// Runs `plugin` straight away with `values` in its fields, without showing the dialog. Macros and plugin tests
+    /// run plugins this way.
+    pub fn run_headless(&mut self, plugin: &Rc<RefCell<PluginLua>>, curr_track: usize, values: &FieldValues, andromeda_obj: AndromedaObj) -> Result<(), mlua::Error> {
+        let lua = plugin.borrow().lua.clone();
+        andromeda_obj.set_globals(&lua, curr_track)?;
+
+        self.curr_track = curr_track;
+        if self.load_plugin_dialog(plugin)? {
+            self.load_field_values(values);
+        }
+        self.run_plugin()
+    }
+
     /// Returns [`true`] if the dialog has fields and would need to be shown
     pub fn load_plugin_dialog(&mut self, plugin: &Rc<RefCell<PluginLua>>) -> Result<bool, mlua::Error> {
         self.plugin = Some(plugin.clone());
@@ -56,196 +136,267 @@ impl PluginDialog {
                 return Ok(false);
             }
 
-            for (idx, field) in fields.sequence_values::<Value>().enumerate() {
-                let field = field?;
-                let Some(field_table) = field.as_table() else {
-                    Debugger::log_warning("[PluginWarning] skipping field {idx} because it is not a table");
-                    continue;
-                };
+            self.fields = parse_fields(fields)?;
+            self.plugin_files = plugin.get_data_folder().as_deref().map(list_plugin_files).unwrap_or_default();
 
-                if field_table.is_empty() {
-                    self.add_separator();
-                    continue;
-                }
+            self.default_values.clear();
+            collect_values(&self.fields, &mut self.default_values);
 
-                let field_id = field_table.get::<String>("id");
-                let field_id = match field_id {
-                    Ok(field_id) => field_id,
-                    Err(_) => { // no field id present, maybe it's a label?
-                        // so let's expect a label
-                        let field_type = field_table.get::<String>("type")?;
-                        if field_type != "label" {
-                            return Err(mlua::Error::runtime(
-                                format!("[PluginError] expected unnested field type to be label, not {field_type}")
-                            ));
-                        }
-                        // ... it is a label, so push it to the fields
-                        let field_label = field_table.get::<String>("label").unwrap_or("".into());
-                        self.add_label(field_label);
-                        continue;
-                    }
-                };
-
-                let Ok(field_contents) = field_table.get::<Table>(1) else {
-                    Debugger::log_warning(format!("[PluginWarning] skipping field {field_id} because the contents are empty"));
-                    continue;
-                };
-
-                let field_type = field_contents.get::<String>("type")?;
-
-                match field_type.as_str() {
-                    "separator" => {
-                        self.add_separator();
-                    },
-                    "label" => { 
-                        let label = field_contents.get::<String>("label").unwrap_or("".into()); 
-                        self.add_label(label);
-                    }, 
-                    "number" => {
-                        self.add_number(field_id, &field_contents);
-                    },
-                    "slider" => {
-                        self.add_slider(field_id, &field_contents);
-                    },
-                    "textedit" => {
-                        self.add_textedit(field_id, &field_contents);
-                    }, 
-                    "toggle" => {
-                        self.add_toggle(field_id, &field_contents);
-                    },
-                    "dropdown" => {
-                        self.add_dropdown(field_id, &field_contents)?;
-                    }, 
-                    _ => {
-                        Debugger::log_warning(format!("[PluginWarning] Unknown field type \"{}\", skipping...", field_type));
-                        continue;
-                    }
-                }
+            // pick up where the user left off
+            let settings = self.settings.borrow();
+            if let Some(last_values) = settings.get_last_values(&plugin.plugin_name) {
+                restore_values(&mut self.fields, last_values, fields)?;
             }
+
+            // preview the default values right away
+            self.preview_requested_at = Instant::now().checked_sub(PREVIEW_DEBOUNCE);
             Ok(true)
         } else {
             Ok(false)
         }
     }
 
-    pub fn run_plugin(&mut self) -> Result<(), mlua::Error> {
-        if self.plugin.is_none() { return Ok(()); }
+    fn get_field_values(&self) -> FieldValues {
+        let mut values = FieldValues::new();
+        collect_values(&self.fields, &mut values);
+        values
+    }
+
+    pub fn load_field_values(&mut self, values: &FieldValues) {
+        let Some(plugin) = self.plugin.as_ref() else { return; };
+        let plugin = plugin.try_borrow().unwrap();
+        let Some(dialog_fields) = plugin.dialog_field_table.as_ref() else { return; };
+
+        if let Err(err) = restore_values(&mut self.fields, values, dialog_fields) {
+            Debugger::log_error(format!("[PluginError] couldn't load field values for {}: {}", plugin.plugin_name, err));
+        }
+        self.preview_requested_at = Instant::now().checked_sub(PREVIEW_DEBOUNCE);
+    }
+
+    fn get_plugin_name(&self) -> String {
         let plugin = self.plugin.as_ref().unwrap();
+        let plugin = plugin.try_borrow().unwrap();
+        plugin.plugin_name.clone()
+    }
+
+    fn draw_presets(&mut self, ui: &mut egui::Ui) {
+        let plugin_name = self.get_plugin_name();
+        let preset_names = self.settings.borrow().get_preset_names(&plugin_name);
+
+        ui.horizontal(|ui| {
+            ui.label("Preset");
+
+            let mut picked = None;
+            egui::ComboBox::from_id_salt("plugin_preset")
+                .selected_text(self.selected_preset.as_deref().unwrap_or("(none)"))
+                .show_ui(ui, |ui| {
+                    for name in preset_names.iter() {
+                        if ui.selectable_label(self.selected_preset.as_ref() == Some(name), name.as_str()).clicked() {
+                            picked = Some(name.clone());
+                        }
+                    }
+                });
+
+            if let Some(name) = picked {
+                let values = self.settings.borrow().get_preset(&plugin_name, &name).cloned();
+                if let Some(values) = values { self.load_field_values(&values); }
+                self.new_preset_name = name.clone();
+                self.selected_preset = Some(name);
+            }
+
+            if ui.add_enabled(self.selected_preset.is_some(), egui::Button::new("Delete")).clicked() {
+                if let Some(name) = self.selected_preset.take() {
+                    self.settings.borrow_mut().delete_preset(&plugin_name, &name);
+                }
+            }
 
-        let (lua, apply_fn) = {
+            if ui.button("Reset").on_hover_text("Go back to the plugin's own values").clicked() {
+                let defaults = self.default_values.clone();
+                self.load_field_values(&defaults);
+                self.selected_preset = None;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_preset_name).hint_text("Preset name").desired_width(140.0));
+
+            let name = self.new_preset_name.trim().to_string();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new("Save preset")).clicked() {
+                let values = self.get_field_values();
+                self.settings.borrow_mut().save_preset(&plugin_name, &name, values);
+                self.selected_preset = Some(name);
+            }
+        });
+
+        ui.separator();
+    }
+
+    /// Calls the plugin's `on_apply` with `lua_note_editing`. Returns [`false`] if there was nothing to call.
+    /// Previews get [`PREVIEW_BUDGET`] instead of the plugin's own budget.
+    fn call_on_apply(&self, lua_note_editing: &mut LuaNoteEditing, is_preview: bool) -> Result<bool, mlua::Error> {
+        let Some(plugin) = self.plugin.as_ref() else { return Ok(false); };
+
+        let (lua, apply_fn, budget) = {
             let p = plugin.try_borrow().unwrap();
-            (p.lua.clone(), p.on_apply_fn.clone())
+            (p.lua.clone(), p.on_apply_fn.clone(), if is_preview { PREVIEW_BUDGET } else { p.budget })
         };
 
-        if apply_fn.is_none() { return Ok(()); }
-        let apply_fn = apply_fn.unwrap();
+        let Some(apply_fn) = apply_fn else { return Ok(false); };
 
-        let mut lua_note_editing = LuaNoteEditing::new(&self.note_editing);
-
-        match lua.scope(|scope| {
-            let note_track_ref = scope.create_userdata_ref_mut(&mut lua_note_editing)?;
+        run_with_budget(&lua, budget, || lua.scope(|scope| {
+            let note_track_ref = scope.create_userdata_ref_mut(&mut *lua_note_editing)?;
             apply_fn.call::<()>(note_track_ref)?;
             Ok(())
-        }) {
-            Ok(_) => {
-                let mut editor_actions = self.editor_actions.try_borrow_mut().unwrap();
-                lua_note_editing.apply_changes(self.curr_track as u16, &mut editor_actions);
+        }))?;
+        Ok(true)
+    }
+
+    pub fn run_plugin(&mut self) -> Result<(), mlua::Error> {
+        if self.plugin.is_none() { return Ok(()); }
+
+        let mut lua_note_editing = LuaNoteEditing::new(&self.note_editing, &self.meta_editing);
+        if self.macro_player.is_some() { lua_note_editing.allow_macro_steps(); }
+
+        match self.call_on_apply(&mut lua_note_editing, false) {
+            Ok(ran) => {
+                if ran {
+                    let macro_steps = lua_note_editing.take_macro_steps();
+                    {
+                        let mut editor_actions = self.editor_actions.try_borrow_mut().unwrap();
+                        lua_note_editing.apply_changes(&mut editor_actions);
+                    }
+
+                    if let Some(macro_player) = self.macro_player.as_ref().filter(|_| !macro_steps.is_empty()) {
+                        if let Err(lua_error) = macro_player.play(&macro_steps, self.curr_track) {
+                            PluginConsole::log_error(&format!("while running {}", self.get_plugin_name()), &lua_error.to_string());
+                            return Err(lua_error);
+                        }
+                    }
+                }
+
+                let plugin_name = self.get_plugin_name();
+                let values = self.get_field_values();
+                MacroRecorder::record(MacroStep::run_plugin(&plugin_name, values.clone()));
+                if !self.fields.is_empty() {
+                    self.settings.borrow_mut().set_last_values(&plugin_name, values);
+                }
                 return Ok(());
             },
             Err(lua_error) => {
-                let plugin = plugin.try_borrow().unwrap();
-                Debugger::log_error(format!("[PluginError] (While running {}): \n{}", plugin.plugin_name, lua_error));
+                lua_note_editing.discard_changes();
+                let plugin = self.plugin.as_ref().unwrap().try_borrow().unwrap();
+                PluginConsole::log_error(&format!("while running {}", plugin.plugin_name), &lua_error.to_string());
                 return Err(lua_error);
             }
         }
     }
 
-    fn add_separator(&mut self) {
-        self.fields.push(DialogField::Separator);
-    }
-
-    fn add_label(&mut self, label: String) {
-        self.fields.push(DialogField::Label { contents: label });
-    }
-
-    fn add_number(&mut self, field_id: String, field_contents: &Table) {
-        let (label, value) = self.get_label_and_value(field_contents);
+    /// Why this plugin can't be previewed, if it can't.
+    fn preview_blocker(&self) -> Option<&'static str> {
+        let plugin = self.plugin.as_ref()?.try_borrow().unwrap();
+        // a preview run would really write the files
+        if plugin.capabilities.contains(&PluginCapability::WriteFiles) {
+            return Some("Preview is off for plugins that write files.");
+        }
 
-        let (min, max) = if let Ok(number_range) = field_contents.get::<Table>("range") {
-            (
-                number_range.get::<f64>("min").ok(),
-                number_range.get::<f64>("max").ok()
-            )
-        } else {
-            (None, None)
+        let note_count: usize = {
+            let tracks = self.note_editing.lock().unwrap().get_tracks();
+            let tracks = tracks.read().unwrap();
+            tracks.iter().map(|track| track.get_notes().len()).sum()
         };
+        if note_count > PREVIEW_MAX_NOTES {
+            return Some("This project is too big to preview.");
+        }
 
-        let numeric_field = NumericField::new(value, min, max);
-        self.fields.push(DialogField::Number { field_id, label, field: numeric_field });
+        None
     }
 
-    fn add_slider(&mut self, field_id: String, field_contents: &Table) {
-        let (label, value) = self.get_label_and_value(field_contents);
+    /// Runs the plugin on a copy of the project once the fields have settled, and shows what it would change.
+    fn update_preview(&mut self, ctx: &egui::Context) {
+        let Some(requested_at) = self.preview_requested_at else { return; };
+        let waited = requested_at.elapsed();
+        if waited < PREVIEW_DEBOUNCE {
+            ctx.request_repaint_after(PREVIEW_DEBOUNCE - waited);
+            return;
+        }
+        self.preview_requested_at = None;
 
-        let (min, max) = if let Ok(slider_range) = field_contents.get::<Table>("range") {
-            (
-                slider_range.get::<f64>("min").unwrap_or(0.0),
-                slider_range.get::<f64>("max").unwrap_or(1.0)
-            )
-        } else {
-            (0.0, 1.0)
-        };
+        if !self.preview_enabled { return; }
+        if let Some(reason) = self.preview_blocker() {
+            self.preview = Some(Err(reason.into()));
+            self.show_preview_notes(Vec::new());
+            return;
+        }
 
-        let step = field_contents.get::<mlua::Number>("step").ok();
-        self.fields.push(DialogField::Slider { field_id, label, value, min, max, step });
+        let mut scratch = LuaNoteEditing::new_scratch(&self.note_editing, &self.meta_editing);
+        if self.macro_player.is_some() { scratch.allow_macro_steps(); }
+        match self.call_on_apply(&mut scratch, true) {
+            Ok(_) => {
+                let mut preview = scratch.into_preview(self.curr_track as u16);
+                self.show_preview_notes(std::mem::take(&mut preview.ghost_notes));
+                self.preview = Some(Ok(preview));
+            },
+            Err(lua_error) if is_budget_exceeded(&lua_error) => {
+                self.show_preview_notes(Vec::new());
+                self.preview = Some(Err("The preview took too long. Applying still runs the plugin in full.".into()));
+            },
+            Err(lua_error) => {
+                self.show_preview_notes(Vec::new());
+                self.preview = Some(Err(lua_error.to_string()));
+            }
+        }
     }
 
-    fn add_textedit(&mut self, field_id: String, field_contents: &Table) {
-        let (label, value) = self.get_label_and_value(field_contents);
-        self.fields.push(DialogField::TextField { field_id, label, value });
-    }
+    fn show_preview_notes(&mut self, notes: Vec<Note>) {
+        *self.preview_notes.lock().unwrap() = notes;
 
-    fn add_toggle(&mut self, field_id: String, field_contents: &Table) {
-        let (label, value) = self.get_label_and_value(field_contents);
-        self.fields.push(DialogField::Toggle { field_id, label, value });
-    }
+        if self.preview_notes_shown { return; }
+        let Some(render_manager) = self.render_manager.as_ref() else { return; };
 
-    fn add_dropdown(&mut self, field_id: String, field_contents: &Table) -> Result<(), mlua::Error> {
-        let (label, mut value) = self.get_label_and_value(field_contents);
+        let piano_roll = render_manager.lock().unwrap().get_renderer(RenderType::PianoRoll);
+        piano_roll.lock().unwrap().set_ghost_notes(self.preview_notes.clone());
+        self.preview_notes_shown = true;
+    }
 
-        let mut value_labels = Vec::new();
-        if let Ok(val_labels) = field_contents.get::<Table>("value_labels") {
-            let val_labels_len = val_labels.len().unwrap() as usize;
-            if val_labels_len == 0 { return Err(mlua::Error::RuntimeError("Dropdown widget must contain at least one value".into())); }
-            if value > val_labels_len { value = val_labels_len - 1; }
+    /// Gives the piano roll its usual ghost notes back.
+    fn hide_preview_notes(&mut self) {
+        self.preview_notes.lock().unwrap().clear();
 
-            for label in val_labels.sequence_values::<Value>() {
-                value_labels.push(label.unwrap().to_string().unwrap());
-            }
-        } else {
-            return Err(mlua::Error::RuntimeError("Dropdown widget must contain at least one value".into()));
-        }
+        if !self.preview_notes_shown { return; }
+        let Some(render_manager) = self.render_manager.as_ref() else { return; };
 
-        self.fields.push(DialogField::Dropdown { field_id: field_id, label, value, value_labels });
-        Ok(())
+        let ghost_notes = self.note_editing.lock().unwrap().get_ghost_notes();
+        let piano_roll = render_manager.lock().unwrap().get_renderer(RenderType::PianoRoll);
+        piano_roll.lock().unwrap().set_ghost_notes(ghost_notes);
+        self.preview_notes_shown = false;
     }
 
-    fn get_label_and_value<T: Default + FromLua>(&self, field_contents: &Table) -> (String, T) {
-        let label = field_contents.get::<String>("label").unwrap_or("".into());
-        let value = field_contents.get::<T>("value").unwrap_or_default();
-        (label, value)
-    }
+    fn draw_preview(&mut self, ui: &mut egui::Ui) {
+        ui.separator();
+        if ui.checkbox(&mut self.preview_enabled, "Preview").changed() {
+            if self.preview_enabled {
+                self.preview_requested_at = Instant::now().checked_sub(PREVIEW_DEBOUNCE);
+            } else {
+                self.preview = None;
+                self.hide_preview_notes();
+            }
+        }
 
-    fn get_field_by_id(plugin: &Rc<RefCell<PluginLua>>, id: String) -> Option<Table> {
-        let plugin = plugin.borrow();
-        let dialog_fields = plugin.dialog_field_table.as_ref().unwrap();
-        for field in dialog_fields.sequence_values::<Table>() {
-            let field = field.unwrap();
-            if let Ok(id_) = field.get::<String>("id") {
-                if id == id_ { return Some(field.clone()); }
+        if !self.preview_enabled { return; }
+        match self.preview.as_ref() {
+            None => { ui.label("Previewing..."); },
+            Some(Err(err)) => { ui.colored_label(egui::Color32::LIGHT_RED, err); },
+            Some(Ok(preview)) if preview.is_empty() => { ui.label("Nothing would change."); },
+            Some(Ok(preview)) => {
+                ui.label(format!("Notes: {} added, {} changed, {} deleted", preview.notes_added, preview.notes_changed, preview.notes_deleted));
+                if preview.events_added > 0 || preview.events_removed > 0 {
+                    ui.label(format!("Events: {} added, {} removed", preview.events_added, preview.events_removed));
+                }
+                if preview.macro_steps > 0 {
+                    ui.label(format!("{} macro steps, not shown until applied", preview.macro_steps));
+                }
             }
         }
-        None
     }
 
     pub fn get_plugin_run_result(&self) -> Result<(), mlua::Error> {
@@ -268,95 +419,41 @@ impl Dialog for PluginDialog {
                     ui.separator();
                 }
             }
-        }
-        
-        for field in self.fields.iter_mut() {
-            match field {
-                DialogField::Separator => {
-                    ui.separator();
-                },
-                DialogField::Label { contents } => {
-                    ui.label(contents.as_str());
-                },
-                DialogField::Number { field_id, label, field } => {
-                    field.show(&label, ui, None);
-                    if field.changed() {
-                        let val = field.value();
-                        Self::get_field_by_id(
-                            self.plugin.as_ref().unwrap(),
-                            field_id.to_string()
-                        ).unwrap()
-                            .get::<Table>(1).unwrap()
-                            .set("value", val).unwrap();
-                    }
-                },
-                DialogField::Slider { field_id, label, value, min, max, step } => {
-                    ui.horizontal(|ui| {
-                        ui.label(&*label);
-                        let mut slider = egui::Slider::new(value, *min..=*max);
-                        if let Some(step) = step { slider = slider.step_by(*step); }
-                        
-                        if ui.add(slider).changed() {
-                            let plugin = self.plugin.as_ref().unwrap();
-                            let field_id = field_id.to_string();
-
-                            Self::get_field_by_id(plugin, field_id).unwrap()
-                                .get::<Table>(1).unwrap()
-                                .set("value", *value)
-                                .unwrap();
-                        }
-                    });
-                },
-                DialogField::TextField { field_id, label, value } => {
-                    ui.horizontal(|ui| {
-                        ui.label(&*label);
-                        if ui.text_edit_singleline(value).changed() {
-                            let plugin = self.plugin.as_ref().unwrap();
-                            let field_id = field_id.to_string();
-                            
-                            Self::get_field_by_id(plugin, field_id).unwrap()
-                                .get::<Table>(1).unwrap()
-                                .set("value", value.clone())
-                                .unwrap();
-                        }
-                    });
-                },
-                DialogField::Toggle { field_id, label, value } => {
-                    ui.horizontal(|ui| {
-                        ui.label(&*label);
-                        if ui.checkbox(value, "").changed() {
-                            let plugin = self.plugin.as_ref().unwrap();
-                            let field_id = field_id.to_string();
-                            
-                            Self::get_field_by_id(plugin, field_id).unwrap()
-                                .get::<Table>(1).unwrap()
-                                .set("value", *value)
-                                .unwrap();
-                        }
-                    });
-                },
-                DialogField::Dropdown { field_id, label, value, value_labels } => {
-                    ui.horizontal(|ui| {
-                        ui.label(&*label);
-                        if egui::ComboBox::from_id_salt(&*field_id)
-                            .selected_text(&value_labels[*value])
-                            .show_index(ui, &mut *value, value_labels.len(), |i| &value_labels[i]).changed() {
-                                let plugin = self.plugin.as_ref().unwrap();
-                                let field_id = field_id.to_string();
-                                
-                                Self::get_field_by_id(plugin, field_id).unwrap()
-                                    .get::<Table>(1).unwrap()
-                                    .set("value", *value)
-                                    .unwrap();
-                            }
-                    });
+
+            if !plugin.capabilities.is_empty() {
+                for capability in plugin.capabilities.iter() {
+                    ui.label(format!("⚠ {}", capability.describe()));
                 }
+                ui.separator();
             }
         }
+        
+        self.draw_presets(ui);
+
+        let fields_changed = {
+            let plugin = self.plugin.as_ref().unwrap();
+            let plugin = plugin.try_borrow().unwrap();
+            let ctx = FieldContext {
+                dialog_fields: plugin.dialog_field_table.as_ref().unwrap(),
+                bar_cacher: &self.bar_cacher,
+                plugin_files: &self.plugin_files,
+                has_plugin_folder: plugin.get_data_folder().is_some()
+            };
+            draw_fields(&mut self.fields, ui, &ctx)
+        };
+
+        if fields_changed { self.preview_requested_at = Some(Instant::now()); }
+        self.update_preview(ui.ctx());
+        self.draw_preview(ui);
 
         None
     }
 
+    fn cleanup_dialog(&mut self) -> Result<(), &'static str> {
+        self.hide_preview_notes();
+        Ok(())
+    }
+
     fn get_dialog_name(&self) -> &'static str {
         DIALOG_NAME_PLUGIN_DIALOG
     }
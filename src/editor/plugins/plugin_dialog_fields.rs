@@ -0,0 +1,544 @@
+// plugin_dialog_fields.rs - the fields a plugin can put in its dialog.
+//
+// Fields come from `P.dialog_fields`. Most look like `{ id = "amount", { type = "number", label = "Amount", value = 5 } }`,
+// labels, separators (`{}`) and groups are written without an id. Whenever a field is edited, its new value is written
+// back into the table, which is where the plugin's `get_field_value` reads it from.
+
+use std::{path::Path, sync::{Arc, Mutex}};
+
+use eframe::egui;
+use mlua::{FromLua, Table, Value};
+use serde_json::Value as JsonValue;
+
+use crate::{app::custom_widgets::{NumberField, NumericField}, editor::{midi_bar_cacher::BarCacher, plugins::plugin_settings::FieldValues, util::{MIDIKey, MIDITick, key_to_name, name_to_key}}, util::debugger::Debugger};
+
+/// How deep the file picker looks into the plugin's data folder.
+const FILE_PICKER_MAX_DEPTH: usize = 4;
+
+pub enum DialogField {
+    Label { contents: String },
+    Number { field_id: String, label: String, field: NumericField<f64> },
+    Slider { field_id: String, label: String, value: f64, min: f64, max: f64, step: Option<f64> },
+    TextField { field_id: String, label: String, value: String },
+    Toggle { field_id: String, label: String, value: bool },
+    Dropdown { field_id: String, label: String, value: usize, value_labels: Vec<String> },
+    Key { field_id: String, label: String, value: MIDIKey },
+    /// zero-based, shown as 1 to 16
+    Channel { field_id: String, label: String, value: u8 },
+    /// A tick position, or a duration if `duration` is set. Takes plain ticks or bar:beat:tick.
+    Ticks { field_id: String, label: String, value: MIDITick, duration: bool, text: String, error: Option<String> },
+    /// value is "#rrggbb"
+    Color { field_id: String, label: String, value: [u8; 3] },
+    /// A path relative to the plugin's data folder, empty if nothing is picked
+    File { field_id: String, label: String, value: String, extensions: Vec<String> },
+    MultiLine { field_id: String, label: String, value: String, rows: usize, code: bool },
+    Group { label: String, open: bool, fields: Vec<DialogField> },
+    Separator
+}
+
+impl DialogField {
+    pub fn get_id(&self) -> Option<&str> {
+        match self {
+            DialogField::Number { field_id, .. } | DialogField::Slider { field_id, .. } | DialogField::TextField { field_id, .. }
+            | DialogField::Toggle { field_id, .. } | DialogField::Dropdown { field_id, .. } | DialogField::Key { field_id, .. }
+            | DialogField::Channel { field_id, .. } | DialogField::Ticks { field_id, .. } | DialogField::Color { field_id, .. }
+            | DialogField::File { field_id, .. } | DialogField::MultiLine { field_id, .. } => Some(field_id),
+            DialogField::Label { .. } | DialogField::Group { .. } | DialogField::Separator => None
+        }
+    }
+
+    fn to_json(&self) -> Option<JsonValue> {
+        Some(match self {
+            DialogField::Number { field, .. } => JsonValue::from(field.value()),
+            DialogField::Slider { value, .. } => JsonValue::from(*value),
+            DialogField::TextField { value, .. } | DialogField::File { value, .. } | DialogField::MultiLine { value, .. } => JsonValue::from(value.clone()),
+            DialogField::Toggle { value, .. } => JsonValue::from(*value),
+            DialogField::Dropdown { value, .. } => JsonValue::from(*value),
+            DialogField::Key { value, .. } | DialogField::Channel { value, .. } => JsonValue::from(*value),
+            DialogField::Ticks { value, .. } => JsonValue::from(*value),
+            DialogField::Color { value, .. } => JsonValue::from(format_hex_color(*value)),
+            DialogField::Label { .. } | DialogField::Group { .. } | DialogField::Separator => return None
+        })
+    }
+
+    /// Takes a saved value. Values of the wrong type are ignored, out of range ones get clamped.
+    fn set_from_json(&mut self, json: &JsonValue) {
+        match self {
+            DialogField::Number { field, .. } => if let Some(v) = json.as_f64() { field.set_value(v); },
+            DialogField::Slider { value, min, max, .. } => if let Some(v) = json.as_f64() { *value = v.clamp(*min, *max); },
+            DialogField::TextField { value, .. } | DialogField::MultiLine { value, .. } => if let Some(v) = json.as_str() { *value = v.into(); },
+            DialogField::File { value, .. } => if let Some(v) = json.as_str() { *value = v.into(); },
+            DialogField::Toggle { value, .. } => if let Some(v) = json.as_bool() { *value = v; },
+            DialogField::Dropdown { value, value_labels, .. } => if let Some(v) = json.as_u64() { *value = (v as usize).min(value_labels.len() - 1); },
+            DialogField::Key { value, .. } => if let Some(v) = json.as_u64() { *value = v.min(127) as MIDIKey; },
+            DialogField::Channel { value, .. } => if let Some(v) = json.as_u64() { *value = v.min(15) as u8; },
+            DialogField::Ticks { value, .. } => if let Some(v) = json.as_u64() { *value = v.min(MIDITick::MAX as u64) as MIDITick; },
+            DialogField::Color { value, .. } => if let Some(v) = json.as_str().and_then(parse_hex_color) { *value = v; },
+            DialogField::Label { .. } | DialogField::Group { .. } | DialogField::Separator => {}
+        }
+    }
+
+    /// Writes the field's value into its table in `dialog_fields`.
+    fn write_to_lua(&self, dialog_fields: &Table) -> mlua::Result<()> {
+        let Some(field_id) = self.get_id() else { return Ok(()); };
+        let Some(field_table) = find_field_table(dialog_fields, field_id) else { return Ok(()); };
+        let contents = field_table.get::<Table>(1)?;
+
+        match self {
+            DialogField::Number { field, .. } => contents.set("value", field.value()),
+            DialogField::Slider { value, .. } => contents.set("value", *value),
+            DialogField::TextField { value, .. } | DialogField::File { value, .. } | DialogField::MultiLine { value, .. } => contents.set("value", value.as_str()),
+            DialogField::Toggle { value, .. } => contents.set("value", *value),
+            DialogField::Dropdown { value, .. } => contents.set("value", *value),
+            DialogField::Key { value, .. } | DialogField::Channel { value, .. } => contents.set("value", *value),
+            DialogField::Ticks { value, .. } => contents.set("value", *value),
+            DialogField::Color { value, .. } => contents.set("value", format_hex_color(*value)),
+            DialogField::Label { .. } | DialogField::Group { .. } | DialogField::Separator => Ok(())
+        }
+    }
+}
+
+/// Finds the table of the field with `field_id`, looking inside groups too.
+pub fn find_field_table(dialog_fields: &Table, field_id: &str) -> Option<Table> {
+    for field in dialog_fields.sequence_values::<Table>().flatten() {
+        if field.get::<String>("id").is_ok_and(|id| id == field_id) { return Some(field); }
+
+        if field.get::<String>("type").is_ok_and(|field_type| field_type == "group") {
+            if let Some(found) = field.get::<Table>("fields").ok().and_then(|fields| find_field_table(&fields, field_id)) {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+// ======== READING FIELDS ========
+
+pub fn parse_fields(dialog_fields: &Table) -> mlua::Result<Vec<DialogField>> {
+    let mut fields = Vec::new();
+
+    for (idx, field) in dialog_fields.sequence_values::<Value>().enumerate() {
+        let field = field?;
+        let Some(field_table) = field.as_table() else {
+            Debugger::log_warning(format!("[PluginWarning] skipping field {idx} because it is not a table"));
+            continue;
+        };
+
+        if field_table.is_empty() {
+            fields.push(DialogField::Separator);
+            continue;
+        }
+
+        let field_id = match field_table.get::<String>("id") {
+            Ok(field_id) => field_id,
+            Err(_) => { // no field id present, so it has to be a label or a group
+                let field_type = field_table.get::<String>("type")?;
+                match field_type.as_str() {
+                    "label" => {
+                        let field_label = field_table.get::<String>("label").unwrap_or("".into());
+                        fields.push(DialogField::Label { contents: field_label });
+                    },
+                    "group" => {
+                        let label = field_table.get::<String>("label").unwrap_or("".into());
+                        let open = field_table.get::<bool>("open").unwrap_or(true);
+                        let group_fields = match field_table.get::<Table>("fields") {
+                            Ok(group_fields) => parse_fields(&group_fields)?,
+                            Err(_) => Vec::new()
+                        };
+                        fields.push(DialogField::Group { label, open, fields: group_fields });
+                    },
+                    _ => {
+                        return Err(mlua::Error::runtime(
+                            format!("[PluginError] expected unnested field type to be label or group, not {field_type}")
+                        ));
+                    }
+                }
+                continue;
+            }
+        };
+
+        let Ok(field_contents) = field_table.get::<Table>(1) else {
+            Debugger::log_warning(format!("[PluginWarning] skipping field {field_id} because the contents are empty"));
+            continue;
+        };
+
+        let field_type = field_contents.get::<String>("type")?;
+        let label = field_contents.get::<String>("label").unwrap_or("".into());
+
+        let field = match field_type.as_str() {
+            "separator" => DialogField::Separator,
+            "label" => DialogField::Label { contents: label },
+            "number" => {
+                let value: f64 = get_value(&field_contents);
+                let (min, max) = if let Ok(number_range) = field_contents.get::<Table>("range") {
+                    (number_range.get::<f64>("min").ok(), number_range.get::<f64>("max").ok())
+                } else {
+                    (None, None)
+                };
+
+                DialogField::Number { field_id, label, field: NumericField::new(value, min, max) }
+            },
+            "slider" => {
+                let value: f64 = get_value(&field_contents);
+                let (min, max) = if let Ok(slider_range) = field_contents.get::<Table>("range") {
+                    (slider_range.get::<f64>("min").unwrap_or(0.0), slider_range.get::<f64>("max").unwrap_or(1.0))
+                } else {
+                    (0.0, 1.0)
+                };
+
+                let step = field_contents.get::<mlua::Number>("step").ok();
+                DialogField::Slider { field_id, label, value, min, max, step }
+            },
+            "textedit" => {
+                let value: String = get_value(&field_contents);
+                DialogField::TextField { field_id, label, value }
+            },
+            "toggle" => {
+                let value: bool = get_value(&field_contents);
+                DialogField::Toggle { field_id, label, value }
+            },
+            "dropdown" => {
+                let mut value: usize = get_value(&field_contents);
+
+                let mut value_labels = Vec::new();
+                if let Ok(val_labels) = field_contents.get::<Table>("value_labels") {
+                    let val_labels_len = val_labels.len().unwrap() as usize;
+                    if val_labels_len == 0 { return Err(mlua::Error::RuntimeError("Dropdown widget must contain at least one value".into())); }
+                    if value >= val_labels_len { value = val_labels_len - 1; }
+
+                    for label in val_labels.sequence_values::<Value>() {
+                        value_labels.push(label.unwrap().to_string().unwrap());
+                    }
+                } else {
+                    return Err(mlua::Error::RuntimeError("Dropdown widget must contain at least one value".into()));
+                }
+
+                DialogField::Dropdown { field_id, label, value, value_labels }
+            },
+            "key" => {
+                let value = field_contents.get::<u8>("value").unwrap_or(60).min(127);
+                DialogField::Key { field_id, label, value }
+            },
+            "channel" => {
+                let value = field_contents.get::<u8>("value").unwrap_or(0).min(15);
+                DialogField::Channel { field_id, label, value }
+            },
+            "ticks" => {
+                let value: MIDITick = get_value(&field_contents);
+                let duration = field_contents.get::<bool>("duration").unwrap_or(false);
+                DialogField::Ticks { field_id, label, value, duration, text: String::new(), error: None }
+            },
+            "color" => {
+                let value = field_contents.get::<String>("value").ok()
+                    .and_then(|hex| parse_hex_color(&hex))
+                    .unwrap_or([255, 255, 255]);
+                DialogField::Color { field_id, label, value }
+            },
+            "file" => {
+                let value: String = get_value(&field_contents);
+                let extensions = field_contents.get::<Vec<String>>("extensions").unwrap_or_default();
+                DialogField::File { field_id, label, value, extensions }
+            },
+            "multiline" | "code" => {
+                let value: String = get_value(&field_contents);
+                let rows = field_contents.get::<usize>("rows").unwrap_or(6);
+                DialogField::MultiLine { field_id, label, value, rows, code: field_type == "code" }
+            },
+            _ => {
+                Debugger::log_warning(format!("[PluginWarning] Unknown field type \"{}\", skipping...", field_type));
+                continue;
+            }
+        };
+
+        fields.push(field);
+    }
+
+    Ok(fields)
+}
+
+fn get_value<T: Default + FromLua>(field_contents: &Table) -> T {
+    field_contents.get::<T>("value").unwrap_or_default()
+}
+
+// ======== SAVING VALUES ========
+
+/// Every field's value by id, for presets and the last used values.
+pub fn collect_values(fields: &[DialogField], values: &mut FieldValues) {
+    for field in fields.iter() {
+        if let DialogField::Group { fields, .. } = field {
+            collect_values(fields, values);
+        } else if let (Some(field_id), Some(value)) = (field.get_id(), field.to_json()) {
+            values.insert(field_id.into(), value);
+        }
+    }
+}
+
+/// Sets the fields to saved values and writes them to the plugin. Fields that aren't in `values` are left alone.
+pub fn restore_values(fields: &mut [DialogField], values: &FieldValues, dialog_fields: &Table) -> mlua::Result<()> {
+    for field in fields.iter_mut() {
+        if let DialogField::Group { fields, .. } = field {
+            restore_values(fields, values, dialog_fields)?;
+            continue;
+        }
+
+        let Some(value) = field.get_id().and_then(|field_id| values.get(field_id)) else { continue; };
+        field.set_from_json(value);
+        field.write_to_lua(dialog_fields)?;
+    }
+    Ok(())
+}
+
+// ======== DRAWING ========
+
+pub struct FieldContext<'a> {
+    /// `P.dialog_fields`
+    pub dialog_fields: &'a Table,
+    pub bar_cacher: &'a Arc<Mutex<BarCacher>>,
+    /// Files the file picker can offer, relative to the plugin's data folder. Empty for builtin plugins.
+    pub plugin_files: &'a [String],
+    pub has_plugin_folder: bool
+}
+
+/// Draws `fields` and writes whatever got edited back to the plugin. Returns [`true`] if anything changed.
+pub fn draw_fields(fields: &mut [DialogField], ui: &mut egui::Ui, ctx: &FieldContext) -> bool {
+    let mut any_changed = false;
+
+    for (idx, field) in fields.iter_mut().enumerate() {
+        let changed = match field {
+            DialogField::Separator => {
+                ui.separator();
+                false
+            },
+            DialogField::Label { contents } => {
+                ui.label(contents.as_str());
+                false
+            },
+            DialogField::Group { label, open, fields } => {
+                egui::CollapsingHeader::new(label.as_str())
+                    .id_salt(("plugin_field_group", idx, label.as_str()))
+                    .default_open(*open)
+                    .show(ui, |ui| draw_fields(fields, ui, ctx))
+                    .body_returned
+                    .unwrap_or(false)
+            },
+            _ => {
+                let changed = draw_field(field, ui, ctx);
+                if changed {
+                    if let Err(err) = field.write_to_lua(ctx.dialog_fields) {
+                        Debugger::log_error(format!("[PluginError] couldn't update a dialog field: {}", err));
+                    }
+                }
+                changed
+            }
+        };
+
+        any_changed |= changed;
+    }
+
+    any_changed
+}
+
+fn draw_field(field: &mut DialogField, ui: &mut egui::Ui, ctx: &FieldContext) -> bool {
+    match field {
+        DialogField::Number { label, field, .. } => {
+            field.show(label, ui, None);
+            field.changed()
+        },
+        DialogField::Slider { label, value, min, max, step, .. } => {
+            ui.horizontal(|ui| {
+                ui.label(&*label);
+                let mut slider = egui::Slider::new(value, *min..=*max);
+                if let Some(step) = step { slider = slider.step_by(*step); }
+                ui.add(slider).changed()
+            }).inner
+        },
+        DialogField::TextField { label, value, .. } => {
+            ui.horizontal(|ui| {
+                ui.label(&*label);
+                ui.text_edit_singleline(value).changed()
+            }).inner
+        },
+        DialogField::Toggle { label, value, .. } => {
+            ui.horizontal(|ui| {
+                ui.label(&*label);
+                ui.checkbox(value, "").changed()
+            }).inner
+        },
+        DialogField::Dropdown { field_id, label, value, value_labels } => {
+            ui.horizontal(|ui| {
+                ui.label(&*label);
+                egui::ComboBox::from_id_salt(&*field_id)
+                    .selected_text(&value_labels[*value])
+                    .show_index(ui, &mut *value, value_labels.len(), |i| &value_labels[i])
+                    .changed()
+            }).inner
+        },
+        DialogField::Key { label, value, .. } => {
+            ui.horizontal(|ui| {
+                ui.label(&*label);
+                ui.add(egui::DragValue::new(value)
+                    .range(0..=127)
+                    .speed(0.25)
+                    .custom_formatter(|key, _| format!("{} ({})", key_to_name(key as MIDIKey), key))
+                    .custom_parser(|text| {
+                        // accept what the formatter shows too, e.g. "C4 (60)"
+                        let name = text.split('(').next().unwrap_or(text);
+                        name_to_key(name).map(|key| key as f64)
+                    })
+                ).changed()
+            }).inner
+        },
+        DialogField::Channel { field_id, label, value } => {
+            ui.horizontal(|ui| {
+                ui.label(&*label);
+                let mut channel = *value as usize;
+                let changed = egui::ComboBox::from_id_salt(&*field_id)
+                    .selected_text(format!("Channel {}", channel + 1))
+                    .show_index(ui, &mut channel, 16, |i| format!("Channel {}", i + 1))
+                    .changed();
+                *value = channel as u8;
+                changed
+            }).inner
+        },
+        DialogField::Ticks { label, value, duration, text, error, .. } => {
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                ui.label(&*label);
+
+                let response = ui.add(egui::TextEdit::singleline(text).desired_width(100.0));
+                if response.lost_focus() {
+                    let mut bar_cacher = ctx.bar_cacher.lock().unwrap();
+                    match parse_ticks(text, *duration, &mut bar_cacher) {
+                        Ok(ticks) => {
+                            changed = ticks != *value;
+                            *value = ticks;
+                            *error = None;
+                        },
+                        Err(err) => *error = Some(err)
+                    }
+                }
+                if !response.has_focus() {
+                    let mut bar_cacher = ctx.bar_cacher.lock().unwrap();
+                    *text = format_ticks(*value, *duration, &mut bar_cacher);
+                }
+
+                ui.weak(format!("{} ticks", value));
+            });
+
+            if let Some(error) = error {
+                ui.colored_label(egui::Color32::LIGHT_RED, error.as_str());
+            }
+            changed
+        },
+        DialogField::Color { label, value, .. } => {
+            ui.horizontal(|ui| {
+                ui.label(&*label);
+                egui::color_picker::color_edit_button_srgb(ui, value).changed()
+            }).inner
+        },
+        DialogField::File { field_id, label, value, extensions } => {
+            ui.horizontal(|ui| {
+                ui.label(&*label);
+
+                if !ctx.has_plugin_folder {
+                    ui.weak("(builtin plugins have no files)");
+                    return false;
+                }
+
+                let mut changed = false;
+                let selected_text = if value.is_empty() { "(none)" } else { value.as_str() };
+                egui::ComboBox::from_id_salt(&*field_id)
+                    .selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        changed |= ui.selectable_value(value, String::new(), "(none)").changed();
+                        for file in ctx.plugin_files.iter().filter(|file| has_extension(file, extensions)) {
+                            changed |= ui.selectable_value(value, file.clone(), file.as_str()).changed();
+                        }
+                    });
+                changed
+            }).inner
+        },
+        DialogField::MultiLine { label, value, rows, code, .. } => {
+            ui.label(&*label);
+            let mut text_edit = egui::TextEdit::multiline(value).desired_rows(*rows).desired_width(f32::INFINITY);
+            if *code { text_edit = text_edit.code_editor(); }
+            ui.add(text_edit).changed()
+        },
+        DialogField::Label { .. } | DialogField::Group { .. } | DialogField::Separator => false
+    }
+}
+
+// ======== HELPERS ========
+
+fn format_hex_color(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+fn parse_hex_color(hex: &str) -> Option<[u8; 3]> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() { return None; }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Positions show up as bar:beat:tick counting from 1, durations as bars:beats:ticks counting from 0.
+fn format_ticks(ticks: MIDITick, duration: bool, bar_cacher: &mut BarCacher) -> String {
+    let (bar, beat, tick) = bar_cacher.tick_to_bar_beat(ticks);
+    if duration {
+        format!("{}:{}:{}", bar, beat, tick)
+    } else {
+        format!("{}:{}:{}", bar + 1, beat + 1, tick)
+    }
+}
+
+/// Opposite of [`format_ticks`], plain tick counts work too. Durations are measured from the start of the project,
+/// so they follow its first time signatures.
+fn parse_ticks(input: &str, duration: bool, bar_cacher: &mut BarCacher) -> Result<MIDITick, String> {
+    let input = input.trim();
+    if let Ok(ticks) = input.parse::<MIDITick>() { return Ok(ticks); }
+
+    let parts: Option<Vec<u32>> = input.split(':').map(|part| part.trim().parse::<u32>().ok()).collect();
+    let (bar, beat, tick) = match parts.as_deref() {
+        Some(&[bar, beat, tick]) => (bar, beat, tick),
+        Some(&[bar, beat]) => (bar, beat, 0),
+        _ => return Err("Expected ticks or bar:beat:tick, e.g. 2:1:0.".into())
+    };
+
+    if duration {
+        Ok(bar_cacher.bar_beat_to_tick(bar as usize, beat, tick))
+    } else {
+        if bar == 0 || beat == 0 { return Err("Bars and beats start at 1.".into()); }
+        Ok(bar_cacher.bar_beat_to_tick(bar as usize - 1, beat - 1, tick))
+    }
+}
+
+fn has_extension(file: &str, extensions: &[String]) -> bool {
+    if extensions.is_empty() { return true; }
+
+    let Some(ext) = Path::new(file).extension().and_then(|ext| ext.to_str()) else { return false; };
+    extensions.iter().any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(ext))
+}
+
+/// Every file under `folder`, relative to it and with `/` as the separator.
+pub fn list_plugin_files(folder: &Path) -> Vec<String> {
+    fn visit(dir: &Path, prefix: &str, depth: usize, files: &mut Vec<String>) {
+        let Ok(read_dir) = std::fs::read_dir(dir) else { return; };
+        for entry in read_dir.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let relative = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+
+            let path = entry.path();
+            if path.is_dir() {
+                if depth < FILE_PICKER_MAX_DEPTH { visit(&path, &relative, depth + 1, files); }
+            } else {
+                files.push(relative);
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    visit(folder, "", 0, &mut files);
+    files.sort();
+    files
+}
@@ -0,0 +1,234 @@
+// plugin_hooks.rs - runs "hook" plugins when things happen in the editor, instead of from the menu.
+//
+// A hook plugin sets `P.plugin_type = "hook"` and defines any of the callbacks below on `P`. Each one gets
+// the same `notes` object `on_apply` gets, plus an event table:
+//
+//   on_note_placed(notes, { track = 0, ids = {...} })      notes placed with the pencil or pasted
+//   on_selection_changed(notes, { track = 0 })              `track` is the current track
+//   on_playback_tick(notes, { tick = 1920 })                every frame while playing
+//   on_project_loaded(notes, {})                            after a MIDI import or a new project
+//   on_before_export(notes, { path = "..." })               before the MIDI file gets written
+//
+// Whatever a callback changes through `notes` becomes one undo step, except that everything `on_playback_tick`
+// changes during one playback run is a single step, so it doesn't push the rest out of the undo history. Returning a string shows it as a warning in
+// the plugin console; `on_before_export` can also return `false, "reason"` to stop the export.
+
+use std::{cell::{Cell, RefCell}, rc::Rc, sync::{Arc, Mutex, RwLock}, time::Duration};
+
+use mlua::{Function, Lua, MultiValue, Table, Value};
+
+use crate::editor::{actions::EditorActions, editing::{lua_note_editing::LuaNoteEditing, meta_editing::MetaEditing, note_editing::NoteEditing}, midi_bar_cacher::BarCacher, playhead::Playhead, plugins::{plugin_andromeda_obj::AndromedaObj, plugin_console::PluginConsole, plugin_lua::PluginLua, plugin_sandbox::{ExecutionBudget, run_with_budget}}, project::project_manager::ProjectManager};
+
+/// Hooks that fire while someone is editing or listening can't hold the editor up for long.
+const LIVE_HOOK_BUDGET: ExecutionBudget = ExecutionBudget {
+    max_instructions: 100_000_000,
+    max_duration: Duration::from_millis(500)
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PluginHook {
+    NotePlaced,
+    SelectionChanged,
+    PlaybackTick,
+    ProjectLoaded,
+    BeforeExport
+}
+
+impl PluginHook {
+    pub const ALL: [PluginHook; 5] = [
+        PluginHook::NotePlaced,
+        PluginHook::SelectionChanged,
+        PluginHook::PlaybackTick,
+        PluginHook::ProjectLoaded,
+        PluginHook::BeforeExport
+    ];
+
+    /// What the callback is called in the plugin.
+    pub fn callback_name(&self) -> &'static str {
+        match self {
+            PluginHook::NotePlaced => "on_note_placed",
+            PluginHook::SelectionChanged => "on_selection_changed",
+            PluginHook::PlaybackTick => "on_playback_tick",
+            PluginHook::ProjectLoaded => "on_project_loaded",
+            PluginHook::BeforeExport => "on_before_export"
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        matches!(self, PluginHook::NotePlaced | PluginHook::SelectionChanged | PluginHook::PlaybackTick)
+    }
+}
+
+/// What the hook plugins had to say about an event.
+#[derive(Default)]
+pub struct HookOutcome {
+    pub warnings: Vec<(String, String)>,
+    /// (plugin name, reason), only ever set by `on_before_export`
+    pub cancelled: Option<(String, String)>,
+    /// a hook failed and got turned off
+    pub had_errors: bool
+}
+
+/// Reads the hook callbacks a plugin defined.
+pub fn read_hooks(plugin_table: &Table) -> mlua::Result<Vec<(PluginHook, Function)>> {
+    let mut hooks = Vec::new();
+    for hook in PluginHook::ALL {
+        if let Some(callback) = plugin_table.get::<Option<Function>>(hook.callback_name())? {
+            hooks.push((hook, callback));
+        }
+    }
+    Ok(hooks)
+}
+
+pub struct PluginHookRunner {
+    note_editing: Arc<Mutex<NoteEditing>>,
+    meta_editing: Arc<Mutex<MetaEditing>>,
+    editor_actions: Rc<RefCell<EditorActions>>,
+    project_manager: Arc<RwLock<ProjectManager>>,
+    playhead: Rc<RefCell<Playhead>>,
+    bar_cacher: Arc<Mutex<BarCacher>>,
+    // the change count right after on_playback_tick last registered its edits, so the next ones can go in the same undo step
+    tick_change_count: Cell<Option<u64>>
+}
+
+impl PluginHookRunner {
+    pub fn new(
+        note_editing: &Arc<Mutex<NoteEditing>>,
+        meta_editing: &Arc<Mutex<MetaEditing>>,
+        editor_actions: &Rc<RefCell<EditorActions>>,
+        project_manager: &Arc<RwLock<ProjectManager>>,
+        playhead: &Rc<RefCell<Playhead>>,
+        bar_cacher: &Arc<Mutex<BarCacher>>
+    ) -> Self {
+        Self {
+            note_editing: note_editing.clone(),
+            meta_editing: meta_editing.clone(),
+            editor_actions: editor_actions.clone(),
+            project_manager: project_manager.clone(),
+            playhead: playhead.clone(),
+            bar_cacher: bar_cacher.clone(),
+            tick_change_count: Cell::new(None)
+        }
+    }
+
+    /// True if any enabled plugin in `plugins` would be called for `hook`.
+    pub fn is_hooked(plugins: &[Rc<RefCell<PluginLua>>], hook: PluginHook) -> bool {
+        plugins.iter().any(|plugin| plugin.borrow().get_hook(hook).is_some())
+    }
+
+    /// Call when playback stops, so the next run's `on_playback_tick` edits get an undo step of their own.
+    pub fn end_playback_run(&self) {
+        self.tick_change_count.set(None);
+    }
+
+    /// Calls `hook` on every enabled plugin that defines it. `fill_event` sets up the event table.
+    /// A plugin whose hook fails gets its hooks turned off, so a broken `on_playback_tick` doesn't error every frame.
+    pub fn dispatch(
+        &self,
+        plugins: &[Rc<RefCell<PluginLua>>],
+        hook: PluginHook,
+        curr_track: usize,
+        fill_event: impl Fn(&Table) -> mlua::Result<()>
+    ) -> HookOutcome {
+        let mut outcome = HookOutcome::default();
+
+        for plugin in plugins.iter() {
+            let (lua, callback, budget, plugin_name) = {
+                let plugin = plugin.borrow();
+                let Some(callback) = plugin.get_hook(hook) else { continue; };
+                let budget = if hook.is_live() { LIVE_HOOK_BUDGET } else { plugin.budget };
+                (plugin.lua.clone(), callback, budget, plugin.plugin_name.clone())
+            };
+
+            let mut lua_note_editing = LuaNoteEditing::new(&self.note_editing, &self.meta_editing);
+            let result = self.call_hook(&lua, &callback, budget, curr_track, &mut lua_note_editing, &fill_event);
+
+            match result {
+                Ok(returned) => {
+                    let mut editor_actions = self.editor_actions.try_borrow_mut().unwrap();
+                    if hook == PluginHook::PlaybackTick {
+                        let change_count = editor_actions.get_change_count();
+                        lua_note_editing.apply_changes_merged(&mut editor_actions, self.tick_change_count.get());
+                        // a tick that changed nothing mustn't let the next one merge into someone else's edit
+                        if editor_actions.get_change_count() != change_count {
+                            self.tick_change_count.set(Some(editor_actions.get_change_count()));
+                        }
+                    } else {
+                        lua_note_editing.apply_changes(&mut editor_actions);
+                    }
+
+                    match returned {
+                        HookReturn::Nothing => {},
+                        HookReturn::Warning(warning) => {
+                            PluginConsole::log_warning(&plugin_name, &warning);
+                            outcome.warnings.push((plugin_name, warning));
+                        },
+                        HookReturn::Cancel(reason) => {
+                            if hook == PluginHook::BeforeExport {
+                                PluginConsole::log_warning(&plugin_name, &format!("stopped the export: {}", reason));
+                                if outcome.cancelled.is_none() { outcome.cancelled = Some((plugin_name, reason)); }
+                            } else if !reason.is_empty() {
+                                // only an export can be stopped, anything else just gets the reason shown
+                                PluginConsole::log_warning(&plugin_name, &reason);
+                                outcome.warnings.push((plugin_name, reason));
+                            }
+                        }
+                    }
+                },
+                Err(err) => {
+                    lua_note_editing.discard_changes();
+                    PluginConsole::log_error(&format!("in {} of {}", hook.callback_name(), plugin_name), &err.to_string());
+                    PluginConsole::log_info(&plugin_name, "hooks turned off until the plugin is reloaded");
+                    plugin.borrow_mut().hooks_enabled = false;
+                    outcome.had_errors = true;
+                }
+            }
+        }
+
+        outcome
+    }
+
+    fn call_hook(
+        &self,
+        lua: &Lua,
+        callback: &Function,
+        budget: ExecutionBudget,
+        curr_track: usize,
+        lua_note_editing: &mut LuaNoteEditing,
+        fill_event: &impl Fn(&Table) -> mlua::Result<()>
+    ) -> mlua::Result<HookReturn> {
+        AndromedaObj::new(&self.project_manager, &self.playhead, &self.bar_cacher).set_globals(lua, curr_track)?;
+
+        let event = lua.create_table()?;
+        fill_event(&event)?;
+
+        run_with_budget(lua, budget, || lua.scope(|scope| {
+            let notes = scope.create_userdata_ref_mut(&mut *lua_note_editing)?;
+            let returned = callback.call::<MultiValue>((notes, event))?;
+            HookReturn::from_lua_values(returned)
+        }))
+    }
+}
+
+enum HookReturn {
+    Nothing,
+    Warning(String),
+    Cancel(String)
+}
+
+impl HookReturn {
+    fn from_lua_values(values: MultiValue) -> mlua::Result<Self> {
+        let mut values = values.into_iter();
+        Ok(match values.next() {
+            Some(Value::Boolean(false)) => {
+                let reason = match values.next() {
+                    Some(Value::String(reason)) => reason.to_str()?.to_string(),
+                    _ => String::new()
+                };
+                HookReturn::Cancel(reason)
+            },
+            Some(Value::String(warning)) => HookReturn::Warning(warning.to_str()?.to_string()),
+            _ => HookReturn::Nothing
+        })
+    }
+}
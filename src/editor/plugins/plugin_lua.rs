@@ -1,6 +1,6 @@
-use mlua::{Error, Function, Lua, Table, Value};
-use crate::{editor::plugins::PluginType, util::debugger::Debugger};
-use std::{path::PathBuf, rc::Rc};
+use mlua::{Error, Function, Lua, Table};
+use crate::{editor::plugins::{PluginType, plugin_console::PluginConsole, plugin_hooks::{PluginHook, read_hooks}, plugin_package::{MANIFEST_FILE_NAME, PackageContext, install_require}, plugin_sandbox::{ExecutionBudget, PluginCapability, get_plugin_data_folder, install_file_access, new_sandboxed_lua, read_capabilities, run_with_budget}}, util::debugger::Debugger};
+use std::{path::{Path, PathBuf}, rc::Rc};
 use regex::Regex;
 
 pub struct PluginInfo {
@@ -13,10 +13,19 @@ pub struct PluginLua {
     pub plugin_type: PluginType,
     pub plugin_info: Option<PluginInfo>,
     plugin_path: Option<PathBuf>,
+    /// what the plugin declared in `P.capabilities`
+    pub capabilities: Vec<PluginCapability>,
+    pub budget: ExecutionBudget,
 
     pub on_apply_fn: Option<Function>,
+    /// callbacks of a hook plugin, see plugin_hooks
+    hooks: Vec<(PluginHook, Function)>,
+    /// turned off from the menu, or after one of the hooks failed
+    pub hooks_enabled: bool,
     pub lua: Rc<Lua>,
     pub dialog_field_table: Option<Table>,
+    /// set for plugins that are part of a package, see plugin_package
+    package: Option<PackageContext>,
 
     loaded: bool,
     is_builtin: bool,
@@ -24,14 +33,22 @@ pub struct PluginLua {
 
 impl PluginLua {
     pub fn new() -> Self {
+        let lua = new_sandboxed_lua().expect("failed to create the plugin sandbox");
+        PluginConsole::install_print(&lua, "plugin").expect("failed to set up plugin output");
+
         Self {
             plugin_name: "unnamed plugin".into(),
             plugin_type: PluginType::Manipluate,
             plugin_info: None,
             plugin_path: None,
-            lua: Rc::new(Lua::new()),
+            capabilities: Vec::new(),
+            budget: ExecutionBudget::default(),
+            lua: Rc::new(lua),
             on_apply_fn: None,
+            hooks: Vec::new(),
+            hooks_enabled: true,
             dialog_field_table: None,
+            package: None,
 
             loaded: false,
             is_builtin: true
@@ -56,6 +73,33 @@ impl PluginLua {
         Ok(())
     }
 
+    /// The callback for `hook`, unless the plugin doesn't have one or its hooks are turned off.
+    pub fn get_hook(&self, hook: PluginHook) -> Option<Function> {
+        if !self.hooks_enabled { return None; }
+        self.hooks.iter().find(|(h, _)| *h == hook).map(|(_, callback)| callback.clone())
+    }
+
+    /// The file the plugin was loaded from. Builtin plugins don't have one.
+    pub fn get_plugin_path(&self) -> Option<&Path> {
+        self.plugin_path.as_deref()
+    }
+
+    /// Where the plugin's files live: the package's folder if it's part of one, otherwise a folder of its own next to
+    /// the plugin file, so plugins in the same folder can't touch each other. Builtin plugins don't have one.
+    pub fn get_data_folder(&self) -> Option<PathBuf> {
+        if let Some(package) = self.package.as_ref() { return Some(package.root.clone()); }
+        self.plugin_path.as_deref().and_then(get_plugin_data_folder)
+    }
+
+    /// Makes the plugin part of a package. Has to happen before it's loaded.
+    pub fn use_package(&mut self, package: PackageContext) {
+        self.package = Some(package);
+    }
+
+    pub fn get_package_id(&self) -> Option<&str> {
+        self.package.as_ref().map(|package| package.id.as_str())
+    }
+
     pub fn reload_plugin(&mut self) -> Result<(), Error> {
         if self.is_builtin {
             Debugger::log(format!("Skipping {} reload because it is a builtin plugin", self.plugin_name));
@@ -67,11 +111,12 @@ impl PluginLua {
         }
 
         self.loaded = false;
-        let path = self.plugin_path.take().unwrap();
+        let path = self.plugin_path.clone().unwrap();
         let result = self.load_plugin_from_path(path);
 
-        if let Err(ref e) = result {
-            Debugger::log_error(format!("[PluginError] (while reloading {}): \n--> {}", self.plugin_name, e.to_string()));
+        match result {
+            Ok(_) => PluginConsole::log_info(&self.plugin_name, "reloaded"),
+            Err(ref e) => PluginConsole::log_error(&format!("while reloading {}", self.plugin_name), &e.to_string())
         }
 
         result
@@ -79,28 +124,17 @@ impl PluginLua {
 
     pub fn load_plugin_from_str(&mut self, src_code: &String) -> Result<(), Error> {
         if self.loaded { return Ok(()); }
-        let lua = &mut self.lua;
-
-        let disallowed_modules = vec!["socket", "os", "package"];
-        let globals = lua.globals();
-        let require: Function = globals.get("require")?;
-        let require_strip = lua.create_function(move |_, name: String| {
-            if disallowed_modules.iter().any(|&m| m == name) {
-                return Err(mlua::Error::RuntimeError(format!(
-                    "for security reasons, usage of module '{}' is not allowed",
-                    name
-                )));
-            }
-
-            require.call::<Value>(name)
-        })?;
-
-        globals.set("require", require_strip)?;
-        globals.set("os", Value::Nil)?;
-        globals.set("package", Value::Nil)?;
+        let lua = &self.lua;
 
+        // the lua state is already sandboxed (see plugin_sandbox), only the top level code has to be kept in check here
         let src_code = Self::preprocess_plugin_src(src_code);
-        let globals = lua.load(src_code).eval::<Table>()?;
+        // named after the file, so errors and tracebacks point at lines in it
+        let chunk_name = match self.plugin_path.as_ref().and_then(|path| path.file_name()) {
+            Some(file_name) => format!("@{}", file_name.to_string_lossy()),
+            None => "=builtin plugin".into()
+        };
+        install_require(lua, self.package.as_ref())?;
+        let globals = run_with_budget(lua, self.budget, || lua.load(src_code).set_name(chunk_name).eval::<Table>())?;
 
         let plugin_name = globals.get::<String>("plugin_name");
         if plugin_name.is_err() {
@@ -117,6 +151,9 @@ impl PluginLua {
                 "generate" => {
                     PluginType::Generate
                 },
+                "hook" => {
+                    PluginType::Hook
+                },
                 _ => {
                     PluginType::Manipluate
                 }
@@ -133,12 +170,32 @@ impl PluginLua {
             });
         }
 
-        let on_apply = globals.get::<mlua::Function>("on_apply")?;
+        let capabilities = read_capabilities(&globals)?;
+        if let Some(package) = self.package.as_ref() {
+            if let Some(capability) = capabilities.iter().find(|capability| !package.capabilities.contains(capability)) {
+                return Err(Error::runtime(format!("the plugin asks for '{}', which the package's {} doesn't list in \"capabilities\"", capability.name(), MANIFEST_FILE_NAME)));
+            }
+        }
+        install_file_access(lua, self.get_data_folder().as_deref(), &capabilities)?;
+
+        // hook plugins are only ever called through their hooks
+        let on_apply = match plugin_type {
+            PluginType::Hook => globals.get::<Option<mlua::Function>>("on_apply")?,
+            _ => Some(globals.get::<mlua::Function>("on_apply")?)
+        };
+        let hooks = read_hooks(&globals)?;
+        if plugin_type == PluginType::Hook && hooks.is_empty() {
+            return Err(Error::runtime("hook plugins need at least one hook (e.g. P.on_note_placed)"));
+        }
         let dialog_field_table = globals.get::<Table>("dialog_fields").ok();
         
         self.plugin_name = plugin_name.unwrap();
+        PluginConsole::set_source(lua, &self.plugin_name);
         self.plugin_type = plugin_type;
-        self.on_apply_fn = Some(on_apply);
+        self.capabilities = capabilities;
+        self.on_apply_fn = on_apply;
+        self.hooks = hooks;
+        self.hooks_enabled = true;
         self.dialog_field_table = dialog_field_table;
         self.loaded = true;
 
@@ -146,14 +203,17 @@ impl PluginLua {
     }
 
     fn preprocess_plugin_src(source: &str) -> String {
-        let re = Regex::new(r"(?m)^\s*local\s+P\s*=\s*\{\s*\}\s*;?\s*$").unwrap();
-        re.replace_all(source, r#"local P = {}
-function get_field_value(f_id)
-    for i,f in ipairs(P.dialog_fields) do
-        if f.id == f_id then
-            return f[1].value
-        end
-    end
-end"#).to_string()
+        // kept on the one line it replaces, so line numbers in errors still match the file
+        let re = Regex::new(r"(?m)^[ \t]*local[ \t]+P[ \t]*=[ \t]*\{[ \t]*\}[ \t]*;?[ \t]*\r?$").unwrap();
+        re.replace_all(source, concat!(
+            "local P = {}; ",
+            "local function find_field(fields, f_id) ",
+                "for i,f in ipairs(fields) do ",
+                    "if f.id == f_id then return f end ",
+                    "if f.type == \"group\" and f.fields then local found = find_field(f.fields, f_id); if found then return found end end ",
+                "end ",
+            "end; ",
+            "function get_field_value(f_id) local f = find_field(P.dialog_fields, f_id); if f then return f[1].value end end"
+        )).to_string()
     }
 }
\ No newline at end of file
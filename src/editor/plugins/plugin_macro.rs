@@ -0,0 +1,578 @@
+// plugin_macro.rs - records what gets done to the selection, and saves it as a plugin that does it all again.
+//
+// While recording, edit functions, plugin runs and track operations are noted down as steps, along with their
+// settings. Saving writes a "manipulate" plugin into the custom plugin folder whose `on_apply` asks for the same steps:
+//
+//   notes:edit_function("transpose", { semitones = 12 })
+//   notes:run_plugin("Humanize", { vel_range_min = 90, vel_range_max = 100 })
+//   notes:track_op("duplicate", { times = 1, channel_offset = 0, transpose = 12 })
+//
+// The steps run once `on_apply` returns, after whatever the plugin changed itself, and always on what's selected in
+// the current track at that point. Positions (like where `slice` cuts) are kept relative to the start of the
+// selection, so a macro works wherever it's used.
+//
+// Edit functions: stretch { factor }, chop { length }, glue { threshold, separate_channels }, remove_overlaps,
+// slice { offset }, fade { fade_out }, transpose { semitones }, conform_to_scale { scale }, formula { text },
+// quantize { grid, quantize_start, end_mode = "keep"/"snap"/"length", strength, swing, window, humanize }
+// Track operations: duplicate { times, channel_offset, transpose },
+// split { mode = "key"/"velocity" with points = {...}, mode = "channel", or mode = "voices" with voices }
+
+use std::{cell::RefCell, fs, path::{Path, PathBuf}, rc::Rc, sync::{Arc, Mutex, RwLock}};
+
+use chrono::Local;
+use mlua::{Error, Table, Value};
+use once_cell::sync::Lazy;
+use serde_json::Value as JsonValue;
+
+use crate::{app::ui::dialogs::{duplicate_track::MAX_DUPLICATES, merge_split_tracks::{MAX_SPLIT_VOICES, MIN_SPLIT_VOICES}}, editor::{actions::EditorActions, edit_functions::{EditFunction, EditFunctions, NoteFormulas, QuantizeEndMode, QuantizeSettings}, editing::{meta_editing::MetaEditing, note_editing::NoteEditing, track_editing::TrackEditing}, midi_bar_cacher::BarCacher, playhead::Playhead, plugins::{plugin_andromeda_obj::AndromedaObj, plugin_dialog::PluginDialog, plugin_lua::PluginLua, plugin_settings::FieldValues}, project::{project_manager::ProjectManager, track_restructure::TrackSplitMode}, scales::{ScaleMap, ScaleMode}, util::{MIDITick, SignedMIDIKey}}, midi::events::note::Note};
+
+const EDIT_FUNCTION_NAMES: &[&str] = &[
+    "stretch", "chop", "glue", "remove_overlaps", "slice", "fade", "transpose", "conform_to_scale", "quantize", "formula"
+];
+const TRACK_OP_NAMES: &[&str] = &["duplicate", "split"];
+
+/// A macro running itself would never stop, so nesting stops here.
+const MAX_MACRO_DEPTH: usize = 8;
+/// How deep a table passed as step settings may go.
+const MAX_PARAM_DEPTH: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MacroStepKind {
+    EditFunction,
+    RunPlugin,
+    TrackOp
+}
+
+impl MacroStepKind {
+    /// The `notes` method that queues this kind of step.
+    fn lua_method(&self) -> &'static str {
+        match self {
+            MacroStepKind::EditFunction => "edit_function",
+            MacroStepKind::RunPlugin => "run_plugin",
+            MacroStepKind::TrackOp => "track_op"
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MacroStep {
+    pub kind: MacroStepKind,
+    /// edit function or track operation name, or the plugin's name
+    pub name: String,
+    pub params: FieldValues
+}
+
+impl MacroStep {
+    fn new(kind: MacroStepKind, name: &str, params: JsonValue) -> Self {
+        let params = match params {
+            JsonValue::Object(params) => params,
+            _ => FieldValues::new()
+        };
+        Self { kind, name: name.into(), params }
+    }
+
+    /// Reads a step queued from Lua. `params` can be left out.
+    pub fn from_lua(kind: MacroStepKind, name: String, params: Option<Table>) -> mlua::Result<Self> {
+        let known = match kind {
+            MacroStepKind::EditFunction => EDIT_FUNCTION_NAMES.contains(&name.as_str()),
+            MacroStepKind::TrackOp => TRACK_OP_NAMES.contains(&name.as_str()),
+            MacroStepKind::RunPlugin => true
+        };
+        if !known {
+            return Err(Error::runtime(format!("{}: there's nothing called \"{}\"", kind.lua_method(), name)));
+        }
+
+        let params = match params {
+            Some(params) => table_to_json(&params, 0)?,
+            None => FieldValues::new()
+        };
+        Ok(Self { kind, name, params })
+    }
+
+    /// The step for an edit function that's about to run on `sel_ids` in `notes`.
+    /// Formulas are left out, their dialog records them as text (see [`MacroStep::formula`]).
+    pub fn from_edit_function(func: &EditFunction, notes: &[Note], sel_ids: &[usize]) -> Option<Self> {
+        let step = |name: &str, params: JsonValue| Some(Self::new(MacroStepKind::EditFunction, name, params));
+
+        match func {
+            EditFunction::Stretch(_, factor) => step("stretch", serde_json::json!({ "factor": f32_to_json(*factor) })),
+            EditFunction::Chop(_, length) => step("chop", serde_json::json!({ "length": length })),
+            EditFunction::Glue(_, threshold, separate_channels) => step("glue", serde_json::json!({
+                "threshold": threshold,
+                "separate_channels": separate_channels
+            })),
+            EditFunction::RemoveOverlaps => step("remove_overlaps", JsonValue::Null),
+            EditFunction::SliceAtTick(_, tick) => {
+                let selection_start = sel_ids.iter().map(|&id| notes[id].start()).min()?;
+                step("slice", serde_json::json!({ "offset": *tick as i64 - selection_start as i64 }))
+            },
+            EditFunction::FadeNotes(fade_out) => step("fade", serde_json::json!({ "fade_out": fade_out })),
+            EditFunction::Transpose(semitones) => step("transpose", serde_json::json!({ "semitones": semitones })),
+            EditFunction::ConformToScale(scale_map) => step("conform_to_scale", serde_json::json!({ "scale": scale_map.mode.to_string() })),
+            EditFunction::Quantize(_, settings) => Some(Self::quantize(settings)),
+            EditFunction::FlipX(_) | EditFunction::FlipY(_) | EditFunction::SetByFormula(_, _) => None
+        }
+    }
+
+    pub fn quantize(settings: &QuantizeSettings) -> Self {
+        let end_mode = match settings.end_mode {
+            QuantizeEndMode::Keep => "keep",
+            QuantizeEndMode::Snap => "snap",
+            QuantizeEndMode::Length => "length"
+        };
+
+        Self::new(MacroStepKind::EditFunction, "quantize", serde_json::json!({
+            "grid": settings.grid,
+            "quantize_start": settings.quantize_start,
+            "end_mode": end_mode,
+            "strength": f32_to_json(settings.strength),
+            "swing": f32_to_json(settings.swing),
+            "window": f32_to_json(settings.window),
+            "humanize": settings.humanize
+        }))
+    }
+
+    pub fn formula(text: &str) -> Self {
+        Self::new(MacroStepKind::EditFunction, "formula", serde_json::json!({ "text": text }))
+    }
+
+    pub fn run_plugin(plugin_name: &str, values: FieldValues) -> Self {
+        Self { kind: MacroStepKind::RunPlugin, name: plugin_name.into(), params: values }
+    }
+
+    pub fn duplicate_track(times: u16, channel_offset: i16, transpose: i16) -> Self {
+        Self::new(MacroStepKind::TrackOp, "duplicate", serde_json::json!({
+            "times": times,
+            "channel_offset": channel_offset,
+            "transpose": transpose
+        }))
+    }
+
+    pub fn split_track(mode: &TrackSplitMode) -> Self {
+        let params = match mode {
+            TrackSplitMode::Key(points) => serde_json::json!({ "mode": "key", "points": points }),
+            TrackSplitMode::Velocity(points) => serde_json::json!({ "mode": "velocity", "points": points }),
+            TrackSplitMode::Channel => serde_json::json!({ "mode": "channel" }),
+            TrackSplitMode::Voices(voices) => serde_json::json!({ "mode": "voices", "voices": voices })
+        };
+        Self::new(MacroStepKind::TrackOp, "split", params)
+    }
+
+    /// e.g. `transpose { semitones = 12 }`
+    pub fn describe(&self) -> String {
+        if self.params.is_empty() { return self.name.clone(); }
+        format!("{} {}", self.name, map_to_lua(&self.params))
+    }
+
+    fn to_lua_call(&self) -> String {
+        format!("notes:{}({}, {})", self.kind.lua_method(), lua_quote(&self.name), map_to_lua(&self.params))
+    }
+}
+
+// ======== RECORDING ========
+
+#[derive(Default)]
+struct MacroRecording {
+    recording: bool,
+    // macros being played back right now, nothing gets recorded while they run
+    replaying: usize,
+    steps: Vec<MacroStep>
+}
+
+static MACRO_RECORDING: Lazy<Mutex<MacroRecording>> = Lazy::new(|| Mutex::new(MacroRecording::default()));
+
+/// Collects steps between "Record macro" and saving. Anything that can end up in a macro calls
+/// [`MacroRecorder::record`] after it's done, whether or not a recording is going on.
+pub struct MacroRecorder;
+
+impl MacroRecorder {
+    /// Starts over, dropping anything recorded but not saved.
+    pub fn start() {
+        let mut recording = MACRO_RECORDING.lock().unwrap();
+        recording.recording = true;
+        recording.steps.clear();
+    }
+
+    /// Stops recording and hands over the steps.
+    pub fn stop() -> Vec<MacroStep> {
+        let mut recording = MACRO_RECORDING.lock().unwrap();
+        recording.recording = false;
+        std::mem::take(&mut recording.steps)
+    }
+
+    pub fn is_recording() -> bool {
+        let recording = MACRO_RECORDING.lock().unwrap();
+        recording.recording && recording.replaying == 0
+    }
+
+    pub fn get_step_count() -> usize {
+        MACRO_RECORDING.lock().unwrap().steps.len()
+    }
+
+    pub fn record(step: MacroStep) {
+        let mut recording = MACRO_RECORDING.lock().unwrap();
+        if recording.recording && recording.replaying == 0 {
+            recording.steps.push(step);
+        }
+    }
+
+    /// Runs `f` without recording anything it does. A macro that gets played back shows up as the plugin run
+    /// that started it, not as the steps inside it.
+    fn while_replaying<R>(f: impl FnOnce() -> R) -> R {
+        MACRO_RECORDING.lock().unwrap().replaying += 1;
+        let result = f();
+        MACRO_RECORDING.lock().unwrap().replaying -= 1;
+        result
+    }
+}
+
+/// Writes `steps` as a plugin called `name` into `folder`, next to any plugins already there.
+/// Returns where it went. The plugin watcher loads it from there like any other new custom plugin.
+pub fn save_macro_plugin(folder: &Path, name: &str, steps: &[MacroStep]) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(folder)?;
+
+    let mut file_stem: String = name.trim().chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    file_stem = file_stem.split('_').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("_");
+    if file_stem.is_empty() { file_stem = "macro".into(); }
+
+    let mut path = folder.join(format!("{}.lua", file_stem));
+    let mut suffix = 2;
+    while path.exists() {
+        path = folder.join(format!("{}_{}.lua", file_stem, suffix));
+        suffix += 1;
+    }
+
+    fs::write(&path, macro_plugin_source(name, steps))?;
+    Ok(path)
+}
+
+fn macro_plugin_source(name: &str, steps: &[MacroStep]) -> String {
+    let mut source = String::new();
+    source += &format!("-- Macro recorded on {}.\n", Local::now().format("%Y-%m-%d %H:%M"));
+    source += "-- Runs on whatever is selected in the current track. Each line is one recorded step, they can be edited\n";
+    source += "-- or reordered like any other plugin code.\n";
+    source += "local P={}\n";
+    source += &format!("P.plugin_name={}\n", lua_quote(name));
+    source += "P.plugin_type=\"manipulate\"\n";
+    source += &format!("P.plugin_info={{ author=\"Macro recorder\", description={} }}\n", lua_quote(&format!("Recorded macro with {} steps.", steps.len())));
+    source += "P.dialog_fields={}\n";
+    source += "function on_apply(notes)\n";
+    for step in steps {
+        source += &format!("    {}\n", step.to_lua_call());
+    }
+    source += "end\n";
+    source += "P.on_apply=on_apply\n";
+    source += "return P\n";
+    source
+}
+
+// ======== PLAYBACK ========
+
+/// Runs the steps a plugin queued through `notes:edit_function`, `notes:run_plugin` and `notes:track_op`.
+#[derive(Clone)]
+pub struct MacroPlayer {
+    note_editing: Arc<Mutex<NoteEditing>>,
+    meta_editing: Arc<Mutex<MetaEditing>>,
+    track_editing: Arc<Mutex<TrackEditing>>,
+    editor_functions: Rc<RefCell<EditFunctions>>,
+    editor_actions: Rc<RefCell<EditorActions>>,
+    project_manager: Arc<RwLock<ProjectManager>>,
+    playhead: Rc<RefCell<Playhead>>,
+    bar_cacher: Arc<Mutex<BarCacher>>,
+    /// what `notes:run_plugin` can pick from
+    plugins: Vec<Rc<RefCell<PluginLua>>>,
+    depth: usize
+}
+
+impl MacroPlayer {
+    pub fn new(
+        note_editing: &Arc<Mutex<NoteEditing>>,
+        meta_editing: &Arc<Mutex<MetaEditing>>,
+        track_editing: &Arc<Mutex<TrackEditing>>,
+        editor_functions: &Rc<RefCell<EditFunctions>>,
+        editor_actions: &Rc<RefCell<EditorActions>>,
+        project_manager: &Arc<RwLock<ProjectManager>>,
+        playhead: &Rc<RefCell<Playhead>>,
+        bar_cacher: &Arc<Mutex<BarCacher>>,
+        plugins: Vec<Rc<RefCell<PluginLua>>>
+    ) -> Self {
+        Self {
+            note_editing: note_editing.clone(),
+            meta_editing: meta_editing.clone(),
+            track_editing: track_editing.clone(),
+            editor_functions: editor_functions.clone(),
+            editor_actions: editor_actions.clone(),
+            project_manager: project_manager.clone(),
+            playhead: playhead.clone(),
+            bar_cacher: bar_cacher.clone(),
+            plugins,
+            depth: 0
+        }
+    }
+
+    /// Runs `steps` one after another on `curr_track`, stopping at the first one that fails.
+    /// Note edits in a row become one undo step. Track operations stay separate, the track editor undoes those.
+    pub fn play(&self, steps: &[MacroStep], curr_track: usize) -> mlua::Result<()> {
+        if self.depth >= MAX_MACRO_DEPTH {
+            return Err(Error::runtime("macros are nested too deep, does one of them run itself?"));
+        }
+
+        MacroRecorder::while_replaying(|| {
+            let mut squash_from = self.editor_actions.borrow().get_change_count();
+            let mut result = Ok(());
+
+            for (i, step) in steps.iter().enumerate() {
+                let step_result = match step.kind {
+                    MacroStepKind::EditFunction => self.play_edit_function(step, curr_track),
+                    MacroStepKind::RunPlugin => self.play_plugin(step, curr_track),
+                    MacroStepKind::TrackOp => {
+                        self.editor_actions.borrow_mut().squash_since(squash_from);
+                        let step_result = self.play_track_op(step, curr_track);
+                        squash_from = self.editor_actions.borrow().get_change_count();
+                        step_result
+                    }
+                };
+
+                if let Err(err) = step_result {
+                    result = Err(Error::runtime(format!("macro step {} ({}) failed: {}", i + 1, step.describe(), err)));
+                    break;
+                }
+            }
+
+            self.editor_actions.borrow_mut().squash_since(squash_from);
+            result
+        })
+    }
+
+    fn play_edit_function(&self, step: &MacroStep, curr_track: usize) -> mlua::Result<()> {
+        let (ppq, scale_map) = {
+            let project_manager = self.project_manager.read().unwrap();
+            let scale_map = if step.name == "conform_to_scale" {
+                let scale_name = get_string(step, "scale")?;
+                let Some(mode) = ScaleMode::ALL.into_iter().find(|mode| mode.to_string().eq_ignore_ascii_case(scale_name)) else {
+                    return Err(Error::runtime(format!("there's no scale called \"{}\"", scale_name)));
+                };
+                let metas = project_manager.get_metas().read().unwrap();
+                Some(ScaleMap::from_metas(&metas, mode))
+            } else { None };
+            (project_manager.get_ppq(), scale_map)
+        };
+
+        let note_editing = self.note_editing.lock().unwrap();
+        let tracks = note_editing.get_tracks();
+        let mut tracks = tracks.write().unwrap();
+        let Some(track) = tracks.get_mut(curr_track) else {
+            return Err(Error::runtime(format!("track {} doesn't exist", curr_track)));
+        };
+        let notes = track.get_notes_mut();
+
+        let mut sel_notes = note_editing.get_shared_selected_ids().write().unwrap();
+        let sel_notes = sel_notes.get_selected_ids_mut(curr_track as u16);
+        // every edit function works on the selection
+        if sel_notes.is_empty() { return Ok(()); }
+
+        let sel_ids = sel_notes.clone();
+        let func = match step.name.as_str() {
+            "stretch" => EditFunction::Stretch(sel_ids, get_number(step, "factor")? as f32),
+            "chop" => EditFunction::Chop(sel_ids, get_number(step, "length")?.max(1.0) as MIDITick),
+            "glue" => EditFunction::Glue(sel_ids, get_number(step, "threshold")?.max(0.0) as MIDITick, get_bool(step, "separate_channels")?),
+            "remove_overlaps" => EditFunction::RemoveOverlaps,
+            "slice" => {
+                let selection_start = sel_ids.iter().map(|&id| notes[id].start()).min().unwrap();
+                let tick = selection_start as i64 + get_number(step, "offset")? as i64;
+                if tick < 0 { return Ok(()); }
+                EditFunction::SliceAtTick(sel_ids, tick as MIDITick)
+            },
+            "fade" => EditFunction::FadeNotes(get_bool(step, "fade_out")?),
+            "transpose" => EditFunction::Transpose(get_number(step, "semitones")? as SignedMIDIKey),
+            "conform_to_scale" => EditFunction::ConformToScale(scale_map.unwrap()),
+            "quantize" => EditFunction::Quantize(sel_ids, read_quantize_settings(step)),
+            "formula" => {
+                let formulas = NoteFormulas::parse(get_string(step, "text")?, ppq)
+                    .map_err(|(line, err)| Error::runtime(format!("formula line {}: {}", line, err)))?;
+                EditFunction::SetByFormula(sel_ids, formulas)
+            },
+            name => return Err(Error::runtime(format!("unknown edit function \"{}\"", name)))
+        };
+
+        let mut editor_functions = self.editor_functions.borrow_mut();
+        let mut editor_actions = self.editor_actions.borrow_mut();
+        editor_functions.apply_function(notes, sel_notes, func, curr_track as u16, &mut editor_actions);
+        Ok(())
+    }
+
+    fn play_plugin(&self, step: &MacroStep, curr_track: usize) -> mlua::Result<()> {
+        let Some(plugin) = self.plugins.iter().find(|plugin| plugin.borrow().plugin_name == step.name).cloned() else {
+            return Err(Error::runtime(format!("there's no plugin called \"{}\"", step.name)));
+        };
+
+        // no settings store, so this run doesn't become the plugin's "last used" values
+        let mut plugin_dialog = PluginDialog::default();
+        plugin_dialog.init(&self.editor_actions, &self.note_editing, &self.meta_editing, &self.bar_cacher);
+        plugin_dialog.use_macro_player(Self { depth: self.depth + 1, ..self.clone() });
+        let andromeda_obj = AndromedaObj::new(&self.project_manager, &self.playhead, &self.bar_cacher);
+        plugin_dialog.run_headless(&plugin, curr_track, &step.params, andromeda_obj)
+    }
+
+    fn play_track_op(&self, step: &MacroStep, curr_track: usize) -> mlua::Result<()> {
+        let mut track_editing = self.track_editing.lock().unwrap();
+        match step.name.as_str() {
+            "duplicate" => {
+                let times = get_number(step, "times")?.clamp(1.0, MAX_DUPLICATES as f64) as u16;
+                let channel_offset = get_number_or(step, "channel_offset", 0.0) as i16;
+                let transpose = get_number_or(step, "transpose", 0.0) as i16;
+                track_editing.duplicate_track(curr_track as u16, times, channel_offset, transpose);
+            },
+            "split" => {
+                let points = || -> mlua::Result<Vec<u8>> {
+                    let points = step.params.get("points").and_then(|points| points.as_array())
+                        .ok_or_else(|| Error::runtime("split needs a list of \"points\""))?;
+                    Ok(points.iter().filter_map(|point| point.as_u64()).map(|point| point.min(127) as u8).collect())
+                };
+                let mode = match get_string(step, "mode")? {
+                    "key" => TrackSplitMode::Key(points()?),
+                    "velocity" => TrackSplitMode::Velocity(points()?),
+                    "channel" => TrackSplitMode::Channel,
+                    "voices" => TrackSplitMode::Voices(get_number(step, "voices")?.clamp(MIN_SPLIT_VOICES as f64, MAX_SPLIT_VOICES as f64) as u16),
+                    mode => return Err(Error::runtime(format!("split can't go by \"{}\"", mode)))
+                };
+                track_editing.split_track(curr_track as u16, &mode);
+            },
+            name => return Err(Error::runtime(format!("unknown track operation \"{}\"", name)))
+        }
+        Ok(())
+    }
+}
+
+fn read_quantize_settings(step: &MacroStep) -> QuantizeSettings {
+    let defaults = QuantizeSettings::default();
+    let end_mode = match step.params.get("end_mode").and_then(|mode| mode.as_str()) {
+        Some("snap") => QuantizeEndMode::Snap,
+        Some("length") => QuantizeEndMode::Length,
+        Some("keep") => QuantizeEndMode::Keep,
+        _ => defaults.end_mode
+    };
+
+    QuantizeSettings {
+        grid: (get_number_or(step, "grid", defaults.grid as f64).max(1.0)) as MIDITick,
+        quantize_start: step.params.get("quantize_start").and_then(|v| v.as_bool()).unwrap_or(defaults.quantize_start),
+        end_mode,
+        strength: get_number_or(step, "strength", defaults.strength as f64).clamp(0.0, 1.0) as f32,
+        swing: get_number_or(step, "swing", defaults.swing as f64).clamp(0.0, 1.0) as f32,
+        window: get_number_or(step, "window", defaults.window as f64).clamp(0.0, 1.0) as f32,
+        humanize: get_number_or(step, "humanize", defaults.humanize as f64).max(0.0) as MIDITick
+    }
+}
+
+fn get_number(step: &MacroStep, key: &str) -> mlua::Result<f64> {
+    step.params.get(key).and_then(|value| value.as_f64())
+        .ok_or_else(|| Error::runtime(format!("{} needs a number for \"{}\"", step.name, key)))
+}
+
+fn get_number_or(step: &MacroStep, key: &str, default: f64) -> f64 {
+    step.params.get(key).and_then(|value| value.as_f64()).unwrap_or(default)
+}
+
+fn get_bool(step: &MacroStep, key: &str) -> mlua::Result<bool> {
+    step.params.get(key).and_then(|value| value.as_bool())
+        .ok_or_else(|| Error::runtime(format!("{} needs true or false for \"{}\"", step.name, key)))
+}
+
+fn get_string<'a>(step: &'a MacroStep, key: &str) -> mlua::Result<&'a str> {
+    step.params.get(key).and_then(|value| value.as_str())
+        .ok_or_else(|| Error::runtime(format!("{} needs a string for \"{}\"", step.name, key)))
+}
+
+// ======== LUA <-> JSON ========
+
+/// Goes through the shortest text form, so 0.8 is saved as 0.8 rather than 0.800000011920929.
+fn f32_to_json(value: f32) -> JsonValue {
+    value.to_string().parse::<f64>().map(JsonValue::from).unwrap_or(JsonValue::Null)
+}
+
+fn table_to_json(table: &Table, depth: usize) -> mlua::Result<FieldValues> {
+    let mut values = FieldValues::new();
+    for pair in table.pairs::<String, Value>() {
+        let (key, value) = pair?;
+        values.insert(key, lua_to_json(value, depth + 1)?);
+    }
+    Ok(values)
+}
+
+fn lua_to_json(value: Value, depth: usize) -> mlua::Result<JsonValue> {
+    if depth > MAX_PARAM_DEPTH { return Err(Error::runtime("step settings are nested too deep")); }
+
+    Ok(match value {
+        Value::Nil => JsonValue::Null,
+        Value::Boolean(b) => JsonValue::from(b),
+        Value::Integer(i) => JsonValue::from(i),
+        // whole numbers stay whole, so they still work as ticks, keys and dropdown indices
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 9.0e15 => JsonValue::from(n as i64),
+        Value::Number(n) => serde_json::Number::from_f64(n).map(JsonValue::Number).unwrap_or(JsonValue::Null),
+        Value::String(s) => JsonValue::from(s.to_str()?.to_string()),
+        Value::Table(table) if table.raw_len() > 0 => {
+            let items = table.sequence_values::<Value>()
+                .map(|item| lua_to_json(item?, depth + 1))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            JsonValue::Array(items)
+        },
+        Value::Table(table) => JsonValue::Object(table_to_json(&table, depth)?),
+        other => return Err(Error::runtime(format!("a {} can't be a step setting", other.type_name())))
+    })
+}
+
+fn map_to_lua(values: &FieldValues) -> String {
+    if values.is_empty() { return "{}".into(); }
+
+    let entries: Vec<String> = values.iter().map(|(key, value)| {
+        if is_lua_identifier(key) {
+            format!("{} = {}", key, json_to_lua(value))
+        } else {
+            format!("[{}] = {}", lua_quote(key), json_to_lua(value))
+        }
+    }).collect();
+    format!("{{ {} }}", entries.join(", "))
+}
+
+fn json_to_lua(value: &JsonValue) -> String {
+    match value {
+        JsonValue::Null => "nil".into(),
+        JsonValue::Bool(b) => b.to_string(),
+        JsonValue::Number(n) => n.to_string(),
+        JsonValue::String(s) => lua_quote(s),
+        JsonValue::Array(items) if items.is_empty() => "{}".into(),
+        JsonValue::Array(items) => format!("{{ {} }}", items.iter().map(json_to_lua).collect::<Vec<_>>().join(", ")),
+        JsonValue::Object(values) => map_to_lua(values)
+    }
+}
+
+fn is_lua_identifier(s: &str) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+        "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while"
+    ];
+
+    let mut chars = s.chars();
+    let starts_ok = chars.next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false);
+    starts_ok && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && !KEYWORDS.contains(&s)
+}
+
+fn lua_quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            '\t' => quoted += "\\t",
+            c if (c as u32) < 0x20 => quoted += &format!("\\{:03}", c as u32),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
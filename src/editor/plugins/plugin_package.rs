@@ -0,0 +1,574 @@
+// plugin_package.rs - plugins that come as a package: a folder with a `plugin.json` manifest, shared as a zip of that folder.
+//
+// {
+//     "id": "arp-tools",                          letters, digits, '-', '_' and '.'
+//     "name": "Arp Tools",                        optional, shown in the plugin manager
+//     "version": "1.2.0",
+//     "min_editor_version": "0.1.0",              optional
+//     "dependencies": { "music-theory": "1.0" },  optional, package id -> lowest version that works
+//     "capabilities": ["read_files"],             optional, the most the package's plugins may ask for in P.capabilities
+//     "plugins": ["arp.lua", "strum.lua"]         optional, every .lua file next to the manifest if left out
+// }
+//
+// Any other .lua file in the package is a module, loaded with `require("lib.chords")` (lib/chords.lua or lib/chords/init.lua).
+// Modules are looked up in the package first, then in the packages it depends on. A dependency is met by any
+// version from the required one up, as long as the major version is the same.
+//
+// Packages can be disabled from the plugin manager; that's remembered in `packages.json` in the plugin folder.
+
+use std::{cell::RefCell, collections::{HashMap, HashSet}, fmt, fs, io::{self, Read}, path::{Path, PathBuf}, rc::Rc, sync::atomic::{AtomicBool, Ordering}};
+
+use mlua::{Lua, Value};
+use serde_json::{Map, Value as JsonValue};
+
+use crate::editor::plugins::{plugin_sandbox::{PluginCapability, is_plugin_data_folder, resolve_plugin_path}, plugin_watcher::is_lua_file};
+
+pub const MANIFEST_FILE_NAME: &str = "plugin.json";
+/// Installed packages go in here, inside the plugin folder.
+pub const PACKAGES_FOLDER_NAME: &str = "packages";
+const PACKAGE_STATE_FILE_NAME: &str = "packages.json";
+/// Package folders nested deeper than this in the plugin folder aren't found.
+const MAX_PACKAGE_DEPTH: usize = 4;
+
+// set when packages got installed, enabled or disabled, until the plugin loader picks them up again
+static PACKAGES_CHANGED: AtomicBool = AtomicBool::new(false);
+
+pub fn mark_packages_changed() {
+    PACKAGES_CHANGED.store(true, Ordering::Relaxed);
+}
+
+/// True (once) if packages changed since the last call.
+pub fn take_packages_changed() -> bool {
+    PACKAGES_CHANGED.swap(false, Ordering::Relaxed)
+}
+
+/// `major.minor.patch`. Missing parts count as 0, so "1.2" is 1.2.0.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct PackageVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32
+}
+
+impl PackageVersion {
+    pub fn parse(version: &str) -> Option<Self> {
+        let mut parts = version.trim().split('.');
+        let mut next_part = |required: bool| -> Option<u32> {
+            match parts.next() {
+                Some(part) => part.parse().ok(),
+                None if required => None,
+                None => Some(0)
+            }
+        };
+
+        let version = Self { major: next_part(true)?, minor: next_part(false)?, patch: next_part(false)? };
+        if parts.next().is_some() { return None; }
+        Some(version)
+    }
+
+    /// The version this editor build has, going by [`crate::EDITOR_VERSION`].
+    pub fn editor() -> Self {
+        Self::parse(crate::EDITOR_VERSION).unwrap()
+    }
+
+    /// True if this version can stand in for `required`: same major version, and not older.
+    pub fn satisfies(&self, required: &PackageVersion) -> bool {
+        self.major == required.major && self >= required
+    }
+}
+
+impl fmt::Display for PackageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Clone)]
+pub struct PackageManifest {
+    pub id: String,
+    pub name: String,
+    pub version: PackageVersion,
+    pub min_editor_version: Option<PackageVersion>,
+    /// (package id, lowest version that works)
+    pub dependencies: Vec<(String, PackageVersion)>,
+    pub capabilities: Vec<PluginCapability>,
+    /// plugin files, relative to the package folder. Empty means every .lua file next to the manifest.
+    pub plugins: Vec<String>
+}
+
+impl PackageManifest {
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let manifest = match serde_json::from_str::<JsonValue>(json) {
+            Ok(JsonValue::Object(manifest)) => manifest,
+            Ok(_) => return Err("the manifest has to be a JSON object".into()),
+            Err(err) => return Err(format!("the manifest isn't valid JSON: {}", err))
+        };
+
+        let id = get_str(&manifest, "id")?.ok_or("the manifest needs an \"id\"")?.to_string();
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) || id.starts_with('.') {
+            return Err(format!("\"{}\" can't be a package id, only letters, digits, '-', '_' and '.' are allowed", id));
+        }
+
+        let version = get_version(&manifest, "version")?.ok_or("the manifest needs a \"version\"")?;
+        let min_editor_version = get_version(&manifest, "min_editor_version")?;
+        let name = get_str(&manifest, "name")?.unwrap_or(id.as_str()).to_string();
+
+        let mut dependencies = Vec::new();
+        match manifest.get("dependencies") {
+            None | Some(JsonValue::Null) => {},
+            Some(JsonValue::Object(deps)) => {
+                for (dep_id, dep_version) in deps.iter() {
+                    let dep_version = dep_version.as_str().and_then(PackageVersion::parse)
+                        .ok_or_else(|| format!("the version needed of dependency \"{}\" isn't a version like \"1.0.0\"", dep_id))?;
+                    dependencies.push((dep_id.clone(), dep_version));
+                }
+            },
+            Some(_) => return Err("\"dependencies\" has to be an object of package ids and versions".into())
+        }
+
+        let mut capabilities = Vec::new();
+        for name in get_str_list(&manifest, "capabilities")? {
+            match PluginCapability::from_name(&name) {
+                Some(capability) => if !capabilities.contains(&capability) { capabilities.push(capability); },
+                None => return Err(format!("unknown capability '{}' (known capabilities: read_files, write_files)", name))
+            }
+        }
+
+        let plugins = get_str_list(&manifest, "plugins")?;
+        if let Some(plugin) = plugins.iter().find(|plugin| !is_relative_lua_path(plugin)) {
+            return Err(format!("\"{}\" isn't a .lua file inside the package", plugin));
+        }
+
+        Ok(Self { id, name, version, min_editor_version, dependencies, capabilities, plugins })
+    }
+
+    /// Reads the manifest of the package in `folder`.
+    pub fn load(folder: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(folder.join(MANIFEST_FILE_NAME)).map_err(|err| format!("couldn't read {}: {}", MANIFEST_FILE_NAME, err))?;
+        Self::parse(&json)
+    }
+}
+
+fn get_str<'a>(manifest: &'a Map<String, JsonValue>, key: &str) -> Result<Option<&'a str>, String> {
+    match manifest.get(key) {
+        None | Some(JsonValue::Null) => Ok(None),
+        Some(JsonValue::String(value)) => Ok(Some(value)),
+        Some(_) => Err(format!("\"{}\" has to be a string", key))
+    }
+}
+
+fn get_version(manifest: &Map<String, JsonValue>, key: &str) -> Result<Option<PackageVersion>, String> {
+    match get_str(manifest, key)? {
+        Some(version) => PackageVersion::parse(version).map(Some).ok_or_else(|| format!("\"{}\" isn't a version like \"1.0.0\"", key)),
+        None => Ok(None)
+    }
+}
+
+fn get_str_list(manifest: &Map<String, JsonValue>, key: &str) -> Result<Vec<String>, String> {
+    match manifest.get(key) {
+        None | Some(JsonValue::Null) => Ok(Vec::new()),
+        Some(JsonValue::Array(values)) => values.iter()
+            .map(|value| value.as_str().map(String::from).ok_or_else(|| format!("\"{}\" can only contain strings", key)))
+            .collect(),
+        Some(_) => Err(format!("\"{}\" has to be a list", key))
+    }
+}
+
+fn is_relative_lua_path(path: &str) -> bool {
+    let path = Path::new(path);
+    is_lua_file(path) && path.components().all(|c| matches!(c, std::path::Component::Normal(_)))
+}
+
+#[derive(Clone)]
+pub struct PluginPackage {
+    pub manifest: PackageManifest,
+    /// the folder the manifest is in
+    pub root: PathBuf,
+    pub enabled: bool
+}
+
+impl PluginPackage {
+    /// The package's plugin files, as listed in the manifest (or found next to it).
+    pub fn get_plugin_files(&self) -> Vec<PathBuf> {
+        if !self.manifest.plugins.is_empty() {
+            return self.manifest.plugins.iter().map(|plugin| self.root.join(plugin)).collect();
+        }
+
+        let Ok(read_dir) = fs::read_dir(&self.root) else { return Vec::new(); };
+        let mut files: Vec<PathBuf> = read_dir.flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && is_lua_file(path))
+            .collect();
+        files.sort();
+        files
+    }
+}
+
+/// Why a package isn't loaded.
+#[derive(Clone, PartialEq)]
+pub enum PackageProblem {
+    Disabled,
+    /// another package with the same id wins, being newer (or found first)
+    DuplicateId { other_root: PathBuf },
+    EditorTooOld { required: PackageVersion },
+    MissingDependency { id: String, required: PackageVersion },
+    DependencyVersion { id: String, required: PackageVersion, found: PackageVersion },
+    /// the dependency is there, but isn't loaded itself
+    DependencyNotLoaded { id: String },
+    DependencyCycle { id: String }
+}
+
+impl PackageProblem {
+    pub fn describe(&self) -> String {
+        match self {
+            PackageProblem::Disabled => "Disabled".into(),
+            PackageProblem::DuplicateId { other_root } => format!("Another package with the same id is used instead ({})", other_root.display()),
+            PackageProblem::EditorTooOld { required } => format!("Needs editor version {} or newer (this is {})", required, PackageVersion::editor()),
+            PackageProblem::MissingDependency { id, required } => format!("Needs \"{}\" {} or newer, which isn't installed", id, required),
+            PackageProblem::DependencyVersion { id, required, found } => format!("Needs \"{}\" {} or newer (with the same major version), but {} is installed", id, required, found),
+            PackageProblem::DependencyNotLoaded { id } => format!("Needs \"{}\", which isn't loaded", id),
+            PackageProblem::DependencyCycle { id } => format!("Depends on itself through \"{}\"", id)
+        }
+    }
+
+    /// Disabling a package is a choice, not a conflict.
+    pub fn is_conflict(&self) -> bool {
+        *self != PackageProblem::Disabled
+    }
+}
+
+/// Every package in the plugin folder, and which of them can be loaded.
+#[derive(Default)]
+pub struct PackageIndex {
+    pub packages: Vec<PluginPackage>,
+    /// (package index, problem). Packages without any get loaded.
+    pub problems: Vec<(usize, PackageProblem)>,
+    /// (folder, error) of packages whose manifest couldn't be read
+    pub broken: Vec<(PathBuf, String)>,
+    // package indices, dependencies before what needs them
+    load_order: Vec<usize>
+}
+
+impl PackageIndex {
+    pub fn scan(plugins_path: &Path) -> Self {
+        let mut index = Self::default();
+        let disabled = load_disabled_ids(plugins_path);
+        index.scan_folder(plugins_path, 0, &disabled);
+        index.resolve();
+        index
+    }
+
+    fn scan_folder(&mut self, folder: &Path, depth: usize, disabled: &HashSet<String>) {
+        let Ok(read_dir) = fs::read_dir(folder) else { return; };
+        let mut sub_folders: Vec<PathBuf> = read_dir.flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect();
+        sub_folders.sort();
+
+        for sub_folder in sub_folders {
+            if is_plugin_data_folder(&sub_folder) { continue; }
+            if is_package_folder(&sub_folder) {
+                match PackageManifest::load(&sub_folder) {
+                    Ok(manifest) => {
+                        let enabled = !disabled.contains(&manifest.id);
+                        self.packages.push(PluginPackage { manifest, root: sub_folder, enabled });
+                    },
+                    Err(err) => self.broken.push((sub_folder, err))
+                }
+            } else if depth < MAX_PACKAGE_DEPTH {
+                self.scan_folder(&sub_folder, depth + 1, disabled);
+            }
+        }
+    }
+
+    fn resolve(&mut self) {
+        // with the same id twice, only the newest one is in the running
+        let mut active: HashMap<&str, usize> = HashMap::new();
+        let mut duplicates = Vec::new();
+        for (i, package) in self.packages.iter().enumerate() {
+            match active.get(package.manifest.id.as_str()) {
+                Some(&other) if self.packages[other].manifest.version >= package.manifest.version => duplicates.push((i, other)),
+                Some(&other) => {
+                    duplicates.push((other, i));
+                    active.insert(&package.manifest.id, i);
+                },
+                None => { active.insert(&package.manifest.id, i); }
+            }
+        }
+        for (i, other) in duplicates {
+            self.problems.push((i, PackageProblem::DuplicateId { other_root: self.packages[other].root.clone() }));
+        }
+
+        let editor_version = PackageVersion::editor();
+        for (i, package) in self.packages.iter().enumerate() {
+            if !package.enabled { self.problems.push((i, PackageProblem::Disabled)); }
+            if let Some(required) = package.manifest.min_editor_version {
+                if editor_version < required { self.problems.push((i, PackageProblem::EditorTooOld { required })); }
+            }
+        }
+
+        // dependencies get looked at depth first, so the load order comes out with them first
+        let mut state = vec![VisitState::New; self.packages.len()];
+        let active: HashMap<String, usize> = active.into_iter().map(|(id, i)| (id.to_string(), i)).collect();
+        for i in 0..self.packages.len() {
+            self.visit(i, &active, &mut state);
+        }
+    }
+
+    fn visit(&mut self, i: usize, active: &HashMap<String, usize>, state: &mut [VisitState]) -> bool {
+        match state[i] {
+            VisitState::Done(loadable) => return loadable,
+            VisitState::Visiting => return false,
+            VisitState::New => {}
+        }
+        state[i] = VisitState::Visiting;
+
+        let mut loadable = !self.problems.iter().any(|(p, _)| *p == i);
+        for (dep_id, required) in self.packages[i].manifest.dependencies.clone() {
+            let problem = match active.get(&dep_id) {
+                None => Some(PackageProblem::MissingDependency { id: dep_id, required }),
+                Some(&dep) => {
+                    let found = self.packages[dep].manifest.version;
+                    if !found.satisfies(&required) {
+                        Some(PackageProblem::DependencyVersion { id: dep_id, required, found })
+                    } else if state[dep] == VisitState::Visiting {
+                        Some(PackageProblem::DependencyCycle { id: dep_id })
+                    } else if !self.visit(dep, active, state) {
+                        Some(PackageProblem::DependencyNotLoaded { id: dep_id })
+                    } else {
+                        None
+                    }
+                }
+            };
+
+            if let Some(problem) = problem {
+                self.problems.push((i, problem));
+                loadable = false;
+            }
+        }
+
+        state[i] = VisitState::Done(loadable);
+        if loadable { self.load_order.push(i); }
+        loadable
+    }
+
+    /// The packages to load, dependencies first.
+    pub fn get_load_order(&self) -> impl Iterator<Item = &PluginPackage> {
+        self.load_order.iter().map(|&i| &self.packages[i])
+    }
+
+    pub fn is_loaded(&self, package_index: usize) -> bool {
+        self.load_order.contains(&package_index)
+    }
+
+    pub fn get_problems(&self, package_index: usize) -> impl Iterator<Item = &PackageProblem> {
+        self.problems.iter().filter(move |(i, _)| *i == package_index).map(|(_, problem)| problem)
+    }
+
+    /// The loaded package with this id.
+    pub fn find_loaded(&self, id: &str) -> Option<&PluginPackage> {
+        self.get_load_order().find(|package| package.manifest.id == id)
+    }
+
+    /// The package `path` is part of, if any.
+    pub fn find_containing(&self, path: &Path) -> Option<&PluginPackage> {
+        self.packages.iter().find(|package| path.starts_with(&package.root))
+    }
+
+    /// What a plugin of the loaded package `package` needs to find its modules.
+    pub fn get_context(&self, package: &PluginPackage) -> PackageContext {
+        let mut module_roots = vec![package.root.clone()];
+        for (dep_id, _) in package.manifest.dependencies.iter() {
+            if let Some(dep) = self.find_loaded(dep_id) { module_roots.push(dep.root.clone()); }
+        }
+
+        PackageContext {
+            id: package.manifest.id.clone(),
+            root: package.root.clone(),
+            capabilities: package.manifest.capabilities.clone(),
+            module_roots
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VisitState {
+    New,
+    Visiting,
+    Done(bool)
+}
+
+pub fn is_package_folder(folder: &Path) -> bool {
+    folder.join(MANIFEST_FILE_NAME).is_file()
+}
+
+fn load_disabled_ids(plugins_path: &Path) -> HashSet<String> {
+    let Ok(contents) = fs::read_to_string(plugins_path.join(PACKAGE_STATE_FILE_NAME)) else { return HashSet::new(); };
+    serde_json::from_str::<JsonValue>(&contents).ok()
+        .and_then(|state| state.get("disabled")?.as_array().cloned())
+        .map(|ids| ids.iter().filter_map(|id| id.as_str().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
+pub fn set_package_enabled(plugins_path: &Path, id: &str, enabled: bool) -> io::Result<()> {
+    let mut disabled = load_disabled_ids(plugins_path);
+    if enabled { disabled.remove(id); } else { disabled.insert(id.to_string()); }
+
+    let mut disabled: Vec<String> = disabled.into_iter().collect();
+    disabled.sort();
+    let state = serde_json::json!({ "disabled": disabled });
+    fs::write(plugins_path.join(PACKAGE_STATE_FILE_NAME), serde_json::to_string_pretty(&state).unwrap())?;
+
+    mark_packages_changed();
+    Ok(())
+}
+
+/// Installs the package in `source` (a zip file or a folder) into the packages folder, replacing an installed
+/// package with the same id. The zip can have the manifest at the top, or in a single folder at the top.
+pub fn install_package(plugins_path: &Path, source: &Path) -> Result<PackageManifest, String> {
+    let packages_folder = plugins_path.join(PACKAGES_FOLDER_NAME);
+    fs::create_dir_all(&packages_folder).map_err(|err| format!("couldn't create {}: {}", packages_folder.display(), err))?;
+
+    // everything goes into a temporary folder first, so a broken archive doesn't leave half a package behind
+    let staging = packages_folder.join(".installing");
+    if staging.exists() { fs::remove_dir_all(&staging).map_err(|err| err.to_string())?; }
+
+    let result = if source.is_dir() {
+        copy_folder(source, &staging).map_err(|err| format!("couldn't copy the package: {}", err))
+    } else {
+        extract_zip(source, &staging)
+    };
+
+    let manifest = result.and_then(|_| PackageManifest::load(&staging));
+    let manifest = match manifest {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(err);
+        }
+    };
+
+    let target = packages_folder.join(&manifest.id);
+    if target.exists() {
+        fs::remove_dir_all(&target).map_err(|err| format!("couldn't remove the installed version: {}", err))?;
+    }
+    fs::rename(&staging, &target).map_err(|err| format!("couldn't move the package into place: {}", err))?;
+
+    mark_packages_changed();
+    Ok(manifest)
+}
+
+fn copy_folder(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            copy_folder(&path, &to.join(entry.file_name()))?;
+        } else {
+            fs::copy(&path, to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+fn extract_zip(archive_path: &Path, to: &Path) -> Result<(), String> {
+    let file = fs::File::open(archive_path).map_err(|err| format!("couldn't open {}: {}", archive_path.display(), err))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|err| format!("not a zip file: {}", err))?;
+
+    // entries that would land outside the folder (absolute paths, "..") don't have an enclosed name
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i).map_err(|err| err.to_string())?;
+        let Some(name) = entry.enclosed_name() else {
+            return Err(format!("\"{}\" would end up outside the package", entry.name()));
+        };
+        entries.push((i, name, entry.is_dir()));
+    }
+
+    let manifest_prefix = entries.iter()
+        .filter(|(_, name, is_dir)| !is_dir && name.file_name().is_some_and(|file_name| file_name == MANIFEST_FILE_NAME))
+        .map(|(_, name, _)| name.parent().unwrap_or(Path::new("")).to_path_buf())
+        .min_by_key(|prefix| prefix.components().count())
+        .ok_or_else(|| format!("there's no {} in the archive", MANIFEST_FILE_NAME))?;
+    if manifest_prefix.components().count() > 1 {
+        return Err(format!("{} has to be at the top of the archive, or in a single folder at the top", MANIFEST_FILE_NAME));
+    }
+
+    for (i, name, is_dir) in entries {
+        let Ok(relative) = name.strip_prefix(&manifest_prefix) else { continue; };
+        if relative.as_os_str().is_empty() { continue; }
+        let out_path = to.join(relative);
+
+        if is_dir {
+            fs::create_dir_all(&out_path).map_err(|err| err.to_string())?;
+            continue;
+        }
+
+        if let Some(parent) = out_path.parent() { fs::create_dir_all(parent).map_err(|err| err.to_string())?; }
+        let mut entry = archive.by_index(i).map_err(|err| err.to_string())?;
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).map_err(|err| format!("couldn't unpack \"{}\": {}", name.display(), err))?;
+        fs::write(&out_path, contents).map_err(|err| err.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// What a plugin from a package gets to know about it.
+#[derive(Clone)]
+pub struct PackageContext {
+    pub id: String,
+    pub root: PathBuf,
+    /// from the manifest
+    pub capabilities: Vec<PluginCapability>,
+    /// where `require` looks: the package itself, then its dependencies
+    pub module_roots: Vec<PathBuf>
+}
+
+/// Sets up `require` for a plugin from a package, or takes it away from one that isn't.
+/// Each module runs once per load of the plugin; reloading the plugin picks up changed modules.
+pub fn install_require(lua: &Lua, package: Option<&PackageContext>) -> mlua::Result<()> {
+    let globals = lua.globals();
+    let Some(package) = package else {
+        globals.raw_remove("require")?;
+        return Ok(());
+    };
+
+    let module_roots = package.module_roots.clone();
+    let loaded: Rc<RefCell<HashMap<String, Value>>> = Rc::new(RefCell::new(HashMap::new()));
+    let loading: Rc<RefCell<HashSet<String>>> = Rc::new(RefCell::new(HashSet::new()));
+
+    globals.set("require", lua.create_function(move |lua, name: String| {
+        if let Some(module) = loaded.borrow().get(&name) { return Ok(module.clone()); }
+
+        if name.is_empty() || !name.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))) {
+            return Err(mlua::Error::runtime(format!("'{}' isn't a module name (like \"lib.chords\")", name)));
+        }
+        if !loading.borrow_mut().insert(name.clone()) {
+            return Err(mlua::Error::runtime(format!("module '{}' ends up requiring itself", name)));
+        }
+
+        let module_path = name.replace('.', "/");
+        let candidates = [format!("{}.lua", module_path), format!("{}/init.lua", module_path)];
+        let found = module_roots.iter()
+            .flat_map(|root| candidates.iter().map(move |candidate| (root, candidate)))
+            .find_map(|(root, candidate)| resolve_plugin_path(root, candidate).ok().filter(|path| path.is_file()).map(|path| (root, path)));
+
+        let result = match found {
+            Some((root, path)) => fs::read_to_string(&path).map_err(mlua::Error::external).and_then(|source| {
+                let chunk_name = format!("@{}", path.strip_prefix(root).unwrap_or(&path).display());
+                match lua.load(source).set_name(chunk_name).call::<Value>(name.as_str())? {
+                    Value::Nil => Ok(Value::Boolean(true)),
+                    module => Ok(module)
+                }
+            }),
+            None => Err(mlua::Error::runtime(format!("module '{}' not found in the package or its dependencies (tried {})", name, candidates.join(", "))))
+        };
+
+        loading.borrow_mut().remove(&name);
+        let module = result?;
+        loaded.borrow_mut().insert(name, module.clone());
+        Ok(module)
+    })?)?;
+
+    Ok(())
+}
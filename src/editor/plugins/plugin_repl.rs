@@ -0,0 +1,100 @@
+// plugin_repl.rs - runs Lua typed into the plugin console against the open project.
+//
+// The REPL has its own sandboxed state that lives as long as the project, so locals and functions defined
+// in one line are still there in the next. `notes` is the same object `on_apply` gets, and `andromeda` and
+// `curr_track` are set like they are for plugins.
+
+use std::{cell::RefCell, rc::Rc, sync::{Arc, Mutex, RwLock}};
+
+use mlua::{Lua, MultiValue};
+
+use crate::editor::{actions::EditorActions, editing::{lua_note_editing::LuaNoteEditing, meta_editing::MetaEditing, note_editing::NoteEditing}, midi_bar_cacher::BarCacher, playhead::Playhead, plugins::{plugin_andromeda_obj::AndromedaObj, plugin_console::PluginConsole, plugin_sandbox::{ExecutionBudget, new_sandboxed_lua, run_with_budget}}, project::project_manager::ProjectManager};
+
+const REPL_SOURCE: &str = "repl";
+
+pub struct PluginRepl {
+    lua: Lua,
+    budget: ExecutionBudget,
+    andromeda_obj: AndromedaObj,
+
+    note_editing: Arc<Mutex<NoteEditing>>,
+    meta_editing: Arc<Mutex<MetaEditing>>,
+    editor_actions: Rc<RefCell<EditorActions>>,
+}
+
+impl PluginRepl {
+    pub fn new(
+        note_editing: &Arc<Mutex<NoteEditing>>,
+        meta_editing: &Arc<Mutex<MetaEditing>>,
+        editor_actions: &Rc<RefCell<EditorActions>>,
+        project_manager: &Arc<RwLock<ProjectManager>>,
+        playhead: &Rc<RefCell<Playhead>>,
+        bar_cacher: &Arc<Mutex<BarCacher>>
+    ) -> Self {
+        let lua = new_sandboxed_lua().expect("failed to create the plugin sandbox");
+        PluginConsole::install_print(&lua, REPL_SOURCE).unwrap();
+
+        Self {
+            lua,
+            budget: ExecutionBudget::default(),
+            andromeda_obj: AndromedaObj::new(project_manager, playhead, bar_cacher),
+            note_editing: note_editing.clone(),
+            meta_editing: meta_editing.clone(),
+            editor_actions: editor_actions.clone()
+        }
+    }
+
+    /// Runs `input` and prints whatever it returns. Everything it changes through `notes` becomes one undo step.
+    pub fn eval(&mut self, input: &str, curr_track: usize) {
+        PluginConsole::log_input(REPL_SOURCE, input);
+
+        let lua = &self.lua;
+        if let Err(err) = self.andromeda_obj.clone().set_globals(lua, curr_track) {
+            PluginConsole::log_error(REPL_SOURCE, &err.to_string());
+            return;
+        }
+
+        // try it as an expression first, so typing `andromeda:get_track_count()` shows the count
+        let chunk = match lua.load(format!("return {}", input)).set_name("=repl").into_function() {
+            Ok(chunk) => chunk,
+            Err(_) => match lua.load(input).set_name("=repl").into_function() {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    PluginConsole::log_error(REPL_SOURCE, &err.to_string());
+                    return;
+                }
+            }
+        };
+
+        let mut lua_note_editing = LuaNoteEditing::new(&self.note_editing, &self.meta_editing);
+        let result = run_with_budget(lua, self.budget, || lua.scope(|scope| {
+            let notes = scope.create_userdata_ref_mut(&mut lua_note_editing)?;
+            lua.globals().set("notes", notes)?;
+
+            // turned into text here, while a returned `notes` can still be printed
+            let returned = chunk.call::<MultiValue>(());
+            lua.globals().set("notes", mlua::Nil)?;
+
+            let mut parts = Vec::new();
+            for value in returned?.iter() {
+                parts.push(value.to_string()?);
+            }
+            Ok(parts)
+        }));
+
+        match result {
+            Ok(parts) => {
+                if !parts.is_empty() {
+                    PluginConsole::log_output(REPL_SOURCE, &parts.join("\t"));
+                }
+
+                let mut editor_actions = self.editor_actions.try_borrow_mut().unwrap();
+                lua_note_editing.apply_changes(&mut editor_actions);
+            },
+            Err(err) => {
+                lua_note_editing.discard_changes();
+                PluginConsole::log_error(REPL_SOURCE, &err.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,342 @@
+// plugin_sandbox.rs - what Lua plugins are allowed to touch, and how long they're allowed to run.
+//
+// Plugins get the base library (minus anything that loads files or bytecode), table, string, math and bit.
+// io, os, debug, package and ffi are never loaded. Files are only reachable through the `files` table,
+// which a plugin has to ask for with `P.capabilities`, and which can't leave the plugin's data folder:
+// `<plugin name>.data` next to a loose plugin file, or the package's folder for plugins in a package.
+// string.rep, string.gsub and string.format refuse to make strings longer than MAX_STRING_LEN.
+
+use std::{cell::Cell, fmt, path::{Component, Path, PathBuf}, time::{Duration, Instant}};
+
+use mlua::{Error, HookTriggers, Lua, LuaOptions, StdLib, Table, Value, VmState};
+
+/// Base library globals plugins keep. Everything else in `_G` is removed.
+const ALLOWED_GLOBALS: &[&str] = &[
+    "_G", "_VERSION",
+    "assert", "error", "getfenv", "getmetatable", "ipairs", "loadstring", "next", "pairs", "pcall", "print",
+    "rawequal", "rawget", "rawset", "select", "setfenv", "setmetatable", "tonumber", "tostring", "type", "unpack", "xpcall",
+    "table", "string", "math", "bit"
+];
+
+/// Added to a loose plugin's file name (without `.lua`) to get its data folder.
+const DATA_FOLDER_SUFFIX: &str = ".data";
+
+/// How many instructions run between two budget checks.
+const HOOK_INSTRUCTION_INTERVAL: u32 = 10_000;
+
+/// The longest string the string library makes for a plugin. A single `string.rep` can ask for gigabytes,
+/// which would run the editor out of memory before the budget ever gets checked.
+const MAX_STRING_LEN: usize = 64 * 1024 * 1024;
+
+/// Something a plugin has to declare in `P.capabilities` before it's allowed to do it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PluginCapability {
+    /// `files.read`, `files.exists` and `files.list`, inside the plugin's data folder
+    ReadFiles,
+    /// `files.write`, inside the plugin's data folder
+    WriteFiles
+}
+
+impl PluginCapability {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "read_files" => Some(PluginCapability::ReadFiles),
+            "write_files" => Some(PluginCapability::WriteFiles),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PluginCapability::ReadFiles => "read_files",
+            PluginCapability::WriteFiles => "write_files"
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            PluginCapability::ReadFiles => "Can read files in its data folder",
+            PluginCapability::WriteFiles => "Can write files in its data folder"
+        }
+    }
+}
+
+/// Limits for a single run of plugin code. Going over either one stops the plugin with an error.
+#[derive(Clone, Copy)]
+pub struct ExecutionBudget {
+    pub max_instructions: u64,
+    pub max_duration: Duration
+}
+
+impl Default for ExecutionBudget {
+    fn default() -> Self {
+        Self {
+            max_instructions: 2_000_000_000,
+            max_duration: Duration::from_secs(10)
+        }
+    }
+}
+
+/// Creates a Lua state with only the safe parts of the standard library loaded.
+pub fn new_sandboxed_lua() -> Result<Lua, Error> {
+    let lua = Lua::new_with(StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::BIT | StdLib::JIT, LuaOptions::new())?;
+
+    // instruction hooks never fire inside JIT-compiled code, so an endless loop would never get stopped
+    lua.load("jit.off(); jit.flush()").exec()?;
+
+    let globals = lua.globals();
+    let mut to_remove = Vec::new();
+    for pair in globals.pairs::<Value, Value>() {
+        let (key, _) = pair?;
+        let allowed = match &key {
+            Value::String(name) => ALLOWED_GLOBALS.iter().any(|&allowed| &*name.as_bytes() == allowed.as_bytes()),
+            _ => false
+        };
+        if !allowed { to_remove.push(key); }
+    }
+    for key in to_remove { globals.raw_remove(key)?; }
+
+    // precompiled chunks skip LuaJIT's bytecode checks and can crash the whole editor
+    let string: Table = globals.get("string")?;
+    string.raw_remove("dump")?;
+    limit_string_lengths(&lua, &string)?;
+
+    // a plugin over its budget gets stopped with an error, which pcall would otherwise catch and carry on
+    for name in ["pcall", "xpcall"] {
+        let protected_call = globals.get::<mlua::Function>(name)?;
+        globals.set(name, lua.create_function(move |lua, args: mlua::MultiValue| {
+            let results = protected_call.call::<mlua::MultiValue>(args)?;
+            check_budget(lua)?;
+            Ok(results)
+        })?)?;
+    }
+
+    let loadstring = globals.get::<mlua::Function>("loadstring")?;
+    globals.set("loadstring", lua.create_function(move |_, (chunk, chunk_name): (mlua::String, Option<String>)| {
+        if chunk.as_bytes().first() == Some(&0x1B) {
+            return Err(Error::runtime("loading precompiled chunks is not allowed in plugins"));
+        }
+        loadstring.call::<mlua::MultiValue>((chunk, chunk_name))
+    })?)?;
+
+    Ok(lua)
+}
+
+fn check_string_len(len: usize) -> Result<(), Error> {
+    if len > MAX_STRING_LEN {
+        return Err(Error::runtime(format!("plugins can't make strings longer than {} bytes", MAX_STRING_LEN)));
+    }
+    Ok(())
+}
+
+/// Wraps the string functions that can make a much longer string than they're given, so they stay under [`MAX_STRING_LEN`].
+fn limit_string_lengths(lua: &Lua, string: &Table) -> Result<(), Error> {
+    let rep = string.get::<mlua::Function>("rep")?;
+    string.set("rep", lua.create_function(move |_, (s, n, sep): (mlua::String, f64, Option<mlua::String>)| {
+        let count = if n >= 1.0 { n as usize } else { 0 };
+        let sep_len = sep.as_ref().map_or(0, |sep| sep.as_bytes().len());
+        check_string_len(s.as_bytes().len().saturating_mul(count).saturating_add(sep_len.saturating_mul(count.saturating_sub(1))))?;
+        rep.call::<mlua::String>((s, n, sep))
+    })?)?;
+
+    let gsub = string.get::<mlua::Function>("gsub")?;
+    string.set("gsub", lua.create_function(move |lua, (s, pattern, repl, n): (mlua::String, Value, Value, Value)| {
+        let s_len = s.as_bytes().len();
+        let repl = match repl {
+            Value::String(text) => {
+                // every match adds at most the replacement, and every capture it refers to adds at most all of `s` over all matches
+                let (_, matches) = gsub.call::<(Value, usize)>((s.clone(), pattern.clone(), "", n.clone()))?;
+                let bytes = text.as_bytes();
+                let capture_refs = bytes.windows(2).filter(|pair| pair[0] == b'%' && pair[1].is_ascii_digit()).count();
+                check_string_len(s_len.saturating_add(matches.saturating_mul(bytes.len())).saturating_add(capture_refs.saturating_mul(s_len)))?;
+                Value::String(text.clone())
+            },
+            Value::Function(_) | Value::Table(_) => {
+                // the replacements are only known as they get made, so they're added up along the way
+                let made = Cell::new(s_len);
+                Value::Function(lua.create_function(move |_, captures: mlua::MultiValue| {
+                    let replacement = match &repl {
+                        Value::Function(function) => function.call::<Value>(captures)?,
+                        Value::Table(table) => table.get::<Value>(captures.into_iter().next().unwrap_or(Value::Nil))?,
+                        _ => Value::Nil
+                    };
+                    if let Value::String(text) = &replacement {
+                        made.set(made.get().saturating_add(text.as_bytes().len()));
+                        check_string_len(made.get())?;
+                    }
+                    Ok(replacement)
+                })?)
+            },
+            repl => repl
+        };
+
+        let (result, matches) = gsub.call::<(mlua::String, usize)>((s, pattern, repl, n))?;
+        check_string_len(result.as_bytes().len())?;
+        Ok((result, matches))
+    })?)?;
+
+    // the result can't be much longer than the arguments put together, so checking it afterwards is enough
+    let format = string.get::<mlua::Function>("format")?;
+    string.set("format", lua.create_function(move |_, args: mlua::MultiValue| {
+        let result = format.call::<mlua::String>(args)?;
+        check_string_len(result.as_bytes().len())?;
+        Ok(result)
+    })?)?;
+
+    Ok(())
+}
+
+/// The data folder of the loose plugin at `plugin_path`.
+pub fn get_plugin_data_folder(plugin_path: &Path) -> Option<PathBuf> {
+    let stem = plugin_path.file_stem()?.to_string_lossy();
+    Some(plugin_path.with_file_name(format!("{}{}", stem, DATA_FOLDER_SUFFIX)))
+}
+
+/// Plugins write whatever they like in here, so nothing inside gets loaded as a plugin.
+pub fn is_plugin_data_folder(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(DATA_FOLDER_SUFFIX))
+}
+
+/// Reads `P.capabilities` from a plugin table.
+pub fn read_capabilities(plugin_table: &Table) -> Result<Vec<PluginCapability>, Error> {
+    let Ok(names) = plugin_table.get::<Table>("capabilities") else { return Ok(Vec::new()); };
+
+    let mut capabilities = Vec::new();
+    for name in names.sequence_values::<String>() {
+        let name = name?;
+        match PluginCapability::from_name(&name) {
+            Some(capability) => if !capabilities.contains(&capability) { capabilities.push(capability); },
+            None => return Err(Error::runtime(format!("unknown capability '{}' (known capabilities: read_files, write_files)", name)))
+        }
+    }
+
+    Ok(capabilities)
+}
+
+/// Turns a path given by a plugin into one inside `root`. Absolute paths and `..` are rejected,
+/// and so is anything that only ends up outside `root` through a symlink.
+pub fn resolve_plugin_path(root: &Path, path: &str) -> Result<PathBuf, Error> {
+    let relative = Path::new(path);
+    if relative.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(Error::runtime(format!("'{}' is outside of the plugin's data folder", path)));
+    }
+
+    let root = root.canonicalize().map_err(Error::external)?;
+    let full_path = root.join(relative);
+
+    // whatever already exists along the way has to stay inside the folder
+    let existing = full_path.ancestors().find(|p| p.exists()).unwrap_or(&root);
+    let existing = existing.canonicalize().map_err(Error::external)?;
+    if !existing.starts_with(&root) {
+        return Err(Error::runtime(format!("'{}' is outside of the plugin's data folder", path)));
+    }
+
+    Ok(full_path)
+}
+
+/// Sets up the `files` table for the capabilities the plugin asked for. `root` is the plugin's data folder,
+/// which gets created if it's needed; builtin plugins don't have one, so they never get file access.
+pub fn install_file_access(lua: &Lua, root: Option<&Path>, capabilities: &[PluginCapability]) -> Result<(), Error> {
+    let globals = lua.globals();
+    let Some(root) = root else {
+        globals.raw_remove("files")?;
+        return Ok(());
+    };
+    if !capabilities.is_empty() {
+        std::fs::create_dir_all(root).map_err(Error::external)?;
+    }
+
+    let files = lua.create_table()?;
+
+    if capabilities.contains(&PluginCapability::ReadFiles) {
+        let read_root = root.to_path_buf();
+        files.set("read", lua.create_function(move |_, path: String| {
+            let full_path = resolve_plugin_path(&read_root, &path)?;
+            std::fs::read_to_string(full_path).map_err(Error::external)
+        })?)?;
+
+        let exists_root = root.to_path_buf();
+        files.set("exists", lua.create_function(move |_, path: String| {
+            Ok(resolve_plugin_path(&exists_root, &path)?.exists())
+        })?)?;
+
+        let list_root = root.to_path_buf();
+        files.set("list", lua.create_function(move |lua, _: ()| {
+            let names = lua.create_table()?;
+            for entry in std::fs::read_dir(&list_root).map_err(Error::external)? {
+                let entry = entry.map_err(Error::external)?;
+                names.push(entry.file_name().to_string_lossy().into_owned())?;
+            }
+            Ok(names)
+        })?)?;
+    }
+
+    if capabilities.contains(&PluginCapability::WriteFiles) {
+        let write_root = root.to_path_buf();
+        files.set("write", lua.create_function(move |_, (path, contents): (String, mlua::String)| {
+            let full_path = resolve_plugin_path(&write_root, &path)?;
+            std::fs::write(full_path, &*contents.as_bytes()).map_err(Error::external)
+        })?)?;
+    }
+
+    globals.set("files", files)?;
+    Ok(())
+}
+
+/// Set once a run goes over its budget, until [`run_with_budget`] returns. Then it's the error the run fails with.
+#[derive(Debug)]
+struct BudgetExceeded(String);
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// True if `error` is from a run [`run_with_budget`] stopped, instead of from the plugin itself.
+pub fn is_budget_exceeded(error: &Error) -> bool {
+    matches!(error, Error::ExternalError(err) if err.is::<BudgetExceeded>())
+}
+
+/// Fails if the current run already went over its budget.
+fn check_budget(lua: &Lua) -> Result<(), Error> {
+    match lua.app_data_ref::<BudgetExceeded>() {
+        Some(exceeded) => Err(Error::runtime(exceeded.0.clone())),
+        None => Ok(())
+    }
+}
+
+/// Runs `f` (which calls into `lua`) and stops it with an error once it goes over `budget`.
+/// Plugin code can't catch that error: the sandbox's pcall and xpcall raise it again.
+pub fn run_with_budget<R>(lua: &Lua, budget: ExecutionBudget, f: impl FnOnce() -> Result<R, Error>) -> Result<R, Error> {
+    let start = Instant::now();
+    let executed = Cell::new(0u64);
+    lua.remove_app_data::<BudgetExceeded>();
+
+    lua.set_hook(HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTION_INTERVAL), move |lua, _| {
+        check_budget(lua)?;
+        executed.set(executed.get() + HOOK_INSTRUCTION_INTERVAL as u64);
+
+        let message = if executed.get() > budget.max_instructions {
+            format!("the plugin was stopped after running more than {} instructions. It might be stuck in an endless loop.", budget.max_instructions)
+        } else if start.elapsed() > budget.max_duration {
+            format!("the plugin was stopped after running for more than {} seconds. It might be stuck in an endless loop.", budget.max_duration.as_secs())
+        } else {
+            return Ok(VmState::Continue);
+        };
+
+        lua.set_app_data(BudgetExceeded(message.clone()));
+        Err(Error::runtime(message))
+    })?;
+
+    let result = f();
+    lua.remove_hook();
+
+    // also in case the error got swallowed anyway, e.g. by a plugin callback that only logs errors
+    match lua.remove_app_data::<BudgetExceeded>() {
+        Some(exceeded) => Err(Error::external(exceeded)),
+        None => result
+    }
+}
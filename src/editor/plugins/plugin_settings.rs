@@ -0,0 +1,94 @@
+// plugin_settings.rs - remembers the dialog values each plugin last ran with, and the presets saved for it.
+//
+// Everything goes in one JSON file next to the plugins, keyed by plugin name:
+// { "Humanize": { "last": { "start_range_min": 0, ... }, "presets": { "Subtle": { ... } } } }
+
+use std::path::PathBuf;
+
+use serde_json::{Map, Value};
+
+use crate::util::debugger::Debugger;
+
+/// Field id to value.
+pub type FieldValues = Map<String, Value>;
+
+#[derive(Default)]
+pub struct PluginSettingsStore {
+    // nothing gets written without a path
+    path: Option<PathBuf>,
+    plugins: Map<String, Value>
+}
+
+impl PluginSettingsStore {
+    /// Reads the settings at `path`. A missing file just means nothing was saved yet.
+    pub fn load(path: PathBuf) -> Self {
+        let plugins = match std::fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str::<Value>(&contents) {
+                Ok(Value::Object(plugins)) => plugins,
+                _ => {
+                    Debugger::log_warning(format!("[PluginWarning] {} is not valid plugin settings, starting over", path.display()));
+                    Map::new()
+                }
+            },
+            Err(_) => Map::new()
+        };
+
+        Self { path: Some(path), plugins }
+    }
+
+    pub fn get_last_values(&self, plugin_name: &str) -> Option<&FieldValues> {
+        self.plugins.get(plugin_name)?.get("last")?.as_object()
+    }
+
+    pub fn set_last_values(&mut self, plugin_name: &str, values: FieldValues) {
+        self.plugin_entry(plugin_name).insert("last".into(), Value::Object(values));
+        self.save();
+    }
+
+    pub fn get_preset_names(&self, plugin_name: &str) -> Vec<String> {
+        self.get_presets(plugin_name)
+            .map(|presets| presets.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get_preset(&self, plugin_name: &str, preset_name: &str) -> Option<&FieldValues> {
+        self.get_presets(plugin_name)?.get(preset_name)?.as_object()
+    }
+
+    /// Saves `values` as a preset, replacing any preset with the same name.
+    pub fn save_preset(&mut self, plugin_name: &str, preset_name: &str, values: FieldValues) {
+        let entry = self.plugin_entry(plugin_name);
+        let presets = entry.entry("presets").or_insert_with(|| Value::Object(Map::new()));
+        if !presets.is_object() { *presets = Value::Object(Map::new()); }
+
+        presets.as_object_mut().unwrap().insert(preset_name.into(), Value::Object(values));
+        self.save();
+    }
+
+    pub fn delete_preset(&mut self, plugin_name: &str, preset_name: &str) {
+        let entry = self.plugin_entry(plugin_name);
+        if let Some(presets) = entry.get_mut("presets").and_then(|presets| presets.as_object_mut()) {
+            presets.remove(preset_name);
+        }
+        self.save();
+    }
+
+    fn get_presets(&self, plugin_name: &str) -> Option<&Map<String, Value>> {
+        self.plugins.get(plugin_name)?.get("presets")?.as_object()
+    }
+
+    fn plugin_entry(&mut self, plugin_name: &str) -> &mut Map<String, Value> {
+        let entry = self.plugins.entry(plugin_name).or_insert_with(|| Value::Object(Map::new()));
+        if !entry.is_object() { *entry = Value::Object(Map::new()); }
+        entry.as_object_mut().unwrap()
+    }
+
+    fn save(&self) {
+        let Some(path) = self.path.as_ref() else { return; };
+
+        let contents = serde_json::to_string_pretty(&self.plugins).unwrap();
+        if let Err(err) = std::fs::write(path, contents) {
+            Debugger::log_error(format!("[PluginError] couldn't save plugin settings to {}: {}", path.display(), err));
+        }
+    }
+}
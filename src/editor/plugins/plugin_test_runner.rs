@@ -0,0 +1,452 @@
+// plugin_test_runner.rs - runs a plugin's `on_apply` without the editor window, so plugins can be tested.
+//
+// A test is a JSON file:
+// {
+//     "plugin": "my_plugin.lua",           relative to the test file, or "builtin:arpeggiator" for a builtin plugin
+//     "ppq": 960,                          optional, 960 if left out (ignored with "midi")
+//     "midi": "fixture.mid",               the project, either a MIDI file (relative to the test file)...
+//     "tracks": [ [ { "start": 0, "length": 960, "key": 60, "velocity": 100, "channel": 0 } ] ],
+//                                          ...or the notes of each track. velocity and channel are optional (100 and 0)
+//     "key_signatures": [ { "tick": 0, "sharps_flats": 2, "minor": false } ],   optional
+//     "track": 0,                          the current track, 0 if left out
+//     "select": "all",                     "all" or a list of note ids in the current track; nothing is selected if left out
+//     "fields": { "pattern": 0 },          dialog field values, the plugin's own values for anything left out
+//     "expect": [ { "start": 0, ... } ]    the notes the current track should end up with, in any order
+// }
+//
+// `AndromedaRust test-plugin <test.json>...` runs tests from the command line. The builtin plugins are tested in the
+// tests module at the bottom, which builds the same tests in code.
+
+use std::{cell::RefCell, path::{Path, PathBuf}, rc::Rc, sync::{Arc, Mutex, RwLock}};
+
+use serde_json::Value as JsonValue;
+
+use crate::{app::{main_window::{EditorToolSettings, ToolBarSettings}, view_settings::ViewSettings}, editor::{actions::EditorActions, editing::{SharedClipboard, SharedSelectedNotes, meta_editing::MetaEditing, note_editing::NoteEditing}, midi_bar_cacher::BarCacher, navigation::PianoRollNavigation, playhead::Playhead, plugins::{get_builtin_plugin_source, plugin_andromeda_obj::AndromedaObj, plugin_dialog::PluginDialog, plugin_lua::PluginLua, plugin_settings::FieldValues}, project::project_manager::ProjectManager, util::MIDITick}, midi::{events::{meta_event::{KeySignature, MetaEvent, MetaEventType}, note::Note}, io::MIDIParseStatus, midi_track::MIDITrack}};
+
+pub enum TestPlugin {
+    File(PathBuf),
+    /// by file name, e.g. "arpeggiator"
+    Builtin(String)
+}
+
+pub enum TestProject {
+    MidiFile(PathBuf),
+    Tracks { ppq: u16, tracks: Vec<Vec<Note>> }
+}
+
+pub enum TestSelection {
+    Nothing,
+    All,
+    Ids(Vec<usize>)
+}
+
+pub struct PluginTest {
+    pub plugin: TestPlugin,
+    pub project: TestProject,
+    pub key_signatures: Vec<(MIDITick, KeySignature)>,
+    pub track: usize,
+    pub selection: TestSelection,
+    pub fields: FieldValues,
+    /// None just runs the plugin and checks that it doesn't fail
+    pub expect: Option<Vec<Note>>
+}
+
+impl PluginTest {
+    /// Reads a test from JSON. Paths in it are relative to `base_folder`.
+    pub fn parse(json: &str, base_folder: &Path) -> Result<Self, String> {
+        let test = match serde_json::from_str::<JsonValue>(json) {
+            Ok(JsonValue::Object(test)) => test,
+            Ok(_) => return Err("a plugin test has to be a JSON object".into()),
+            Err(err) => return Err(format!("not valid JSON: {}", err))
+        };
+
+        let plugin = match test.get("plugin").and_then(|plugin| plugin.as_str()) {
+            Some(plugin) => match plugin.strip_prefix("builtin:") {
+                Some(name) => TestPlugin::Builtin(name.into()),
+                None => TestPlugin::File(base_folder.join(plugin))
+            },
+            None => return Err("\"plugin\" is missing".into())
+        };
+
+        let project = match (test.get("midi").and_then(|midi| midi.as_str()), test.get("tracks")) {
+            (Some(midi), _) => TestProject::MidiFile(base_folder.join(midi)),
+            (None, Some(JsonValue::Array(tracks))) => {
+                let ppq = test.get("ppq").and_then(|ppq| ppq.as_u64()).unwrap_or(960).clamp(1, u16::MAX as u64) as u16;
+                let tracks = tracks.iter().map(notes_from_json).collect::<Result<Vec<_>, _>>()?;
+                TestProject::Tracks { ppq, tracks }
+            },
+            _ => return Err("the test needs either \"midi\" or \"tracks\"".into())
+        };
+
+        let mut key_signatures = Vec::new();
+        if let Some(JsonValue::Array(key_sigs)) = test.get("key_signatures") {
+            for key_sig in key_sigs {
+                let tick = key_sig.get("tick").and_then(|tick| tick.as_u64()).unwrap_or(0) as MIDITick;
+                let sharps_flats = key_sig.get("sharps_flats").and_then(|sf| sf.as_i64()).unwrap_or(0).clamp(-7, 7) as i8;
+                let minor = key_sig.get("minor").and_then(|minor| minor.as_bool()).unwrap_or(false);
+                key_signatures.push((tick, KeySignature::new(sharps_flats, minor)));
+            }
+        }
+
+        let selection = match test.get("select") {
+            None | Some(JsonValue::Null) => TestSelection::Nothing,
+            Some(JsonValue::String(all)) if all == "all" => TestSelection::All,
+            Some(JsonValue::Array(ids)) => TestSelection::Ids(ids.iter().filter_map(|id| id.as_u64()).map(|id| id as usize).collect()),
+            Some(_) => return Err("\"select\" has to be \"all\" or a list of note ids".into())
+        };
+
+        let fields = match test.get("fields") {
+            Some(JsonValue::Object(fields)) => fields.clone(),
+            _ => FieldValues::new()
+        };
+
+        let expect = match test.get("expect") {
+            Some(expect) => Some(notes_from_json(expect)?),
+            None => None
+        };
+
+        Ok(Self {
+            plugin,
+            project,
+            key_signatures,
+            track: test.get("track").and_then(|track| track.as_u64()).unwrap_or(0) as usize,
+            selection,
+            fields,
+            expect
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+        Self::parse(&json, path.parent().unwrap_or(Path::new(".")))
+    }
+
+    /// Runs the plugin on the fixture project and compares the current track's notes with what's expected.
+    /// Returns the notes the track ended up with.
+    pub fn run(&self) -> Result<Vec<Note>, String> {
+        let plugin = self.load_plugin()?;
+
+        let runner = match &self.project {
+            TestProject::MidiFile(path) => PluginTestRunner::from_midi_file(path)?,
+            TestProject::Tracks { ppq, tracks } => PluginTestRunner::new(*ppq, tracks.clone())
+        };
+        runner.add_key_signatures(&self.key_signatures);
+
+        if self.track >= runner.get_track_count() {
+            return Err(format!("track {} doesn't exist in the fixture (there are {} tracks)", self.track, runner.get_track_count()));
+        }
+        match &self.selection {
+            TestSelection::Nothing => {},
+            TestSelection::All => runner.select_all(self.track as u16),
+            TestSelection::Ids(ids) => runner.select(self.track as u16, ids.clone())
+        }
+
+        runner.run(&plugin, self.track, &self.fields).map_err(|err| format!("the plugin failed: {}", err))?;
+
+        let notes = runner.get_notes(self.track as u16);
+        if let Some(expect) = self.expect.as_ref() {
+            compare_notes(expect, &notes)?;
+        }
+        Ok(notes)
+    }
+
+    fn load_plugin(&self) -> Result<Rc<RefCell<PluginLua>>, String> {
+        let mut plugin = PluginLua::new();
+        let result = match &self.plugin {
+            TestPlugin::File(path) => plugin.load_plugin_from_path(path.clone()),
+            TestPlugin::Builtin(name) => {
+                let source = get_builtin_plugin_source(name).ok_or_else(|| format!("there's no builtin plugin called \"{}\"", name))?;
+                plugin.load_plugin_from_str(&source.to_string())
+            }
+        };
+        result.map_err(|err| format!("the plugin didn't load: {}", err))?;
+        Ok(Rc::new(RefCell::new(plugin)))
+    }
+}
+
+fn notes_from_json(notes: &JsonValue) -> Result<Vec<Note>, String> {
+    let JsonValue::Array(notes) = notes else { return Err("notes have to be a list".into()); };
+
+    notes.iter().map(|note| {
+        let get = |name: &str, default: Option<u64>| -> Result<u64, String> {
+            note.get(name).and_then(|value| value.as_u64()).or(default).ok_or_else(|| format!("a note is missing \"{}\"", name))
+        };
+
+        Ok(Note {
+            start: get("start", None)?.min(MIDITick::MAX as u64) as MIDITick,
+            length: get("length", None)?.min(MIDITick::MAX as u64) as MIDITick,
+            key: get("key", None)?.min(127) as u8,
+            velocity: get("velocity", Some(100))?.min(127) as u8,
+            channel: get("channel", Some(0))?.min(15) as u8
+        })
+    }).collect()
+}
+
+fn describe_note(note: &Note) -> String {
+    format!("{{ start = {}, length = {}, key = {}, velocity = {}, channel = {} }}", note.start, note.length, note.key, note.velocity, note.channel)
+}
+
+/// Order doesn't matter, notes on the same tick can end up in any order.
+pub fn compare_notes(expected: &[Note], actual: &[Note]) -> Result<(), String> {
+    let sort_key = |note: &Note| (note.start, note.key, note.channel, note.length, note.velocity);
+    let mut expected: Vec<Note> = expected.to_vec();
+    let mut actual: Vec<Note> = actual.to_vec();
+    expected.sort_by_key(sort_key);
+    actual.sort_by_key(sort_key);
+
+    let missing: Vec<String> = expected.iter().filter(|e| !actual.iter().any(|a| sort_key(a) == sort_key(e))).map(describe_note).collect();
+    let unexpected: Vec<String> = actual.iter().filter(|a| !expected.iter().any(|e| sort_key(e) == sort_key(a))).map(describe_note).collect();
+
+    if missing.is_empty() && unexpected.is_empty() && expected.len() == actual.len() {
+        return Ok(());
+    }
+
+    let mut message = format!("expected {} notes, got {}", expected.len(), actual.len());
+    if !missing.is_empty() { message += &format!("\n  missing:\n    {}", missing.join("\n    ")); }
+    if !unexpected.is_empty() { message += &format!("\n  unexpected:\n    {}", unexpected.join("\n    ")); }
+    Err(message)
+}
+
+/// A project with the editors plugins work through, but nothing to draw or play it.
+pub struct PluginTestRunner {
+    project_manager: Arc<RwLock<ProjectManager>>,
+    note_editing: Arc<Mutex<NoteEditing>>,
+    meta_editing: Arc<Mutex<MetaEditing>>,
+    editor_actions: Rc<RefCell<EditorActions>>,
+    shared_selected_notes: Arc<RwLock<SharedSelectedNotes>>,
+    playhead: Rc<RefCell<Playhead>>,
+    bar_cacher: Arc<Mutex<BarCacher>>
+}
+
+impl PluginTestRunner {
+    /// A project with a track for each list of notes, plus the usual 120 BPM and 4/4 at the start.
+    pub fn new(ppq: u16, tracks: Vec<Vec<Note>>) -> Self {
+        let mut project_manager = ProjectManager::new();
+        project_manager.new_empty_project();
+
+        let metas = project_manager.get_metas().read().unwrap().clone();
+        let tracks = tracks.into_iter().map(|mut notes| {
+            notes.sort_by_key(|note| note.start);
+            MIDITrack::new(notes, Vec::new(), Vec::new())
+        }).collect();
+        project_manager.swap_events_with_ppq(ppq, (tracks, metas));
+
+        Self::with_project(project_manager)
+    }
+
+    pub fn from_midi_file(path: &Path) -> Result<Self, String> {
+        let mut project_manager = ProjectManager::new();
+        match project_manager.import_from_midi_file(path.to_string_lossy().into_owned()) {
+            MIDIParseStatus::ParseOK => Ok(Self::with_project(project_manager)),
+            _ => Err(format!("couldn't read the MIDI file {}", path.display()))
+        }
+    }
+
+    fn with_project(project_manager: ProjectManager) -> Self {
+        let ppq = project_manager.get_ppq();
+        let project_manager = Arc::new(RwLock::new(project_manager));
+        let editor_actions = Rc::new(RefCell::new(EditorActions::new(256)));
+        let shared_selected_notes: Arc<RwLock<SharedSelectedNotes>> = Default::default();
+        let bar_cacher = Arc::new(Mutex::new(BarCacher::new(&project_manager)));
+
+        let (note_editing, meta_editing) = {
+            let project_manager = project_manager.read().unwrap();
+            let mut meta_editing = MetaEditing::new(project_manager.get_metas(), &bar_cacher, &editor_actions, project_manager.get_tempo_map());
+            meta_editing.ppq = ppq;
+            let meta_editing = Arc::new(Mutex::new(meta_editing));
+
+            let mut note_editing = NoteEditing::new(
+                project_manager.get_tracks(),
+                &Arc::new(Mutex::new(PianoRollNavigation::new())),
+                &Rc::new(RefCell::new(EditorToolSettings::default())),
+                &editor_actions,
+                &Rc::new(RefCell::new(ToolBarSettings::default())),
+                &Arc::new(RwLock::new(SharedClipboard::default())),
+                &shared_selected_notes,
+                &meta_editing,
+                &Arc::new(Mutex::new(ViewSettings::default()))
+            );
+            note_editing.ppq = ppq;
+            (note_editing, meta_editing)
+        };
+
+        Self {
+            project_manager,
+            note_editing: Arc::new(Mutex::new(note_editing)),
+            meta_editing,
+            editor_actions,
+            shared_selected_notes,
+            playhead: Rc::new(RefCell::new(Playhead::default())),
+            bar_cacher
+        }
+    }
+
+    pub fn add_key_signatures(&self, key_signatures: &[(MIDITick, KeySignature)]) {
+        if key_signatures.is_empty() { return; }
+
+        let project_manager = self.project_manager.read().unwrap();
+        let mut metas = project_manager.get_metas().write().unwrap();
+        for &(tick, key_sig) in key_signatures {
+            metas.push(MetaEvent { tick, event_type: MetaEventType::KeySignature, data: key_sig.to_meta_data() });
+        }
+        metas.sort_by_key(|meta| meta.tick);
+    }
+
+    pub fn get_track_count(&self) -> usize {
+        self.project_manager.read().unwrap().get_tracks().read().unwrap().len()
+    }
+
+    pub fn select(&self, track: u16, mut ids: Vec<usize>) {
+        ids.sort_unstable();
+        ids.dedup();
+        self.shared_selected_notes.write().unwrap().set_selected_in_track(ids, track);
+    }
+
+    pub fn select_all(&self, track: u16) {
+        let note_count = self.get_notes(track).len();
+        self.select(track, (0..note_count).collect());
+    }
+
+    /// Runs the plugin the way the Plugins menu would, with `fields` as the dialog values.
+    pub fn run(&self, plugin: &Rc<RefCell<PluginLua>>, curr_track: usize, fields: &FieldValues) -> mlua::Result<()> {
+        // no settings store, so nothing gets remembered between runs
+        let mut plugin_dialog = PluginDialog::default();
+        plugin_dialog.init(&self.editor_actions, &self.note_editing, &self.meta_editing, &self.bar_cacher);
+        let andromeda_obj = AndromedaObj::new(&self.project_manager, &self.playhead, &self.bar_cacher);
+        plugin_dialog.run_headless(plugin, curr_track, fields, andromeda_obj)
+    }
+
+    pub fn get_notes(&self, track: u16) -> Vec<Note> {
+        let project_manager = self.project_manager.read().unwrap();
+        let tracks = project_manager.get_tracks().read().unwrap();
+        tracks.get(track as usize).map(|track| track.get_notes().clone()).unwrap_or_default()
+    }
+}
+
+/// `test-plugin <test.json>...` from the command line. Returns the exit code: 0 if every test passed.
+pub fn run_cli(paths: &[String]) -> i32 {
+    if paths.is_empty() {
+        eprintln!("usage: test-plugin <test.json>...");
+        return 2;
+    }
+
+    let mut failed = 0;
+    for path in paths {
+        let result = PluginTest::load(Path::new(path)).and_then(|test| test.run());
+        match result {
+            Ok(_) => println!("ok    {}", path),
+            Err(err) => {
+                println!("FAIL  {}\n  {}", path, err);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", paths.len() - failed, failed);
+    if failed == 0 { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(start: MIDITick, length: MIDITick, key: u8) -> Note {
+        Note { start, length, key, velocity: 100, channel: 0 }
+    }
+
+    fn builtin_test(name: &str, tracks: Vec<Vec<Note>>, fields: JsonValue, expect: Vec<Note>) -> PluginTest {
+        PluginTest {
+            plugin: TestPlugin::Builtin(name.into()),
+            project: TestProject::Tracks { ppq: 960, tracks },
+            key_signatures: Vec::new(),
+            track: 0,
+            selection: TestSelection::All,
+            fields: fields.as_object().cloned().unwrap_or_default(),
+            expect: Some(expect)
+        }
+    }
+
+    fn c_major_chord(length: MIDITick) -> Vec<Note> {
+        vec![note(0, length, 60), note(0, length, 64), note(0, length, 67)]
+    }
+
+    #[test]
+    fn arpeggiator_up() {
+        let test = builtin_test("arpeggiator", vec![c_major_chord(1920)],
+            serde_json::json!({ "pattern": 0, "rate": 1, "octaves": 1, "gate": 50 }),
+            vec![note(0, 240, 60), note(480, 240, 64), note(960, 240, 67), note(1440, 240, 60)]);
+        test.run().unwrap();
+    }
+
+    #[test]
+    fn arpeggiator_up_down_doesnt_repeat_the_top() {
+        let test = builtin_test("arpeggiator", vec![c_major_chord(1920)],
+            serde_json::json!({ "pattern": 2, "rate": 1, "octaves": 1, "gate": 100 }),
+            vec![note(0, 480, 60), note(480, 480, 64), note(960, 480, 67), note(1440, 480, 64)]);
+        test.run().unwrap();
+    }
+
+    #[test]
+    fn arpeggiator_leaves_unselected_notes() {
+        let mut test = builtin_test("arpeggiator", vec![vec![note(0, 960, 60), note(0, 960, 64), note(3840, 960, 72)]],
+            serde_json::json!({ "pattern": 1, "rate": 0, "octaves": 1, "gate": 100 }),
+            vec![note(0, 960, 64), note(3840, 960, 72)]);
+        test.selection = TestSelection::Ids(vec![0, 1]);
+        test.run().unwrap();
+    }
+
+    #[test]
+    fn strummer_down() {
+        let test = builtin_test("strummer", vec![c_major_chord(960)],
+            serde_json::json!({ "direction": 0, "offset": 10, "keep_ends": true, "curve": 0 }),
+            vec![note(0, 960, 60), note(10, 950, 64), note(20, 940, 67)]);
+        test.run().unwrap();
+    }
+
+    #[test]
+    fn strummer_up_with_rising_velocity() {
+        let velocity = |mut n: Note, velocity: u8| { n.velocity = velocity; n };
+        let test = builtin_test("strummer", vec![c_major_chord(960)],
+            serde_json::json!({ "direction": 1, "offset": 10, "keep_ends": false, "curve": 1, "curve_amount": 30 }),
+            vec![velocity(note(0, 960, 67), 70), velocity(note(10, 960, 64), 85), velocity(note(20, 960, 60), 100)]);
+        test.run().unwrap();
+    }
+
+    #[test]
+    fn chord_generator_diatonic_in_c() {
+        let test = builtin_test("chord_generator", vec![vec![note(0, 960, 62)]],
+            serde_json::json!({ "quality": 0, "inversion": 0, "voicing": 0 }),
+            vec![note(0, 960, 62), note(0, 960, 65), note(0, 960, 69)]);
+        test.run().unwrap();
+    }
+
+    #[test]
+    fn chord_generator_follows_the_key_signature() {
+        // D major: F# A C#
+        let mut test = builtin_test("chord_generator", vec![vec![note(0, 960, 66)]],
+            serde_json::json!({ "quality": 0, "inversion": 0, "voicing": 0 }),
+            vec![note(0, 960, 66), note(0, 960, 69), note(0, 960, 73)]);
+        test.key_signatures = vec![(0, KeySignature::new(2, false))];
+        test.run().unwrap();
+    }
+
+    #[test]
+    fn chord_generator_first_inversion() {
+        let test = builtin_test("chord_generator", vec![vec![note(0, 960, 60)]],
+            serde_json::json!({ "quality": 2, "inversion": 1, "voicing": 0 }),
+            vec![note(0, 960, 64), note(0, 960, 67), note(0, 960, 72)]);
+        test.run().unwrap();
+    }
+
+    #[test]
+    fn parses_json_tests() {
+        let json = r#"{
+            "plugin": "builtin:flip_y",
+            "tracks": [[ { "start": 0, "length": 480, "key": 60 }, { "start": 480, "length": 480, "key": 72, "velocity": 90 } ]],
+            "select": [0, 1],
+            "expect": [ { "start": 0, "length": 480, "key": 72 }, { "start": 480, "length": 480, "key": 60, "velocity": 90 } ]
+        }"#;
+        let test = PluginTest::parse(json, Path::new(".")).unwrap();
+        test.run().unwrap();
+    }
+}
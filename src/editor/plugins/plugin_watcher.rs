@@ -0,0 +1,85 @@
+// plugin_watcher.rs - notices when plugin files are added, changed or deleted, so they can be reloaded without a restart.
+//
+// There's no file system notification crate here, so this just compares modification times every so often.
+
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+
+use crate::editor::plugins::plugin_sandbox::is_plugin_data_folder;
+
+/// How often the folder gets looked at.
+pub const PLUGIN_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// Plugin folders nested deeper than this aren't watched.
+const MAX_WATCH_DEPTH: usize = 4;
+
+pub enum PluginFileChange {
+    Added(PathBuf),
+    Modified(PathBuf),
+    Removed(PathBuf)
+}
+
+pub struct PluginWatcher {
+    folder: PathBuf,
+    files: HashMap<PathBuf, SystemTime>,
+    last_scan: Instant
+}
+
+impl PluginWatcher {
+    /// Starts watching `folder`. Files already in there count as unchanged.
+    pub fn new(folder: PathBuf) -> Self {
+        let files = Self::scan(&folder);
+        Self { folder, files, last_scan: Instant::now() }
+    }
+
+    /// Returns what changed since the last look. Does nothing until [`PLUGIN_WATCH_INTERVAL`] has passed.
+    pub fn poll(&mut self) -> Vec<PluginFileChange> {
+        if self.last_scan.elapsed() < PLUGIN_WATCH_INTERVAL { return Vec::new(); }
+        self.last_scan = Instant::now();
+
+        let files = Self::scan(&self.folder);
+        let mut changes = Vec::new();
+
+        for (path, modified) in files.iter() {
+            match self.files.get(path) {
+                None => changes.push(PluginFileChange::Added(path.clone())),
+                Some(last_modified) if last_modified != modified => changes.push(PluginFileChange::Modified(path.clone())),
+                _ => {}
+            }
+        }
+
+        for path in self.files.keys() {
+            if !files.contains_key(path) { changes.push(PluginFileChange::Removed(path.clone())); }
+        }
+
+        self.files = files;
+        changes
+    }
+
+    fn scan(folder: &Path) -> HashMap<PathBuf, SystemTime> {
+        let mut files = HashMap::new();
+        Self::scan_inner(folder, 0, &mut files);
+        files
+    }
+
+    fn scan_inner(folder: &Path, depth: usize, files: &mut HashMap<PathBuf, SystemTime>) {
+        // the folder not existing yet is fine, it just has no plugins
+        let Ok(read_dir) = fs::read_dir(folder) else { return; };
+
+        for entry in read_dir.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else { continue; };
+
+            if metadata.is_dir() {
+                if depth < MAX_WATCH_DEPTH && !is_plugin_data_folder(&path) { Self::scan_inner(&path, depth + 1, files); }
+            } else if is_lua_file(&path) {
+                if let Ok(modified) = metadata.modified() { files.insert(path, modified); }
+            }
+        }
+    }
+}
+
+pub fn is_lua_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("lua"))
+        .unwrap_or(false)
+}
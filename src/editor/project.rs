@@ -1,5 +1,9 @@
 pub mod project_data;
 pub mod project_manager;
+pub mod event_changes;
+pub mod ppq_conversion;
+pub mod time_shift;
+pub mod track_restructure;
 
 use std::{fs::File, io::{self, Write}, path::PathBuf};
 
@@ -0,0 +1,70 @@
+// event_changes.rs - what a project-wide edit did to the events it couldn't just move.
+//
+// Converting the PPQ or inserting/deleting time moves nearly every event in a way that can be reversed exactly.
+// Only the events that got merged, cut or rounded off need to be kept around to undo the edit, instead of a copy
+// of the whole project.
+
+use crate::{editor::util::MIDITick, midi::{events::{channel_event::ChannelEvent, meta_event::MetaEvent, note::Note}, midi_track::MIDITrack}};
+
+/// The events of one track that an edit did more to than move, as they were before and what they became.
+#[derive(Clone, Default)]
+pub struct TrackChanges {
+    pub old_notes: Vec<Note>,
+    pub new_notes: Vec<Note>,
+    pub old_channel_evs: Vec<ChannelEvent>,
+    pub new_channel_evs: Vec<ChannelEvent>,
+    pub old_metas: Vec<MetaEvent>,
+    pub new_metas: Vec<MetaEvent>
+}
+
+#[derive(Clone, Default)]
+pub struct ProjectChanges {
+    pub tracks: Vec<TrackChanges>,
+    /// only the metas are used
+    pub global_metas: TrackChanges
+}
+
+impl TrackChanges {
+    /// Takes the changed events back out of `track`, moves everything else back with `move_note` and `move_tick`,
+    /// and puts the old events back in.
+    pub fn undo_track(self, track: &mut MIDITrack, move_note: &impl Fn(&mut Note), move_tick: &impl Fn(MIDITick) -> MIDITick) {
+        let TrackChanges { old_notes, new_notes, old_channel_evs, new_channel_evs, old_metas, new_metas } = self;
+
+        let notes = std::mem::take(track.get_notes_mut());
+        *track.get_notes_mut() = undo_events(notes, old_notes, &new_notes, |note| note.start, move_note);
+
+        let channel_evs = std::mem::take(track.get_channel_evs_mut());
+        *track.get_channel_evs_mut() = undo_events(channel_evs, old_channel_evs, &new_channel_evs, |ev| ev.tick, |ev| ev.tick = move_tick(ev.tick));
+
+        let metas = std::mem::take(track.get_meta_events_mut());
+        *track.get_meta_events_mut() = undo_events(metas, old_metas, &new_metas, |meta| meta.tick, |meta| meta.tick = move_tick(meta.tick));
+    }
+
+    /// Same as [`TrackChanges::undo_track`], for the global metas.
+    pub fn undo_metas(self, metas: Vec<MetaEvent>, move_tick: &impl Fn(MIDITick) -> MIDITick) -> Vec<MetaEvent> {
+        undo_events(metas, self.old_metas, &self.new_metas, |meta| meta.tick, |meta| meta.tick = move_tick(meta.tick))
+    }
+}
+
+/// `events` has to be sorted by tick, and moving them back can't change their order.
+fn undo_events<T: PartialEq>(events: Vec<T>, old: Vec<T>, new: &[T], tick: impl Fn(&T) -> MIDITick, move_event: impl Fn(&mut T)) -> Vec<T> {
+    // events that are equal can't be told apart, so it doesn't matter which one gets taken out
+    let mut remove = vec![false; events.len()];
+    for event in new.iter() {
+        let first = events.partition_point(|e| tick(e) < tick(event));
+        let found = (first..events.len())
+            .take_while(|&i| tick(&events[i]) == tick(event))
+            .find(|&i| !remove[i] && events[i] == *event);
+        if let Some(i) = found { remove[i] = true; }
+    }
+
+    let mut events: Vec<T> = events.into_iter().zip(remove)
+        .filter_map(|(event, remove)| (!remove).then_some(event))
+        .collect();
+    events.iter_mut().for_each(move_event);
+
+    // the old events are only a few, so this is mostly already sorted
+    events.extend(old);
+    events.sort_by_key(|event| tick(event));
+    events
+}
@@ -0,0 +1,241 @@
+// ppq_conversion.rs - rescales every event in the project to a new resolution.
+
+use std::collections::HashMap;
+
+use crate::{editor::{project::event_changes::TrackChanges, util::MIDITick}, midi::{events::{channel_event::ChannelEvent, meta_event::{MetaEvent, MetaEventType}, note::Note}, midi_track::MIDITrack}};
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum PPQRounding {
+    Nearest,
+    Floor,
+    Ceil
+}
+
+impl Default for PPQRounding {
+    fn default() -> Self {
+        PPQRounding::Nearest
+    }
+}
+
+impl ToString for PPQRounding {
+    fn to_string(&self) -> String {
+        match self {
+            PPQRounding::Nearest => "Nearest".to_string(),
+            PPQRounding::Floor => "Round down".to_string(),
+            PPQRounding::Ceil => "Round up".to_string()
+        }
+    }
+}
+
+/// What to do with notes (and metas) that end up on top of each other after lowering the PPQ.
+#[derive(PartialEq, Clone, Copy)]
+pub enum PPQCollisionHandling {
+    KeepAll,
+    RemoveDuplicates,
+    TrimOverlaps
+}
+
+impl Default for PPQCollisionHandling {
+    fn default() -> Self {
+        PPQCollisionHandling::RemoveDuplicates
+    }
+}
+
+impl ToString for PPQCollisionHandling {
+    fn to_string(&self) -> String {
+        match self {
+            PPQCollisionHandling::KeepAll => "Keep all".to_string(),
+            PPQCollisionHandling::RemoveDuplicates => "Remove duplicates".to_string(),
+            PPQCollisionHandling::TrimOverlaps => "Remove duplicates and trim overlaps".to_string()
+        }
+    }
+}
+
+pub struct PPQConverter {
+    old_ppq: u64,
+    new_ppq: u64,
+    rounding: PPQRounding,
+    collisions: PPQCollisionHandling,
+}
+
+impl PPQConverter {
+    pub fn new(old_ppq: u16, new_ppq: u16, rounding: PPQRounding, collisions: PPQCollisionHandling) -> Self {
+        Self {
+            old_ppq: old_ppq as u64,
+            new_ppq: new_ppq as u64,
+            rounding,
+            collisions
+        }
+    }
+
+    pub fn convert_tick(&self, tick: MIDITick) -> MIDITick {
+        let scaled = tick as u64 * self.new_ppq;
+        let converted = match self.rounding {
+            PPQRounding::Nearest => (scaled + self.old_ppq / 2) / self.old_ppq,
+            PPQRounding::Floor => scaled / self.old_ppq,
+            PPQRounding::Ceil => (scaled + self.old_ppq - 1) / self.old_ppq
+        };
+
+        converted.min(MIDITick::MAX as u64) as MIDITick
+    }
+
+    /// The converter that takes ticks back to the old PPQ. Every event it turns back into the original
+    /// doesn't have to be remembered to undo the conversion.
+    pub fn reversed(&self) -> Self {
+        Self {
+            old_ppq: self.new_ppq,
+            new_ppq: self.old_ppq,
+            rounding: PPQRounding::Nearest,
+            collisions: PPQCollisionHandling::KeepAll
+        }
+    }
+
+    /// Converts everything in `track`. Returns the events the reversed converter can't turn back into the originals.
+    pub fn convert_track(&self, track: &mut MIDITrack) -> TrackChanges {
+        let mut changes = TrackChanges::default();
+        let reverse = self.reversed();
+
+        let notes = std::mem::take(track.get_notes_mut());
+        *track.get_notes_mut() = self.convert_notes(notes, &mut changes);
+
+        for ch_ev in track.get_channel_evs_mut().iter_mut() {
+            let old_tick = ch_ev.tick;
+            ch_ev.tick = self.convert_tick(old_tick);
+
+            if reverse.convert_tick(ch_ev.tick) != old_tick {
+                changes.old_channel_evs.push(ChannelEvent { tick: old_tick, ..ch_ev.clone() });
+                changes.new_channel_evs.push(ch_ev.clone());
+            }
+        }
+
+        let metas = std::mem::take(track.get_meta_events_mut());
+        *track.get_meta_events_mut() = self.convert_metas(metas, &mut changes);
+
+        changes
+    }
+
+    pub fn convert_note(&self, mut note: Note) -> Note {
+        // scale the end instead of the length, so notes that were touching stay touching
+        let new_start = self.convert_tick(note.start());
+        let new_end = self.convert_tick(note.end());
+
+        *note.start_mut() = new_start;
+        *note.length_mut() = new_end.saturating_sub(new_start).max(1);
+        note
+    }
+
+    pub fn convert_notes(&self, notes: Vec<Note>, changes: &mut TrackChanges) -> Vec<Note> {
+        // each note keeps its old index, so the ones that get merged or trimmed can be found again
+        let converted: Vec<(Note, usize)> = notes.iter().enumerate()
+            .map(|(i, &note)| (self.convert_note(note), i))
+            .collect();
+
+        // scaling never changes the order, so the notes are still sorted here
+        let converted = match self.collisions {
+            PPQCollisionHandling::KeepAll => converted,
+            PPQCollisionHandling::RemoveDuplicates => Self::remove_duplicate_notes(converted),
+            PPQCollisionHandling::TrimOverlaps => Self::trim_overlapping_notes(Self::remove_duplicate_notes(converted))
+        };
+
+        let reverse = self.reversed();
+        let mut kept = vec![false; notes.len()];
+        let result = converted.into_iter().map(|(note, i)| {
+            kept[i] = true;
+            if reverse.convert_note(note) != notes[i] {
+                changes.old_notes.push(notes[i]);
+                changes.new_notes.push(note);
+            }
+            note
+        }).collect();
+
+        changes.old_notes.extend(notes.into_iter().zip(kept).filter(|(_, kept)| !kept).map(|(note, _)| note));
+        result
+    }
+
+    pub fn convert_metas(&self, metas: Vec<MetaEvent>, changes: &mut TrackChanges) -> Vec<MetaEvent> {
+        // (converted meta, old tick)
+        let mut converted: Vec<(MetaEvent, MIDITick)> = metas.into_iter().map(|mut meta| {
+            let old_tick = meta.tick;
+            meta.tick = self.convert_tick(old_tick);
+            (meta, old_tick)
+        }).collect();
+
+        if self.collisions != PPQCollisionHandling::KeepAll {
+            // only one tempo/time sig/key sig can be in effect per tick, the last one wins
+            let mut deduped: Vec<(MetaEvent, MIDITick)> = Vec::with_capacity(converted.len());
+            for (meta, old_tick) in converted.into_iter() {
+                let is_exclusive = matches!(meta.event_type, MetaEventType::Tempo | MetaEventType::TimeSignature | MetaEventType::KeySignature);
+                if is_exclusive {
+                    let existing = deduped.iter()
+                        .rev()
+                        .take_while(|(m, _)| m.tick == meta.tick)
+                        .position(|(m, _)| m.event_type == meta.event_type);
+
+                    if let Some(rev_idx) = existing {
+                        let idx = deduped.len() - 1 - rev_idx;
+                        let (removed, removed_tick) = deduped.remove(idx);
+                        changes.old_metas.push(MetaEvent { tick: removed_tick, ..removed });
+                    }
+                }
+
+                deduped.push((meta, old_tick));
+            }
+            converted = deduped;
+        }
+
+        let reverse = self.reversed();
+        converted.into_iter().map(|(meta, old_tick)| {
+            if reverse.convert_tick(meta.tick) != old_tick {
+                changes.old_metas.push(MetaEvent { tick: old_tick, ..meta.clone() });
+                changes.new_metas.push(meta.clone());
+            }
+            meta
+        }).collect()
+    }
+
+    /// Removes notes with the same start, key and channel. The longest one is kept.
+    fn remove_duplicate_notes(notes: Vec<(Note, usize)>) -> Vec<(Note, usize)> {
+        let mut result: Vec<(Note, usize)> = Vec::with_capacity(notes.len());
+        let mut run_start_tick = None;
+        let mut run_notes: HashMap<(u8, u8), usize> = HashMap::new();
+
+        for (note, i) in notes.into_iter() {
+            if run_start_tick != Some(note.start()) {
+                run_start_tick = Some(note.start());
+                run_notes.clear();
+            }
+
+            match run_notes.get(&(note.channel(), note.key())) {
+                Some(&idx) => {
+                    if note.length() > result[idx].0.length() { result[idx] = (note, i); }
+                },
+                None => {
+                    run_notes.insert((note.channel(), note.key()), result.len());
+                    result.push((note, i));
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Cuts notes short where they run into the next note on the same key and channel.
+    /// Expects duplicates to already be removed.
+    fn trim_overlapping_notes(mut notes: Vec<(Note, usize)>) -> Vec<(Note, usize)> {
+        let mut last_note_idx: Vec<Option<usize>> = vec![None; 16 * 128];
+
+        for i in 0..notes.len() {
+            let slot = (notes[i].0.channel() as usize) * 128 + notes[i].0.key() as usize;
+            if let Some(prev) = last_note_idx[slot] {
+                let start = notes[i].0.start();
+                if notes[prev].0.end() > start {
+                    let prev_start = notes[prev].0.start();
+                    *notes[prev].0.length_mut() = (start - prev_start).max(1);
+                }
+            }
+            last_note_idx[slot] = Some(i);
+        }
+
+        notes
+    }
+}
@@ -1,6 +1,8 @@
 use std::{cell::RefCell, rc::Rc, sync::{Arc, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard}};
 
-use crate::{editor::{editing::meta_editing::MetaEditing, midi_bar_cacher::BarCacher, project::{self, ProjectWriter, project_data::{ProjectData, ProjectInfo}}, settings::editor_settings::ESGeneralSettings, tempo_map::TempoMap}, midi::{events::{channel_event::ChannelEvent, meta_event::MetaEvent, note::Note}, io::MIDIParseStatus, midi_file::MIDIFile, midi_track::MIDITrack}, util::debugger::Debugger};
+use rayon::prelude::*;
+
+use crate::{editor::{editing::meta_editing::MetaEditing, midi_bar_cacher::BarCacher, project::{self, ProjectWriter, event_changes::{ProjectChanges, TrackChanges}, ppq_conversion::{PPQCollisionHandling, PPQConverter, PPQRounding}, time_shift::TimeShift, project_data::{ProjectData, ProjectInfo}}, settings::editor_settings::ESGeneralSettings, tempo_map::TempoMap, util::MIDITick}, midi::{events::{channel_event::ChannelEvent, meta_event::MetaEvent, note::Note}, io::MIDIParseStatus, midi_file::MIDIFile, midi_track::MIDITrack}, util::debugger::Debugger};
 
 #[derive(Default)]
 pub struct ProjectManager {
@@ -22,6 +24,103 @@ impl ProjectManager {
         self.ppq_changed = true;
     }
 
+    /// Changes the PPQ and rescales every note, channel event and meta to match.
+    /// Returns the events that can't just be scaled back, so it can be undone with [`ProjectManager::revert_ppq`].
+    pub fn convert_ppq(&mut self, new_ppq: u16, rounding: PPQRounding, collisions: PPQCollisionHandling) -> ProjectChanges {
+        let converter = PPQConverter::new(self.get_ppq(), new_ppq, rounding, collisions);
+
+        let track_changes = {
+            let mut tracks = self.get_tracks().write().unwrap();
+            tracks.par_iter_mut().map(|track| converter.convert_track(track)).collect()
+        };
+
+        let mut global_metas = TrackChanges::default();
+        {
+            let mut metas = self.get_metas().write().unwrap();
+            let old_metas = std::mem::take(&mut *metas);
+            *metas = converter.convert_metas(old_metas, &mut global_metas);
+        }
+
+        self.change_ppq(new_ppq);
+        ProjectChanges { tracks: track_changes, global_metas }
+    }
+
+    /// Scales everything back to `old_ppq` and puts back the events [`ProjectManager::convert_ppq`] merged or rounded off.
+    pub fn revert_ppq(&mut self, old_ppq: u16, changes: ProjectChanges) {
+        let reverse = PPQConverter::new(self.get_ppq(), old_ppq, PPQRounding::Nearest, PPQCollisionHandling::KeepAll);
+        let move_note = |note: &mut Note| *note = reverse.convert_note(*note);
+        let move_tick = |tick: MIDITick| reverse.convert_tick(tick);
+
+        {
+            let mut tracks = self.get_tracks().write().unwrap();
+            tracks.par_iter_mut().zip(changes.tracks).for_each(|(track, changes)| changes.undo_track(track, &move_note, &move_tick));
+        }
+
+        {
+            let mut metas = self.get_metas().write().unwrap();
+            let old_metas = std::mem::take(&mut *metas);
+            *metas = changes.global_metas.undo_metas(old_metas, &move_tick);
+        }
+
+        self.change_ppq(old_ppq);
+    }
+
+    /// Swaps the project's events with `events` and sets the PPQ, without any rescaling.
+    /// Returns the events that were swapped out.
+    pub fn swap_events_with_ppq(&mut self, ppq: u16, events: (Vec<MIDITrack>, Vec<MetaEvent>)) -> (Vec<MIDITrack>, Vec<MetaEvent>) {
+        let old_events = self.swap_events(events);
+        self.change_ppq(ppq);
+        old_events
+    }
+
+    /// Inserts or deletes time in every track and the global metas.
+    /// Returns the events that were cut or removed instead of just moved, so it can be undone with [`ProjectManager::unshift_time`].
+    pub fn shift_time(&mut self, shift: TimeShift) -> ProjectChanges {
+        let track_changes = {
+            let mut tracks = self.get_tracks().write().unwrap();
+            tracks.par_iter_mut().map(|track| shift.shift_track(track)).collect()
+        };
+
+        let mut global_metas = TrackChanges::default();
+        {
+            let mut metas = self.get_metas().write().unwrap();
+            let old_metas = std::mem::take(&mut *metas);
+            *metas = shift.shift_metas(old_metas, &mut global_metas);
+        }
+
+        self.get_tempo_map_mut().rebuild_tempo_map();
+        ProjectChanges { tracks: track_changes, global_metas }
+    }
+
+    /// Moves everything back and puts back the events [`ProjectManager::shift_time`] cut or removed.
+    pub fn unshift_time(&mut self, shift: TimeShift, changes: ProjectChanges) {
+        let move_note = |note: &mut Note| note.set_start(shift.unshift_tick(note.start()));
+        let move_tick = |tick: MIDITick| shift.unshift_tick(tick);
+
+        {
+            let mut tracks = self.get_tracks().write().unwrap();
+            tracks.par_iter_mut().zip(changes.tracks).for_each(|(track, changes)| changes.undo_track(track, &move_note, &move_tick));
+        }
+
+        {
+            let mut metas = self.get_metas().write().unwrap();
+            let old_metas = std::mem::take(&mut *metas);
+            *metas = changes.global_metas.undo_metas(old_metas, &move_tick);
+        }
+
+        self.get_tempo_map_mut().rebuild_tempo_map();
+    }
+
+    /// Swaps the project's events with `events`, returning the ones that were swapped out.
+    pub fn swap_events(&mut self, events: (Vec<MIDITrack>, Vec<MetaEvent>)) -> (Vec<MIDITrack>, Vec<MetaEvent>) {
+        let (tracks, metas) = events;
+        let old_tracks = std::mem::replace(&mut *self.get_tracks().write().unwrap(), tracks);
+        let old_metas = std::mem::replace(&mut *self.get_metas().write().unwrap(), metas);
+
+        self.get_tempo_map_mut().rebuild_tempo_map();
+        (old_tracks, old_metas)
+    }
+
     pub fn get_ppq(&self) -> u16 {
         self.project_data.ppq
     }
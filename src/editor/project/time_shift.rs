@@ -0,0 +1,220 @@
+// time_shift.rs - inserts or removes a span of time across the whole project.
+
+use std::collections::HashSet;
+
+use crate::{editor::{project::event_changes::TrackChanges, util::MIDITick}, midi::{events::{channel_event::{ChannelEvent, ChannelEventType}, meta_event::{MetaEvent, MetaEventType}, note::Note}, midi_track::MIDITrack}};
+
+/// What happens to notes that are still playing at the point where time gets inserted.
+#[derive(PartialEq, Clone, Copy)]
+pub enum CrossingNoteHandling {
+    /// cut the note in two, the second half moves with everything else
+    Split,
+    /// keep the note in one piece, longer by the inserted amount
+    Extend,
+    /// cut the note off at the insertion point
+    Trim
+}
+
+impl Default for CrossingNoteHandling {
+    fn default() -> Self {
+        CrossingNoteHandling::Split
+    }
+}
+
+impl ToString for CrossingNoteHandling {
+    fn to_string(&self) -> String {
+        match self {
+            CrossingNoteHandling::Split => "Split".to_string(),
+            CrossingNoteHandling::Extend => "Extend".to_string(),
+            CrossingNoteHandling::Trim => "Trim".to_string()
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum TimeShift {
+    /// (tick, amount)
+    Insert(MIDITick, MIDITick, CrossingNoteHandling),
+    /// (start tick, end tick), the end is exclusive
+    Delete(MIDITick, MIDITick)
+}
+
+impl TimeShift {
+    /// Shifts everything in `track`. Returns the events that were cut, split or removed instead of just moved.
+    pub fn shift_track(&self, track: &mut MIDITrack) -> TrackChanges {
+        let mut changes = TrackChanges::default();
+
+        let notes = std::mem::take(track.get_notes_mut());
+        *track.get_notes_mut() = self.shift_notes(notes, &mut changes);
+
+        let channel_evs = std::mem::take(track.get_channel_evs_mut());
+        *track.get_channel_evs_mut() = self.shift_channel_events(channel_evs, &mut changes);
+
+        let metas = std::mem::take(track.get_meta_events_mut());
+        *track.get_meta_events_mut() = self.shift_metas(metas, &mut changes);
+
+        changes
+    }
+
+    pub fn shift_notes(&self, notes: Vec<Note>, changes: &mut TrackChanges) -> Vec<Note> {
+        let mut result = Vec::with_capacity(notes.len());
+
+        match *self {
+            TimeShift::Insert(at, amount, crossing) => {
+                for mut note in notes.into_iter() {
+                    let (start, end) = (note.start(), note.end());
+
+                    if start >= at {
+                        *note.start_mut() = start.saturating_add(amount);
+                    } else if end > at {
+                        changes.old_notes.push(note);
+                        match crossing {
+                            CrossingNoteHandling::Split => {
+                                *note.length_mut() = at - start;
+                                let second_half = Note { start: at.saturating_add(amount), length: end - at, ..note };
+                                changes.new_notes.push(second_half);
+                                result.push(second_half);
+                            },
+                            CrossingNoteHandling::Extend => *note.length_mut() = note.length().saturating_add(amount),
+                            CrossingNoteHandling::Trim => *note.length_mut() = at - start
+                        }
+                        changes.new_notes.push(note);
+                    }
+
+                    result.push(note);
+                }
+            },
+            TimeShift::Delete(range_start, range_end) => {
+                let removed = range_end - range_start;
+
+                for mut note in notes.into_iter() {
+                    let (start, end) = (note.start(), note.end());
+
+                    if start >= range_end {
+                        *note.start_mut() = start - removed;
+                        result.push(note);
+                        continue;
+                    }
+                    // zero length notes right at range_start would get moved by unshift_tick, so they count as cut
+                    if start < range_start && end <= range_start {
+                        result.push(note);
+                        continue;
+                    }
+
+                    // whatever part of the note is inside the range gets cut out
+                    changes.old_notes.push(note);
+                    let new_start = start.min(range_start);
+                    let new_end = if end >= range_end { end - removed } else { end.min(range_start) };
+                    if new_end <= new_start { continue; }
+
+                    *note.start_mut() = new_start;
+                    *note.length_mut() = new_end - new_start;
+                    changes.new_notes.push(note);
+                    result.push(note);
+                }
+            }
+        }
+
+        // split halves and notes pulled back to the range start can be out of order
+        result.sort_by_key(|n| n.start());
+        result
+    }
+
+    pub fn shift_channel_events(&self, channel_evs: Vec<ChannelEvent>, changes: &mut TrackChanges) -> Vec<ChannelEvent> {
+        let mut result = match *self {
+            TimeShift::Insert(at, amount, _) => {
+                channel_evs.into_iter().map(|mut ev| {
+                    if ev.tick >= at { ev.tick = ev.tick.saturating_add(amount); }
+                    ev
+                }).collect::<Vec<_>>()
+            },
+            TimeShift::Delete(range_start, range_end) => {
+                // keep the last controller/program/pitch bend value from the deleted range so the state after it doesn't change
+                let mut kept_states = HashSet::new();
+                let mut keep = vec![false; channel_evs.len()];
+                for (i, ev) in channel_evs.iter().enumerate().rev() {
+                    if ev.tick < range_start || ev.tick >= range_end { continue; }
+                    if let Some(state) = channel_event_state(ev) {
+                        if kept_states.insert(state) { keep[i] = true; }
+                    }
+                }
+
+                channel_evs.into_iter().zip(keep).filter_map(|(mut ev, keep)| {
+                    if ev.tick >= range_end { ev.tick -= range_end - range_start; }
+                    else if ev.tick >= range_start {
+                        changes.old_channel_evs.push(ev.clone());
+                        if !keep { return None; }
+                        ev.tick = range_start;
+                        changes.new_channel_evs.push(ev.clone());
+                    }
+                    Some(ev)
+                }).collect::<Vec<_>>()
+            }
+        };
+
+        result.sort_by_key(|ev| ev.tick);
+        result
+    }
+
+    pub fn shift_metas(&self, metas: Vec<MetaEvent>, changes: &mut TrackChanges) -> Vec<MetaEvent> {
+        let mut result = match *self {
+            TimeShift::Insert(at, amount, _) => {
+                metas.into_iter().map(|mut meta| {
+                    if meta.tick >= at { meta.tick = meta.tick.saturating_add(amount); }
+                    meta
+                }).collect::<Vec<_>>()
+            },
+            TimeShift::Delete(range_start, range_end) => {
+                // same idea as with channel events: the last tempo/time sig/key sig in the range is kept at the range start,
+                // unless there's already a new one right where the range ends
+                let mut kept_types = HashSet::new();
+                for meta in metas.iter().filter(|m| m.tick == range_end && is_state_meta(m.event_type)) {
+                    kept_types.insert(meta.event_type as u8);
+                }
+
+                let mut keep = vec![false; metas.len()];
+                for (i, meta) in metas.iter().enumerate().rev() {
+                    if meta.tick < range_start || meta.tick >= range_end || !is_state_meta(meta.event_type) { continue; }
+                    if kept_types.insert(meta.event_type as u8) { keep[i] = true; }
+                }
+
+                metas.into_iter().zip(keep).filter_map(|(mut meta, keep)| {
+                    if meta.tick >= range_end { meta.tick -= range_end - range_start; }
+                    else if meta.tick >= range_start {
+                        changes.old_metas.push(meta.clone());
+                        if !keep { return None; }
+                        meta.tick = range_start;
+                        changes.new_metas.push(meta.clone());
+                    }
+                    Some(meta)
+                }).collect::<Vec<_>>()
+            }
+        };
+
+        result.sort_by_key(|m| m.tick);
+        result
+    }
+
+    /// Moves a tick that was only moved by the shift back to where it was.
+    pub fn unshift_tick(&self, tick: MIDITick) -> MIDITick {
+        match *self {
+            TimeShift::Insert(at, amount, _) => if tick >= at { tick.saturating_sub(amount) } else { tick },
+            TimeShift::Delete(range_start, range_end) => if tick >= range_start { tick + (range_end - range_start) } else { tick }
+        }
+    }
+}
+
+fn is_state_meta(event_type: MetaEventType) -> bool {
+    matches!(event_type, MetaEventType::Tempo | MetaEventType::TimeSignature | MetaEventType::KeySignature | MetaEventType::EndOfTrack)
+}
+
+/// Identifies which piece of channel state an event sets, if any. Events with the same state overwrite each other.
+fn channel_event_state(ev: &ChannelEvent) -> Option<(u8, u8, u8)> {
+    match ev.event_type {
+        ChannelEventType::Controller(controller, _) => Some((ev.channel, 0, controller)),
+        ChannelEventType::ProgramChange(_) => Some((ev.channel, 1, 0)),
+        ChannelEventType::PitchBend(_, _) => Some((ev.channel, 2, 0)),
+        ChannelEventType::ChannelAftertouch(_) => Some((ev.channel, 3, 0)),
+        _ => None
+    }
+}
@@ -0,0 +1,161 @@
+// track_restructure.rs - merging, splitting and duplicating tracks.
+
+use crate::{editor::{editing::note_editing::note_sequence_funcs::merge_notes, util::{MIDIKey, MIDITick}}, midi::{events::{meta_event::MetaEventType, note::Note}, midi_track::MIDITrack}};
+
+/// How a track gets split into multiple tracks.
+#[derive(Clone, PartialEq)]
+pub enum TrackSplitMode {
+    /// split points (ascending), a note goes into the layer of the last split point at or below its key
+    Key(Vec<MIDIKey>),
+    /// split points (ascending), same as with keys
+    Velocity(Vec<u8>),
+    /// one layer per channel
+    Channel,
+    /// overlapping notes get pushed into separate layers, up to this many layers
+    Voices(u16)
+}
+
+impl ToString for TrackSplitMode {
+    fn to_string(&self) -> String {
+        match self {
+            TrackSplitMode::Key(_) => "Key range".to_string(),
+            TrackSplitMode::Velocity(_) => "Velocity".to_string(),
+            TrackSplitMode::Channel => "Channel".to_string(),
+            TrackSplitMode::Voices(_) => "Overlapping voices".to_string()
+        }
+    }
+}
+
+/// Merges `tracks` into one track. `channel_remap[i]` moves every note and channel event of the i-th track to that channel.
+pub fn merge_tracks(tracks: Vec<MIDITrack>, channel_remap: &[Option<u8>]) -> MIDITrack {
+    let mut merged = MIDITrack::new_empty();
+    merged.muted = !tracks.is_empty() && tracks.iter().all(|t| t.muted);
+
+    for (i, track) in tracks.into_iter().enumerate() {
+        let MIDITrack { muted: _, mut channel_events, meta_events, mut notes } = track;
+
+        if let Some(channel) = channel_remap.get(i).copied().flatten() {
+            for note in notes.iter_mut() { *note.channel_mut() = channel; }
+            for ev in channel_events.iter_mut() { ev.channel = channel; }
+        }
+
+        merged.notes = merge_notes(std::mem::take(&mut merged.notes), notes);
+        merged.channel_events = merge_by_tick(std::mem::take(&mut merged.channel_events), channel_events, |ev| ev.tick);
+        merged.meta_events = merge_by_tick(std::mem::take(&mut merged.meta_events), meta_events, |m| m.tick);
+    }
+
+    // every source track had its own end of track, only the last one means anything now
+    if let Some(last_eot) = merged.meta_events.iter().rposition(|m| m.event_type == MetaEventType::EndOfTrack) {
+        let mut i = 0;
+        merged.meta_events.retain(|m| {
+            let keep = m.event_type != MetaEventType::EndOfTrack || i == last_eot;
+            i += 1;
+            keep
+        });
+    }
+
+    merged
+}
+
+/// Splits `track` into layers. The first layer keeps the track's channel events, metas and mute state,
+/// the other layers only get notes. Empty layers (other than the first) are left out.
+pub fn split_track(track: MIDITrack, mode: &TrackSplitMode) -> Vec<MIDITrack> {
+    let MIDITrack { muted, channel_events, meta_events, notes } = track;
+
+    let layer_count = match mode {
+        TrackSplitMode::Key(points) => points.len() + 1,
+        TrackSplitMode::Velocity(points) => points.len() + 1,
+        TrackSplitMode::Channel => 16,
+        TrackSplitMode::Voices(max_layers) => (*max_layers).max(1) as usize
+    };
+
+    let mut layers: Vec<Vec<Note>> = vec![Vec::new(); layer_count];
+
+    match mode {
+        TrackSplitMode::Key(points) => {
+            for note in notes { layers[points.partition_point(|&p| p <= note.key())].push(note); }
+        },
+        TrackSplitMode::Velocity(points) => {
+            for note in notes { layers[points.partition_point(|&p| p <= note.velocity())].push(note); }
+        },
+        TrackSplitMode::Channel => {
+            for note in notes { layers[note.channel() as usize].push(note); }
+        },
+        TrackSplitMode::Voices(_) => {
+            // a note goes into the first layer that's free by the time it starts.
+            // once every layer is taken, the rest piles into the last one
+            let mut layer_ends: Vec<MIDITick> = vec![0; layer_count];
+            for note in notes {
+                let layer = layer_ends.iter().position(|&end| end <= note.start()).unwrap_or(layer_count - 1);
+                layer_ends[layer] = layer_ends[layer].max(note.end());
+                layers[layer].push(note);
+            }
+        }
+    }
+
+    let mut split: Vec<MIDITrack> = Vec::with_capacity(layer_count);
+    split.push(MIDITrack { muted, channel_events, meta_events, notes: Vec::new() });
+
+    for (i, layer) in layers.into_iter().enumerate() {
+        if i == 0 { split[0].notes = layer; continue; }
+        if layer.is_empty() { continue; }
+
+        let mut new_track = MIDITrack::new(layer, Vec::new(), Vec::new());
+        new_track.muted = muted;
+        split.push(new_track);
+    }
+
+    // if the first layer came out empty, let the next one take its place
+    if split.len() > 1 && split[0].notes.is_empty() {
+        let notes = std::mem::take(&mut split[1].notes);
+        split[0].notes = notes;
+        split.remove(1);
+    }
+
+    split
+}
+
+/// Copies `track` with its channels moved up by `channel_offset` (wrapping around at 16) and its notes transposed by `transpose`.
+/// Notes that would be transposed out of the key range are left out.
+pub fn duplicate_track(track: &MIDITrack, channel_offset: i16, transpose: i16) -> MIDITrack {
+    let offset_channel = |channel: u8| (channel as i32 + channel_offset as i32).rem_euclid(16) as u8;
+
+    let notes = track.get_notes().iter().filter_map(|note| {
+        let key = (note.key() as i16).checked_add(transpose)?;
+        if !(0..=127).contains(&key) { return None; }
+        Some(Note { key: key as u8, channel: offset_channel(note.channel()), ..*note })
+    }).collect();
+
+    let channel_events = track.get_channel_evs().iter().map(|ev| {
+        let mut ev = ev.clone();
+        ev.channel = offset_channel(ev.channel);
+        ev
+    }).collect();
+
+    let mut duplicate = MIDITrack::new(notes, channel_events, track.get_meta_events().clone());
+    duplicate.muted = track.muted;
+    duplicate
+}
+
+/// Merges two event lists that are sorted by tick. Events from `a` come first on equal ticks.
+fn merge_by_tick<T>(a: Vec<T>, b: Vec<T>, tick: impl Fn(&T) -> MIDITick) -> Vec<T> {
+    if a.is_empty() { return b; }
+    if b.is_empty() { return a; }
+
+    let mut merged = Vec::with_capacity(a.len() + b.len());
+    let mut a_iter = a.into_iter().peekable();
+    let mut b_iter = b.into_iter().peekable();
+
+    loop {
+        let take_a = match (a_iter.peek(), b_iter.peek()) {
+            (Some(ev_a), Some(ev_b)) => tick(ev_a) <= tick(ev_b),
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => break
+        };
+
+        merged.push(if take_a { a_iter.next().unwrap() } else { b_iter.next().unwrap() });
+    }
+
+    merged
+}
@@ -0,0 +1,145 @@
+// scales.rs - the scales derived from key signatures.
+
+use crate::{editor::util::MIDITick, midi::events::meta_event::{KeySignature, MetaEvent, MetaEventType}};
+
+/// Which scale gets built on top of the key signature.
+/// [`ScaleMode::KeySignature`] uses the notes of the signature itself, the others are built from its tonic.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScaleMode {
+    KeySignature,
+    Major,
+    NaturalMinor,
+    HarmonicMinor,
+    MelodicMinor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+}
+
+impl Default for ScaleMode {
+    fn default() -> Self {
+        ScaleMode::KeySignature
+    }
+}
+
+impl ToString for ScaleMode {
+    fn to_string(&self) -> String {
+        match self {
+            ScaleMode::KeySignature => "From key signature",
+            ScaleMode::Major => "Major",
+            ScaleMode::NaturalMinor => "Natural minor",
+            ScaleMode::HarmonicMinor => "Harmonic minor",
+            ScaleMode::MelodicMinor => "Melodic minor",
+            ScaleMode::Dorian => "Dorian",
+            ScaleMode::Phrygian => "Phrygian",
+            ScaleMode::Lydian => "Lydian",
+            ScaleMode::Mixolydian => "Mixolydian",
+            ScaleMode::Locrian => "Locrian",
+            ScaleMode::MajorPentatonic => "Major pentatonic",
+            ScaleMode::MinorPentatonic => "Minor pentatonic",
+        }.to_string()
+    }
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 12] = [
+        ScaleMode::KeySignature,
+        ScaleMode::Major,
+        ScaleMode::NaturalMinor,
+        ScaleMode::HarmonicMinor,
+        ScaleMode::MelodicMinor,
+        ScaleMode::Dorian,
+        ScaleMode::Phrygian,
+        ScaleMode::Lydian,
+        ScaleMode::Mixolydian,
+        ScaleMode::Locrian,
+        ScaleMode::MajorPentatonic,
+        ScaleMode::MinorPentatonic,
+    ];
+
+    fn intervals(&self) -> &'static [u8] {
+        match self {
+            ScaleMode::KeySignature | ScaleMode::Major => &[0, 2, 4, 5, 7, 9, 11],
+            ScaleMode::NaturalMinor => &[0, 2, 3, 5, 7, 8, 10],
+            ScaleMode::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            ScaleMode::MelodicMinor => &[0, 2, 3, 5, 7, 9, 11],
+            ScaleMode::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            ScaleMode::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            ScaleMode::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            ScaleMode::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            ScaleMode::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            ScaleMode::MajorPentatonic => &[0, 2, 4, 7, 9],
+            ScaleMode::MinorPentatonic => &[0, 3, 5, 7, 10],
+        }
+    }
+
+    /// Returns a 12-bit mask where bit `n` is set if pitch class `n` (0 = C) is in the scale.
+    pub fn scale_mask(&self, key_sig: &KeySignature) -> u16 {
+        // a key signature's notes are always those of its relative major
+        let root = match self {
+            ScaleMode::KeySignature => key_sig.major_tonic(),
+            _ => key_sig.tonic()
+        };
+
+        self.intervals().iter()
+            .fold(0u16, |mask, &interval| mask | (1 << ((root + interval) % 12)))
+    }
+}
+
+#[inline(always)]
+pub fn is_key_in_scale(key: u8, scale_mask: u16) -> bool {
+    scale_mask & (1 << (key % 12)) != 0
+}
+
+/// Moves the key to the closest key in the scale. Ties go down.
+pub fn snap_key_to_scale(key: u8, scale_mask: u16) -> u8 {
+    if scale_mask == 0 || is_key_in_scale(key, scale_mask) { return key; }
+
+    for dist in 1..12i16 {
+        let below = key as i16 - dist;
+        if below >= 0 && is_key_in_scale(below as u8, scale_mask) { return below as u8; }
+
+        let above = key as i16 + dist;
+        if above <= 127 && is_key_in_scale(above as u8, scale_mask) { return above as u8; }
+    }
+
+    key
+}
+
+/// The key signature changes of a project, for looking up which scale is in effect at a tick.
+pub struct ScaleMap {
+    key_sigs: Vec<(MIDITick, KeySignature)>,
+    pub mode: ScaleMode,
+}
+
+impl ScaleMap {
+    pub fn from_metas(metas: &[MetaEvent], mode: ScaleMode) -> Self {
+        let key_sigs = metas.iter()
+            .filter(|m| m.event_type == MetaEventType::KeySignature)
+            .map(|m| (m.tick, KeySignature::from_meta_data(&m.data)))
+            .collect();
+
+        Self { key_sigs, mode }
+    }
+
+    /// Returns the key signature at `tick`. C major is assumed before the first key signature.
+    pub fn key_signature_at(&self, tick: MIDITick) -> KeySignature {
+        let idx = self.key_sigs.partition_point(|(t, _)| *t <= tick);
+        match idx {
+            0 => KeySignature::default(),
+            _ => self.key_sigs[idx - 1].1
+        }
+    }
+
+    pub fn scale_mask_at(&self, tick: MIDITick) -> u16 {
+        self.mode.scale_mask(&self.key_signature_at(tick))
+    }
+
+    pub fn snap_key(&self, key: u8, tick: MIDITick) -> u8 {
+        snap_key_to_scale(key, self.scale_mask_at(tick))
+    }
+}
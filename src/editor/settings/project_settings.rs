@@ -1,30 +1,70 @@
 use eframe::egui::{self, RichText};
 
-use crate::{app::ui::dialog::{Dialog, DialogAction, DialogActionButtons, flags::{DIALOG_NO_COLLAPSABLE, DIALOG_NO_RESIZABLE}, names::DIALOG_NAME_PROJECT_SETTINGS}, editor::{midi_bar_cacher::BarCacher, project::{project_data::ProjectData, project_manager::ProjectManager}}};
+use crate::{app::ui::dialog::{Dialog, DialogAction, DialogActionButtons, flags::{DIALOG_NO_COLLAPSABLE, DIALOG_NO_RESIZABLE}, names::DIALOG_NAME_PROJECT_SETTINGS}, editor::{actions::{EditorAction, EditorActions}, editing::SharedSelectedNotes, midi_bar_cacher::BarCacher, project::{ppq_conversion::{PPQCollisionHandling, PPQRounding}, project_data::ProjectData, project_manager::ProjectManager}}, util::debugger::Debugger};
 use core::f32;
-use std::sync::{Arc, RwLock, Mutex};
+use std::{cell::RefCell, rc::Rc, sync::{Arc, RwLock, Mutex}};
 
 pub struct ProjectSettings {
     pub project_manager: Arc<RwLock<ProjectManager>>,
+    editor_actions: Rc<RefCell<EditorActions>>,
+    shared_selected_notes: Arc<RwLock<SharedSelectedNotes>>,
     is_showing: bool,
+
+    target_ppq: u16,
+    ppq_rounding: PPQRounding,
+    ppq_collisions: PPQCollisionHandling,
 }
 
 impl Default for ProjectSettings {
     fn default() -> Self {
         Self {
             project_manager: Default::default(),
-            is_showing: false
+            editor_actions: Default::default(),
+            shared_selected_notes: Default::default(),
+            is_showing: false,
+
+            target_ppq: 960,
+            ppq_rounding: Default::default(),
+            ppq_collisions: Default::default(),
         }
     }
 }
 
 impl ProjectSettings {
-    pub fn new(project_data: &Arc<RwLock<ProjectManager>>) -> Self {
+    pub fn new(project_data: &Arc<RwLock<ProjectManager>>, editor_actions: &Rc<RefCell<EditorActions>>, shared_selected_notes: &Arc<RwLock<SharedSelectedNotes>>) -> Self {
+        let target_ppq = project_data.read().unwrap().get_ppq();
+
         Self { 
             project_manager: project_data.clone(),
-            is_showing: false
+            editor_actions: editor_actions.clone(),
+            shared_selected_notes: shared_selected_notes.clone(),
+            is_showing: false,
+
+            target_ppq,
+            ppq_rounding: Default::default(),
+            ppq_collisions: Default::default(),
         }
     }
+
+    fn convert_ppq(&mut self) {
+        let (old_ppq, changes) = {
+            let mut project_manager = self.project_manager.write().unwrap();
+            let old_ppq = project_manager.get_ppq();
+            if old_ppq == self.target_ppq { return; }
+
+            (old_ppq, project_manager.convert_ppq(self.target_ppq, self.ppq_rounding, self.ppq_collisions))
+        };
+
+        {
+            let mut shared_selected = self.shared_selected_notes.write().unwrap();
+            shared_selected.clear_selected();
+        }
+
+        let mut editor_actions = self.editor_actions.borrow_mut();
+        editor_actions.register_action(EditorAction::ChangePPQ(old_ppq, self.target_ppq, self.ppq_rounding, self.ppq_collisions, Some(changes)));
+
+        Debugger::log(format!("Converted PPQ from {} to {}", old_ppq, self.target_ppq));
+    }
 }
 
 impl Dialog for ProjectSettings {
@@ -50,28 +90,50 @@ impl Dialog for ProjectSettings {
             });
         }
 
+        let curr_ppq = project_manager.get_ppq();
+        drop(project_manager);
+
         ui.separator();
         ui.horizontal(|ui| {
-            ui.label("PPQ");
+            ui.label(format!("PPQ: {}", curr_ppq));
+            ui.label("Convert to");
 
-            // let mut project_manager = self.project_manager.write().unwrap();
-            let ppq = project_manager.get_ppq();
-            egui::ComboBox::from_label("")
-                .selected_text(format!("{}", ppq))
+            egui::ComboBox::from_id_salt("target_ppq")
+                .selected_text(format!("{}", self.target_ppq))
                 .show_ui(ui, |ui| {
                     let ppq_values = [96, 120, 192, 240, 384, 480, 768, 960, 1920, 3840];
-                    let old_value = ppq;
-                    let mut new_value = ppq;
                     for ppq in ppq_values {
-                        ui.selectable_value(&mut new_value, ppq, format!("{}", ppq));
+                        ui.selectable_value(&mut self.target_ppq, ppq, format!("{}", ppq));
                     }
-                    
-                    if new_value != old_value {
-                        project_manager.change_ppq(new_value);
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Rounding");
+            egui::ComboBox::from_id_salt("ppq_rounding")
+                .selected_text(self.ppq_rounding.to_string())
+                .show_ui(ui, |ui| {
+                    for rounding in [PPQRounding::Nearest, PPQRounding::Floor, PPQRounding::Ceil] {
+                        ui.selectable_value(&mut self.ppq_rounding, rounding, rounding.to_string());
                     }
                 });
         });
 
+        ui.horizontal(|ui| {
+            ui.label("Overlapping events");
+            egui::ComboBox::from_id_salt("ppq_collisions")
+                .selected_text(self.ppq_collisions.to_string())
+                .show_ui(ui, |ui| {
+                    for collisions in [PPQCollisionHandling::KeepAll, PPQCollisionHandling::RemoveDuplicates, PPQCollisionHandling::TrimOverlaps] {
+                        ui.selectable_value(&mut self.ppq_collisions, collisions, collisions.to_string());
+                    }
+                });
+        });
+
+        if ui.add_enabled(self.target_ppq != curr_ppq, egui::Button::new("Convert")).clicked() {
+            self.convert_ppq();
+        }
+
         None
     }
 
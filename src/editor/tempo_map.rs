@@ -5,9 +5,20 @@ use crate::editor::{
     util::MIDITick
 };
 
+const FALLBACK_TEMPO: f64 = 120.0;
+
+#[derive(Clone, Copy)]
+struct TempoSegment {
+    tick: MIDITick,
+    tempo: f64,
+    // seconds elapsed before this segment, as if ppq was 1. divide by the ppq to get actual seconds.
+    // keeping it ppq-independent means the map doesn't need rebuilding when the ppq changes
+    start_secs_ppq: f64,
+}
+
 pub struct TempoMap {
     pub meta_events: Arc<RwLock<Vec<MetaEvent>>>,
-    tempo_map: Vec<(MIDITick, f32)>
+    tempo_map: Vec<TempoSegment>
 }
 
 impl Default for TempoMap {
@@ -22,59 +33,53 @@ impl Default for TempoMap {
 impl TempoMap {
     pub fn rebuild_tempo_map(&mut self) {
         let meta = self.meta_events.read().unwrap();
-        self.tempo_map = meta.iter()
+        let mut tempos = meta.iter()
             .filter(|m| m.event_type == MetaEventType::Tempo)
-            .map(|m| (m.tick, bytes_as_tempo(&m.data)))
-            .collect::<Vec<_>>()
-    }
+            .map(|m| (m.tick, bytes_as_tempo(&m.data) as f64));
+
+        self.tempo_map.clear();
+
+        // the first tempo is always treated as starting at tick 0
+        let Some((_, first_tempo)) = tempos.next() else { return; };
+        self.tempo_map.push(TempoSegment { tick: 0, tempo: first_tempo, start_secs_ppq: 0.0 });
 
-    pub fn ticks_to_secs_from_map(&self, ppq: u16, tick: f32) -> f32 {
-        let mut last_tick = 0.0_f32;
-        let mut last_tempo = if !self.tempo_map.is_empty() { self.tempo_map[0].1 } else { 120.0 }; // fallback
-        let mut seconds = 0.0_f32;
+        for (tick, tempo) in tempos {
+            let last = self.tempo_map.last().unwrap();
+            let delta_ticks = tick.saturating_sub(last.tick) as f64;
+            let start_secs_ppq = last.start_secs_ppq + delta_ticks * 60.0 / last.tempo;
 
-        for &(ev_tick, ev_tempo) in self.tempo_map.iter().skip(1) {
-            let ev_tick_f = ev_tick as f32;
-            if ev_tick_f > tick { break; }
-            let delta_ticks = ev_tick_f - last_tick;
-            let us_per_qn = 60_000_000.0_f32 / last_tempo as f32;
-            let sec_per_tick = us_per_qn / 1_000_000.0_f32 / ppq as f32;
-            seconds += delta_ticks * sec_per_tick;
-            last_tick = ev_tick_f;
-            last_tempo = ev_tempo;
+            self.tempo_map.push(TempoSegment { tick, tempo, start_secs_ppq });
         }
+    }
 
-        let delta_ticks = tick - last_tick;
-        let us_per_qn = 60_000_000.0_f32 / last_tempo as f32;
-        let sec_per_tick = us_per_qn / 1_000_000.0_f32 / ppq as f32;
-        seconds + delta_ticks * sec_per_tick
+    pub fn ticks_to_secs_from_map(&self, ppq: u16, tick: f64) -> f64 {
+        let segment = self.segment_at_tick(tick);
+        let secs_ppq = segment.start_secs_ppq + (tick - segment.tick as f64) * 60.0 / segment.tempo;
+        secs_ppq / ppq as f64
     }
 
-    pub fn secs_to_ticks_from_map(&self, ppq: u16, secs: f32) -> f32 {
-        let mut last_tick = 0.0_f32;
-        let mut last_tempo = if !self.tempo_map.is_empty() { self.tempo_map[0].1 } else { 120.0 }; // fallback
-        let mut elapsed_secs = 0.0_f32;
+    pub fn secs_to_ticks_from_map(&self, ppq: u16, secs: f64) -> f64 {
+        let secs_ppq = secs * ppq as f64;
+        let segment = self.segment_at_secs_ppq(secs_ppq);
+        segment.tick as f64 + (secs_ppq - segment.start_secs_ppq) * segment.tempo / 60.0
+    }
 
-        for &(ev_tick, ev_tempo) in self.tempo_map.iter().skip(1) {
-            let ev_tick_f = ev_tick as f32;
+    fn segment_at_tick(&self, tick: f64) -> TempoSegment {
+        // if multiple tempos share a tick, the last one wins
+        let idx = self.tempo_map.partition_point(|s| s.tick as f64 <= tick);
+        self.segment_or_fallback(idx)
+    }
 
-            let delta_ticks = ev_tick_f - last_tick;
-            let us_per_qn = 60_000_000.0_f32 / last_tempo as f32;
-            let sec_per_tick = us_per_qn / 1_000_000.0_f32 / ppq as f32;
-            let delta_secs = delta_ticks * sec_per_tick;
-        
-            if elapsed_secs + delta_secs > secs {
-                let rem = secs - elapsed_secs;
-                return last_tick + rem / sec_per_tick;
-            }
+    fn segment_at_secs_ppq(&self, secs_ppq: f64) -> TempoSegment {
+        let idx = self.tempo_map.partition_point(|s| s.start_secs_ppq <= secs_ppq);
+        self.segment_or_fallback(idx)
+    }
 
-            elapsed_secs += delta_secs;
-            last_tick = ev_tick_f;
-            last_tempo = ev_tempo;
+    #[inline(always)]
+    fn segment_or_fallback(&self, partition_idx: usize) -> TempoSegment {
+        match self.tempo_map.get(partition_idx.saturating_sub(1)) {
+            Some(segment) => *segment,
+            None => TempoSegment { tick: 0, tempo: FALLBACK_TEMPO, start_secs_ppq: 0.0 }
         }
-
-        let us_per_qn = 60_000_000.0_f32 / last_tempo;
-        let sec_per_tick = us_per_qn / 1_000_000.0_f32 / ppq as f32;
-        last_tick + (secs - elapsed_secs) / sec_per_tick
     }
-}
\ No newline at end of file
+}
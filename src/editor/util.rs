@@ -476,8 +476,12 @@ pub fn path_rel_to_abs(path: String) -> PathBuf {
     std::path::absolute(path).unwrap()
 }
 
+/// The slowest tempo a tempo meta can hold, its microseconds per quarter note only get 24 bits.
+pub const MIN_TEMPO_BPM: f32 = 60000000.0 / 0xFFFFFF as f32;
+
 pub fn tempo_as_bytes(tempo: f32) -> [u8; 3] {
-    let tempo_conv = (60000000.0 / tempo) as u32;
+    // rounding can push the slowest tempo just past 24 bits
+    let tempo_conv = ((60000000.0 / tempo) as u32).min(0xFFFFFF);
     return [
         ((tempo_conv >> 16) & 0xFF) as u8,
         ((tempo_conv >> 8) & 0xFF) as u8,
@@ -502,4 +506,63 @@ pub fn get_next_specific_ch_ev_idx(ch_evs: &[ChannelEvent], ev_type: &ChannelEve
     }
 
     None
-}
\ No newline at end of file
+}
+/// Tiny xorshift generator for humanizing. Not meant for anything that needs good randomness.
+pub struct XorShiftRng(u64);
+
+impl XorShiftRng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on 0
+        Self(if seed == 0 { 0x9E3779B97F4A7C15 } else { seed })
+    }
+
+    pub fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Random integer in `-range..=range`.
+    pub fn next_offset(&mut self, range: SignedMIDITick) -> SignedMIDITick {
+        if range <= 0 { return 0; }
+        (self.next_u64() % (range as u64 * 2 + 1)) as SignedMIDITick - range
+    }
+}
+
+/// Note name with octave, middle C (60) being C4.
+pub fn key_to_name(key: MIDIKey) -> String {
+    const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    format!("{}{}", NAMES[(key % 12) as usize], (key / 12) as i32 - 1)
+}
+
+/// Opposite of [`key_to_name`]. Also takes flats (`Bb2`) and plain key numbers.
+pub fn name_to_key(name: &str) -> Option<MIDIKey> {
+    let name = name.trim();
+    if let Ok(key) = name.parse::<u8>() { return (key <= 127).then_some(key); }
+
+    let mut chars = name.chars();
+    let pitch_class: i32 = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0, 'D' => 2, 'E' => 4, 'F' => 5, 'G' => 7, 'A' => 9, 'B' => 11,
+        _ => return None
+    };
+
+    let rest = chars.as_str();
+    let (accidental, octave) = match rest.chars().next() {
+        Some('#') => (1, &rest[1..]),
+        Some('b') => (-1, &rest[1..]),
+        _ => (0, rest)
+    };
+
+    let key = (octave.parse::<i32>().ok()? + 1) * 12 + pitch_class + accidental;
+    (0..=127).contains(&key).then_some(key as MIDIKey)
+}
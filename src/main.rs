@@ -9,7 +9,7 @@ pub const EDITOR_STAGE: &'static str = "Beta";
 
 use std::{panic, sync::Mutex};
 
-use crate::{app::main_window::MainWindow, editor::util::path_rel_to_abs, util::debugger::Debugger};
+use crate::{app::main_window::MainWindow, editor::{plugins::plugin_test_runner, util::path_rel_to_abs}, util::debugger::Debugger};
 
 #[macro_export]
 macro_rules! deprecated {
@@ -40,6 +40,12 @@ fn make_panic_hook() {
 }
 
 fn main() -> eframe::Result {
+    // `test-plugin <test.json>...` runs plugin tests without opening the editor
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|arg| arg.as_str()) == Some("test-plugin") {
+        std::process::exit(plugin_test_runner::run_cli(&args[2..]));
+    }
+
     // initialize debugger
     Debugger::init_log_file(path_rel_to_abs("./logs/debug.log".into()).to_str().unwrap());
     Debugger::log_notime("***** APPLICATION STARTED *****");
@@ -12,7 +12,7 @@ pub enum ChannelEventType {
     PitchBend(u8, u8)
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct ChannelEvent {
     pub tick: MIDITick,
     pub channel: u8,
@@ -23,7 +23,7 @@ pub enum MetaEventType {
     SequencerSpecific = 0x7F
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct MetaEvent {
     pub tick: MIDITick,
     pub event_type: MetaEventType,
@@ -37,15 +37,35 @@ impl ToString for MetaEventType {
             MetaEventType::Tempo => "Tempo",
             MetaEventType::KeySignature => "Key Signature",
             MetaEventType::Marker => "Marker",
-            _ => ""
+            MetaEventType::Lyric => "Lyric",
+            MetaEventType::CuePoint => "Cue Point",
+            MetaEventType::SequenceNumber => "Sequence Number",
+            MetaEventType::Text => "Text",
+            MetaEventType::Copyright => "Copyright",
+            MetaEventType::TrackName => "Track Name",
+            MetaEventType::InstrumentName => "Instrument Name",
+            MetaEventType::ProgramName => "Program Name",
+            MetaEventType::DeviceName => "Device Name",
+            MetaEventType::ChannelPrefix => "Channel Prefix",
+            MetaEventType::MIDIPort => "MIDI Port",
+            MetaEventType::EndOfTrack => "End of Track",
+            MetaEventType::SMPTEOffset => "SMPTE Offset",
+            MetaEventType::SequencerSpecific => "Sequencer Specific"
         }.to_string()
     }
 }
 
+impl MetaEventType {
+    /// Metas that carry plain text, such as markers and lyrics.
+    pub fn is_text(&self) -> bool {
+        (*self as u8) >= 0x01 && (*self as u8) <= 0x09
+    }
+}
+
 impl MetaEvent {
     pub fn get_value_string(&self) -> String {
         match self.event_type {
-            MetaEventType::Marker => {
+            MetaEventType::Marker | MetaEventType::Lyric | MetaEventType::CuePoint => {
                 String::from_utf8_lossy(&self.data).to_string()
             },
             MetaEventType::Tempo => {
@@ -53,7 +73,64 @@ impl MetaEvent {
                 let tempof = 60000000.0 / tempo as f32;
                 tempof.to_string()
             },
+            MetaEventType::KeySignature => {
+                KeySignature::from_meta_data(&self.data).name()
+            },
             _ => { String::from("N/A") }
         }
     }
-}
\ No newline at end of file
+}
+
+const MAJOR_NAMES: [&str; 15] = ["Cb", "Gb", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#"];
+const MINOR_NAMES: [&str; 15] = ["Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#", "G#", "D#", "A#"];
+
+/// A key signature as stored in the SMF meta event (sharps/flats + major/minor).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeySignature {
+    pub sharps_flats: i8,
+    pub minor: bool,
+}
+
+impl Default for KeySignature {
+    fn default() -> Self {
+        Self { sharps_flats: 0, minor: false }
+    }
+}
+
+impl KeySignature {
+    pub fn new(sharps_flats: i8, minor: bool) -> Self {
+        Self { sharps_flats: sharps_flats.clamp(-7, 7), minor }
+    }
+
+    pub fn from_meta_data(data: &[u8]) -> Self {
+        let sharps_flats = data.first().map_or(0, |&sf| sf as i8);
+        let minor = data.get(1).map_or(false, |&mi| mi != 0);
+        Self::new(sharps_flats, minor)
+    }
+
+    pub fn to_meta_data(&self) -> Vec<u8> {
+        vec![self.sharps_flats as u8, self.minor as u8]
+    }
+
+    /// Pitch class (0 = C) of the major key with this many sharps/flats.
+    pub fn major_tonic(&self) -> u8 {
+        (self.sharps_flats as i32 * 7).rem_euclid(12) as u8
+    }
+
+    /// Pitch class of the key's tonic, taking major/minor into account.
+    pub fn tonic(&self) -> u8 {
+        if self.minor { (self.major_tonic() + 9) % 12 }
+        else { self.major_tonic() }
+    }
+
+    pub fn name(&self) -> String {
+        let idx = (self.sharps_flats + 7) as usize;
+        if self.minor { format!("{} minor", MINOR_NAMES[idx]) }
+        else { format!("{} major", MAJOR_NAMES[idx]) }
+    }
+
+    /// Every valid key signature, in circle-of-fifths order.
+    pub fn all(minor: bool) -> impl Iterator<Item = KeySignature> {
+        (-7..=7).map(move |sf| KeySignature::new(sf, minor))
+    }
+}
@@ -2,7 +2,7 @@
 use crate::editor::util::MIDITick;
 
 // channel and track is implied
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub struct Note {
     pub channel: u8,
     pub start: MIDITick,
@@ -195,7 +195,8 @@ impl MIDIFile {
                     MetaEventType::TimeSignature | 
                     MetaEventType::KeySignature | 
                     MetaEventType::Lyric | 
-                    MetaEventType::Marker => {
+                    MetaEventType::Marker |
+                    MetaEventType::CuePoint => {
                         m_track.push(meta_ev);
                     },
                     _ => {
@@ -518,15 +519,19 @@ impl MIDIFileWriter {
     pub fn write_midi(&self, path: &str) -> Result<()> {
         let file = File::create(path)?;
         let mut out = BufWriter::with_capacity(16 * 1024 * 1024, file);
-        
+        self.write_midi_to(&mut out)
+    }
+
+    /// Writes the whole file to `out`, for when the file should end up somewhere other than on disk.
+    pub fn write_midi_to<W: Write>(&self, out: &mut W) -> Result<()> {
         // header
-        self.write_u32(&mut out, 0x4D546864)?;
+        self.write_u32(out, 0x4D546864)?;
 
         // header length
-        self.write_u32(&mut out, 6)?;
-        self.write_u16(&mut out, 1)?; // format
-        self.write_u16(&mut out, self.track_count)?;
-        self.write_u16(&mut out, self.ppq)?;
+        self.write_u32(out, 6)?;
+        self.write_u16(out, 1)?; // format
+        self.write_u16(out, self.track_count)?;
+        self.write_u16(out, self.ppq)?;
 
         // iterate through tracks
         for track in self.tracks.iter() {
@@ -538,11 +543,11 @@ impl MIDIFileWriter {
                 ).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "track length overflow"))?;
             }
 
-            self.write_u32(&mut out, 0x4D54726B)?;
-            self.write_u32(&mut out, track_len)?;
+            self.write_u32(out, 0x4D54726B)?;
+            self.write_u32(out, track_len)?;
 
             for ev in track.iter() {
-                ev.write_to(&mut out)?;
+                ev.write_to(out)?;
             }
         }
 
@@ -1,23 +1,202 @@
+use std::fmt::Display;
+
+use crate::editor::util::XorShiftRng;
+
+#[derive(Debug)]
 pub enum ExpressionError {
     SyntaxError(String),
-    ZeroDivision
+    ZeroDivision,
+    UnknownVariable(String),
+    UnknownFunction(String),
+    /// function name, expected argument count
+    ArgumentCount(String, &'static str)
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpressionError::SyntaxError(msg) => write!(f, "Syntax error: {}", msg),
+            ExpressionError::ZeroDivision => write!(f, "Division by zero"),
+            ExpressionError::UnknownVariable(name) => write!(f, "Unknown variable '{}'", name),
+            ExpressionError::UnknownFunction(name) => write!(f, "Unknown function '{}'", name),
+            ExpressionError::ArgumentCount(name, expected) => write!(f, "'{}' takes {} argument(s)", name, expected),
+        }
+    }
 }
 
-enum Expr {
-    Int(i64),
-    UInt(u64),
-    Float(f64),
+pub enum Expr {
+    Number(f64),
     Var(String),
     UnaryNeg(Box<Expr>),
-    Binary(Box<Expr>, Op, Box<Expr>)
+    Not(Box<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+    Call(String, Vec<Expr>),
+    // condition ? then : else
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>)
 }
 
-enum Op {
+#[derive(Clone, Copy)]
+pub enum Op {
     Add,
     Sub,
     Mul,
     Div,
-    Pow
+    Mod,
+    Pow,
+    Eq,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    And,
+    Or
+}
+
+/// Everything an expression can see while it's being evaluated.
+pub struct EvalContext<'a> {
+    pub variables: &'a [(&'a str, f64)],
+    pub rng: &'a mut XorShiftRng
+}
+
+#[inline(always)]
+fn as_bool(value: f64) -> bool {
+    value != 0.0
+}
+
+#[inline(always)]
+fn from_bool(value: bool) -> f64 {
+    if value { 1.0 } else { 0.0 }
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Expr, ExpressionError> {
+        let mut p = ExpressionParser::new(input);
+        let expr = p.parse_expr()?;
+
+        p.skip_whitespace();
+        if let Some(&c) = p.peek() {
+            return Err(ExpressionError::SyntaxError(format!("unexpected '{}' at position {}", c as char, p.pos + 1)));
+        }
+
+        Ok(expr)
+    }
+
+    pub fn eval(&self, ctx: &mut EvalContext) -> Result<f64, ExpressionError> {
+        match self {
+            Expr::Number(n) => Ok(*n),
+            Expr::Var(name) => {
+                if let Some((_, value)) = ctx.variables.iter().find(|(var, _)| *var == name.as_str()) {
+                    return Ok(*value);
+                }
+
+                match name.as_str() {
+                    "pi" => Ok(std::f64::consts::PI),
+                    "true" => Ok(1.0),
+                    "false" => Ok(0.0),
+                    _ => Err(ExpressionError::UnknownVariable(name.clone()))
+                }
+            },
+            Expr::UnaryNeg(expr) => Ok(-expr.eval(ctx)?),
+            Expr::Not(expr) => Ok(from_bool(!as_bool(expr.eval(ctx)?))),
+            Expr::Binary(lhs, op, rhs) => {
+                let lhs = lhs.eval(ctx)?;
+
+                // short circuit so things like `x != 0 && 1 / x > 2` work
+                match op {
+                    Op::And if !as_bool(lhs) => return Ok(0.0),
+                    Op::Or if as_bool(lhs) => return Ok(1.0),
+                    _ => {}
+                }
+
+                let rhs = rhs.eval(ctx)?;
+                match op {
+                    Op::Add => Ok(lhs + rhs),
+                    Op::Sub => Ok(lhs - rhs),
+                    Op::Mul => Ok(lhs * rhs),
+                    Op::Div => if rhs == 0.0 { Err(ExpressionError::ZeroDivision) } else { Ok(lhs / rhs) },
+                    Op::Mod => if rhs == 0.0 { Err(ExpressionError::ZeroDivision) } else { Ok(lhs.rem_euclid(rhs)) },
+                    Op::Pow => Ok(lhs.powf(rhs)),
+                    Op::Eq => Ok(from_bool(lhs == rhs)),
+                    Op::NotEq => Ok(from_bool(lhs != rhs)),
+                    Op::Less => Ok(from_bool(lhs < rhs)),
+                    Op::LessEq => Ok(from_bool(lhs <= rhs)),
+                    Op::Greater => Ok(from_bool(lhs > rhs)),
+                    Op::GreaterEq => Ok(from_bool(lhs >= rhs)),
+                    Op::And | Op::Or => Ok(from_bool(as_bool(rhs))),
+                }
+            },
+            Expr::Conditional(cond, then_expr, else_expr) => {
+                if as_bool(cond.eval(ctx)?) { then_expr.eval(ctx) } else { else_expr.eval(ctx) }
+            },
+            Expr::Call(name, args) => Self::call(name, args, ctx)
+        }
+    }
+
+    fn call(name: &str, args: &[Expr], ctx: &mut EvalContext) -> Result<f64, ExpressionError> {
+        let arg_count_err = |expected| Err(ExpressionError::ArgumentCount(name.to_string(), expected));
+
+        // if() only evaluates the branch it takes
+        if name == "if" {
+            if args.len() != 3 { return arg_count_err("3"); }
+            return if as_bool(args[0].eval(ctx)?) { args[1].eval(ctx) } else { args[2].eval(ctx) };
+        }
+
+        let values = args.iter().map(|arg| arg.eval(ctx)).collect::<Result<Vec<_>, _>>()?;
+
+        match (name, values.as_slice()) {
+            ("abs", &[x]) => Ok(x.abs()),
+            ("round", &[x]) => Ok(x.round()),
+            ("floor", &[x]) => Ok(x.floor()),
+            ("ceil", &[x]) => Ok(x.ceil()),
+            ("sqrt", &[x]) => Ok(x.sqrt()),
+            ("sin", &[x]) => Ok(x.sin()),
+            ("cos", &[x]) => Ok(x.cos()),
+            ("clamp", &[x, min, max]) => Ok(x.max(min).min(max)),
+            ("min", [first, rest @ ..]) => Ok(rest.iter().fold(*first, |a, &b| a.min(b))),
+            ("max", [first, rest @ ..]) => Ok(rest.iter().fold(*first, |a, &b| a.max(b))),
+            // snaps x to the nearest multiple of step
+            ("snap", &[x, step]) => if step == 0.0 { Err(ExpressionError::ZeroDivision) } else { Ok((x / step).round() * step) },
+            ("rand", &[]) => Ok((ctx.rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64),
+            ("rand", &[min, max]) => {
+                let (min, max) = (min.min(max), min.max(max));
+                let unit = (ctx.rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+                Ok(min + unit * (max - min))
+            },
+
+            ("abs" | "round" | "floor" | "ceil" | "sqrt" | "sin" | "cos", _) => arg_count_err("1"),
+            ("clamp", _) => arg_count_err("3"),
+            ("min" | "max", _) => arg_count_err("at least 1"),
+            ("snap", _) => arg_count_err("2"),
+            ("rand", _) => arg_count_err("0 or 2"),
+            _ => Err(ExpressionError::UnknownFunction(name.to_string()))
+        }
+    }
+}
+
+/// Evaluates a constant expression like `960*3/4`.
+pub fn eval_str(input: &str) -> Result<f64, ExpressionError> {
+    let expr = Expr::parse(input)?;
+    let mut rng = XorShiftRng::from_time();
+    expr.eval(&mut EvalContext { variables: &[], rng: &mut rng })
+}
+
+/// Parses `name = expression`. The name is returned as written.
+pub fn parse_assignment(input: &str) -> Result<(String, Expr), ExpressionError> {
+    let mut p = ExpressionParser::new(input);
+    p.skip_whitespace();
+
+    let name = p.parse_identifier()
+        .ok_or_else(|| ExpressionError::SyntaxError("expected a property name".into()))?;
+
+    p.skip_whitespace();
+    if p.peek() != Some(&b'=') || p.text.get(p.pos + 1) == Some(&b'=') {
+        return Err(ExpressionError::SyntaxError(format!("expected '=' after '{}'", name)));
+    }
+    p.next();
+
+    let expr = Expr::parse(std::str::from_utf8(&p.text[p.pos..]).unwrap())?;
+    Ok((name, expr))
 }
 
 struct ExpressionParser<'a> {
@@ -30,12 +209,6 @@ impl<'a> ExpressionParser<'a> {
         Self { text: text.as_bytes(), pos: 0 }
     }
 
-    /*fn parse(input: &str) -> Result<Expr, ExpressionError> {
-        let mut p = Self::new(input);
-        //let expr = 
-        Ok(())
-    }
-
     fn peek(&self) -> Option<&u8> {
         self.text.get(self.pos)
     }
@@ -46,16 +219,167 @@ impl<'a> ExpressionParser<'a> {
     }
 
     fn skip_whitespace(&mut self) {
-        while matches!(self.peek(), Some(b' ' | b'\t')) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\r' | b'\n')) {
             self.next()
         }
     }
 
+    /// Skips whitespace, then consumes `token` if it's next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.text[self.pos..].starts_with(token.as_bytes()) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), ExpressionError> {
+        if self.eat(token) { Ok(()) }
+        else { Err(ExpressionError::SyntaxError(format!("expected '{}' at position {}", token, self.pos + 1))) }
+    }
+
     fn parse_expr(&mut self) -> Result<Expr, ExpressionError> {
+        let cond = self.parse_or()?;
+
+        if self.eat("?") {
+            let then_expr = self.parse_expr()?;
+            self.expect(":")?;
+            let else_expr = self.parse_expr()?;
+            return Ok(Expr::Conditional(Box::new(cond), Box::new(then_expr), Box::new(else_expr)));
+        }
+
+        Ok(cond)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ExpressionError> {
+        let mut node = self.parse_and()?;
+        while self.eat("||") {
+            node = Expr::Binary(Box::new(node), Op::Or, Box::new(self.parse_and()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ExpressionError> {
+        let mut node = self.parse_comparison()?;
+        while self.eat("&&") {
+            node = Expr::Binary(Box::new(node), Op::And, Box::new(self.parse_comparison()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, ExpressionError> {
+        let node = self.parse_sum()?;
+
+        // the two-character operators have to be checked first
+        let op = if self.eat("==") { Op::Eq }
+            else if self.eat("!=") { Op::NotEq }
+            else if self.eat("<=") { Op::LessEq }
+            else if self.eat(">=") { Op::GreaterEq }
+            else if self.eat("<") { Op::Less }
+            else if self.eat(">") { Op::Greater }
+            else { return Ok(node); };
+
+        Ok(Expr::Binary(Box::new(node), op, Box::new(self.parse_sum()?)))
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, ExpressionError> {
         let mut node = self.parse_term()?;
+        loop {
+            let op = if self.eat("+") { Op::Add }
+                else if self.eat("-") { Op::Sub }
+                else { return Ok(node); };
+            node = Expr::Binary(Box::new(node), op, Box::new(self.parse_term()?));
+        }
     }
 
-    fn parse_variable(&mut self) -> Expr {
-        
-    }*/
-}
\ No newline at end of file
+    fn parse_term(&mut self) -> Result<Expr, ExpressionError> {
+        let mut node = self.parse_unary()?;
+        loop {
+            let op = if self.eat("*") { Op::Mul }
+                else if self.eat("/") { Op::Div }
+                else if self.eat("%") { Op::Mod }
+                else { return Ok(node); };
+            node = Expr::Binary(Box::new(node), op, Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ExpressionError> {
+        if self.eat("-") { return Ok(Expr::UnaryNeg(Box::new(self.parse_unary()?))); }
+        if self.eat("+") { return self.parse_unary(); }
+        // don't mistake != for a not
+        self.skip_whitespace();
+        if self.peek() == Some(&b'!') && self.text.get(self.pos + 1) != Some(&b'=') {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+
+        self.parse_power()
+    }
+
+    fn parse_power(&mut self) -> Result<Expr, ExpressionError> {
+        let base = self.parse_primary()?;
+
+        // right associative, 2^3^2 = 2^9
+        if self.eat("^") {
+            return Ok(Expr::Binary(Box::new(base), Op::Pow, Box::new(self.parse_unary()?)));
+        }
+
+        Ok(base)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ExpressionError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some(b'(') => {
+                self.next();
+                let expr = self.parse_expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            },
+            Some(c) if c.is_ascii_digit() || *c == b'.' => self.parse_number(),
+            Some(c) if c.is_ascii_alphabetic() || *c == b'_' => {
+                let name = self.parse_identifier().unwrap();
+
+                if !self.eat("(") { return Ok(Expr::Var(name)); }
+
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.parse_expr()?);
+                        if self.eat(")") { break; }
+                        self.expect(",")?;
+                    }
+                }
+
+                Ok(Expr::Call(name, args))
+            },
+            Some(&c) => Err(ExpressionError::SyntaxError(format!("unexpected '{}' at position {}", c as char, self.pos + 1))),
+            None => Err(ExpressionError::SyntaxError("unexpected end of expression".into()))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Expr, ExpressionError> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || *c == b'.') {
+            self.next();
+        }
+
+        let literal = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+        literal.parse::<f64>()
+            .map(Expr::Number)
+            .map_err(|_| ExpressionError::SyntaxError(format!("invalid number '{}'", literal)))
+    }
+
+    fn parse_identifier(&mut self) -> Option<String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || *c == b'_') {
+            self.next();
+        }
+
+        if start == self.pos || self.text[start].is_ascii_digit() { return None; }
+        Some(std::str::from_utf8(&self.text[start..self.pos]).unwrap().to_string())
+    }
+}